@@ -0,0 +1,319 @@
+// jkcoxson
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    error::{AsyncClientError, LockdowndError},
+    idevice::Device,
+    service::Service,
+    services::{
+        afc::AfcClient, companion_proxy::CompanionProxy, diagnostics_relay::DiagnosticsRelay,
+        file_relay::FileRelay, house_arrest::HouseArrest, lockdownd::LockdowndClient,
+        misagent::MisagentClient, mobile_backup::MobileBackup2Client,
+        mobile_sync::MobileSyncClient, notification_proxy::NotificationProxyClient,
+        preboard::PreboardClient, screenshotr::ScreenshotrClient,
+        springboard_services::SpringboardServicesClient, syslog_relay::SyslogRelayClient,
+        web_inspector::WebInspectorClient,
+    },
+};
+
+use log::{info, warn};
+
+/// An AFC client that can be awaited, see `AsyncClient`
+pub type AsyncAfcClient = AsyncClient<AfcClient<'static>>;
+/// A mobilesync client that can be awaited, see `AsyncClient`
+pub type AsyncMobileSyncClient = AsyncClient<MobileSyncClient<'static>>;
+/// A lockdownd client that can be awaited, see `AsyncClient`
+pub type AsyncLockdowndClient = AsyncClient<LockdowndClient<'static>>;
+
+/// A client that can run on an `AsyncClient`'s thread.
+/// It's implemented on the `'static` form of each client, and names the client borrowing the device
+/// for any lifetime, so the thread can lend the client to calls without it ever leaving the thread.
+pub trait AsyncService: 'static {
+    /// The client, borrowing the device for `'d`
+    type Client<'d>;
+    type Error: Send + 'static;
+
+    /// Connects the client on the thread
+    /// # Arguments
+    /// * `device` - The device owned by the thread
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    fn connect(device: &Device) -> Result<Self::Client<'_>, Self::Error>;
+}
+
+macro_rules! async_service {
+    ($($client:ident),* $(,)?) => {$(
+        impl AsyncService for $client<'static> {
+            type Client<'d> = $client<'d>;
+            type Error = <$client<'static> as Service<'static>>::Error;
+
+            fn connect(device: &Device) -> Result<$client<'_>, Self::Error> {
+                <$client as Service>::connect(device)
+            }
+        }
+    )*};
+}
+
+async_service!(
+    AfcClient,
+    CompanionProxy,
+    DiagnosticsRelay,
+    FileRelay,
+    HouseArrest,
+    MisagentClient,
+    MobileBackup2Client,
+    MobileSyncClient,
+    NotificationProxyClient,
+    PreboardClient,
+    ScreenshotrClient,
+    SpringboardServicesClient,
+    SyslogRelayClient,
+    WebInspectorClient,
+);
+
+impl AsyncService for LockdowndClient<'static> {
+    type Client<'d> = LockdowndClient<'d>;
+    type Error = LockdowndError;
+
+    fn connect(device: &Device) -> Result<LockdowndClient<'_>, LockdowndError> {
+        LockdowndClient::new(device, "rusty_libimobiledevice")
+    }
+}
+
+/// A call queued for the thread
+trait Job<C: AsyncService>: Send {
+    fn run(self: Box<Self>, client: &mut C::Client<'_>);
+}
+
+struct Call<F, T> {
+    op: F,
+    completer: Completer<T>,
+}
+
+impl<C: AsyncService, F, T> Job<C> for Call<F, T>
+where
+    F: for<'d> FnOnce(&mut C::Client<'d>) -> T + Send,
+    T: Send,
+{
+    fn run(self: Box<Self>, client: &mut C::Client<'_>) {
+        let Call { op, completer } = *self;
+        completer.complete(op(client));
+    }
+}
+
+/// Runs a service client on a thread of its own, so its blocking calls can be awaited.
+///
+/// The thread owns the device and the client, and runs the closures passed to `call` one at a time
+/// in the order they were made. A call such as `MobileSyncClient::receive` only blocks that thread,
+/// never the executor. This works with any executor, since the futures are woken from the thread.
+///
+/// ```ignore
+/// let afc = AsyncAfcClient::connect(device).await?;
+/// let files = afc.call(|afc| afc.read_directory("/")).await??;
+/// ```
+///
+/// The closures get the client for a lifetime they can't name, so nothing borrowing the device,
+/// such as a clone of the client, can be returned from them.
+///
+/// Dropping the client lets the thread finish the calls already queued, then it closes the connection.
+pub struct AsyncClient<C: AsyncService> {
+    jobs: Mutex<mpsc::Sender<Box<dyn Job<C>>>>,
+}
+
+impl<C: AsyncService> AsyncClient<C> {
+    /// Starts the thread and connects on it. The client never leaves the thread
+    fn spawn<E: Send + 'static>(
+        device: Device,
+        connect: impl for<'d> FnOnce(&'d Device) -> Result<C::Client<'d>, E> + Send + 'static,
+    ) -> Connecting<C, E> {
+        let (sender, jobs) = mpsc::channel::<Box<dyn Job<C>>>();
+        let (connected, completer) = Pending::new();
+
+        std::thread::spawn(move || {
+            let mut client = match connect(&device) {
+                Ok(client) => client,
+                Err(e) => {
+                    completer.complete(Err(e));
+                    return;
+                }
+            };
+            completer.complete(Ok(()));
+
+            for job in jobs {
+                job.run(&mut client);
+            }
+            info!("Async client dropped, closing its connection");
+        });
+
+        Connecting {
+            connected,
+            client: Some(AsyncClient {
+                jobs: Mutex::new(sender),
+            }),
+        }
+    }
+
+    /// Starts a thread for the client and connects to the service on it, see `Service::connect`
+    /// # Arguments
+    /// * `device` - The device to connect to, owned by the client's thread from now on
+    /// # Returns
+    /// A future that resolves with the client once it's connected
+    ///
+    /// ***Verified:*** False
+    pub fn connect(device: Device) -> Connecting<C, C::Error> {
+        Self::spawn(device, C::connect)
+    }
+
+    /// Runs a closure with the client on its thread
+    /// # Arguments
+    /// * `op` - The closure to run, which can block for as long as it needs
+    /// # Returns
+    /// A future that resolves with what the closure returned,
+    /// or `ThreadStopped` if a closure panicked and took the client down with it
+    ///
+    /// ***Verified:*** False
+    pub fn call<T: Send + 'static>(
+        &self,
+        op: impl for<'d> FnOnce(&mut C::Client<'d>) -> T + Send + 'static,
+    ) -> Pending<T> {
+        let (pending, completer) = Pending::new();
+        let job: Box<dyn Job<C>> = Box::new(Call { op, completer });
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        // The job is dropped if the thread is gone, which marks the future abandoned
+        if jobs.send(job).is_err() {
+            warn!("The async client's thread has stopped");
+        }
+        pending
+    }
+}
+
+impl AsyncLockdowndClient {
+    /// Starts a thread for a lockdownd client and connects on it
+    /// # Arguments
+    /// * `device` - The device to connect to, owned by the client's thread from now on
+    /// * `label` - The label to give the underlying service as it starts
+    /// # Returns
+    /// A future that resolves with the client once it's connected
+    ///
+    /// ***Verified:*** False
+    pub fn lockdownd(
+        device: Device,
+        label: impl Into<String>,
+    ) -> Connecting<LockdowndClient<'static>, LockdowndError> {
+        let label = label.into();
+        Self::spawn(device, move |device| LockdowndClient::new(device, label))
+    }
+}
+
+/// A future that resolves with an `AsyncClient` once its thread has connected
+pub struct Connecting<C: AsyncService, E> {
+    connected: Pending<Result<(), E>>,
+    client: Option<AsyncClient<C>>,
+}
+
+// Nothing in it is pinned, the client only moves out once it's ready
+impl<C: AsyncService, E> Unpin for Connecting<C, E> {}
+
+impl<C: AsyncService, E> Future for Connecting<C, E> {
+    type Output = Result<AsyncClient<C>, AsyncClientError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.connected).poll(cx) {
+            Poll::Ready(Ok(Ok(()))) => match this.client.take() {
+                Some(client) => Poll::Ready(Ok(client)),
+                None => panic!("Connecting polled after it resolved"),
+            },
+            Poll::Ready(Ok(Err(e))) => Poll::Ready(Err(AsyncClientError::Connect(e))),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(AsyncClientError::ThreadStopped)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A future resolved by an `AsyncClient`'s thread
+pub struct Pending<T> {
+    state: Arc<Mutex<PendingState<T>>>,
+}
+
+struct PendingState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    abandoned: bool,
+}
+
+/// The thread's half of a `Pending`. Dropping it without completing marks the future abandoned
+struct Completer<T> {
+    state: Arc<Mutex<PendingState<T>>>,
+    completed: bool,
+}
+
+impl<T> Pending<T> {
+    fn new() -> (Self, Completer<T>) {
+        let state = Arc::new(Mutex::new(PendingState {
+            value: None,
+            waker: None,
+            abandoned: false,
+        }));
+        (
+            Pending {
+                state: state.clone(),
+            },
+            Completer {
+                state,
+                completed: false,
+            },
+        )
+    }
+}
+
+impl<T> Future for Pending<T> {
+    type Output = Result<T, AsyncClientError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+        if state.abandoned {
+            warn!("The async client's thread stopped before finishing the call");
+            return Poll::Ready(Err(AsyncClientError::ThreadStopped));
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Completer<T> {
+    fn complete(mut self, value: T) {
+        self.completed = true;
+        self.finish(Some(value));
+    }
+
+    fn finish(&self, value: Option<T>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match value {
+            Some(value) => state.value = Some(value),
+            None => state.abandoned = true,
+        }
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.finish(None);
+        }
+    }
+}
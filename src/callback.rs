@@ -36,8 +36,13 @@ pub unsafe extern "C" fn idevice_event_callback(
     event: *const unsafe_bindings::idevice_event_t,
     user_data: *mut c_void,
 ) {
+    if event.is_null() || user_data.is_null() {
+        return;
+    }
     let event: IDeviceEvent = (*event).into();
 
+    // SAFETY: user_data points into the callback owned by an EventSubscription,
+    // which unsubscribes before freeing it
     let callback = &mut *(user_data as *mut IDeviceEventCallback);
 
     if let Some(ref filter_udid) = callback._udid_filter {
@@ -6,12 +6,17 @@ use std::os::raw::c_char;
 use crate::bindings as unsafe_bindings;
 use crate::error::IdeviceError;
 use crate::idevice::Device;
+use crate::service::{ConnectionStats, StatsCounter};
+use crate::services::lockdownd::LockdowndService;
 
 pub struct DeviceConnection<'a> {
     pub(crate) pointer: *mut unsafe_bindings::idevice_connection_private,
+    stats: StatsCounter,
     phantom: PhantomData<&'a Device>,
 }
 
+unsafe impl Send for DeviceConnection<'_> {}
+
 pub struct SslData {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,7 +25,7 @@ pub enum DeviceConnectionType {
     Network,
 }
 
-impl DeviceConnection<'_> {
+impl<'a> DeviceConnection<'a> {
     /// Create a connection to an iOS device
     /// This is NOT a lockdown connection, for things like debugging use a specific service
     /// # Arguments
@@ -30,7 +35,7 @@ impl DeviceConnection<'_> {
     /// A handle for the connection
     ///
     /// ***Verified:*** False
-    pub fn connect(device: Device, port: u16) -> Result<Self, IdeviceError> {
+    pub fn connect(device: &'a Device, port: u16) -> Result<Self, IdeviceError> {
         let mut to_fill = unsafe { std::mem::zeroed() };
 
         let result =
@@ -42,10 +47,46 @@ impl DeviceConnection<'_> {
 
         Ok(DeviceConnection {
             pointer: to_fill,
+            stats: StatsCounter::default(),
             phantom: std::marker::PhantomData,
         })
     }
 
+    /// Connects to a service started by lockdownd, enabling SSL if the service requires it
+    /// # Arguments
+    /// * `device` - The device to create a connection to
+    /// * `service` - The service to connect to
+    /// # Returns
+    /// A handle for the connection
+    ///
+    /// ***Verified:*** False
+    pub fn service_connect(
+        device: &'a Device,
+        service: &LockdowndService,
+    ) -> Result<Self, IdeviceError> {
+        let mut to_fill = unsafe { std::mem::zeroed() };
+
+        let result = unsafe {
+            unsafe_bindings::idevice_connect(device.pointer, service.port as u16, &mut to_fill)
+        }
+        .into();
+
+        if result != IdeviceError::Success {
+            return Err(result);
+        }
+
+        let mut connection = DeviceConnection {
+            pointer: to_fill,
+            stats: StatsCounter::default(),
+            phantom: std::marker::PhantomData,
+        };
+        if service.requires_ssl() {
+            connection.enable_ssl()?;
+        }
+
+        Ok(connection)
+    }
+
     /// Sends data to the device
     /// # Arguments
     /// * `data` - The data to send
@@ -69,6 +110,7 @@ impl DeviceConnection<'_> {
             return Err(result);
         }
 
+        self.stats.sent(to_fill as usize);
         Ok(to_fill)
     }
 
@@ -110,23 +152,65 @@ impl DeviceConnection<'_> {
         }
 
         buffer.truncate(received as usize);
+        self.stats.received(buffer.len());
 
         Ok(buffer)
     }
 
-    /// Toggles SSL on the connection
+    /// Starts counting the traffic on this connection, see `stats`
     /// # Arguments
-    /// * `enable` - Whether to enable SSL
+    /// *none*
     /// # Returns
+    /// The connection
+    ///
+    /// ***Verified:*** False
+    pub fn with_stats(mut self) -> Self {
+        self.stats.enable();
+        self
+    }
+
+    /// Gets the traffic totals since `with_stats` was called
+    /// # Arguments
     /// *none*
+    /// # Returns
+    /// The totals, all zero if counting was never enabled
     ///
     /// ***Verified:*** False
-    pub fn enable_ssl(&self, enable: bool) -> Result<(), IdeviceError> {
-        let result = match enable {
-            true => unsafe { unsafe_bindings::idevice_connection_enable_ssl(self.pointer) },
-            false => unsafe { unsafe_bindings::idevice_connection_disable_ssl(self.pointer) },
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.get()
+    }
+
+    /// Starts an SSL session on the connection using the device's pairing record.
+    /// This has to happen at the exact point in the protocol where the device expects the handshake,
+    /// usually right after a `StartSession` reply or when connecting to a service that requires SSL.
+    /// At any other point the device treats the handshake as garbage and resets the connection.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn enable_ssl(&mut self) -> Result<(), IdeviceError> {
+        let result = unsafe { unsafe_bindings::idevice_connection_enable_ssl(self.pointer) }.into();
+
+        if result != IdeviceError::Success {
+            return Err(result);
         }
-        .into();
+
+        Ok(())
+    }
+
+    /// Shuts down the SSL session on the connection, sending the device the SSL close notification.
+    /// Like `enable_ssl`, this must line up with the point the device expects it, such as after `StopSession`.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn disable_ssl(&mut self) -> Result<(), IdeviceError> {
+        let result =
+            unsafe { unsafe_bindings::idevice_connection_disable_ssl(self.pointer) }.into();
 
         if result != IdeviceError::Success {
             return Err(result);
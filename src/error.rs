@@ -44,8 +44,12 @@ pub enum LockdowndError {
     McProtected,
     McChallengeRequired,
     UnknownError,
+    /// The device answered a `request` with an error, see `LockdowndClient::device_error`
+    DeviceError,
     // Internal errors
     MissingObjectDepenency,
+    /// The operation needs a trusted session the client doesn't have, see `LockdowndClient::start_session`
+    SessionRequired,
 }
 
 impl std::error::Error for LockdowndError {}
@@ -146,6 +150,8 @@ impl std::fmt::Display for LockdowndError {
             LockdowndError::McChallengeRequired => "McChallengeRequired",
             LockdowndError::UnknownError => "UnknownError",
             LockdowndError::MissingObjectDepenency => "MissingObjectDepenency",
+            LockdowndError::SessionRequired => "SessionRequired",
+            LockdowndError::DeviceError => "DeviceError",
         })
     }
 }
@@ -166,6 +172,12 @@ pub enum IdeviceError {
     ConnRefused,
     SslError,
     Timeout,
+    /// usbmuxd couldn't be reached
+    MuxerUnavailable,
+    /// The user tapped "Don't Trust" on the pairing dialog
+    PairingDenied,
+    /// The user didn't accept the pairing dialog in time
+    PairingTimeout,
 }
 
 impl std::error::Error for IdeviceError {}
@@ -196,6 +208,13 @@ impl std::fmt::Display for IdeviceError {
             IdeviceError::ConnRefused => "ConnRefused",
             IdeviceError::SslError => "SslError",
             IdeviceError::Timeout => "Timeout",
+            IdeviceError::MuxerUnavailable => {
+                "MuxerUnavailable: usbmuxd isn't running or its socket can't be accessed"
+            }
+            IdeviceError::PairingDenied => "PairingDenied: the user didn't trust this computer",
+            IdeviceError::PairingTimeout => {
+                "PairingTimeout: the user didn't answer the trust dialog in time"
+            }
         })
     }
 }
@@ -206,6 +225,20 @@ impl From<IdeviceError> for String {
     }
 }
 
+impl From<LockdowndError> for IdeviceError {
+    fn from(value: LockdowndError) -> IdeviceError {
+        match value {
+            LockdowndError::Success => IdeviceError::Success,
+            LockdowndError::InvalidArg => IdeviceError::InvalidArg,
+            LockdowndError::MuxError => IdeviceError::NoDevice,
+            LockdowndError::SslError => IdeviceError::SslError,
+            LockdowndError::ReceiveTimeout => IdeviceError::Timeout,
+            LockdowndError::UserDeniedPairing => IdeviceError::PairingDenied,
+            _ => IdeviceError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UserPrefError {
     Success,
@@ -685,6 +718,20 @@ impl From<WebInspectorError> for String {
     }
 }
 
+impl From<LockdowndError> for WebInspectorError {
+    fn from(value: LockdowndError) -> WebInspectorError {
+        match value {
+            LockdowndError::Success => WebInspectorError::Success,
+            LockdowndError::InvalidArg => WebInspectorError::InvalidArg,
+            LockdowndError::PlistError => WebInspectorError::PlistError,
+            LockdowndError::MuxError => WebInspectorError::MuxError,
+            LockdowndError::SslError => WebInspectorError::SslError,
+            LockdowndError::ReceiveTimeout => WebInspectorError::ReceiveTimeout,
+            _ => WebInspectorError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SyslogRelayError {
     Success,
@@ -732,6 +779,19 @@ impl From<SyslogRelayError> for String {
     }
 }
 
+impl From<LockdowndError> for SyslogRelayError {
+    fn from(value: LockdowndError) -> SyslogRelayError {
+        match value {
+            LockdowndError::Success => SyslogRelayError::Success,
+            LockdowndError::InvalidArg => SyslogRelayError::InvalidArg,
+            LockdowndError::MuxError => SyslogRelayError::MuxError,
+            LockdowndError::SslError => SyslogRelayError::SslError,
+            LockdowndError::ReceiveTimeout => SyslogRelayError::Timeout,
+            _ => SyslogRelayError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScreenshotrError {
     Success,
@@ -742,6 +802,13 @@ pub enum ScreenshotrError {
     ReceiveTimeout,
     BadVersion,
     UnknownError,
+
+    // Internal errors
+    IoError,
+    /// The screenshot had to be converted to another format, which needs the `image` feature
+    ConversionUnavailable,
+    /// The screenshot couldn't be decoded for conversion
+    ImageError,
 }
 
 impl std::error::Error for ScreenshotrError {}
@@ -772,6 +839,9 @@ impl std::fmt::Display for ScreenshotrError {
             ScreenshotrError::ReceiveTimeout => "ReceiveTimeout",
             ScreenshotrError::BadVersion => "BadVersion",
             ScreenshotrError::UnknownError => "UnknownError",
+            ScreenshotrError::IoError => "IoError",
+            ScreenshotrError::ConversionUnavailable => "ConversionUnavailable",
+            ScreenshotrError::ImageError => "ImageError",
         })
     }
 }
@@ -782,6 +852,20 @@ impl From<ScreenshotrError> for String {
     }
 }
 
+impl From<LockdowndError> for ScreenshotrError {
+    fn from(value: LockdowndError) -> ScreenshotrError {
+        match value {
+            LockdowndError::Success => ScreenshotrError::Success,
+            LockdowndError::InvalidArg => ScreenshotrError::InvalidArg,
+            LockdowndError::PlistError => ScreenshotrError::PlistError,
+            LockdowndError::MuxError => ScreenshotrError::MuxError,
+            LockdowndError::SslError => ScreenshotrError::SslError,
+            LockdowndError::ReceiveTimeout => ScreenshotrError::ReceiveTimeout,
+            _ => ScreenshotrError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SbservicesError {
     Success,
@@ -789,6 +873,8 @@ pub enum SbservicesError {
     PlistError,
     ConnFailed,
     UnknownError,
+    // Internal errors
+    Timeout,
 }
 
 impl std::error::Error for SbservicesError {}
@@ -813,6 +899,7 @@ impl std::fmt::Display for SbservicesError {
             SbservicesError::PlistError => "PlistError",
             SbservicesError::ConnFailed => "ConnFailed",
             SbservicesError::UnknownError => "UnknownError",
+            SbservicesError::Timeout => "Timeout",
         })
     }
 }
@@ -823,6 +910,18 @@ impl From<SbservicesError> for String {
     }
 }
 
+impl From<LockdowndError> for SbservicesError {
+    fn from(value: LockdowndError) -> SbservicesError {
+        match value {
+            LockdowndError::Success => SbservicesError::Success,
+            LockdowndError::InvalidArg => SbservicesError::InvalidArg,
+            LockdowndError::PlistError => SbservicesError::PlistError,
+            LockdowndError::MuxError => SbservicesError::ConnFailed,
+            _ => SbservicesError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReverseProxyError {
     Success,
@@ -973,6 +1072,20 @@ impl From<PreboardError> for String {
     }
 }
 
+impl From<LockdowndError> for PreboardError {
+    fn from(value: LockdowndError) -> PreboardError {
+        match value {
+            LockdowndError::Success => PreboardError::Success,
+            LockdowndError::InvalidArg => PreboardError::InvalidArg,
+            LockdowndError::PlistError => PreboardError::PlistError,
+            LockdowndError::MuxError => PreboardError::MuxError,
+            LockdowndError::SslError => PreboardError::SslError,
+            LockdowndError::ReceiveTimeout => PreboardError::Timeout,
+            _ => PreboardError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NpError {
     Success,
@@ -980,6 +1093,8 @@ pub enum NpError {
     PlistError,
     ConnFailed,
     UnknownError,
+    // Internal errors
+    Timeout,
 }
 
 impl std::error::Error for NpError {}
@@ -1004,6 +1119,7 @@ impl std::fmt::Display for NpError {
             NpError::PlistError => "PlistError",
             NpError::ConnFailed => "ConnFailed",
             NpError::UnknownError => "UnknownError",
+            NpError::Timeout => "Timeout",
         })
     }
 }
@@ -1014,6 +1130,18 @@ impl From<NpError> for String {
     }
 }
 
+impl From<LockdowndError> for NpError {
+    fn from(value: LockdowndError) -> NpError {
+        match value {
+            LockdowndError::Success => NpError::Success,
+            LockdowndError::InvalidArg => NpError::InvalidArg,
+            LockdowndError::PlistError => NpError::PlistError,
+            LockdowndError::MuxError => NpError::ConnFailed,
+            _ => NpError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MobileSyncError {
     Success,
@@ -1028,6 +1156,12 @@ pub enum MobileSyncError {
     WrongDirection,
     NotReady,
     UnknownError,
+
+    // Internal errors
+    /// Reading or writing saved anchors on the host failed
+    IoError,
+    /// The device cancelled the session, see `MobileSyncClient::cancel_reason`
+    DeviceCancelled,
 }
 
 impl std::error::Error for MobileSyncError {}
@@ -1066,6 +1200,8 @@ impl std::fmt::Display for MobileSyncError {
             MobileSyncError::WrongDirection => "WrongDirection",
             MobileSyncError::NotReady => "NotReady",
             MobileSyncError::UnknownError => "UnknownError",
+            MobileSyncError::IoError => "IoError",
+            MobileSyncError::DeviceCancelled => "DeviceCancelled",
         })
     }
 }
@@ -1076,6 +1212,20 @@ impl From<MobileSyncError> for String {
     }
 }
 
+impl From<LockdowndError> for MobileSyncError {
+    fn from(value: LockdowndError) -> MobileSyncError {
+        match value {
+            LockdowndError::Success => MobileSyncError::Success,
+            LockdowndError::InvalidArg => MobileSyncError::InvalidArg,
+            LockdowndError::PlistError => MobileSyncError::PlistError,
+            LockdowndError::MuxError => MobileSyncError::MuxError,
+            LockdowndError::SslError => MobileSyncError::SslError,
+            LockdowndError::ReceiveTimeout => MobileSyncError::ReceiveTimeout,
+            _ => MobileSyncError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MobileBackup2Error {
     Success,
@@ -1087,7 +1237,13 @@ pub enum MobileBackup2Error {
     BadVersion,
     ReplyNotOk,
     NoCommonVersion,
+    DeviceLocked,
     UnknownError,
+
+    // Internal errors
+    IoError,
+    BackupFailed,
+    Cancelled,
 }
 
 impl std::error::Error for MobileBackup2Error {}
@@ -1121,11 +1277,32 @@ impl std::fmt::Display for MobileBackup2Error {
             MobileBackup2Error::BadVersion => "BadVersion",
             MobileBackup2Error::ReplyNotOk => "ReplyNotOk",
             MobileBackup2Error::NoCommonVersion => "NoCommonVersion",
+            MobileBackup2Error::DeviceLocked => "DeviceLocked",
             MobileBackup2Error::UnknownError => "UnknownError",
+            MobileBackup2Error::IoError => "IoError",
+            MobileBackup2Error::BackupFailed => "BackupFailed",
+            MobileBackup2Error::Cancelled => "Cancelled",
         })
     }
 }
 
+impl From<LockdowndError> for MobileBackup2Error {
+    fn from(value: LockdowndError) -> MobileBackup2Error {
+        match value {
+            LockdowndError::Success => MobileBackup2Error::Success,
+            LockdowndError::InvalidArg => MobileBackup2Error::InvalidArg,
+            LockdowndError::PlistError => MobileBackup2Error::PlistError,
+            LockdowndError::MuxError => MobileBackup2Error::MuxError,
+            LockdowndError::SslError => MobileBackup2Error::SslError,
+            LockdowndError::ReceiveTimeout => MobileBackup2Error::ReceiveTimeout,
+            LockdowndError::PasswordProtected | LockdowndError::EscrowLocked => {
+                MobileBackup2Error::DeviceLocked
+            }
+            _ => MobileBackup2Error::UnknownError,
+        }
+    }
+}
+
 impl From<MobileBackup2Error> for String {
     fn from(value: MobileBackup2Error) -> String {
         value.to_string()
@@ -1333,6 +1510,18 @@ impl From<MisagentError> for String {
     }
 }
 
+impl From<LockdowndError> for MisagentError {
+    fn from(value: LockdowndError) -> MisagentError {
+        match value {
+            LockdowndError::Success => MisagentError::Success,
+            LockdowndError::InvalidArg => MisagentError::InvalidArg,
+            LockdowndError::PlistError => MisagentError::PlistError,
+            LockdowndError::MuxError => MisagentError::ConnFailed,
+            _ => MisagentError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HouseArrestError {
     Success,
@@ -1377,6 +1566,18 @@ impl From<HouseArrestError> for String {
     }
 }
 
+impl From<LockdowndError> for HouseArrestError {
+    fn from(value: LockdowndError) -> HouseArrestError {
+        match value {
+            LockdowndError::Success => HouseArrestError::Success,
+            LockdowndError::InvalidArg => HouseArrestError::InvalidArg,
+            LockdowndError::PlistError => HouseArrestError::PlistError,
+            LockdowndError::MuxError => HouseArrestError::ConnFailed,
+            _ => HouseArrestError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HeartbeatError {
     Success,
@@ -1477,6 +1678,18 @@ impl From<FileRelayError> for String {
     }
 }
 
+impl From<LockdowndError> for FileRelayError {
+    fn from(value: LockdowndError) -> FileRelayError {
+        match value {
+            LockdowndError::Success => FileRelayError::Success,
+            LockdowndError::InvalidArg => FileRelayError::InvalidArg,
+            LockdowndError::PlistError => FileRelayError::PlistError,
+            LockdowndError::MuxError => FileRelayError::MuxError,
+            _ => FileRelayError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DiagnosticsRelayError {
     Success,
@@ -1485,6 +1698,11 @@ pub enum DiagnosticsRelayError {
     MuxError,
     UnknownRequest,
     UnknownError,
+
+    // Internal errors
+    /// The device answered a sleep, restart or shutdown request with a failure, such as when it isn't permitted
+    Refused,
+    Timeout,
 }
 
 impl std::error::Error for DiagnosticsRelayError {}
@@ -1511,6 +1729,8 @@ impl std::fmt::Display for DiagnosticsRelayError {
             DiagnosticsRelayError::MuxError => "MuxError",
             DiagnosticsRelayError::UnknownRequest => "UnknownRequest",
             DiagnosticsRelayError::UnknownError => "UnknownError",
+            DiagnosticsRelayError::Refused => "Refused",
+            DiagnosticsRelayError::Timeout => "Timeout",
         })
     }
 }
@@ -1521,6 +1741,18 @@ impl From<DiagnosticsRelayError> for String {
     }
 }
 
+impl From<LockdowndError> for DiagnosticsRelayError {
+    fn from(value: LockdowndError) -> DiagnosticsRelayError {
+        match value {
+            LockdowndError::Success => DiagnosticsRelayError::Success,
+            LockdowndError::InvalidArg => DiagnosticsRelayError::InvalidArg,
+            LockdowndError::PlistError => DiagnosticsRelayError::PlistError,
+            LockdowndError::MuxError => DiagnosticsRelayError::MuxError,
+            _ => DiagnosticsRelayError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompanionProxyError {
     Success,
@@ -1583,6 +1815,20 @@ impl From<CompanionProxyError> for String {
     }
 }
 
+impl From<LockdowndError> for CompanionProxyError {
+    fn from(value: LockdowndError) -> CompanionProxyError {
+        match value {
+            LockdowndError::Success => CompanionProxyError::Success,
+            LockdowndError::InvalidArg => CompanionProxyError::InvalidArg,
+            LockdowndError::PlistError => CompanionProxyError::PlistError,
+            LockdowndError::MuxError => CompanionProxyError::MuxError,
+            LockdowndError::SslError => CompanionProxyError::SslError,
+            LockdowndError::ReceiveTimeout => CompanionProxyError::Timeout,
+            _ => CompanionProxyError::UnknownError,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AfcError {
     Success,
@@ -1695,3 +1941,44 @@ impl From<AfcError> for String {
         value.to_string()
     }
 }
+
+impl From<AfcError> for std::io::Error {
+    fn from(value: AfcError) -> std::io::Error {
+        let kind = match value {
+            AfcError::ObjectNotFound => std::io::ErrorKind::NotFound,
+            AfcError::ObjectExists => std::io::ErrorKind::AlreadyExists,
+            AfcError::PermDenied => std::io::ErrorKind::PermissionDenied,
+            AfcError::InvalidArg => std::io::ErrorKind::InvalidInput,
+            AfcError::OpTimeout => std::io::ErrorKind::TimedOut,
+            AfcError::OpWouldBlock => std::io::ErrorKind::WouldBlock,
+            AfcError::EndOfData | AfcError::NotEnoughData => std::io::ErrorKind::UnexpectedEof,
+            // Not `Interrupted`, which `read_exact` and `write_all` retry forever.
+            // The device aborted the operation, so retrying it won't help
+            _ => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, value)
+    }
+}
+
+/// Why an `AsyncClient` didn't finish connecting or running a call
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncClientError<E = std::convert::Infallible> {
+    /// The service couldn't be connected to
+    Connect(E),
+    /// The client's thread stopped before finishing, because a call on it panicked
+    ThreadStopped,
+}
+
+#[cfg(feature = "async")]
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for AsyncClientError<E> {}
+
+#[cfg(feature = "async")]
+impl<E: std::fmt::Display> std::fmt::Display for AsyncClientError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsyncClientError::Connect(e) => write!(f, "Connect({})", e),
+            AsyncClientError::ThreadStopped => f.write_str("ThreadStopped"),
+        }
+    }
+}
@@ -3,22 +3,35 @@
 use crate::bindings::idevice_info_t;
 use crate::callback::IDeviceEventCallback;
 use crate::error::{
-    self, AfcError, DebugServerError, HeartbeatError, IdeviceError, InstProxyError, LockdowndError,
-    MisagentError, MobileImageMounterError, ScreenshotrError,
+    self, AfcError, DebugServerError, DiagnosticsRelayError, HeartbeatError, IdeviceError,
+    InstProxyError, LockdowndError, MisagentError, MobileImageMounterError, NpError,
+    ScreenshotrError,
 };
+use crate::service::{ClientHandle, Service};
 use crate::services::afc::AfcClient;
+use crate::services::diagnostics_relay::{ChargingState, DiagnosticsRelay};
 use crate::services::heartbeat::HeartbeatClient;
-use crate::services::lockdownd::LockdowndClient;
+use crate::services::instproxy::BrowseOption;
+use crate::services::lockdownd::{DeviceInfo, LockdowndClient};
 use crate::services::misagent::MisagentClient;
 use crate::services::mobile_image_mounter::MobileImageMounter;
+use crate::services::notification_proxy::NotificationProxyClient;
 use crate::{bindings as unsafe_bindings, callback};
 use core::fmt;
 use log::{info, trace, warn};
-use std::ffi::CStr;
+use plist_plus::Plist;
+use std::ffi::{CStr, CString};
+use std::future::Future;
 use std::net::IpAddr;
 use std::os::raw::c_char;
 use std::os::raw::c_void;
-use std::{fmt::Debug, fmt::Formatter, ptr::null_mut};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+use std::{cmp::Ordering as CmpOrdering, fmt::Debug, fmt::Formatter, ptr::null_mut};
 
 /// Get a list of UDIDs
 /// # Arguments
@@ -57,7 +70,8 @@ pub fn get_udid_list() -> Result<Vec<String>, IdeviceError> {
 }
 
 /// Gets all devices detected by usbmuxd
-/// An abstraction that fetches the device list and connects to it
+/// An abstraction that fetches the device list and connects to it.
+/// Devices that fail to open are skipped, use `Device::list_devices_lossy` to see why.
 /// # Arguments
 /// *none*
 /// # Returns
@@ -65,56 +79,18 @@ pub fn get_udid_list() -> Result<Vec<String>, IdeviceError> {
 ///
 /// ***Verified:*** False
 pub fn get_devices() -> Result<Vec<Device>, IdeviceError> {
-    let mut device_list: *mut idevice_info_t = null_mut();
-    let mut device_count: i32 = 0;
-    info!("Getting device list from the muxer");
-    let result: error::IdeviceError = unsafe {
-        unsafe_bindings::idevice_get_device_list_extended(&mut device_list, &mut device_count)
-    }
-    .into();
-
-    if result != error::IdeviceError::Success {
-        return Err(result);
-    }
-
-    info!("Determining devices from slice");
-    // Create slice of mutable references to idevice_info_t from device_list and device_count
-    let device_list_slice =
-        unsafe { std::slice::from_raw_parts_mut(device_list, device_count as usize) };
-
-    let mut to_return = Vec::with_capacity(device_list_slice.len());
-    for i in device_list_slice.iter_mut() {
-        let network = unsafe { (*(*i)).conn_type != 1 };
-
-        let mut device_info: unsafe_bindings::idevice_t = unsafe { std::mem::zeroed() };
-        let device_info_ptr: *mut unsafe_bindings::idevice_t = &mut device_info;
-        let result = unsafe {
-            unsafe_bindings::idevice_new_with_options(
-                device_info_ptr,
-                (*(*i)).udid,
-                if network {
-                    unsafe_bindings::idevice_options_IDEVICE_LOOKUP_NETWORK
-                } else {
-                    unsafe_bindings::idevice_options_IDEVICE_LOOKUP_USBMUX
-                },
-            )
-        };
-        if result != 0 {
-            trace!("Failed to create device struct");
-            continue;
-        }
-        let to_push = device_info.into();
-        to_return.push(to_push);
-    }
-
-    // Drop the memory that the C library allocated
-    info!("Freeing device list");
-    let device_list_ptr = device_list as *mut *mut std::os::raw::c_char;
-    unsafe {
-        unsafe_bindings::idevice_device_list_free(device_list_ptr);
-    }
+    let devices = Device::list_devices_lossy()?;
     info!("Returning device structs");
-    Ok(to_return)
+    Ok(devices
+        .into_iter()
+        .filter_map(|(udid, device)| match device {
+            Ok(device) => Some(device),
+            Err(e) => {
+                trace!("Failed to create device struct for {}: {:?}", udid, e);
+                None
+            }
+        })
+        .collect())
 }
 
 /// Fetches a list of devices, but returns one with the given udid
@@ -172,26 +148,94 @@ pub fn set_debug(debug: bool) {
     unsafe { unsafe_bindings::idevice_set_debug_level(debug) }
 }
 
-pub fn event_subscribe(cb: IDeviceEventCallback) -> Result<(), IdeviceError> {
-    let callback_box = Box::new(cb);
-    let callback_ptr = Box::into_raw(callback_box) as *mut c_void;
+/// Counts up with every subscription, so the guards can tell which one libimobiledevice is calling.
+/// It's locked while subscribing or unsubscribing, so those never race each other
+static EVENT_GENERATION: Mutex<u64> = Mutex::new(0);
 
-    let result = unsafe {
-        unsafe_bindings::idevice_event_subscribe(
-            Some(callback::idevice_event_callback),
-            callback_ptr,
-        )
-    }
-    .into();
+/// Subscribes to device events from usbmuxd
+/// The callback is owned by the returned guard, which unsubscribes before freeing it.
+/// libimobiledevice only keeps one global subscription, so subscribing again replaces the last callback.
+/// Dropping a guard that was replaced this way only frees its callback, and leaves the newer subscription running.
+/// # Arguments
+/// * `cb` - The callback to run for every event
+/// # Returns
+/// A guard that keeps the subscription alive until dropped
+///
+/// ***Verified:*** False
+pub fn event_subscribe(cb: IDeviceEventCallback) -> Result<EventSubscription, IdeviceError> {
+    EventSubscription::new(cb, |callback_ptr| {
+        unsafe {
+            unsafe_bindings::idevice_event_subscribe(
+                Some(callback::idevice_event_callback),
+                callback_ptr,
+            )
+        }
+        .into()
+    })
+}
 
-    if result != IdeviceError::Success {
-        unsafe { drop(Box::from_raw(callback_ptr as *mut IDeviceEventCallback)) };
-        return Err(result);
-    }
+/// Subscribes to device events and hands them out as an iterator, or a `Stream` with the `async` feature.
+/// Like `event_subscribe`, this replaces any other subscription while the stream is alive.
+/// # Arguments
+/// * `udid` - Only return events for this device
+/// * `connection` - Only return events for devices connected this way
+/// # Returns
+/// The events, in the order they happened. Dropping the stream unsubscribes
+///
+/// ***Verified:*** False
+pub fn event_stream(
+    udid: Option<String>,
+    connection: Option<ConnectionType>,
+) -> Result<EventStream, IdeviceError> {
+    let (sender, receiver) = mpsc::channel();
+    let waker: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+    let callback_waker = waker.clone();
+    let callback = IDeviceEventCallback::new(
+        Box::new(move |event: IDeviceEvent, _: &dyn std::any::Any| {
+            if connection.is_some() && event.connection_type() != connection {
+                return;
+            }
+            if sender.send(event.to_owned_event()).is_err() {
+                return;
+            }
+            let waker = callback_waker
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }),
+        Box::new(()),
+        udid,
+    );
+
+    Ok(EventStream {
+        receiver,
+        waker,
+        _subscription: event_subscribe(callback)?,
+    })
+}
 
+/// Stops whichever subscription is current, see `event_subscribe`.
+/// Its guard then only frees the callback when dropped
+/// # Arguments
+/// *none*
+/// # Returns
+/// *none*
+///
+/// ***Verified:*** False
+pub fn event_unsubscribe() -> Result<(), IdeviceError> {
+    let mut generation = EVENT_GENERATION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    unsubscribe_events()?;
+    *generation += 1;
     Ok(())
 }
-pub fn event_unsubscribe() -> Result<(), IdeviceError> {
+
+/// Tears down libimobiledevice's subscription. The caller holds `EVENT_GENERATION`
+fn unsubscribe_events() -> Result<(), IdeviceError> {
     let result = unsafe { unsafe_bindings::idevice_event_unsubscribe() };
 
     if result != IdeviceError::Success as i32 {
@@ -201,10 +245,197 @@ pub fn event_unsubscribe() -> Result<(), IdeviceError> {
     Ok(())
 }
 
+/// An active device event subscription.
+/// The pointer handed to the C library points into the callback owned here,
+/// so it is only freed after the subscription has been torn down or replaced.
+pub struct EventSubscription {
+    callback: Option<Box<IDeviceEventCallback>>,
+    /// The `EVENT_GENERATION` this subscription was made at.
+    /// It's only still the subscription libimobiledevice calls while the two match
+    generation: u64,
+}
+
+impl EventSubscription {
+    /// Hands the callback to `subscribe` and takes the next generation if it succeeds
+    fn new(
+        cb: IDeviceEventCallback,
+        subscribe: impl FnOnce(*mut c_void) -> IdeviceError,
+    ) -> Result<Self, IdeviceError> {
+        let mut callback = Box::new(cb);
+        let callback_ptr = callback.as_mut() as *mut IDeviceEventCallback as *mut c_void;
+
+        let mut generation = EVENT_GENERATION
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let result = subscribe(callback_ptr);
+
+        // Subscribing only fails this way when usbmuxd can't be reached
+        if result == IdeviceError::UnknownError {
+            return Err(IdeviceError::MuxerUnavailable);
+        }
+        if result != IdeviceError::Success {
+            return Err(result);
+        }
+
+        *generation += 1;
+        Ok(EventSubscription {
+            callback: Some(callback),
+            generation: *generation,
+        })
+    }
+
+    /// Stops receiving events and frees the callback
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn unsubscribe(mut self) -> Result<(), IdeviceError> {
+        self.release(unsubscribe_events)
+    }
+
+    /// Unsubscribes with `unsubscribe` if this is still the current subscription, then frees the callback.
+    /// A subscription that was replaced is no longer reachable from C, so its callback is just freed
+    fn release(
+        &mut self,
+        unsubscribe: impl FnOnce() -> Result<(), IdeviceError>,
+    ) -> Result<(), IdeviceError> {
+        let mut generation = EVENT_GENERATION
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *generation == self.generation {
+            // Only free the callback once C can no longer reach it
+            unsubscribe()?;
+            *generation += 1;
+        }
+        self.callback.take();
+        Ok(())
+    }
+}
+
+impl Drop for EventSubscription {
+    fn drop(&mut self) {
+        if self.callback.is_none() {
+            return;
+        }
+        if let Err(e) = self.release(unsubscribe_events) {
+            // The C side may still call into the callback, so it can't be freed
+            warn!("Failed to unsubscribe from device events: {:?}", e);
+            if let Some(callback) = self.callback.take() {
+                Box::leak(callback);
+            }
+        }
+    }
+}
+
+/// Device events from `event_stream`. Dropping it unsubscribes.
+pub struct EventStream {
+    receiver: mpsc::Receiver<IDeviceEvent>,
+    waker: Arc<Mutex<Option<Waker>>>,
+    _subscription: EventSubscription,
+}
+
+impl EventStream {
+    /// Waits for the next event for at most `timeout`
+    /// # Arguments
+    /// * `timeout` - How long to wait
+    /// # Returns
+    /// The event, or `None` if none arrived in time
+    ///
+    /// ***Verified:*** False
+    pub fn next_timeout(&self, timeout: Duration) -> Option<IDeviceEvent> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = IDeviceEvent;
+
+    /// Blocks until the next event. The subscription lives as long as the stream, so this never returns `None`
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for EventStream {
+    type Item = IDeviceEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Ok(event) = self.receiver.try_recv() {
+            return Poll::Ready(Some(event));
+        }
+        *self
+            .waker
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(cx.waker().clone());
+        // An event may have arrived before the waker was stored
+        match self.receiver.try_recv() {
+            Ok(event) => Poll::Ready(Some(event)),
+            Err(mpsc::TryRecvError::Empty) => Poll::Pending,
+            Err(mpsc::TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+}
+
+/// A future that resolves with the next device event, returned by `Device::next_event`.
+/// Dropping it unsubscribes.
+pub struct NextEvent {
+    state: Arc<Mutex<NextEventState>>,
+    subscription: Option<EventSubscription>,
+}
+
+struct NextEventState {
+    event: Option<IDeviceEvent>,
+    waker: Option<Waker>,
+}
+
+impl Future for NextEvent {
+    type Output = IDeviceEvent;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match state.event.take() {
+            Some(event) => {
+                drop(state);
+                // Only one event is wanted, so stop listening right away
+                this.subscription.take();
+                Poll::Ready(event)
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The lockdownd domain and key springboard reports its lock state under, used by `Device::is_locked`
+const LOCK_STATE_DOMAIN: &str = "com.apple.springboard";
+const LOCK_STATE_KEY: &str = "isLocked";
+/// Posted by springboard whenever the device is locked or unlocked
+const LOCK_STATE_NOTIFICATION: &str = "com.apple.springboard.lockstate";
+
+/// How long a cached lockdownd connection is reused by the convenience getters
+const LOCKDOWND_CACHE_TTL: Duration = Duration::from_secs(5);
+
 // Structs
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+/// A handle to a connected device.
+///
+/// Every client made from the device, such as a `LockdowndClient` or an `AfcClient`, borrows it.
+/// Each client owns its own connection and closes it when dropped, and the borrow checker won't
+/// let the device be dropped or `disconnect`ed while any of them are still alive.
 pub struct Device {
     pub(crate) pointer: unsafe_bindings::idevice_t,
+    /// A lockdownd connection shared by back-to-back convenience getters, and when it was made.
+    /// It's kept as a bare handle, since a client would borrow this device. The connection still
+    /// refers to the device, so `Drop` clears it before the device is freed.
+    lockdownd_cache: Mutex<Option<(ClientHandle<unsafe_bindings::lockdownd_client_t>, Instant)>>,
 }
 
 unsafe impl Send for Device {}
@@ -427,6 +658,466 @@ impl Device {
         data.to_vec()
     }
 
+    /// Opens a device over the preferred transport, falling back to the other one if it isn't available there.
+    /// A device that's reachable over both USB and the network is otherwise opened over either,
+    /// and the network is much slower. USB is strongly recommended for bulk transfers like backups.
+    /// # Arguments
+    /// * `udid` - The UDID of the device to open
+    /// * `prefer` - The transport to try first
+    /// # Returns
+    /// The device and the transport it was opened over
+    ///
+    /// ***Verified:*** False
+    pub fn new_prefer(
+        udid: impl Into<String>,
+        prefer: ConnectionType,
+    ) -> Result<(Device, ConnectionType), IdeviceError> {
+        let udid_c_string = CString::new(udid.into()).unwrap();
+        let fallback = match prefer {
+            ConnectionType::Usb => ConnectionType::Network,
+            ConnectionType::Network => ConnectionType::Usb,
+        };
+
+        let mut last_error = IdeviceError::NoDevice;
+        for connection_type in [prefer, fallback] {
+            let mut device: unsafe_bindings::idevice_t = null_mut();
+            let result = unsafe {
+                unsafe_bindings::idevice_new_with_options(
+                    &mut device,
+                    udid_c_string.as_ptr(),
+                    match connection_type {
+                        ConnectionType::Usb => {
+                            unsafe_bindings::idevice_options_IDEVICE_LOOKUP_USBMUX
+                        }
+                        ConnectionType::Network => {
+                            unsafe_bindings::idevice_options_IDEVICE_LOOKUP_NETWORK
+                        }
+                    },
+                )
+            }
+            .into();
+
+            if result == IdeviceError::Success {
+                return Ok((device.into(), connection_type));
+            }
+            trace!("Device not found over {:?}: {}", connection_type, result);
+            last_error = result;
+        }
+        Err(last_error)
+    }
+
+    /// Attempts to open every device detected by usbmuxd, reporting each one separately
+    /// A device that fails to open doesn't stop the others from being returned.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The UDID of every detected device, paired with the device or the error opening it.
+    /// Only fails if the device list can't be fetched from the muxer,
+    /// which is `MuxerUnavailable` if usbmuxd isn't running.
+    ///
+    /// ***Verified:*** False
+    pub fn list_devices_lossy() -> Result<Vec<(String, Result<Device, IdeviceError>)>, IdeviceError>
+    {
+        let mut device_list: *mut idevice_info_t = null_mut();
+        let mut device_count: i32 = 0;
+        info!("Getting device list from the muxer");
+        let result: error::IdeviceError = unsafe {
+            unsafe_bindings::idevice_get_device_list_extended(&mut device_list, &mut device_count)
+        }
+        .into();
+
+        // libimobiledevice only fails to list when it can't talk to usbmuxd,
+        // an empty list is returned when nothing is plugged in
+        if result == error::IdeviceError::NoDevice {
+            warn!("Unable to get the device list from usbmuxd");
+            return Err(IdeviceError::MuxerUnavailable);
+        }
+        if result != error::IdeviceError::Success {
+            return Err(result);
+        }
+
+        info!("Determining devices from slice");
+        // Create slice of mutable references to idevice_info_t from device_list and device_count
+        let device_list_slice =
+            unsafe { std::slice::from_raw_parts_mut(device_list, device_count as usize) };
+
+        let mut to_return = Vec::with_capacity(device_list_slice.len());
+        for i in device_list_slice.iter_mut() {
+            let udid = unsafe { CStr::from_ptr((*(*i)).udid) }
+                .to_string_lossy()
+                .into_owned();
+            let network = unsafe { (*(*i)).conn_type != 1 };
+
+            let mut device_info: unsafe_bindings::idevice_t = unsafe { std::mem::zeroed() };
+            let result: IdeviceError = unsafe {
+                unsafe_bindings::idevice_new_with_options(
+                    &mut device_info,
+                    (*(*i)).udid,
+                    if network {
+                        unsafe_bindings::idevice_options_IDEVICE_LOOKUP_NETWORK
+                    } else {
+                        unsafe_bindings::idevice_options_IDEVICE_LOOKUP_USBMUX
+                    },
+                )
+            }
+            .into();
+            if result != IdeviceError::Success {
+                to_return.push((udid, Err(result)));
+                continue;
+            }
+            to_return.push((udid, Ok(device_info.into())));
+        }
+
+        // Drop the memory that the C library allocated
+        info!("Freeing device list");
+        let device_list_ptr = device_list as *mut *mut std::os::raw::c_char;
+        unsafe {
+            unsafe_bindings::idevice_device_list_free(device_list_ptr);
+        }
+        Ok(to_return)
+    }
+
+    /// Waits for a device to be connected
+    /// Returns right away if the device is already connected.
+    /// # Arguments
+    /// * `udid` - The UDID of the device to wait for
+    /// * `timeout` - How long to wait, or `None` to wait forever
+    /// # Returns
+    /// The device once it is connected, or `Timeout` if it didn't show up in time
+    ///
+    /// ***Verified:*** False
+    pub fn wait_for(udid: &str, timeout: Option<Duration>) -> Result<Device, IdeviceError> {
+        let (sender, receiver) = mpsc::channel();
+        let callback = IDeviceEventCallback::new(
+            Box::new(move |event: IDeviceEvent, _: &dyn std::any::Any| {
+                if event.kind() == IDeviceEventType::Add {
+                    let _ = sender.send(());
+                }
+            }),
+            Box::new(()),
+            Some(udid.to_string()),
+        );
+        // Subscribe before checking so a device added in between isn't missed
+        let subscription = event_subscribe(callback)?;
+
+        match get_device(udid) {
+            Ok(device) => return Ok(device),
+            Err(IdeviceError::NoDevice) => {}
+            Err(e) => return Err(e),
+        }
+
+        info!("Waiting for device {}", udid);
+        let added = match timeout {
+            Some(timeout) => receiver.recv_timeout(timeout).is_ok(),
+            None => receiver.recv().is_ok(),
+        };
+        drop(subscription);
+
+        if !added {
+            return Err(IdeviceError::Timeout);
+        }
+        get_device(udid)
+    }
+
+    /// Waits for the next device event of any device.
+    /// The subscription is made straight away so no event is missed before the future is polled,
+    /// and torn down once the event arrives or the future is dropped.
+    /// Like `event_subscribe`, this replaces any other subscription while it's active.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A future resolving to the event
+    ///
+    /// ***Verified:*** False
+    pub fn next_event() -> Result<NextEvent, IdeviceError> {
+        let state = Arc::new(Mutex::new(NextEventState {
+            event: None,
+            waker: None,
+        }));
+        let callback_state = state.clone();
+        let callback = IDeviceEventCallback::new(
+            Box::new(move |event: IDeviceEvent, _: &dyn std::any::Any| {
+                let mut state = callback_state
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                if state.event.is_none() {
+                    state.event = Some(event.to_owned_event());
+                    if let Some(waker) = state.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }),
+            Box::new(()),
+            None,
+        );
+
+        Ok(NextEvent {
+            state,
+            subscription: Some(event_subscribe(callback)?),
+        })
+    }
+
+    /// Subscribes to events for this device only
+    /// The libimobiledevice version this crate binds against has no per-device subscription,
+    /// so this falls back to the global `event_subscribe` with the callback filtered to this UDID.
+    /// Any UDID filter already set on the callback is replaced.
+    /// # Arguments
+    /// * `callback` - The callback to run for every event of this device
+    /// # Returns
+    /// A guard that keeps the subscription alive until dropped
+    ///
+    /// ***Verified:*** False
+    pub fn subscribe_this_device(
+        &self,
+        mut callback: IDeviceEventCallback,
+    ) -> Result<EventSubscription, IdeviceError> {
+        callback._udid_filter = Some(self.get_udid());
+        event_subscribe(callback)
+    }
+
+    /// Reads the common identifying values of the device, see `LockdowndClient::device_info`
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The device's info
+    ///
+    /// ***Verified:*** False
+    pub fn info(&self) -> Result<DeviceInfo, LockdowndError> {
+        self.with_cached_lockdownd(|lockdownd| lockdownd.device_info())
+    }
+
+    /// Reads the hardware identifiers of the device in a single lockdownd session
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The product type, hardware model and chip ID of the device
+    ///
+    /// ***Verified:*** False
+    pub fn hardware_info(&self) -> Result<DeviceHardware, LockdowndError> {
+        let values = self.with_cached_lockdownd(|lockdownd| lockdownd.get_value("", ""))?;
+
+        let get_string = |key: &str| -> Result<String, LockdowndError> {
+            values
+                .dict_get_item(key)
+                .map_err(|_| LockdowndError::MissingValue)?
+                .get_string_val()
+                .map_err(|_| LockdowndError::InvalidResponse)
+        };
+
+        Ok(DeviceHardware {
+            product_type: get_string("ProductType")?,
+            hardware_model: get_string("HardwareModel")?,
+            chip_id: values
+                .dict_get_item("UniqueChipID")
+                .map_err(|_| LockdowndError::MissingValue)?
+                .get_uint_val()
+                .map_err(|_| LockdowndError::InvalidResponse)?,
+        })
+    }
+
+    /// Gets the product type of the device, such as `iPhone14,2`
+    /// Use `hardware_info` when more than one hardware value is needed
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The product type of the device
+    ///
+    /// ***Verified:*** False
+    pub fn product_type(&self) -> Result<String, LockdowndError> {
+        self.with_cached_lockdownd(|lockdownd| lockdownd.get_value("ProductType", ""))?
+            .get_string_val()
+            .map_err(|_| LockdowndError::InvalidResponse)
+    }
+
+    /// Gets the hardware model of the device, such as `D63AP`
+    /// Use `hardware_info` when more than one hardware value is needed
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The hardware model of the device
+    ///
+    /// ***Verified:*** False
+    pub fn hardware_model(&self) -> Result<String, LockdowndError> {
+        self.with_cached_lockdownd(|lockdownd| lockdownd.get_value("HardwareModel", ""))?
+            .get_string_val()
+            .map_err(|_| LockdowndError::InvalidResponse)
+    }
+
+    /// Gets the unique chip ID (ECID) of the device
+    /// Use `hardware_info` when more than one hardware value is needed
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The chip ID of the device
+    ///
+    /// ***Verified:*** False
+    pub fn chip_id(&self) -> Result<u64, LockdowndError> {
+        self.with_cached_lockdownd(|lockdownd| lockdownd.get_value("UniqueChipID", ""))?
+            .get_uint_val()
+            .map_err(|_| LockdowndError::InvalidResponse)
+    }
+
+    /// Gets the iOS version of the device, for comparing against the version a feature needs
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The version, parsed from lockdownd's `ProductVersion`
+    ///
+    /// ***Verified:*** False
+    pub fn ios_version(&self) -> Result<IosVersion, LockdowndError> {
+        let version = self
+            .with_cached_lockdownd(|lockdownd| lockdownd.get_value("ProductVersion", ""))?
+            .get_string_val()
+            .map_err(|_| LockdowndError::InvalidResponse)?;
+        version.parse().map_err(|_| {
+            warn!("Unable to parse the iOS version {}", version);
+            LockdowndError::InvalidResponse
+        })
+    }
+
+    /// Gathers a summary of the device for support tools, from lockdownd, diagnostics, AFC and instproxy.
+    /// Only lockdownd has to be reachable. Values from the other services are left as `None` when
+    /// their query fails, and the reason is added to the report's `errors`.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The report, or the error if lockdownd couldn't be queried
+    ///
+    /// ***Verified:*** False
+    pub fn info_report(&self) -> Result<DeviceReport, LockdowndError> {
+        let values = self.with_cached_lockdownd(|lockdownd| lockdownd.get_value("", ""))?;
+        let get_string = |key: &str| {
+            values
+                .dict_get_item(key)
+                .ok()
+                .and_then(|v| v.get_string_val().ok())
+        };
+
+        let mut report = DeviceReport {
+            udid: self.get_udid(),
+            name: get_string("DeviceName"),
+            product_type: get_string("ProductType"),
+            product_version: get_string("ProductVersion"),
+            build_version: get_string("BuildVersion"),
+            serial_number: get_string("SerialNumber"),
+            battery_level: None,
+            charging_state: None,
+            storage_total: None,
+            storage_free: None,
+            installed_apps: None,
+            errors: Vec::new(),
+        };
+
+        match DiagnosticsRelay::connect(self) {
+            Ok(diagnostics) => {
+                match diagnostics.charging_state() {
+                    Ok(state) => report.charging_state = Some(state),
+                    Err(e) => report.errors.push(format!("charging state: {}", e)),
+                }
+                match diagnostics.query_ioregistry_entry("", "IOPMPowerSource") {
+                    Ok(response) => {
+                        report.battery_level = response
+                            .dict_get_item("IORegistry")
+                            .ok()
+                            .and_then(|r| r.dict_get_item("CurrentCapacity").ok())
+                            .and_then(|c| c.get_uint_val().ok())
+                    }
+                    Err(e) => report.errors.push(format!("battery level: {}", e)),
+                }
+            }
+            Err(e) => report.errors.push(format!("diagnostics: {}", e)),
+        }
+
+        match AfcClient::connect(self) {
+            Ok(afc) => {
+                let get_bytes = |key: &str| -> Result<u64, String> {
+                    afc.get_device_info_key(key)
+                        .map_err(|e| e.to_string())?
+                        .parse()
+                        .map_err(|_| format!("{} isn't a number", key))
+                };
+                match get_bytes("FSTotalBytes") {
+                    Ok(total) => report.storage_total = Some(total),
+                    Err(e) => report.errors.push(format!("storage: {}", e)),
+                }
+                match get_bytes("FSFreeBytes") {
+                    Ok(free) => report.storage_free = Some(free),
+                    Err(e) => report.errors.push(format!("storage: {}", e)),
+                }
+            }
+            Err(e) => report.errors.push(format!("afc: {}", e)),
+        }
+
+        match self
+            .new_instproxy_client("rusty_libimobiledevice")
+            .and_then(|instproxy| instproxy.browse(BrowseOption::User))
+        {
+            Ok(apps) => match apps.array_get_size() {
+                Ok(count) => report.installed_apps = Some(count as u64),
+                Err(_) => report
+                    .errors
+                    .push("installed apps: bad response".to_string()),
+            },
+            Err(e) => report.errors.push(format!("installed apps: {}", e)),
+        }
+
+        if !report.errors.is_empty() {
+            info!(
+                "Device report for {} is missing values: {:?}",
+                report.udid, report.errors
+            );
+        }
+        Ok(report)
+    }
+
+    /// Restarts the device and waits for it to come back.
+    /// The device counts as back once usbmuxd lists it again, services such as lockdownd
+    /// may need a few more seconds before they answer.
+    /// # Arguments
+    /// * `timeout` - How long to wait for the device to go down and come back
+    /// # Returns
+    /// The device once it's back, `Refused` if it declined to restart, or `Timeout` if it didn't come back in time
+    ///
+    /// ***Verified:*** False
+    pub fn restart_and_wait(&self, timeout: Duration) -> Result<Device, DiagnosticsRelayError> {
+        let udid = self.get_udid();
+        let (sender, receiver) = mpsc::channel();
+        let callback = IDeviceEventCallback::new(
+            Box::new(move |event: IDeviceEvent, _: &dyn std::any::Any| {
+                let _ = sender.send(event.kind());
+            }),
+            Box::new(()),
+            Some(udid.clone()),
+        );
+        // Subscribe before restarting so the removal isn't missed
+        let subscription = event_subscribe(callback).map_err(|e| {
+            warn!("Unable to subscribe to device events: {:?}", e);
+            DiagnosticsRelayError::MuxError
+        })?;
+
+        DiagnosticsRelay::connect(self)?.restart(
+            unsafe_bindings::diagnostics_relay_action_t_DIAGNOSTICS_RELAY_ACTION_FLAG_WAIT_FOR_DISCONNECT,
+        )?;
+
+        info!("Waiting for {} to restart", udid);
+        let deadline = Instant::now() + timeout;
+        let mut removed = false;
+        loop {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(IDeviceEventType::Remove) => removed = true,
+                Ok(IDeviceEventType::Add) if removed => break,
+                Ok(_) => {}
+                Err(_) => return Err(DiagnosticsRelayError::Timeout),
+            }
+        }
+        drop(subscription);
+
+        get_device(&udid).map_err(|e| {
+            warn!("Device {} came back but can't be opened: {:?}", udid, e);
+            DiagnosticsRelayError::MuxError
+        })
+    }
+
     /// Starts the lockdown service for the device
     /// This allows things like debuggers to be attached
     /// # Arguments
@@ -442,6 +1133,179 @@ impl Device {
         LockdowndClient::new(self, label.into())
     }
 
+    /// Connects to lockdownd with the default label.
+    /// Keep the returned client around when reading several values instead of calling getters
+    /// like `product_type` one after another, which each need a connection.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A lockdownd client for the device
+    ///
+    /// ***Verified:*** False
+    pub fn lockdownd(&self) -> Result<LockdowndClient, LockdowndError> {
+        self.new_lockdownd_client("rusty_libimobiledevice")
+    }
+
+    /// Runs an operation on a lockdownd connection that is reused for a few seconds.
+    /// The connection is thrown away if the operation fails in a way that means it was lost,
+    /// such as the device being unplugged.
+    fn with_cached_lockdownd<T>(
+        &self,
+        op: impl FnOnce(&LockdowndClient) -> Result<T, LockdowndError>,
+    ) -> Result<T, LockdowndError> {
+        let mut cache = self
+            .lockdownd_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some((_, created)) = cache.as_ref() {
+            if created.elapsed() > LOCKDOWND_CACHE_TTL {
+                trace!("Cached lockdownd connection expired");
+                *cache = None;
+            }
+        }
+
+        // Taken out for the call, so if `op` panics the connection is dropped rather than left cached
+        let (client, created) = match cache.take() {
+            Some((handle, created)) => (LockdowndClient::from_handle(self, handle), created),
+            None => (self.lockdownd()?, Instant::now()),
+        };
+        let result = op(&client);
+        if let Err(
+            LockdowndError::MuxError
+            | LockdowndError::SslError
+            | LockdowndError::ReceiveTimeout
+            | LockdowndError::PlistError,
+        ) = result
+        {
+            info!("Dropping cached lockdownd connection after a connection error");
+        } else {
+            *cache = Some((client.into_handle(), created));
+        }
+        result
+    }
+
+    /// Pairs with the device, waiting for the user to unlock it and accept the trust dialog.
+    /// Pairing is retried every second until it succeeds, the user denies it, or the timeout passes.
+    /// # Arguments
+    /// * `timeout` - How long to wait for the user
+    /// * `on_waiting` - Called each time the device is still waiting on the user, to prompt them
+    /// # Returns
+    /// *none*, `PairingDenied` if the user tapped "Don't Trust", or `PairingTimeout` if the timeout passed
+    ///
+    /// ***Verified:*** False
+    pub fn pair_interactive(
+        &self,
+        timeout: Duration,
+        mut on_waiting: impl FnMut(),
+    ) -> Result<(), IdeviceError> {
+        let lockdownd = LockdowndClient::new(self, "rusty_libimobiledevice")?;
+        let start = std::time::Instant::now();
+
+        loop {
+            let attempt = lockdownd.pair(None, None);
+            match pair_outcome(attempt, start.elapsed() >= timeout) {
+                Some(result) => return result,
+                None => {
+                    on_waiting();
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    }
+
+    /// Gets whether the device is locked, from the lock state springboard reports to lockdownd.
+    /// Devices without a passcode are never locked, so the state is only read when a passcode is set.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// True if the device is locked
+    ///
+    /// ***Verified:*** False
+    pub fn is_locked(&self) -> Result<bool, LockdowndError> {
+        let lockdownd = self.lockdownd()?;
+        let protected = lockdownd
+            .get_value("PasswordProtected", "")?
+            .get_bool_val()
+            .map_err(|_| LockdowndError::InvalidResponse)?;
+        if !protected {
+            return Ok(false);
+        }
+
+        lockdownd
+            .get_value(LOCK_STATE_KEY, LOCK_STATE_DOMAIN)?
+            .get_bool_val()
+            .map_err(|_| LockdowndError::InvalidResponse)
+    }
+
+    /// Checks whether the device offers a service, which varies by iOS version and device type.
+    /// Lockdownd has no list of the services it offers, so this asks it to start the service
+    /// and frees the descriptor straight away without connecting.
+    ///
+    /// Each call costs a lockdownd connection and handshake plus a service start, and the device
+    /// keeps the service's port open until it times out waiting for a connection.
+    /// Check once and keep the answer rather than calling this before every operation.
+    /// # Arguments
+    /// * `identifier` - The identifier of the service, such as `com.apple.afc`
+    /// # Returns
+    /// True if lockdownd started the service. False if it doesn't know the service,
+    /// or if the probe failed for another reason, such as the device being locked, which is logged
+    ///
+    /// ***Verified:*** False
+    pub fn supports_service(&self, identifier: impl Into<String>) -> bool {
+        let identifier = identifier.into();
+        let mut lockdownd = match self.lockdownd() {
+            Ok(lockdownd) => lockdownd,
+            Err(e) => {
+                warn!(
+                    "Unable to connect to lockdownd to probe {}: {}",
+                    identifier, e
+                );
+                return false;
+            }
+        };
+        match lockdownd.start_service(identifier.as_str(), false) {
+            Ok(_) => true,
+            Err(LockdowndError::InvalidService) | Err(LockdowndError::MissingService) => false,
+            Err(e) => {
+                warn!("Unable to probe {}: {}", identifier, e);
+                false
+            }
+        }
+    }
+
+    /// Waits for the user to unlock the device, checking again whenever springboard reports a lock state change
+    /// # Arguments
+    /// * `timeout` - How long to wait
+    /// # Returns
+    /// *none* once the device is unlocked, or `ReceiveTimeout` if the timeout passed
+    ///
+    /// ***Verified:*** False
+    pub fn wait_for_unlock(&self, timeout: Duration) -> Result<(), LockdowndError> {
+        let deadline = Instant::now() + timeout;
+        let notification_error = |e: NpError| {
+            warn!("Unable to watch for the device being unlocked: {}", e);
+            LockdowndError::UnknownError
+        };
+
+        let proxy = NotificationProxyClient::connect(self).map_err(notification_error)?;
+        // Listen before checking so an unlock in between isn't missed
+        let listener = proxy
+            .listen(&[LOCK_STATE_NOTIFICATION])
+            .map_err(notification_error)?;
+
+        loop {
+            if !self.is_locked()? {
+                return Ok(());
+            }
+            match listener.wait(deadline) {
+                Ok(_) => continue,
+                Err(NpError::Timeout) => return Err(LockdowndError::ReceiveTimeout),
+                Err(e) => return Err(notification_error(e)),
+            }
+        }
+    }
+
     /// Starts the heartbeat service for the device
     /// # Arguments
     /// * `label` - The label to give the underlying service as it starts
@@ -452,10 +1316,66 @@ impl Device {
     pub fn new_heartbeat_client(
         &self,
         label: impl Into<String>,
-    ) -> Result<HeartbeatClient, HeartbeatError> {
+    ) -> Result<HeartbeatClient<'_>, HeartbeatError> {
         HeartbeatClient::new(self, label.into())
     }
 
+    /// Runs an operation while a heartbeat keeps the device's connections alive.
+    /// The heartbeat answers the device on a background thread and stops once the operation returns,
+    /// so long mobilesync or mobilebackup2 sessions aren't dropped partway through.
+    /// # Arguments
+    /// * `op` - The operation to run
+    /// # Returns
+    /// The result of the operation, and the error that stopped the heartbeat early if it died.
+    /// If the heartbeat died, the device may have closed the operation's connections.
+    /// If the operation panics, the heartbeat is stopped before the panic is passed on.
+    ///
+    /// ***Verified:*** False
+    pub fn with_heartbeat<T>(
+        &self,
+        op: impl FnOnce() -> T,
+    ) -> Result<(T, Option<HeartbeatError>), HeartbeatError> {
+        let heartbeat = HeartbeatClient::new(self, "rusty_libimobiledevice")?;
+        let stop = AtomicBool::new(false);
+
+        // Scoped, since the heartbeat client borrows the device
+        std::thread::scope(|scope| {
+            let stop = &stop;
+            let thread = scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    // Wake up every second to check if the operation finished
+                    match heartbeat.receive(1000) {
+                        Ok(_) => {
+                            let mut response = Plist::new_dict();
+                            response
+                                .dict_set_item("Command", Plist::new_string("Polo"))
+                                .map_err(|_| HeartbeatError::PlistError)?;
+                            heartbeat.send(response)?;
+                        }
+                        Err(HeartbeatError::Timeout) => continue,
+                        Err(e) => {
+                            warn!("Heartbeat died during the operation: {}", e);
+                            return Err(e);
+                        }
+                    }
+                }
+                Ok(())
+            });
+
+            // The heartbeat is stopped and joined even if the operation panics, then the panic carries on
+            let value = std::panic::catch_unwind(std::panic::AssertUnwindSafe(op));
+            stop.store(true, Ordering::Relaxed);
+            let heartbeat_error = match thread.join() {
+                Ok(result) => result.err(),
+                Err(_) => Some(HeartbeatError::UnknownError),
+            };
+            match value {
+                Ok(value) => Ok((value, heartbeat_error)),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+
     /// Creates an image mounter for the device
     /// # Arguments
     /// * `label` - The label to give the underlying service as it starts
@@ -470,6 +1390,62 @@ impl Device {
         crate::services::mobile_image_mounter::MobileImageMounter::start_service(self, label)
     }
 
+    /// Mounts the developer disk image if it isn't already mounted.
+    /// Services such as screenshotr and debugserver only exist once the image is mounted.
+    /// `ddi_dir` is laid out like Xcode's DeviceSupport folder, with a folder per iOS version
+    /// containing `DeveloperDiskImage.dmg` and `DeveloperDiskImage.dmg.signature`.
+    /// The full version (`15.4.1`) is tried first, then `15.4` and then `15`.
+    /// # Arguments
+    /// * `ddi_dir` - The folder containing the developer disk images
+    /// # Returns
+    /// *none*, or `DmgNotFound` if there is no image for the device's iOS version
+    ///
+    /// ***Verified:*** False
+    pub fn ensure_developer_image(&self, ddi_dir: &Path) -> Result<(), MobileImageMounterError> {
+        let mounter = self.new_mobile_image_mounter("rusty_libimobiledevice")?;
+        if mounter.is_developer_image_mounted()? {
+            return Ok(());
+        }
+
+        let version = match self
+            .new_lockdownd_client("rusty_libimobiledevice")
+            .and_then(|l| l.get_value("ProductVersion", ""))
+            .map(|v| v.get_string_val())
+        {
+            Ok(Ok(version)) => version,
+            _ => return Err(MobileImageMounterError::ConnFailed),
+        };
+
+        let parts: Vec<&str> = version.split('.').collect();
+        let candidates = (1..=parts.len()).rev().map(|n| parts[..n].join("."));
+        let mut image = None;
+        for candidate in candidates {
+            let dmg = ddi_dir.join(&candidate).join("DeveloperDiskImage.dmg");
+            if dmg.is_file() {
+                image = Some(dmg);
+                break;
+            }
+        }
+        let dmg = match image {
+            Some(dmg) => dmg,
+            None => {
+                warn!(
+                    "No developer disk image for iOS {} in {}",
+                    version,
+                    ddi_dir.display()
+                );
+                return Err(MobileImageMounterError::DmgNotFound);
+            }
+        };
+        let dmg_path = dmg.display().to_string();
+        let signature_path = format!("{}.signature", dmg_path);
+
+        info!("Mounting developer disk image {}", dmg_path);
+        mounter.upload_image(dmg_path.clone(), "Developer", signature_path.clone())?;
+        mounter.mount_image(dmg_path, "Developer", signature_path)?;
+        Ok(())
+    }
+
     /// Creates an instproxy client for the device
     /// # Arguments
     /// * `label` - The label to give the underlying service as it starts
@@ -536,6 +1512,179 @@ impl Device {
     ) -> Result<MisagentClient, MisagentError> {
         MisagentClient::start_service(self, label)
     }
+
+    /// Closes the cached lockdownd connection and frees the device handle.
+    /// This is what dropping the device does, spelled out for callers that want the teardown to be explicit.
+    /// Clients borrow the device, so they all have to be dropped or closed before this can be called.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn disconnect(self) {
+        info!("Disconnecting from device {}", self.get_udid());
+        drop(self);
+    }
+}
+
+/// Hardware identifiers of a device, read from lockdownd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceHardware {
+    /// The product type, such as `iPhone14,2`
+    pub product_type: String,
+    /// The hardware model, such as `D63AP`
+    pub hardware_model: String,
+    /// The unique chip ID (ECID)
+    pub chip_id: u64,
+}
+
+/// A summary of a device, as gathered by `Device::info_report`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceReport {
+    pub udid: String,
+    /// The name the user gave the device
+    pub name: Option<String>,
+    /// The product type, such as `iPhone14,2`
+    pub product_type: Option<String>,
+    /// The iOS version, such as `16.4.1`
+    pub product_version: Option<String>,
+    /// The iOS build, such as `20E252`
+    pub build_version: Option<String>,
+    pub serial_number: Option<String>,
+    /// The battery level in percent
+    pub battery_level: Option<u64>,
+    pub charging_state: Option<ChargingState>,
+    /// The size of the data partition in bytes
+    pub storage_total: Option<u64>,
+    /// The free space on the data partition in bytes
+    pub storage_free: Option<u64>,
+    /// How many apps the user installed, not counting system apps
+    pub installed_apps: Option<u64>,
+    /// Why values are missing, one entry per failed query
+    pub errors: Vec<String>,
+}
+
+impl DeviceReport {
+    /// Converts the report to a dictionary, so it can be saved as a plist or sent along with a support request.
+    /// Missing values are left out.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A dictionary keyed by the field names
+    ///
+    /// ***Verified:*** False
+    pub fn to_plist(&self) -> Plist {
+        let mut dict = Plist::new_dict();
+        let strings = [
+            ("udid", Some(&self.udid)),
+            ("name", self.name.as_ref()),
+            ("product_type", self.product_type.as_ref()),
+            ("product_version", self.product_version.as_ref()),
+            ("build_version", self.build_version.as_ref()),
+            ("serial_number", self.serial_number.as_ref()),
+        ];
+        let numbers = [
+            ("battery_level", self.battery_level),
+            ("storage_total", self.storage_total),
+            ("storage_free", self.storage_free),
+            ("installed_apps", self.installed_apps),
+        ];
+        // Setting items on a fresh dictionary can't fail
+        for (key, value) in strings {
+            if let Some(value) = value {
+                let _ = dict.dict_set_item(key, Plist::new_string(value));
+            }
+        }
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                let _ = dict.dict_set_item(key, Plist::new_uint(value));
+            }
+        }
+        if let Some(state) = self.charging_state {
+            let _ =
+                dict.dict_set_item("charging_state", Plist::new_string(&format!("{:?}", state)));
+        }
+        let mut errors = Plist::new_array();
+        for error in &self.errors {
+            let _ = errors.array_append_item(Plist::new_string(error));
+        }
+        let _ = dict.dict_set_item("errors", errors);
+        dict
+    }
+}
+
+/// An iOS version such as `16.4.1`.
+/// Versions compare by their numbers, so `16.10` is newer than `16.9`, and a missing component counts as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IosVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl IosVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        IosVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl std::str::FromStr for IosVersion {
+    type Err = LockdowndError;
+
+    /// Parses one to three numbers separated by dots, such as `16`, `16.4` or `16.4.1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut numbers = [0; 3];
+        let mut count = 0;
+        for part in s.trim().split('.') {
+            if count == numbers.len() {
+                return Err(LockdowndError::InvalidArg);
+            }
+            numbers[count] = part.parse().map_err(|_| LockdowndError::InvalidArg)?;
+            count += 1;
+        }
+        Ok(IosVersion::new(numbers[0], numbers[1], numbers[2]))
+    }
+}
+
+impl fmt::Display for IosVersion {
+    /// Writes the version like iOS does, leaving out a patch of 0
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decides what `pair_interactive` does after a pairing attempt
+/// # Arguments
+/// * `attempt` - The result of the attempt
+/// * `timed_out` - Whether the timeout has passed
+/// # Returns
+/// The result to return, or `None` to keep waiting for the user
+fn pair_outcome(
+    attempt: Result<(), LockdowndError>,
+    timed_out: bool,
+) -> Option<Result<(), IdeviceError>> {
+    match attempt {
+        Ok(()) => Some(Ok(())),
+        // The device needs to be unlocked before it shows the trust dialog
+        Err(LockdowndError::PairingDialogueRepsonsePending)
+        | Err(LockdowndError::PasswordProtected) => {
+            if timed_out {
+                Some(Err(IdeviceError::PairingTimeout))
+            } else {
+                None
+            }
+        }
+        Err(e) => Some(Err(e.into())),
+    }
 }
 
 impl Clone for Device {
@@ -544,6 +1693,14 @@ impl Clone for Device {
         Device::new(self.get_udid(), ip, self.get_mux_id())
     }
 }
+
+/// How a device is connected to the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionType {
+    Usb,
+    Network,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeviceClass {
     IPhone,
@@ -595,9 +1752,12 @@ impl From<DeviceClass> for String {
 
 pub struct IDeviceEvent {
     pub(crate) _pointer: unsafe_bindings::idevice_event_t,
+    /// Keeps the UDID alive for events copied out of the callback
+    _udid: Option<CString>,
 }
 
 impl IDeviceEvent {
+    /// Panics on event types libimobiledevice adds in the future, use `kind` to handle them
     pub fn event_type(&self) -> EventType {
         match self._pointer.event {
             unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_ADD => EventType::Add,
@@ -607,6 +1767,11 @@ impl IDeviceEvent {
         }
     }
 
+    /// Gets what happened to the device
+    pub fn kind(&self) -> IDeviceEventType {
+        self._pointer.event.into()
+    }
+
     pub fn udid(&self) -> String {
         unsafe { CStr::from_ptr(self._pointer.udid) }
             .to_string_lossy()
@@ -616,11 +1781,39 @@ impl IDeviceEvent {
     pub fn conn_type(&self) -> u32 {
         self._pointer.conn_type
     }
+
+    /// Gets how the device is connected, or `None` for a connection type this crate doesn't know
+    pub fn connection_type(&self) -> Option<ConnectionType> {
+        match self._pointer.conn_type {
+            unsafe_bindings::idevice_connection_type_CONNECTION_USBMUXD => {
+                Some(ConnectionType::Usb)
+            }
+            unsafe_bindings::idevice_connection_type_CONNECTION_NETWORK => {
+                Some(ConnectionType::Network)
+            }
+            _ => None,
+        }
+    }
+
+    /// Copies the event so it can be kept after the callback returns.
+    /// The UDID given to callbacks is only valid until they return.
+    pub(crate) fn to_owned_event(&self) -> IDeviceEvent {
+        let udid = CString::new(self.udid()).unwrap_or_default();
+        let mut _pointer = self._pointer;
+        _pointer.udid = udid.as_ptr();
+        IDeviceEvent {
+            _pointer,
+            _udid: Some(udid),
+        }
+    }
 }
 
 impl From<unsafe_bindings::idevice_event_t> for IDeviceEvent {
     fn from(_pointer: unsafe_bindings::idevice_event_t) -> Self {
-        IDeviceEvent { _pointer }
+        IDeviceEvent {
+            _pointer,
+            _udid: None,
+        }
     }
 }
 
@@ -631,9 +1824,53 @@ pub enum EventType {
     Pair,
 }
 
+/// What happened to a device in an `IDeviceEvent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IDeviceEventType {
+    Add,
+    Remove,
+    Paired,
+    /// An event type this crate doesn't know about yet
+    Unknown(i32),
+}
+
+impl From<unsafe_bindings::idevice_event_type> for IDeviceEventType {
+    fn from(event: unsafe_bindings::idevice_event_type) -> Self {
+        match event {
+            unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_ADD => IDeviceEventType::Add,
+            unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_REMOVE => IDeviceEventType::Remove,
+            unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_PAIRED => IDeviceEventType::Paired,
+            _ => IDeviceEventType::Unknown(event as i32),
+        }
+    }
+}
+
 impl From<unsafe_bindings::idevice_t> for Device {
     fn from(device: unsafe_bindings::idevice_t) -> Device {
-        Device { pointer: device }
+        Device {
+            pointer: device,
+            lockdownd_cache: Mutex::new(None),
+        }
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.pointer == other.pointer
+    }
+}
+
+impl Eq for Device {}
+
+impl PartialOrd for Device {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Device {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.pointer.cmp(&other.pointer)
     }
 }
 
@@ -654,8 +1891,152 @@ impl Debug for Device {
 impl Drop for Device {
     fn drop(&mut self) {
         info!("Dropping device {}", self.get_udid());
+        // The cached lockdownd connection refers to the device, so it has to go first
+        *self
+            .lockdownd_cache
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
         unsafe {
             unsafe_bindings::idevice_free(self.pointer);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Subscribes without usbmuxd, returning the pointer libimobiledevice would have been given
+    fn subscribe(calls: &Arc<AtomicUsize>) -> (EventSubscription, *mut c_void) {
+        let calls = calls.clone();
+        let callback = IDeviceEventCallback::new(
+            Box::new(move |_: IDeviceEvent, _: &dyn std::any::Any| {
+                calls.fetch_add(1, Ordering::SeqCst);
+            }),
+            Box::new(()),
+            None,
+        );
+        let mut pointer = null_mut();
+        let subscription = EventSubscription::new(callback, |callback_ptr| {
+            pointer = callback_ptr;
+            IdeviceError::Success
+        })
+        .unwrap();
+        (subscription, pointer)
+    }
+
+    /// Calls the callback behind `pointer` the way libimobiledevice does
+    fn deliver(pointer: *mut c_void) {
+        let udid = CString::new("00008030-001A2D3C0E98802E").unwrap();
+        let event = unsafe_bindings::idevice_event_t {
+            event: unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_ADD,
+            udid: udid.as_ptr(),
+            conn_type: unsafe_bindings::idevice_connection_type_CONNECTION_USBMUXD,
+        };
+        unsafe { callback::idevice_event_callback(&event, pointer) };
+    }
+
+    #[test]
+    fn dropping_a_replaced_subscription_keeps_the_current_one() {
+        let first_calls = Arc::new(AtomicUsize::new(0));
+        let second_calls = Arc::new(AtomicUsize::new(0));
+        let (first, _) = subscribe(&first_calls);
+        let (mut second, second_pointer) = subscribe(&second_calls);
+        let first_generation = first.generation;
+
+        // The first guard was replaced, so dropping it must not unsubscribe the second
+        drop(first);
+        let current = *EVENT_GENERATION
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        assert_ne!(current, first_generation);
+        assert_eq!(current, second.generation);
+
+        // libimobiledevice still calls into the second callback after the first guard is gone
+        deliver(second_pointer);
+        assert_eq!(first_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(second_calls.load(Ordering::SeqCst), 1);
+
+        let mut unsubscribed = false;
+        second
+            .release(|| {
+                unsubscribed = true;
+                Ok(())
+            })
+            .unwrap();
+        assert!(unsubscribed);
+    }
+
+    #[test]
+    fn event_types_map_from_each_c_value() {
+        let cases = [
+            (
+                unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_ADD,
+                IDeviceEventType::Add,
+            ),
+            (
+                unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_REMOVE,
+                IDeviceEventType::Remove,
+            ),
+            (
+                unsafe_bindings::idevice_event_type_IDEVICE_DEVICE_PAIRED,
+                IDeviceEventType::Paired,
+            ),
+            (42, IDeviceEventType::Unknown(42)),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(IDeviceEventType::from(raw), expected);
+        }
+    }
+
+    #[test]
+    fn ios_versions_compare_by_number() {
+        let older: IosVersion = "16.9".parse().unwrap();
+        let newer: IosVersion = "16.10".parse().unwrap();
+        assert_eq!(newer, IosVersion::new(16, 10, 0));
+        assert!(newer > older);
+        assert!("16.10.1".parse::<IosVersion>().unwrap() > newer);
+        assert!("17".parse::<IosVersion>().unwrap() > newer);
+        assert_eq!(newer.to_string(), "16.10");
+    }
+
+    #[test]
+    fn malformed_ios_versions_are_rejected() {
+        for version in ["", "16.x", "16.4.1.2", "16..4"] {
+            assert_eq!(
+                version.parse::<IosVersion>(),
+                Err(LockdowndError::InvalidArg),
+                "{:?}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn pairing_waits_for_the_user_until_the_timeout() {
+        for pending in [
+            LockdowndError::PairingDialogueRepsonsePending,
+            LockdowndError::PasswordProtected,
+        ] {
+            assert_eq!(pair_outcome(Err(pending), false), None);
+            assert_eq!(
+                pair_outcome(Err(pending), true),
+                Some(Err(IdeviceError::PairingTimeout))
+            );
+        }
+        assert_eq!(pair_outcome(Ok(()), true), Some(Ok(())));
+    }
+
+    #[test]
+    fn denied_pairing_is_not_a_timeout() {
+        assert_eq!(
+            pair_outcome(Err(LockdowndError::UserDeniedPairing), false),
+            Some(Err(IdeviceError::PairingDenied))
+        );
+        assert_eq!(
+            pair_outcome(Err(LockdowndError::ReceiveTimeout), false),
+            Some(Err(IdeviceError::Timeout))
+        );
+    }
+}
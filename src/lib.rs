@@ -1,3 +1,6 @@
+/// Service clients that run on a thread of their own so they can be awaited
+#[cfg(feature = "async")]
+pub mod async_client;
 #[doc = include_str!("../README.md")]
 #[allow(clippy::all)]
 mod bindings;
@@ -9,6 +12,7 @@ pub mod connection;
 pub mod error;
 /// Creates connections and manages high level interfaces for iOS devices
 pub mod idevice;
+pub mod plist_util;
 /// A bare bones representation of a service running on a device.
 /// Useful for services that don't have modules or for running raw commands
 pub mod service;
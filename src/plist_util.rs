@@ -0,0 +1,153 @@
+// jkcoxson
+// Helpers for checking the shape of plists received from services
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use plist_plus::{Plist, PlistType};
+
+use crate::bindings as unsafe_bindings;
+
+/// Seconds between the Unix epoch and 2001-01-01, which plist dates count from
+const PLIST_EPOCH_OFFSET: i64 = 978_307_200;
+
+/// Checks that a plist received from a service is of the expected type
+/// # Arguments
+/// * `plist` - The plist to check
+/// * `expected` - The type the plist should be
+/// * `error` - The error to return if the type doesn't match
+/// # Returns
+/// The same plist if it matches, otherwise `error`
+///
+/// ***Verified:*** False
+pub fn expect_type<E>(plist: &Plist, expected: PlistType, error: E) -> Result<&Plist, E> {
+    if plist.plist_type != expected {
+        return Err(error);
+    }
+    Ok(plist)
+}
+
+/// Gets how many bytes a plist takes up in binary form, which is how services send it
+/// # Arguments
+/// * `plist` - The plist to measure
+/// # Returns
+/// The size of the binary plist, or 0 if it couldn't be serialized
+///
+/// ***Verified:*** False
+pub fn binary_size(plist: &Plist) -> usize {
+    let mut data = std::ptr::null_mut();
+    let mut length = 0;
+    unsafe { unsafe_bindings::plist_to_bin(plist.get_pointer(), &mut data, &mut length) };
+    if data.is_null() {
+        return 0;
+    }
+    unsafe { unsafe_bindings::plist_to_bin_free(data) };
+    length as usize
+}
+
+/// Renders a plist as XML, for logging what is sent to a service
+/// # Arguments
+/// * `plist` - The plist to render
+/// # Returns
+/// The XML, or an empty string if it couldn't be serialized
+///
+/// ***Verified:*** False
+pub fn to_xml(plist: &Plist) -> String {
+    let mut data = std::ptr::null_mut();
+    let mut length = 0;
+    unsafe { unsafe_bindings::plist_to_xml(plist.get_pointer(), &mut data, &mut length) };
+    if data.is_null() {
+        return String::new();
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(data as *const u8, length as usize) };
+    let xml = String::from_utf8_lossy(bytes).into_owned();
+    unsafe { unsafe_bindings::plist_to_xml_free(data) };
+    xml
+}
+
+/// Reads a date from a plist
+/// # Arguments
+/// * `plist` - The date
+/// # Returns
+/// The time, or `None` if the plist isn't a date or is before the Unix epoch
+///
+/// ***Verified:*** False
+pub fn get_date(plist: &Plist) -> Option<SystemTime> {
+    if plist.plist_type != PlistType::Date {
+        return None;
+    }
+    let mut sec = 0;
+    let mut usec = 0;
+    unsafe { unsafe_bindings::plist_get_date_val(plist.get_pointer(), &mut sec, &mut usec) };
+    let micros = (sec as i64 + PLIST_EPOCH_OFFSET) * 1_000_000 + usec as i64;
+    if micros < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_micros(micros as u64))
+}
+
+/// Creates a plist date
+/// # Arguments
+/// * `time` - The time to store. Times before the Unix epoch are stored as the epoch,
+///   and times after the latest date a plist holds, early in 2069, are stored as that date
+/// # Returns
+/// The date
+///
+/// ***Verified:*** False
+pub fn new_date(time: SystemTime) -> Plist {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let sec = since_epoch.as_secs() as i64 - PLIST_EPOCH_OFFSET;
+    // Seconds before the epoch always fit, so only the far future can overflow
+    let sec = i32::try_from(sec).unwrap_or(i32::MAX);
+    let usec = since_epoch.subsec_micros() as i32;
+    unsafe { unsafe_bindings::plist_new_date(sec, usec) }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expect_type_rejects_wrong_types() {
+        let string = Plist::new_string("not a dictionary");
+        assert_eq!(
+            expect_type(&string, PlistType::Dictionary, "wrong type").err(),
+            Some("wrong type")
+        );
+        assert_eq!(
+            expect_type(&Plist::new_dict(), PlistType::Array, "wrong type").err(),
+            Some("wrong type")
+        );
+        assert_eq!(
+            expect_type(&Plist::new_uint(1), PlistType::String, "wrong type").err(),
+            Some("wrong type")
+        );
+    }
+
+    #[test]
+    fn expect_type_passes_matching_types() {
+        let dict = Plist::new_dict();
+        assert!(expect_type(&dict, PlistType::Dictionary, "wrong type").is_ok());
+        let array = Plist::new_array();
+        assert!(expect_type(&array, PlistType::Array, "wrong type").is_ok());
+    }
+
+    #[test]
+    fn dates_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        assert_eq!(get_date(&new_date(time)), Some(time));
+        assert_eq!(get_date(&Plist::new_string("2023")), None);
+    }
+
+    #[test]
+    fn dates_out_of_range_are_clamped() {
+        assert_eq!(
+            get_date(&new_date(UNIX_EPOCH - Duration::from_secs(1))),
+            Some(UNIX_EPOCH)
+        );
+
+        let latest =
+            UNIX_EPOCH + Duration::from_secs((i32::MAX as i64 + PLIST_EPOCH_OFFSET) as u64);
+        let far_future = UNIX_EPOCH + Duration::from_secs(200 * 365 * 24 * 60 * 60);
+        assert_eq!(get_date(&new_date(far_future)), Some(latest));
+    }
+}
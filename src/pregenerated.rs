@@ -12064,8 +12064,99 @@ pub const mobilesync_sync_type_t_MOBILESYNC_SYNC_TYPE_RESET: mobilesync_sync_typ
 pub type mobilesync_sync_type_t = ::std::os::raw::c_uint;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
+pub struct device_link_service_client_private {
+    pub parent: property_list_service_client_t,
+}
+#[test]
+fn bindgen_test_layout_device_link_service_client_private() {
+    assert_eq!(
+        ::std::mem::size_of::<device_link_service_client_private>(),
+        8usize,
+        concat!("Size of: ", stringify!(device_link_service_client_private))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<device_link_service_client_private>(),
+        8usize,
+        concat!(
+            "Alignment of ",
+            stringify!(device_link_service_client_private)
+        )
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<device_link_service_client_private>())).parent as *const _
+                as usize
+        },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(device_link_service_client_private),
+            "::",
+            stringify!(parent)
+        )
+    );
+}
+pub type device_link_service_client_t = *mut device_link_service_client_private;
+pub const mobilesync_sync_direction_t_MOBILESYNC_SYNC_DIR_DEVICE_TO_COMPUTER:
+    mobilesync_sync_direction_t = 0;
+pub const mobilesync_sync_direction_t_MOBILESYNC_SYNC_DIR_COMPUTER_TO_DEVICE:
+    mobilesync_sync_direction_t = 1;
+pub type mobilesync_sync_direction_t = ::std::os::raw::c_uint;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct mobilesync_client_private {
-    _unused: [u8; 0],
+    pub parent: device_link_service_client_t,
+    pub direction: mobilesync_sync_direction_t,
+    pub data_class: *mut ::std::os::raw::c_char,
+}
+#[test]
+fn bindgen_test_layout_mobilesync_client_private() {
+    assert_eq!(
+        ::std::mem::size_of::<mobilesync_client_private>(),
+        24usize,
+        concat!("Size of: ", stringify!(mobilesync_client_private))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<mobilesync_client_private>(),
+        8usize,
+        concat!("Alignment of ", stringify!(mobilesync_client_private))
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<mobilesync_client_private>())).parent as *const _ as usize
+        },
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(mobilesync_client_private),
+            "::",
+            stringify!(parent)
+        )
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<mobilesync_client_private>())).direction as *const _ as usize
+        },
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(mobilesync_client_private),
+            "::",
+            stringify!(direction)
+        )
+    );
+    assert_eq!(
+        unsafe {
+            &(*(::std::ptr::null::<mobilesync_client_private>())).data_class as *const _ as usize
+        },
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(mobilesync_client_private),
+            "::",
+            stringify!(data_class)
+        )
+    );
 }
 pub type mobilesync_client_t = *mut mobilesync_client_private;
 #[repr(C)]
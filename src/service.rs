@@ -1,17 +1,277 @@
 // jkcoxson
 
+use std::collections::hash_map::RandomState;
 use std::ffi::CString;
+use std::hash::{BuildHasher, Hasher};
 use std::os::raw::c_char;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::services::lockdownd::LockdowndService;
-use crate::{bindings as unsafe_bindings, error::ServiceError, idevice::Device};
+use crate::services::lockdownd::{LockdowndClient, LockdowndService};
+use crate::{
+    bindings as unsafe_bindings,
+    error::{LockdowndError, ServiceError},
+    idevice::Device,
+};
 
+use plist_plus::Plist;
+
+/// A client for a service that lockdownd can start.
+/// This lets generic code open any service by type, such as `T::connect(&device)`.
+/// The client borrows the device for `'a`, so it can't outlive it.
+///
+/// Every client is `Send` and `Sync`, so it can be moved to another thread or shared between them.
+/// A client locks itself for each call, so calls from different threads never overlap.
+/// Clients whose `send` and `receive` are separate calls can still interleave a request with
+/// another thread's reply, so share those in a `Mutex` for the whole exchange.
+pub trait Service<'a>: Sized {
+    /// The identifier lockdownd knows the service by
+    const SERVICE_NAME: &'static str;
+    type Error: From<LockdowndError>;
+
+    /// Creates the client from a service started by lockdownd
+    /// SSL is enabled on the connection if the descriptor requires it
+    /// # Arguments
+    /// * `device` - The device the service was started on
+    /// * `descriptor` - The lockdown service to connect on
+    /// # Returns
+    /// The client for the service
+    ///
+    /// ***Verified:*** False
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, Self::Error>;
+
+    /// Performs the lockdownd handshake, starts the service and connects to it
+    /// # Arguments
+    /// * `device` - The device to connect to
+    /// # Returns
+    /// The client for the service
+    ///
+    /// ***Verified:*** False
+    fn connect(device: &'a Device) -> Result<Self, Self::Error> {
+        let mut lockdownd = LockdowndClient::new(device, "rusty_libimobiledevice")?;
+        let descriptor = lockdownd.start_service(Self::SERVICE_NAME, false)?;
+        Self::from_descriptor(device, descriptor)
+    }
+}
+
+/// A libimobiledevice client pointer that a `ClientHandle` can own
+pub(crate) trait ClientPointer: Copy {
+    /// Frees the C client, returning libimobiledevice's error code
+    /// # Safety
+    /// The pointer must be a live client, and must not be used again afterwards
+    unsafe fn free(self) -> i32;
+}
+
+/// The pointer behind a service client, guarded by a `Mutex` and freed when the handle is dropped.
+///
+/// Every call into libimobiledevice goes through `lock`, so threads sharing a client never use
+/// the C client at the same time. The handle can't be cloned, so only one of them ever frees it.
+pub(crate) struct ClientHandle<P: ClientPointer> {
+    pointer: Mutex<Option<P>>,
+}
+
+// The pointer is only used with the lock held, and freed once by its single owner
+unsafe impl<P: ClientPointer> Send for ClientHandle<P> {}
+unsafe impl<P: ClientPointer> Sync for ClientHandle<P> {}
+
+impl<P: ClientPointer> ClientHandle<P> {
+    pub(crate) fn new(pointer: P) -> Self {
+        ClientHandle {
+            pointer: Mutex::new(Some(pointer)),
+        }
+    }
+
+    /// Locks the client for a call. The guard derefs to the pointer, so a call written as
+    /// `f(*self.pointer.lock())` holds the lock until `f` returns
+    pub(crate) fn lock(&self) -> HandleGuard<'_, P> {
+        // The pointer is still valid if a call panicked while holding the lock
+        HandleGuard(self.pointer.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Frees the client now rather than on drop, returning libimobiledevice's error code
+    pub(crate) fn close(&mut self) -> i32 {
+        let pointer = self
+            .pointer
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        match pointer {
+            Some(pointer) => unsafe { pointer.free() },
+            None => 0,
+        }
+    }
+}
+
+impl<P: ClientPointer> Drop for ClientHandle<P> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<P: ClientPointer + std::fmt::Debug> std::fmt::Debug for ClientHandle<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.pointer.try_lock() {
+            Ok(pointer) => pointer.fmt(f),
+            Err(_) => f.write_str("<locked>"),
+        }
+    }
+}
+
+/// The lock on a `ClientHandle`, held for one call into libimobiledevice
+pub(crate) struct HandleGuard<'a, P>(MutexGuard<'a, Option<P>>);
+
+impl<P> std::ops::Deref for HandleGuard<'_, P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        // `close` takes the client by value, so nothing can lock it afterwards
+        self.0.as_ref().expect("client used after it was closed")
+    }
+}
+
+/// Which way a message passed through a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A hook registered with a client's `on_message`
+#[derive(Clone, Default)]
+pub(crate) struct MessageHook(Option<Arc<Mutex<dyn FnMut(Direction, &Plist) + Send>>>);
+
+impl MessageHook {
+    pub(crate) fn set(&mut self, hook: impl FnMut(Direction, &Plist) + Send + 'static) {
+        self.0 = Some(Arc::new(Mutex::new(hook)));
+    }
+
+    pub(crate) fn call(&self, direction: Direction, message: &Plist) {
+        if let Some(hook) = &self.0 {
+            // A hook that panicked once shouldn't stop the client from working
+            let mut hook = hook.lock().unwrap_or_else(|e| e.into_inner());
+            hook(direction, message);
+        }
+    }
+}
+
+impl std::fmt::Debug for MessageHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "Some(hook)"
+        } else {
+            "None"
+        })
+    }
+}
+
+/// Traffic totals for a client, collected once enabled with the client's `with_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// The number of successful send calls
+    pub sends: u64,
+    /// The number of successful receive calls
+    pub receives: u64,
+}
+
+/// The counters behind a client's `stats`.
+/// Clients that never enable them only pay for checking the `None`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StatsCounter(Option<Arc<Mutex<ConnectionStats>>>);
+
+impl StatsCounter {
+    pub(crate) fn enable(&mut self) {
+        if self.0.is_none() {
+            self.0 = Some(Arc::new(Mutex::new(ConnectionStats::default())));
+        }
+    }
+
+    pub(crate) fn sent(&self, bytes: usize) {
+        if let Some(stats) = &self.0 {
+            let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+            stats.bytes_sent += bytes as u64;
+            stats.sends += 1;
+        }
+    }
+
+    pub(crate) fn received(&self, bytes: usize) {
+        if let Some(stats) = &self.0 {
+            let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+            stats.bytes_received += bytes as u64;
+            stats.receives += 1;
+        }
+    }
+
+    pub(crate) fn get(&self) -> ConnectionStats {
+        match &self.0 {
+            Some(stats) => *stats.lock().unwrap_or_else(|e| e.into_inner()),
+            None => ConnectionStats::default(),
+        }
+    }
+}
+
+/// Converts a timeout to the milliseconds the C receive functions take.
+/// They treat 0 as no timeout, so this never returns 0 for a timeout that was set
+pub(crate) fn timeout_millis(timeout: Option<Duration>) -> Option<u32> {
+    timeout.map(|t| t.as_millis().clamp(1, u32::MAX as u128) as u32)
+}
+
+/// Generates a random version 4 UUID, in the uppercase form the device's services use.
+/// The bytes come from OpenSSL when it's enabled, otherwise from the randomly seeded std hasher,
+/// which is unique enough for identifiers but not for anything secret
+pub(crate) fn new_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    #[cfg(feature = "openssl")]
+    if openssl::rand::rand_bytes(&mut bytes).is_ok() {
+        return format_uuid(bytes);
+    }
+
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        if let Ok(elapsed) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    format_uuid(bytes)
+}
+
+/// Formats random bytes as a version 4 UUID, setting its version and variant bits
+pub(crate) fn format_uuid(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[derive(Debug)]
 pub struct ServiceClient<'a> {
-    pub(crate) pointer: unsafe_bindings::service_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::service_client_t>,
+    timeout: Option<u32>,
+    stats: StatsCounter,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl ServiceClient<'_> {
+impl ClientPointer for unsafe_bindings::service_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::service_client_free(self)
+    }
+}
+
+impl<'a> ServiceClient<'a> {
     /// Creates a new service on the device
     /// This is useful for services that don't have abstractions and need to be handled manually
     /// # Arguments
@@ -19,7 +279,7 @@ impl ServiceClient<'_> {
     /// * `descriptor` - The lockdown service to jump off of
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, ServiceError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, ServiceError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::service_client_new(device.pointer, descriptor.pointer, &mut pointer)
@@ -31,7 +291,9 @@ impl ServiceClient<'_> {
         }
 
         Ok(ServiceClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
+            timeout: None,
+            stats: StatsCounter::default(),
             phantom: std::marker::PhantomData,
         })
     }
@@ -44,7 +306,7 @@ impl ServiceClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn factory_start_service(
-        device: &Device,
+        device: &'a Device,
         service_name: impl Into<String>,
         label: impl Into<String>,
     ) -> Result<(Self, i32), ServiceError> {
@@ -72,7 +334,9 @@ impl ServiceClient<'_> {
 
         Ok((
             ServiceClient {
-                pointer,
+                pointer: ClientHandle::new(pointer),
+                timeout: None,
+                stats: StatsCounter::default(),
                 phantom: std::marker::PhantomData,
             },
             error_code,
@@ -90,7 +354,7 @@ impl ServiceClient<'_> {
         let mut sent = 0;
         let result = unsafe {
             unsafe_bindings::service_send(
-                self.pointer,
+                *self.pointer.lock(),
                 data.as_ptr() as *const c_char,
                 data.len() as u32,
                 &mut sent,
@@ -102,10 +366,46 @@ impl ServiceClient<'_> {
             return Err(result);
         }
 
+        self.stats.sent(sent as usize);
         Ok(sent)
     }
 
-    /// Receives data from the service, blocking until the amount of data is received
+    /// Starts counting the traffic on this client, see `stats`
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    pub fn with_stats(mut self) -> Self {
+        self.stats.enable();
+        self
+    }
+
+    /// Gets the traffic totals since `with_stats` was called
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The totals, all zero if counting was never enabled
+    ///
+    /// ***Verified:*** False
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.get()
+    }
+
+    /// Sets how long `receive` waits for data before returning `Timeout`
+    /// # Arguments
+    /// * `timeout` - The time to wait, or `None` to block forever, which is the default
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout_millis(timeout);
+    }
+
+    /// Receives data from the service, blocking until the amount of data is received.
+    /// Returns `Timeout` if a timeout was set with `set_timeout` and it passes first
     /// # Arguments
     /// * `size` - The size of the buffer to receive
     /// # Returns
@@ -113,11 +413,14 @@ impl ServiceClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn receive(&self, size: u32) -> Result<Vec<u8>, ServiceError> {
+        if let Some(timeout) = self.timeout {
+            return self.receive_with_timeout(size, timeout);
+        }
         let mut data = vec![0_u8; size as usize];
         let mut received = 0;
         let result = unsafe {
             unsafe_bindings::service_receive(
-                self.pointer,
+                *self.pointer.lock(),
                 data.as_mut_ptr() as *mut c_char,
                 size,
                 &mut received,
@@ -130,6 +433,7 @@ impl ServiceClient<'_> {
         }
 
         data.truncate(received as usize);
+        self.stats.received(data.len());
 
         Ok(data)
     }
@@ -147,7 +451,7 @@ impl ServiceClient<'_> {
         let mut received = 0;
         let result = unsafe {
             unsafe_bindings::service_receive_with_timeout(
-                self.pointer,
+                *self.pointer.lock(),
                 data.as_mut_ptr() as *mut c_char,
                 size,
                 &mut received,
@@ -161,6 +465,7 @@ impl ServiceClient<'_> {
         }
 
         data.truncate(received as usize);
+        self.stats.received(data.len());
 
         Ok(data)
     }
@@ -169,7 +474,7 @@ impl ServiceClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn enable_ssl(&self) -> Result<(), ServiceError> {
-        let result = unsafe { unsafe_bindings::service_enable_ssl(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::service_enable_ssl(*self.pointer.lock()) }.into();
 
         if result != ServiceError::Success {
             return Err(result);
@@ -182,7 +487,7 @@ impl ServiceClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn disable_ssl(&self) -> Result<(), ServiceError> {
-        let result = unsafe { unsafe_bindings::service_disable_ssl(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::service_disable_ssl(*self.pointer.lock()) }.into();
 
         if result != ServiceError::Success {
             return Err(result);
@@ -196,7 +501,8 @@ impl ServiceClient<'_> {
     /// ***Verified:*** False
     pub fn disable_bypass_ssl(&self, bypass: u8) -> Result<(), ServiceError> {
         let result =
-            unsafe { unsafe_bindings::service_disable_bypass_ssl(self.pointer, bypass) }.into();
+            unsafe { unsafe_bindings::service_disable_bypass_ssl(*self.pointer.lock(), bypass) }
+                .into();
 
         if result != ServiceError::Success {
             return Err(result);
@@ -205,11 +511,3 @@ impl ServiceClient<'_> {
         Ok(())
     }
 }
-
-impl Drop for ServiceClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::service_client_free(self.pointer);
-        }
-    }
-}
@@ -5,6 +5,8 @@ pub mod afc;
 /// A proxy for interoping with devices paired with the iOS device
 /// This includes the Apple Watch
 pub mod companion_proxy;
+/// Reads the crash reports stored on the device
+pub mod crash_report;
 /// Used for debugging applications on the device
 pub mod debug_server;
 /// Relays diagnostic logs from the iOS device to the host
@@ -44,6 +46,8 @@ pub mod restored;
 pub mod screenshotr;
 /// Manages the device's OS base and homescreen.
 pub mod springboard_services;
+/// Relays the device's syslog to the host
+pub mod syslog_relay;
 /// The iOS device's settings. Very fun to mess with.
 pub mod userpref;
 /// First used on MacOS, this service is used to inspect the JavaScript and HTML of a site running on the device
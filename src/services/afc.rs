@@ -4,24 +4,48 @@ use std::{
     collections::HashMap,
     convert::TryFrom,
     ffi::{CStr, CString},
+    fs,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::ControlFlow,
     os::raw::c_char,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use log::warn;
+#[cfg(feature = "openssl")]
+use openssl::hash::{Hasher, MessageDigest};
 
 use crate::{
-    bindings as unsafe_bindings, error::AfcError, idevice::Device,
-    services::house_arrest::HouseArrest, services::lockdownd::LockdowndService,
+    bindings as unsafe_bindings,
+    error::AfcError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, ConnectionStats, Service, StatsCounter},
+    services::house_arrest::HouseArrest,
+    services::lockdownd::LockdowndService,
 };
 
+/// How much is read at a time when copying or hashing a file on the device
+const COPY_CHUNK_SIZE: u32 = 1024 * 1024;
+
 /// Transfers files between host and the iDevice
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct AfcClient<'a> {
-    pub(crate) pointer: unsafe_bindings::afc_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::afc_client_t>,
+    stats: StatsCounter,
+    /// The descriptor the client was made from, kept for `lockdownd_service`
+    service: Option<Arc<LockdowndService<'static>>>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl AfcClient<'_> {
+impl ClientPointer for unsafe_bindings::afc_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::afc_client_free(self)
+    }
+}
+
+impl<'a> AfcClient<'a> {
     /// Creates a new afc service connection to the device
     /// The use of this function is unknown
     /// # Arguments
@@ -30,7 +54,7 @@ impl AfcClient<'_> {
     /// The lockdownd service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device) -> Result<(Self, LockdowndService), String> {
+    pub fn new(device: &'a Device) -> Result<(Self, LockdowndService), String> {
         let mut pointer = unsafe { std::mem::zeroed() };
         let mut client_pointer = unsafe { std::mem::zeroed() };
         let result = unsafe {
@@ -41,7 +65,9 @@ impl AfcClient<'_> {
         }
         Ok((
             AfcClient {
-                pointer: client_pointer,
+                pointer: ClientHandle::new(client_pointer),
+                stats: StatsCounter::default(),
+                service: None,
                 phantom: std::marker::PhantomData,
             },
             LockdowndService {
@@ -60,7 +86,7 @@ impl AfcClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn with_service(device: &Device, descriptor: LockdowndService) -> Result<Self, String> {
+    pub fn with_service(device: &'a Device, descriptor: LockdowndService) -> Result<Self, String> {
         let mut client_pointer = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::afc_client_new(device.pointer, descriptor.pointer, &mut client_pointer)
@@ -68,8 +94,16 @@ impl AfcClient<'_> {
         if result != 0 {
             return Err(format!("afc_client_new failed: {}", result));
         }
+        // The lifetime only ties the descriptor to its lockdownd client in name, the C struct is freed on drop
+        let descriptor = std::mem::ManuallyDrop::new(descriptor);
         Ok(AfcClient {
-            pointer: client_pointer,
+            pointer: ClientHandle::new(client_pointer),
+            stats: StatsCounter::default(),
+            service: Some(Arc::new(LockdowndService {
+                pointer: descriptor.pointer,
+                port: descriptor.port,
+                phantom: std::marker::PhantomData,
+            })),
             phantom: std::marker::PhantomData,
         })
     }
@@ -83,7 +117,7 @@ impl AfcClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         service_name: impl Into<String>,
     ) -> Result<Self, AfcError> {
         let service_name_c_string = CString::new(service_name.into()).unwrap();
@@ -100,11 +134,50 @@ impl AfcClient<'_> {
             return Err(result);
         }
         Ok(AfcClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
+            stats: StatsCounter::default(),
+            service: None,
             phantom: std::marker::PhantomData,
         })
     }
 
+    /// Gets the lockdownd service the client was connected with, such as to check its port or SSL flag.
+    /// The service only accepted this client's connection, so it can't be used to open another client;
+    /// start the service again on the same `LockdowndClient` instead, which needs no new handshake.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The service, or `None` if the client wasn't made with `with_service`
+    ///
+    /// ***Verified:*** False
+    pub fn lockdownd_service(&self) -> Option<&LockdowndService<'static>> {
+        self.service.as_deref()
+    }
+
+    /// Starts counting the file data read and written through this client, see `stats`
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    pub fn with_stats(mut self) -> Self {
+        self.stats.enable();
+        self
+    }
+
+    /// Gets the file data totals since `with_stats` was called.
+    /// Only `file_read` and `file_write` are counted, which every file transfer goes through.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The totals, all zero if counting was never enabled
+    ///
+    /// ***Verified:*** False
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.get()
+    }
+
     /// Get information about the device
     /// # Arguments
     /// *none*
@@ -116,7 +189,8 @@ impl AfcClient<'_> {
         let mut info = unsafe { std::mem::zeroed() };
         let mut info_ptr: *mut *mut c_char = &mut info;
         let result =
-            unsafe { unsafe_bindings::afc_get_device_info(self.pointer, &mut info_ptr) }.into();
+            unsafe { unsafe_bindings::afc_get_device_info(*self.pointer.lock(), &mut info_ptr) }
+                .into();
         if result != AfcError::Success {
             return Err(result);
         }
@@ -133,37 +207,160 @@ impl AfcClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn read_directory(&self, directory: impl Into<String>) -> Result<Vec<String>, AfcError> {
-        let directory_c_string = CString::new(directory.into()).unwrap();
-        if directory_c_string.is_empty() {
-            warn!("Cannot use empty string as directory");
+        self.read_dir_iter(directory).collect()
+    }
+
+    /// Reads a directory on the device, yielding the entries one at a time.
+    /// AFC only returns whole listings, so the listing is fetched in one request on the first call to `next`.
+    /// The entries are converted to strings as they are yielded, so `take(10)` on a huge directory
+    /// doesn't build a `String` for every entry, but it does wait for the full listing to arrive.
+    /// # Arguments
+    /// * `directory` - The directory to read
+    /// # Returns
+    /// An iterator over the names in the directory, whose first item is the error if the listing failed
+    ///
+    /// ***Verified:*** False
+    pub fn read_dir_iter(&self, directory: impl Into<String>) -> AfcReadDir<'_> {
+        AfcReadDir {
+            client: self,
+            directory: Some(directory.into()),
+            list: std::ptr::null_mut(),
+            next: 0,
+        }
+    }
+
+    /// Lists a directory, returning the entries along with their metadata, see `AfcClient::metadata`.
+    /// AFC has no batched stat call, so this still costs one `get_file_info` round trip per entry.
+    /// The `.` and `..` entries are skipped.
+    /// # Arguments
+    /// * `path` - The path to the directory
+    /// # Returns
+    /// The entries in the directory, or `UnknownError` if the device left out an entry's size, type or modification time
+    ///
+    /// ***Verified:*** False
+    pub fn list_dir_detailed(&self, path: impl Into<String>) -> Result<Vec<AfcDirEntry>, AfcError> {
+        let path = path.into();
+        let names = self.read_directory(path.clone())?;
+
+        let mut entries = Vec::with_capacity(names.len());
+        for name in names {
+            if name == "." || name == ".." {
+                continue;
+            }
+            let entry_path = join_path(&path, &name);
+            let info = self.metadata(entry_path.clone())?;
+            entries.push(AfcDirEntry {
+                name,
+                path: entry_path,
+                depth: 1,
+                info,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Walks a directory tree on the device, yielding every entry in it, depth first.
+    /// Like `list_dir_detailed`, each entry costs a `get_file_info` round trip.
+    /// The depth and symlink handling are set on the returned iterator, see `AfcWalk`.
+    /// # Arguments
+    /// * `path` - The path to start at, which is yielded first at depth 0
+    /// # Returns
+    /// An iterator over the entries. An entry or directory that can't be read is yielded as an error
+    /// and the walk carries on past it.
+    ///
+    /// ***Verified:*** False
+    pub fn walk(&self, path: impl Into<String>) -> AfcWalk<'_> {
+        AfcWalk {
+            client: self,
+            root: Some(path.into()),
+            min_depth: 0,
+            max_depth: usize::MAX,
+            follow_symlinks: false,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Lists the paths on the device that match a glob pattern, such as `/DCIM/*/IMG_*.HEIC`.
+    /// `*` matches any run of characters and `?` matches a single character, both within one path segment.
+    /// `**` matches any number of directories. It has to list every directory below it,
+    /// which is one round trip per entry and can be very slow over USB for deep trees.
+    /// Symlinks are not followed when matching `**`.
+    /// # Arguments
+    /// * `pattern` - The glob pattern to match full paths against
+    /// # Returns
+    /// The matching paths, sorted
+    ///
+    /// ***Verified:*** False
+    pub fn glob(&self, pattern: &str) -> Result<Vec<String>, AfcError> {
+        if pattern.is_empty() {
+            warn!("Cannot use empty string as glob pattern");
             return Err(AfcError::InvalidArg);
         }
-        let mut list: *mut *mut c_char = std::ptr::null_mut::<*mut c_char>();
+        let segments = pattern
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect::<Vec<&str>>();
 
-        let result = unsafe {
-            unsafe_bindings::afc_read_directory(
-                self.pointer,
-                directory_c_string.as_ptr(),
-                &mut list,
-            )
+        let mut matches = Vec::new();
+        self.glob_from("/", &segments, &mut matches)?;
+        if !pattern.starts_with('/') {
+            for path in matches.iter_mut() {
+                path.remove(0);
+            }
         }
-        .into();
-        if result != AfcError::Success {
-            return Err(result);
+        matches.sort();
+        // `**` can reach the same path more than once, for example with `/**/**`
+        matches.dedup();
+        Ok(matches)
+    }
+
+    fn glob_from(
+        &self,
+        dir: &str,
+        segments: &[&str],
+        matches: &mut Vec<String>,
+    ) -> Result<(), AfcError> {
+        let (segment, rest) = match segments.split_first() {
+            Some(s) => s,
+            None => {
+                matches.push(dir.to_string());
+                return Ok(());
+            }
+        };
+
+        if *segment == "**" {
+            self.glob_from(dir, rest, matches)?;
+            for entry in self.list_dir_detailed(dir)? {
+                if entry.info.is_dir() {
+                    self.glob_from(&entry.path, segments, matches)?;
+                }
+            }
+            return Ok(());
         }
 
-        let mut list_vec: Vec<String> = Vec::new();
-        let mut list_ptr: *mut *mut c_char = list;
-        while !list_ptr.is_null() {
-            if unsafe { *list_ptr }.is_null() {
-                break;
+        let names = if segment.contains(['*', '?']) {
+            self.read_directory(dir)?
+                .into_iter()
+                .filter(|name| name != "." && name != ".." && glob_match(segment, name))
+                .collect::<Vec<String>>()
+        } else {
+            vec![segment.to_string()]
+        };
+
+        for name in names {
+            let path = join_path(dir, &name);
+            let info = match self.get_file_info(path.clone()) {
+                Ok(info) => info,
+                Err(AfcError::ObjectNotFound) => continue,
+                Err(e) => return Err(e),
+            };
+            // Only directories can contain the rest of the pattern
+            if !rest.is_empty() && info.get("st_ifmt").map(|v| v.as_str()) != Some("S_IFDIR") {
+                continue;
             }
-            let list_str = unsafe { CStr::from_ptr(*list_ptr).to_string_lossy().into_owned() };
-            list_vec.push(list_str);
-            list_ptr = unsafe { list_ptr.offset(1) };
+            self.glob_from(&path, rest, matches)?;
         }
-        unsafe { unsafe_bindings::afc_dictionary_free(list) };
-        Ok(list_vec)
+        Ok(())
     }
 
     /// Get information about a file on the device
@@ -185,7 +382,11 @@ impl AfcClient<'_> {
         let mut list: *mut *mut c_char = std::ptr::null_mut::<*mut c_char>();
 
         let result = unsafe {
-            unsafe_bindings::afc_get_file_info(self.pointer, path_c_string.as_ptr(), &mut list)
+            unsafe_bindings::afc_get_file_info(
+                *self.pointer.lock(),
+                path_c_string.as_ptr(),
+                &mut list,
+            )
         }
         .into();
         if result != AfcError::Success {
@@ -212,6 +413,36 @@ impl AfcClient<'_> {
         Ok(ret_properties)
     }
 
+    /// Gets information about a file on the device, parsed into typed fields
+    /// # Arguments
+    /// * `path` - The path to the file
+    /// # Returns
+    /// The file's metadata, or `UnknownError` if the device left out the size, type or modification time
+    ///
+    /// ***Verified:*** False
+    pub fn metadata(&self, path: impl Into<String>) -> Result<AfcFileInfo, AfcError> {
+        let path = path.into();
+        let info = self.get_file_info(path.clone())?;
+        AfcFileInfo::from_info(&info).ok_or_else(|| {
+            warn!(
+                "The device sent incomplete file info for {}: {:?}",
+                path, info
+            );
+            AfcError::UnknownError
+        })
+    }
+
+    /// Gets the target of a symlink on the device
+    /// # Arguments
+    /// * `path` - The path to the symlink
+    /// # Returns
+    /// The target as stored in the link, or `None` if the path isn't a symlink
+    ///
+    /// ***Verified:*** False
+    pub fn read_link(&self, path: impl Into<String>) -> Result<Option<String>, AfcError> {
+        Ok(link_target(&self.get_file_info(path)?))
+    }
+
     /// Open a file on the device and return a handle to it
     /// # Arguments
     /// * `path` - The path to the file
@@ -225,7 +456,7 @@ impl AfcClient<'_> {
         let mut handle = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::afc_file_open(
-                self.pointer,
+                *self.pointer.lock(),
                 path_c_string.as_ptr(),
                 mode.into(),
                 &mut handle,
@@ -238,6 +469,64 @@ impl AfcClient<'_> {
         Ok(handle)
     }
 
+    /// Opens a file on the device for use with `std::io`
+    /// The file is closed when the returned `AfcFile` is dropped
+    /// # Arguments
+    /// * `path` - The path to the file
+    /// * `mode` - The mode to open the file in
+    /// # Returns
+    /// The open file
+    ///
+    /// ***Verified:*** False
+    pub fn open(&self, path: impl Into<String>, mode: AfcFileMode) -> Result<AfcFile, AfcError> {
+        let path = path.into();
+        let handle = self.file_open(path.clone(), mode)?;
+        Ok(AfcFile {
+            client: self,
+            handle: Some(handle),
+            path,
+        })
+    }
+
+    /// Creates a new file on the device for writing, for use with `std::io`.
+    ///
+    /// This is not atomic, and must not be used for lock files. AFC has no exclusive open,
+    /// so this checks that the file doesn't exist and then opens it, see `AfcOpenOptions`.
+    /// If another client creates the file between the two requests, both calls succeed
+    /// and write to the same file.
+    /// # Arguments
+    /// * `path` - The path to the file
+    /// # Returns
+    /// The open file, or `ObjectExists` if the file was already there when checked
+    ///
+    /// ***Verified:*** False
+    pub fn create_new(&self, path: impl Into<String>) -> Result<AfcFile, AfcError> {
+        self.open_with(path, &create_new_options())
+    }
+
+    /// Opens a file on the device with options like `std::fs::OpenOptions`, for use with `std::io`
+    /// # Arguments
+    /// * `path` - The path to the file
+    /// * `options` - How to open the file
+    /// # Returns
+    /// The open file, `ObjectNotFound` if it doesn't exist and wasn't to be created,
+    /// `ObjectExists` if it exists and `create_new` was set, or `InvalidArg` if the options don't make sense together
+    ///
+    /// ***Verified:*** False
+    pub fn open_with(
+        &self,
+        path: impl Into<String>,
+        options: &AfcOpenOptions,
+    ) -> Result<AfcFile, AfcError> {
+        let path = path.into();
+        let mode = options.mode(|| match self.get_file_info(path.clone()) {
+            Ok(_) => Ok(true),
+            Err(AfcError::ObjectNotFound) => Ok(false),
+            Err(e) => Err(e),
+        })?;
+        self.open(path, mode)
+    }
+
     /// Closes a file on the device
     /// # Arguments
     /// * `handle` - The handle to the file
@@ -246,7 +535,8 @@ impl AfcClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn file_close(&self, handle: u64) -> Result<(), AfcError> {
-        let result = unsafe { unsafe_bindings::afc_file_close(self.pointer, handle) }.into();
+        let result =
+            unsafe { unsafe_bindings::afc_file_close(*self.pointer.lock(), handle) }.into();
         if result != AfcError::Success {
             return Err(result);
         }
@@ -262,9 +552,10 @@ impl AfcClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn file_lock(&self, handle: u64, lock_type: AfcLockOp) -> Result<(), AfcError> {
-        let result =
-            unsafe { unsafe_bindings::afc_file_lock(self.pointer, handle, lock_type.into()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::afc_file_lock(*self.pointer.lock(), handle, lock_type.into())
+        }
+        .into();
         if result != AfcError::Success {
             return Err(result);
         }
@@ -281,23 +572,38 @@ impl AfcClient<'_> {
     /// ***Verified:*** False
     pub fn file_read(&self, handle: u64, length: u32) -> Result<Vec<u8>, AfcError> {
         let mut buffer = vec![0u8; length as usize];
-        let mut bytes_written = unsafe { std::mem::zeroed() };
+        let read = self.file_read_into(handle, &mut buffer)?;
+        buffer.truncate(read);
+        Ok(buffer)
+    }
+
+    /// Reads from a file on the device straight into a buffer
+    /// # Arguments
+    /// * `handle` - The handle to the file
+    /// * `buffer` - Where to put the data, up to 4 GiB of which is filled
+    /// # Returns
+    /// How many bytes were read, 0 at the end of the file
+    ///
+    /// ***Verified:*** False
+    pub fn file_read_into(&self, handle: u64, buffer: &mut [u8]) -> Result<usize, AfcError> {
+        let length = buffer.len().min(u32::MAX as usize) as u32;
+        let mut bytes_read = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::afc_file_read(
-                self.pointer,
+                *self.pointer.lock(),
                 handle,
                 buffer.as_mut_ptr() as *mut c_char,
                 length,
-                &mut bytes_written,
+                &mut bytes_read,
             )
         }
         .into();
         if result != AfcError::Success {
             return Err(result);
         }
-        buffer.truncate(bytes_written as usize);
+        self.stats.received(bytes_read as usize);
 
-        Ok(buffer)
+        Ok(bytes_read as usize)
     }
 
     /// Writes data to a file on the device
@@ -309,13 +615,27 @@ impl AfcClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn file_write(&self, handle: u64, data: Vec<u8>) -> Result<(), AfcError> {
+        self.file_write_from(handle, &data)?;
+        Ok(())
+    }
+
+    /// Writes data to a file on the device without copying it first
+    /// # Arguments
+    /// * `handle` - The handle to the file
+    /// * `data` - The data to write, up to 4 GiB of which is written
+    /// # Returns
+    /// How many bytes the device accepted
+    ///
+    /// ***Verified:*** False
+    pub fn file_write_from(&self, handle: u64, data: &[u8]) -> Result<usize, AfcError> {
+        let length = data.len().min(u32::MAX as usize) as u32;
         let mut bytes_written = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::afc_file_write(
-                self.pointer,
+                *self.pointer.lock(),
                 handle,
                 data.as_ptr() as *const c_char,
-                data.len() as u32,
+                length,
                 &mut bytes_written,
             )
         }
@@ -323,39 +643,78 @@ impl AfcClient<'_> {
         if result != AfcError::Success {
             return Err(result);
         }
+        self.stats.sent(bytes_written as usize);
+        Ok(bytes_written as usize)
+    }
+
+    /// Writes a file on the device without ever leaving a partial file at the target path.
+    /// The data is written to a temporary file in the same directory, which is then renamed over the target.
+    /// # Arguments
+    /// * `path` - The path to write the file to
+    /// * `data` - The contents of the file
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn write_atomic(&self, path: impl Into<String>, data: &[u8]) -> Result<(), AfcError> {
+        let path = path.into();
+        if path.is_empty() || path.ends_with('/') {
+            warn!("Cannot atomically write to a directory path");
+            return Err(AfcError::InvalidArg);
+        }
+        // Same directory as the target so the rename never crosses directories
+        let temp_path = format!("{}.{}.tmp", path, std::process::id());
+
+        let handle = self.file_open(temp_path.clone(), AfcFileMode::WriteOnly)?;
+        let written = self.file_write(handle, data.to_vec());
+        let closed = self.file_close(handle);
+
+        if let Err(e) = written.and(closed) {
+            let _ = self.remove_path(temp_path);
+            return Err(e);
+        }
+
+        if let Err(e) = self.rename_path(temp_path.clone(), path) {
+            let _ = self.remove_path(temp_path);
+            return Err(e);
+        }
+
         Ok(())
     }
 
-    /// Seeks for a file or something
+    /// Moves the position of an open file
+    /// `AfcFile` implements `std::io::Seek` on top of this
     /// # Arguments
     /// * `handle` - The handle to the file
-    /// * `offset` - Unknown
-    /// * `whence` - Unknown
+    /// * `offset` - The offset to move by, which may be negative
+    /// * `whence` - What the offset is relative to: 0 for the start, 1 for the current position, 2 for the end
     /// # Returns
     /// *none*
     ///
     /// ***Verified:*** False
     pub fn file_seek(&self, handle: u64, offset: i64, whence: u8) -> Result<(), AfcError> {
-        let result =
-            unsafe { unsafe_bindings::afc_file_seek(self.pointer, handle, offset, whence.into()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::afc_file_seek(*self.pointer.lock(), handle, offset, whence.into())
+        }
+        .into();
         if result != AfcError::Success {
             return Err(result);
         }
         Ok(())
     }
 
-    /// Unknown usage
+    /// Gets the current position of an open file
     /// # Arguments
     /// * `handle` - The handle to the file
     /// # Returns
-    /// *none*
+    /// The offset from the start of the file
     ///
     /// ***Verified:*** False
     pub fn file_tell(&self, handle: u64) -> Result<u64, AfcError> {
         let mut position = unsafe { std::mem::zeroed() };
         let result =
-            unsafe { unsafe_bindings::afc_file_tell(self.pointer, handle, &mut position) }.into();
+            unsafe { unsafe_bindings::afc_file_tell(*self.pointer.lock(), handle, &mut position) }
+                .into();
         if result != AfcError::Success {
             return Err(result);
         }
@@ -372,7 +731,8 @@ impl AfcClient<'_> {
     /// ***Verified:*** False
     pub fn file_truncate(&self, handle: u64, length: u64) -> Result<(), AfcError> {
         let result =
-            unsafe { unsafe_bindings::afc_file_truncate(self.pointer, handle, length) }.into();
+            unsafe { unsafe_bindings::afc_file_truncate(*self.pointer.lock(), handle, length) }
+                .into();
         if result != AfcError::Success {
             return Err(result);
         }
@@ -388,9 +748,10 @@ impl AfcClient<'_> {
     /// ***Verified:*** False
     pub fn remove_path(&self, path: impl Into<String>) -> Result<(), AfcError> {
         let path_c_string = CString::new(path.into()).unwrap();
-        let result =
-            unsafe { unsafe_bindings::afc_remove_path(self.pointer, path_c_string.as_ptr()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::afc_remove_path(*self.pointer.lock(), path_c_string.as_ptr())
+        }
+        .into();
         if result != AfcError::Success {
             return Err(result);
         }
@@ -415,7 +776,7 @@ impl AfcClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::afc_rename_path(
-                self.pointer,
+                *self.pointer.lock(),
                 old_path_c_string.as_ptr(),
                 new_path_c_string.as_ptr(),
             )
@@ -436,34 +797,96 @@ impl AfcClient<'_> {
     /// ***Verified:*** False
     pub fn make_directory(&self, path: impl Into<String>) -> Result<(), AfcError> {
         let path_c_string = CString::new(path.into()).unwrap();
-        let result =
-            unsafe { unsafe_bindings::afc_make_directory(self.pointer, path_c_string.as_ptr()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::afc_make_directory(*self.pointer.lock(), path_c_string.as_ptr())
+        }
+        .into();
         if result != AfcError::Success {
             return Err(result);
         }
         Ok(())
     }
 
-    /// Usage unknown
+    /// Creates a directory on the iOS device along with any missing parents.
+    /// Components that already exist are left alone.
     /// # Arguments
-    /// * `handle` - The handle to the file
-    /// * `length` - Unknown
+    /// * `path` - The path to create
+    /// # Returns
+    /// The directories that were created, parents first, so they can be removed if a later step fails
+    ///
+    /// ***Verified:*** False
+    pub fn create_dir_all(&self, path: impl Into<String>) -> Result<Vec<String>, AfcError> {
+        let path = path.into();
+        let mut created = Vec::new();
+        let mut current = if path.starts_with('/') {
+            String::from("/")
+        } else {
+            String::new()
+        };
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            current = if current.is_empty() {
+                component.to_string()
+            } else {
+                join_path(&current, component)
+            };
+
+            match self.get_file_info(current.clone()) {
+                Ok(_) => continue,
+                Err(AfcError::ObjectNotFound) => {}
+                Err(e) => return Err(e),
+            }
+            match self.make_directory(current.clone()) {
+                Ok(()) => created.push(current.clone()),
+                // Someone else made it in the meantime
+                Err(AfcError::ObjectExists) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(created)
+    }
+
+    /// Truncates a file on the device by its path
+    /// # Arguments
+    /// * `path` - The path to the file
+    /// * `length` - The length to truncate the file to
     /// # Returns
     /// *none*
     ///
     /// ***Verified:*** False
     pub fn truncate(&self, path: impl Into<String>, length: u64) -> Result<(), AfcError> {
         let path_c_string = CString::new(path.into()).unwrap();
-        let result =
-            unsafe { unsafe_bindings::afc_truncate(self.pointer, path_c_string.as_ptr(), length) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::afc_truncate(*self.pointer.lock(), path_c_string.as_ptr(), length)
+        }
+        .into();
         if result != AfcError::Success {
             return Err(result);
         }
         Ok(())
     }
 
+    /// Sets the size of a file through an open handle, such as clearing a log by setting it to 0.
+    /// Growing a file pads it with zeros.
+    /// # Arguments
+    /// * `path` - The path to the file
+    /// * `size` - The new size in bytes
+    /// * `create` - Whether to create the file if it doesn't exist, instead of returning `ObjectNotFound`
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn set_file_size(
+        &self,
+        path: impl Into<String>,
+        size: u64,
+        create: bool,
+    ) -> Result<(), AfcError> {
+        let file = self.open_with(path, &resize_options(create))?;
+        self.file_truncate(file.handle(), size)?;
+        file.close()
+    }
+
     /// Creates a symbolic link on the iOS device
     /// # Arguments
     /// * `target` - The path to the file/folder being linked
@@ -484,7 +907,7 @@ impl AfcClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::afc_make_link(
-                self.pointer,
+                *self.pointer.lock(),
                 link_type.into(),
                 target_c_string.as_ptr(),
                 link_path_c_string.as_ptr(),
@@ -500,7 +923,7 @@ impl AfcClient<'_> {
     /// Sets the time metadata of a file
     /// # Arguments
     /// * `path` - The path to the file
-    /// * `mtime` - The unix epoch time in miliseconds
+    /// * `mtime` - The unix epoch time in nanoseconds, the same as `st_mtime` in `get_file_info`
     /// # Returns
     /// *none*
     ///
@@ -509,7 +932,7 @@ impl AfcClient<'_> {
         let path_c_string = CString::new(path.into()).unwrap();
 
         let result = unsafe {
-            unsafe_bindings::afc_set_file_time(self.pointer, path_c_string.as_ptr(), mtime)
+            unsafe_bindings::afc_set_file_time(*self.pointer.lock(), path_c_string.as_ptr(), mtime)
         }
         .into();
         if result != AfcError::Success {
@@ -518,72 +941,1692 @@ impl AfcClient<'_> {
         Ok(())
     }
 
-    /// Removes a path and the files inside it
+    /// Copies a file to another path on the device.
+    /// AFC has no copy command, so the data is streamed through the host in memory,
+    /// but it never touches the host's disk. The modification time of the source is kept.
     /// # Arguments
-    /// * `path` - The path to the folder being destroyed
+    /// * `from` - The file to copy
+    /// * `to` - Where to copy it to, overwritten if it exists
     /// # Returns
-    /// *none*
+    /// *none*, or `InvalidArg` if both paths name the same file
     ///
     /// ***Verified:*** False
-    pub fn remove_path_and_contents(&self, path: impl Into<String>) -> Result<(), AfcError> {
-        let path_c_string = CString::new(path.into()).unwrap();
-        let result = unsafe {
-            unsafe_bindings::afc_remove_path_and_contents(self.pointer, path_c_string.as_ptr())
+    pub fn copy(&self, from: &str, to: &str) -> Result<(), AfcError> {
+        // Opening the destination truncates it, which would empty the source before it's read
+        if normalize_path(from.split('/'), true) == normalize_path(to.split('/'), true) {
+            warn!("Refusing to copy {} onto itself", from);
+            return Err(AfcError::InvalidArg);
         }
-        .into();
-        if result != AfcError::Success {
-            return Err(result);
+
+        let mtime = self
+            .get_file_info(from)?
+            .get("st_mtime")
+            .and_then(|v| v.parse::<u64>().ok());
+
+        let source = self.open(from, AfcFileMode::ReadOnly)?;
+        let destination = self.open(to, AfcFileMode::WriteOnly)?;
+        loop {
+            let chunk = match self.file_read(source.handle(), COPY_CHUNK_SIZE) {
+                Ok(chunk) => chunk,
+                Err(AfcError::EndOfData) => break,
+                Err(e) => return Err(e),
+            };
+            if chunk.is_empty() {
+                break;
+            }
+            self.file_write(destination.handle(), chunk)?;
+        }
+        destination.close()?;
+        source.close()?;
+
+        if let Some(mtime) = mtime {
+            self.set_file_time(to, mtime)?;
         }
         Ok(())
     }
 
-    /// Gets a specific value for a key on the device's connection
+    /// Hashes a file on the device with SHA-256, such as to check that an upload arrived intact.
+    /// The file is read in chunks, so only one chunk is held in memory at a time.
     /// # Arguments
-    /// * `key` - The key of which to look up
+    /// * `path` - The file to hash
     /// # Returns
-    /// The info value of the lookup
+    /// The digest of the file's contents
     ///
     /// ***Verified:*** False
-    pub fn get_device_info_key(&self, key: impl Into<String>) -> Result<String, AfcError> {
-        let key_c_string = CString::new(key.into()).unwrap();
-        let mut value_ptr = unsafe { std::mem::zeroed() };
-        let result = unsafe {
-            unsafe_bindings::afc_get_device_info_key(
-                self.pointer,
-                key_c_string.as_ptr(),
-                &mut value_ptr,
-            )
-        }
-        .into();
-        if result != AfcError::Success {
-            return Err(result);
-        }
-        Ok(unsafe { CStr::from_ptr(value_ptr) }
+    #[cfg(feature = "openssl")]
+    pub fn sha256(&self, path: impl Into<String>) -> Result<[u8; 32], AfcError> {
+        let digest = self.hash_file(path, MessageDigest::sha256())?;
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&digest);
+        Ok(hash)
+    }
+
+    /// Hashes a file on the device with MD5, for comparing against checksums from older tools.
+    /// The file is read in chunks, so only one chunk is held in memory at a time.
+    /// # Arguments
+    /// * `path` - The file to hash
+    /// # Returns
+    /// The digest of the file's contents
+    ///
+    /// ***Verified:*** False
+    #[cfg(feature = "openssl")]
+    pub fn md5(&self, path: impl Into<String>) -> Result<[u8; 16], AfcError> {
+        let digest = self.hash_file(path, MessageDigest::md5())?;
+        let mut hash = [0; 16];
+        hash.copy_from_slice(&digest);
+        Ok(hash)
+    }
+
+    #[cfg(feature = "openssl")]
+    fn hash_file(
+        &self,
+        path: impl Into<String>,
+        digest: MessageDigest,
+    ) -> Result<Vec<u8>, AfcError> {
+        let file = self.open(path, AfcFileMode::ReadOnly)?;
+        let hash = hash_chunks(digest, || {
+            match self.file_read(file.handle(), COPY_CHUNK_SIZE) {
+                Err(AfcError::EndOfData) => Ok(Vec::new()),
+                result => result,
+            }
+        })?;
+        file.close()?;
+        Ok(hash)
+    }
+
+    /// Renames or moves a file on the device.
+    /// Some iOS versions refuse to rename across directories with `OpNotSupported`, so if that happens
+    /// the file is copied to the new path and the old one removed, keeping its modification time.
+    /// Any other error, such as `PermDenied`, is returned as is.
+    /// Unlike a real rename the fallback isn't atomic: if it's interrupted both paths may exist,
+    /// or the new path may be incomplete. Directories are never moved by the fallback.
+    /// # Arguments
+    /// * `from` - The file to rename
+    /// * `to` - Its new path, overwritten if it exists
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), AfcError> {
+        let error = match self.rename_path(from, to) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if !rename_falls_back(error, from, to) {
+            return Err(error);
+        }
+
+        let is_file = self
+            .get_file_info(from)?
+            .get("st_ifmt")
+            .map(|t| t == "S_IFREG")
+            .unwrap_or(false);
+        if !is_file {
+            return Err(error);
+        }
+
+        warn!(
+            "Renaming {} to {} failed with {:?}, copying it instead",
+            from, to, error
+        );
+        self.copy(from, to)?;
+        self.remove_path(from)
+    }
+
+    /// Removes a path and the files inside it
+    /// # Arguments
+    /// * `path` - The path to the folder being destroyed
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn remove_path_and_contents(&self, path: impl Into<String>) -> Result<(), AfcError> {
+        let path_c_string = CString::new(path.into()).unwrap();
+        let result = unsafe {
+            unsafe_bindings::afc_remove_path_and_contents(
+                *self.pointer.lock(),
+                path_c_string.as_ptr(),
+            )
+        }
+        .into();
+        if result != AfcError::Success {
+            return Err(result);
+        }
+        Ok(())
+    }
+
+    /// Uploads a local directory and everything in it to the device.
+    /// Directories are created on the device as needed and existing files are overwritten.
+    /// Files and directories keep their modification times from the host.
+    /// # Arguments
+    /// * `local` - The directory on the host to upload
+    /// * `remote` - The path on the device to upload it to
+    /// * `symlinks` - Whether to recreate symlinks on the device or upload what they point to
+    /// * `progress` - Called after each file with its local path and the number of bytes uploaded
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn upload_dir(
+        &self,
+        local: &Path,
+        remote: &str,
+        symlinks: SymlinkMode,
+        mut progress: impl FnMut(&Path, u64),
+    ) -> Result<(), AfcError> {
+        self.upload_dir_inner(local, remote, symlinks, &mut progress)
+    }
+
+    fn upload_dir_inner(
+        &self,
+        local: &Path,
+        remote: &str,
+        symlinks: SymlinkMode,
+        progress: &mut dyn FnMut(&Path, u64),
+    ) -> Result<(), AfcError> {
+        self.create_dir_all(remote)?;
+        let entries = fs::read_dir(local).map_err(|e| local_io_error(local, e))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| local_io_error(local, e))?;
+            let local_path = entry.path();
+            let remote_path = join_path(remote, &entry.file_name().to_string_lossy());
+            let metadata =
+                fs::symlink_metadata(&local_path).map_err(|e| local_io_error(&local_path, e))?;
+
+            if metadata.file_type().is_symlink() && symlinks == SymlinkMode::Recreate {
+                let target =
+                    fs::read_link(&local_path).map_err(|e| local_io_error(&local_path, e))?;
+                self.make_link(
+                    target.to_string_lossy(),
+                    LinkType::SymbolicLink,
+                    remote_path,
+                )?;
+                continue;
+            }
+
+            let is_dir = if metadata.file_type().is_symlink() {
+                local_path.is_dir()
+            } else {
+                metadata.is_dir()
+            };
+            if is_dir {
+                self.upload_dir_inner(&local_path, &remote_path, symlinks, progress)?;
+                continue;
+            }
+
+            let mut file =
+                fs::File::open(&local_path).map_err(|e| local_io_error(&local_path, e))?;
+            let mut remote_file = self.open(remote_path.clone(), AfcFileMode::WriteOnly)?;
+            let written = std::io::copy(&mut file, &mut remote_file)
+                .map_err(|e| local_io_error(&local_path, e))?;
+            remote_file.close()?;
+            let modified = file
+                .metadata()
+                .and_then(|m| m.modified())
+                .map_err(|e| local_io_error(&local_path, e))?;
+            self.set_file_time(remote_path, afc_time(modified))?;
+            progress(&local_path, written);
+        }
+
+        // Done last, since adding entries to the directory updates its modification time
+        let modified = fs::metadata(local)
+            .and_then(|m| m.modified())
+            .map_err(|e| local_io_error(local, e))?;
+        self.set_file_time(remote, afc_time(modified))
+    }
+
+    /// Downloads a directory on the device and everything in it to the host.
+    /// Directories are created on the host as needed and existing files are overwritten.
+    /// Files and directories keep their modification times from the device where the host allows it.
+    /// Following symlinks that point back up the tree will loop until the path gets too long.
+    /// # Arguments
+    /// * `remote` - The directory on the device to download
+    /// * `local` - The path on the host to download it to
+    /// * `symlinks` - Whether to recreate symlinks on the host or download what they point to.
+    ///   Symlinks are always followed on platforms other than Unix.
+    /// * `progress` - Called after each file with its local path and the number of bytes downloaded
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn download_dir(
+        &self,
+        remote: &str,
+        local: &Path,
+        symlinks: SymlinkMode,
+        mut progress: impl FnMut(&Path, u64),
+    ) -> Result<(), AfcError> {
+        let mtime = self
+            .get_file_info(remote)?
+            .get("st_mtime")
+            .and_then(|v| parse_afc_time(v));
+        self.download_dir_inner(remote, local, mtime, symlinks, &mut progress)
+    }
+
+    /// Uploads a local directory like `upload_dir`, reporting the progress of the whole transfer.
+    /// The directory is walked first to find the total size, so a rate and time remaining can be shown.
+    /// # Arguments
+    /// * `local` - The directory on the host to upload
+    /// * `remote` - The path on the device to upload it to
+    /// * `symlinks` - Whether to recreate symlinks on the device or upload what they point to
+    /// * `progress` - Called after each file with its local path and the progress so far
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn upload_dir_with_transfer(
+        &self,
+        local: &Path,
+        remote: &str,
+        symlinks: SymlinkMode,
+        mut progress: impl FnMut(&Path, Transfer),
+    ) -> Result<(), AfcError> {
+        let total = local_dir_size(local, symlinks)?;
+        let mut transfer = Transfer::start(Some(total));
+        self.upload_dir(local, remote, symlinks, |path, bytes| {
+            transfer.advance(bytes);
+            progress(path, transfer)
+        })
+    }
+
+    /// Downloads a directory on the device like `download_dir`, reporting the progress of the whole transfer.
+    /// Finding the total size would take a round trip per directory before starting, so it's left unknown.
+    /// # Arguments
+    /// * `remote` - The directory on the device to download
+    /// * `local` - The path on the host to download it to
+    /// * `symlinks` - Whether to recreate symlinks on the host or download what they point to
+    /// * `progress` - Called after each file with its local path and the progress so far
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn download_dir_with_transfer(
+        &self,
+        remote: &str,
+        local: &Path,
+        symlinks: SymlinkMode,
+        mut progress: impl FnMut(&Path, Transfer),
+    ) -> Result<(), AfcError> {
+        let mut transfer = Transfer::start(None);
+        self.download_dir(remote, local, symlinks, |path, bytes| {
+            transfer.advance(bytes);
+            progress(path, transfer)
+        })
+    }
+
+    /// Uploads a file to the device a chunk at a time, reporting the progress after each chunk
+    /// so that large files can be followed and cancelled. The file keeps its modification time from the host.
+    /// # Arguments
+    /// * `local` - The file on the host to upload
+    /// * `remote` - The path on the device to upload it to, overwritten if it exists
+    /// * `progress` - Called after each chunk with the progress so far. Returning `ControlFlow::Break`
+    ///   stops the upload, closes the file on the device and removes the partial upload
+    /// # Returns
+    /// The number of bytes uploaded, or `OpInterrupted` if the upload was cancelled
+    ///
+    /// ***Verified:*** False
+    pub fn upload_file(
+        &self,
+        local: &Path,
+        remote: &str,
+        mut progress: impl FnMut(Transfer) -> ControlFlow<()>,
+    ) -> Result<u64, AfcError> {
+        let mut file = fs::File::open(local).map_err(|e| local_io_error(local, e))?;
+        let metadata = file.metadata().map_err(|e| local_io_error(local, e))?;
+        let mut transfer = Transfer::start(Some(metadata.len()));
+
+        let remote_file = self.open(remote, AfcFileMode::WriteOnly)?;
+        let mut buffer = vec![0; COPY_CHUNK_SIZE as usize];
+        loop {
+            let read = file
+                .read(&mut buffer)
+                .map_err(|e| local_io_error(local, e))?;
+            if read == 0 {
+                break;
+            }
+            let mut written = 0;
+            while written < read {
+                match self.file_write_from(remote_file.handle(), &buffer[written..read])? {
+                    0 => return Err(AfcError::WriteError),
+                    n => written += n,
+                }
+            }
+            transfer.advance(read as u64);
+            if progress(transfer).is_break() {
+                remote_file.close()?;
+                self.remove_path(remote)?;
+                return Err(AfcError::OpInterrupted);
+            }
+        }
+        remote_file.close()?;
+
+        let modified = metadata.modified().map_err(|e| local_io_error(local, e))?;
+        self.set_file_time(remote, afc_time(modified))?;
+        Ok(transfer.bytes_done)
+    }
+
+    /// Downloads a file from the device a chunk at a time, reporting the progress after each chunk
+    /// so that large files can be followed and cancelled. The file keeps its modification time from the device.
+    /// # Arguments
+    /// * `remote` - The file on the device to download
+    /// * `local` - The path on the host to download it to, overwritten if it exists
+    /// * `progress` - Called after each chunk with the progress so far. Returning `ControlFlow::Break`
+    ///   stops the download, closes the file on the device and removes the partial download
+    /// # Returns
+    /// The number of bytes downloaded, or `OpInterrupted` if the download was cancelled
+    ///
+    /// ***Verified:*** False
+    pub fn download_file(
+        &self,
+        remote: &str,
+        local: &Path,
+        mut progress: impl FnMut(Transfer) -> ControlFlow<()>,
+    ) -> Result<u64, AfcError> {
+        let info = self.get_file_info(remote)?;
+        let mtime = info.get("st_mtime").and_then(|v| parse_afc_time(v));
+        let mut transfer = Transfer::start(info.get("st_size").and_then(|v| v.parse().ok()));
+
+        let remote_file = self.open(remote, AfcFileMode::ReadOnly)?;
+        let mut file = fs::File::create(local).map_err(|e| local_io_error(local, e))?;
+        let mut buffer = vec![0; COPY_CHUNK_SIZE as usize];
+        loop {
+            let read = match self.file_read_into(remote_file.handle(), &mut buffer) {
+                Ok(read) => read,
+                Err(AfcError::EndOfData) => 0,
+                Err(e) => return Err(e),
+            };
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buffer[..read])
+                .map_err(|e| local_io_error(local, e))?;
+            transfer.advance(read as u64);
+            if progress(transfer).is_break() {
+                remote_file.close()?;
+                drop(file);
+                fs::remove_file(local).map_err(|e| local_io_error(local, e))?;
+                return Err(AfcError::OpInterrupted);
+            }
+        }
+        remote_file.close()?;
+
+        if let Some(mtime) = mtime {
+            file.set_modified(mtime)
+                .map_err(|e| local_io_error(local, e))?;
+        }
+        Ok(transfer.bytes_done)
+    }
+
+    fn download_dir_inner(
+        &self,
+        remote: &str,
+        local: &Path,
+        mtime: Option<SystemTime>,
+        symlinks: SymlinkMode,
+        progress: &mut dyn FnMut(&Path, u64),
+    ) -> Result<(), AfcError> {
+        fs::create_dir_all(local).map_err(|e| local_io_error(local, e))?;
+
+        for entry in self.list_dir_detailed(remote)? {
+            let local_path = local.join(&entry.name);
+            let mut kind = entry.info.ifmt;
+
+            if kind == AfcFileType::Symlink {
+                let target = entry.info.link_target.clone().unwrap_or_default();
+
+                #[cfg(unix)]
+                if symlinks == SymlinkMode::Recreate {
+                    if local_path.symlink_metadata().is_ok() {
+                        fs::remove_file(&local_path).map_err(|e| local_io_error(&local_path, e))?;
+                    }
+                    std::os::unix::fs::symlink(&target, &local_path)
+                        .map_err(|e| local_io_error(&local_path, e))?;
+                    continue;
+                }
+
+                // Relative targets are relative to the directory the link is in
+                let target_path = if target.starts_with('/') {
+                    target
+                } else {
+                    join_path(remote, &target)
+                };
+                kind = match self.metadata(target_path) {
+                    Ok(info) => info.ifmt,
+                    Err(AfcError::ObjectNotFound) => {
+                        warn!("Skipping broken symlink {}", entry.path);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+            }
+
+            match kind {
+                AfcFileType::Directory => {
+                    self.download_dir_inner(
+                        &entry.path,
+                        &local_path,
+                        Some(entry.info.mtime),
+                        symlinks,
+                        progress,
+                    )?;
+                }
+                AfcFileType::File => {
+                    let mut remote_file = self.open(entry.path, AfcFileMode::ReadOnly)?;
+                    let mut file = fs::File::create(&local_path)
+                        .map_err(|e| local_io_error(&local_path, e))?;
+                    let read = std::io::copy(&mut remote_file, &mut file)
+                        .map_err(|e| local_io_error(&local_path, e))?;
+                    file.set_modified(entry.info.mtime)
+                        .map_err(|e| local_io_error(&local_path, e))?;
+                    progress(&local_path, read);
+                }
+                _ => warn!("Skipping {}, it isn't a file or directory", entry.path),
+            }
+        }
+
+        // Done last, since adding entries to the directory updates its modification time.
+        // Not every platform can open a directory to set it, so failing here isn't fatal.
+        if let Some(mtime) = mtime {
+            if let Err(e) = fs::File::open(local).and_then(|dir| dir.set_modified(mtime)) {
+                warn!(
+                    "Unable to set the modification time of {}: {}",
+                    local.display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets a specific value for a key on the device's connection
+    /// # Arguments
+    /// * `key` - The key of which to look up
+    /// # Returns
+    /// The info value of the lookup
+    ///
+    /// ***Verified:*** False
+    pub fn get_device_info_key(&self, key: impl Into<String>) -> Result<String, AfcError> {
+        let key_c_string = CString::new(key.into()).unwrap();
+        let mut value_ptr = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            unsafe_bindings::afc_get_device_info_key(
+                *self.pointer.lock(),
+                key_c_string.as_ptr(),
+                &mut value_ptr,
+            )
+        }
+        .into();
+        if result != AfcError::Success {
+            return Err(result);
+        }
+        Ok(unsafe { CStr::from_ptr(value_ptr) }
             .to_string_lossy()
             .into_owned())
     }
+
+    /// Scopes the client to a directory, such as an app's `/Documents`, so paths can be given relative to it.
+    /// The scope shares this client's connection.
+    /// # Arguments
+    /// * `root` - The directory every path is resolved under
+    /// # Returns
+    /// The scoped client
+    ///
+    /// ***Verified:*** False
+    pub fn with_root(&self, root: &str) -> ScopedAfc<'_> {
+        ScopedAfc {
+            client: self,
+            // Like on the device, `..` at `/` stays at `/`
+            root: normalize_path(root.split('/'), true).unwrap_or_default(),
+        }
+    }
+
+    /// Closes the AFC connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), AfcError> {
+        let result = self.pointer.close().into();
+        if result != AfcError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<HouseArrest<'a>> for AfcClient<'a> {
+    type Error = AfcError;
+
+    fn try_from(house_arrest: HouseArrest<'a>) -> Result<Self, Self::Error> {
+        let mut to_fill = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            unsafe_bindings::afc_client_new_from_house_arrest_client(
+                *house_arrest.pointer.lock(),
+                &mut to_fill,
+            )
+        }
+        .into();
+        if result != AfcError::Success {
+            return Err(result);
+        }
+        Ok(Self {
+            pointer: ClientHandle::new(to_fill),
+            stats: StatsCounter::default(),
+            service: None,
+            phantom: std::marker::PhantomData,
+        })
+    }
+}
+
+/// An open file on the device.
+/// Reads, writes and seeks go straight to the device, so wrap it in a `BufReader` or `BufWriter` for small operations.
+///
+/// AFC has no flush or sync command, so closing the file is the point where the data is committed.
+/// The file is closed when dropped; use `close` to find out if that failed.
+#[derive(Debug)]
+pub struct AfcFile<'a> {
+    client: &'a AfcClient<'a>,
+    /// Taken by `close`, so `Drop` doesn't close the handle again
+    handle: Option<u64>,
+    path: String,
+}
+
+impl AfcFile<'_> {
+    /// Gets the AFC handle of the file, for use with the `file_*` methods of `AfcClient`
+    pub fn handle(&self) -> u64 {
+        // `close` takes the file by value, so nothing can ask for the handle afterwards
+        self.handle.expect("file used after it was closed")
+    }
+
+    /// Gets the path the file was opened with
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Truncates or extends the file, like `std::fs::File::set_len`.
+    /// The position in the file doesn't change
+    /// # Arguments
+    /// * `length` - The new length of the file
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn set_len(&self, length: u64) -> Result<(), AfcError> {
+        self.client.file_truncate(self.handle(), length)
+    }
+
+    /// Locks or unlocks the file, see `AfcClient::file_lock`
+    /// # Arguments
+    /// * `lock_type` - The type of lock to take, or `Un` to release it
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn lock(&self, lock_type: AfcLockOp) -> Result<(), AfcError> {
+        self.client.file_lock(self.handle(), lock_type)
+    }
+
+    /// Closes the file, committing its contents on the device
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), AfcError> {
+        let client = self.client;
+        close_handle(&mut self.handle, |handle| client.file_close(handle))
+    }
+}
+
+/// Closes a file's handle if it's still open, shared by `AfcFile::close` and `Drop`
+/// # Arguments
+/// * `handle` - The handle, which is taken so it's only ever closed once
+/// * `close` - Closes the handle on the device
+/// # Returns
+/// *none*
+fn close_handle(
+    handle: &mut Option<u64>,
+    close: impl FnOnce(u64) -> Result<(), AfcError>,
+) -> Result<(), AfcError> {
+    match handle.take() {
+        Some(handle) => close(handle),
+        None => Ok(()),
+    }
+}
+
+impl Read for AfcFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.client.file_read_into(self.handle(), buf) {
+            Ok(read) => Ok(read),
+            Err(AfcError::EndOfData) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Write for AfcFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.client.file_write_from(self.handle(), buf)?)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // Every write is already sent to the device, and AFC has no sync command
+        Ok(())
+    }
+}
+
+impl Seek for AfcFile<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (offset, whence) = seek_args(pos)?;
+        self.client.file_seek(self.handle(), offset, whence)?;
+        self.stream_position()
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.client.file_tell(self.handle())?)
+    }
+}
+
+/// Hashes the chunks `next_chunk` returns until it returns an empty one
+#[cfg(feature = "openssl")]
+fn hash_chunks(
+    digest: MessageDigest,
+    mut next_chunk: impl FnMut() -> Result<Vec<u8>, AfcError>,
+) -> Result<Vec<u8>, AfcError> {
+    let hash_error = |e: openssl::error::ErrorStack| {
+        warn!("Unable to hash file: {}", e);
+        AfcError::InternalError
+    };
+    let mut hasher = Hasher::new(digest).map_err(hash_error)?;
+    loop {
+        let chunk = next_chunk()?;
+        if chunk.is_empty() {
+            break;
+        }
+        hasher.update(&chunk).map_err(hash_error)?;
+    }
+    Ok(hasher.finish().map_err(hash_error)?.to_vec())
+}
+
+/// The options `create_new` opens files with
+fn create_new_options() -> AfcOpenOptions {
+    *AfcOpenOptions::new().write(true).create_new(true)
+}
+
+/// The options `set_file_size` opens files with, which never truncate or append on their own
+fn resize_options(create: bool) -> AfcOpenOptions {
+    *AfcOpenOptions::new().write(true).create(create)
+}
+
+/// Converts a `SeekFrom` to the offset and whence `file_seek` takes
+fn seek_args(pos: SeekFrom) -> Result<(i64, u8), AfcError> {
+    // These match SEEK_SET, SEEK_CUR and SEEK_END, which AFC uses for whence
+    match pos {
+        SeekFrom::Start(offset) => match i64::try_from(offset) {
+            Ok(offset) => Ok((offset, 0)),
+            Err(_) => Err(AfcError::InvalidArg),
+        },
+        SeekFrom::Current(offset) => Ok((offset, 1)),
+        SeekFrom::End(offset) => Ok((offset, 2)),
+    }
+}
+
+impl Drop for AfcFile<'_> {
+    fn drop(&mut self) {
+        let client = self.client;
+        if let Err(e) = close_handle(&mut self.handle, |handle| client.file_close(handle)) {
+            warn!("Failed to close AFC file {}: {:?}", self.path, e);
+        }
+    }
+}
+
+/// Options for opening a file on the device, like `std::fs::OpenOptions`.
+/// Pass them to `AfcClient::open_with`.
+///
+/// AFC only has the modes of C's `fopen`, so the combinations it lacks are made by checking
+/// whether the file exists before opening it. Another client creating or removing the file
+/// between the two requests isn't detected.
+///
+/// ```ignore
+/// let mut file = afc.open_with("/Downloads/log.txt", AfcOpenOptions::new().append(true).create(true))?;
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AfcOpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+impl AfcOpenOptions {
+    /// Creates options with everything turned off
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the file can be read
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Sets whether the file can be written
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Sets whether writes go to the end of the file. This implies `write`
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Sets whether the file is emptied as it's opened. Needs `write`
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Sets whether the file is created if it doesn't exist. Needs `write` or `append`
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets whether the file must be created, failing with `ObjectExists` if it's already there.
+    /// This overrides `create` and `truncate`. Needs `write` or `append`
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Picks the AFC mode for the options, checking whether the file exists when the mode depends on it
+    fn mode(
+        &self,
+        exists: impl FnOnce() -> Result<bool, AfcError>,
+    ) -> Result<AfcFileMode, AfcError> {
+        let write = self.write || self.append;
+        if !self.read && !write {
+            warn!("A file has to be opened for reading or writing");
+            return Err(AfcError::InvalidArg);
+        }
+        if !write && (self.truncate || self.create || self.create_new) {
+            warn!("Only files opened for writing can be truncated or created");
+            return Err(AfcError::InvalidArg);
+        }
+        if self.append && self.truncate && !self.create_new {
+            warn!("A file can't be opened for both appending and truncating");
+            return Err(AfcError::InvalidArg);
+        }
+        if !write {
+            return Ok(AfcFileMode::ReadOnly);
+        }
+
+        // The append and write modes create the file, and write also truncates it
+        let creating_mode = match (self.append, self.read) {
+            (true, true) => AfcFileMode::ReadAppend,
+            (true, false) => AfcFileMode::Append,
+            (false, true) => AfcFileMode::WriteRead,
+            (false, false) => AfcFileMode::WriteOnly,
+        };
+        let exists = exists()?;
+        if self.create_new {
+            if exists {
+                return Err(AfcError::ObjectExists);
+            }
+            return Ok(creating_mode);
+        }
+        if !exists && !self.create {
+            return Err(AfcError::ObjectNotFound);
+        }
+        // Read-write is the only mode that neither creates nor truncates, so it's kept for existing files
+        if exists && !self.append && !self.truncate {
+            return Ok(AfcFileMode::ReadWrite);
+        }
+        Ok(creating_mode)
+    }
+}
+
+/// The entries of a directory on the device, made with `AfcClient::read_dir_iter`.
+/// The listing is held in the buffer libimobiledevice returned until the iterator is dropped.
+#[derive(Debug)]
+pub struct AfcReadDir<'a> {
+    client: &'a AfcClient<'a>,
+    /// The directory to list, taken when the listing is fetched
+    directory: Option<String>,
+    list: *mut *mut c_char,
+    next: usize,
+}
+
+impl AfcReadDir<'_> {
+    fn fetch(&mut self, directory: String) -> Result<(), AfcError> {
+        let directory_c_string = CString::new(directory).unwrap();
+        if directory_c_string.is_empty() {
+            warn!("Cannot use empty string as directory");
+            return Err(AfcError::InvalidArg);
+        }
+
+        let result = unsafe {
+            unsafe_bindings::afc_read_directory(
+                *self.client.pointer.lock(),
+                directory_c_string.as_ptr(),
+                &mut self.list,
+            )
+        }
+        .into();
+        if result != AfcError::Success {
+            return Err(result);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for AfcReadDir<'_> {
+    type Item = Result<String, AfcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(directory) = self.directory.take() {
+            if let Err(e) = self.fetch(directory) {
+                return Some(Err(e));
+            }
+        }
+        if self.list.is_null() {
+            return None;
+        }
+
+        // The list ends with a null entry
+        let entry = unsafe { *self.list.add(self.next) };
+        if entry.is_null() {
+            return None;
+        }
+        self.next += 1;
+        Some(Ok(unsafe { CStr::from_ptr(entry) }
+            .to_string_lossy()
+            .into_owned()))
+    }
+}
+
+impl Drop for AfcReadDir<'_> {
+    fn drop(&mut self) {
+        if !self.list.is_null() {
+            unsafe { unsafe_bindings::afc_dictionary_free(self.list) };
+        }
+    }
+}
+
+/// A depth first walk of a directory tree on the device, made with `AfcClient::walk`
+#[derive(Debug)]
+pub struct AfcWalk<'a> {
+    client: &'a AfcClient<'a>,
+    /// The path to start at, taken when the walk starts
+    root: Option<String>,
+    min_depth: usize,
+    max_depth: usize,
+    follow_symlinks: bool,
+    /// The directories being walked, innermost last
+    stack: Vec<WalkFrame<'a>>,
+}
+
+#[derive(Debug)]
+struct WalkFrame<'a> {
+    /// The path of the directory as walked
+    path: String,
+    /// The path of the directory with followed symlinks resolved, for spotting loops
+    real_path: String,
+    /// The depth of the entries in the directory
+    depth: usize,
+    entries: AfcReadDir<'a>,
+}
+
+impl<'a> AfcWalk<'a> {
+    /// Skips entries shallower than `depth`. They are still walked through.
+    /// # Arguments
+    /// * `depth` - The smallest depth to yield, where the starting path is 0
+    /// # Returns
+    /// The walk
+    ///
+    /// ***Verified:*** False
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Stops the walk from going deeper than `depth`
+    /// # Arguments
+    /// * `depth` - The largest depth to yield, where the starting path is 0
+    /// # Returns
+    /// The walk
+    ///
+    /// ***Verified:*** False
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets whether symlinks to directories are walked into.
+    /// When following, an entry's size, type and modification time are those of what the link points to,
+    /// and a link that points back up the tree is yielded but not walked into again.
+    /// Broken links are yielded as they are.
+    /// # Arguments
+    /// * `follow` - Whether to follow symlinks, off by default
+    /// # Returns
+    /// The walk
+    ///
+    /// ***Verified:*** False
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Looks up an entry, returning it with the path to walk into if it's a directory
+    fn visit(
+        &self,
+        path: String,
+        mut real_path: String,
+        depth: usize,
+    ) -> Result<(AfcDirEntry, Option<String>), AfcError> {
+        let info = self.client.metadata(path.clone())?;
+        let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+        let mut entry = AfcDirEntry {
+            name,
+            path,
+            depth,
+            info,
+        };
+
+        if let Some(target) = entry
+            .info
+            .link_target
+            .clone()
+            .filter(|_| self.follow_symlinks)
+        {
+            // Relative targets are relative to the directory the link is in
+            let parent =
+                real_path
+                    .rsplit_once('/')
+                    .map_or("/", |(dir, _)| if dir.is_empty() { "/" } else { dir });
+            let target_path = if target.starts_with('/') {
+                normalize_path(target.split('/'), true)
+            } else {
+                normalize_path(parent.split('/').chain(target.split('/')), true)
+            }
+            .unwrap_or_default();
+
+            match self.client.metadata(target_path.clone()) {
+                Ok(target_info) => {
+                    // The link's target is kept so the entry still reads as a symlink
+                    entry.info = AfcFileInfo {
+                        link_target: entry.info.link_target.take(),
+                        ..target_info
+                    };
+                    real_path = target_path;
+                }
+                Err(AfcError::ObjectNotFound) => return Ok((entry, None)),
+                Err(e) => return Err(e),
+            }
+
+            let looped = self.stack.iter().any(|frame| {
+                frame.real_path == real_path
+                    || real_path == "/"
+                    || frame.real_path.starts_with(&format!("{}/", real_path))
+            });
+            if entry.info.is_dir() && looped {
+                warn!(
+                    "Not following {}, it points back up the tree to {}",
+                    entry.path, real_path
+                );
+                return Ok((entry, None));
+            }
+        }
+
+        if entry.info.is_dir() && depth < self.max_depth {
+            Ok((entry, Some(real_path)))
+        } else {
+            Ok((entry, None))
+        }
+    }
+}
+
+impl Iterator for AfcWalk<'_> {
+    type Item = Result<AfcDirEntry, AfcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, real_path, depth) = if let Some(root) = self.root.take() {
+                let real_path = normalize_path(root.split('/'), true).unwrap_or_default();
+                (root, real_path, 0)
+            } else {
+                let frame = self.stack.last_mut()?;
+                match frame.entries.next() {
+                    Some(Ok(name)) if name == "." || name == ".." => continue,
+                    Some(Ok(name)) => (
+                        join_path(&frame.path, &name),
+                        join_path(&frame.real_path, &name),
+                        frame.depth,
+                    ),
+                    Some(Err(e)) => {
+                        self.stack.pop();
+                        return Some(Err(e));
+                    }
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                }
+            };
+
+            let (entry, walk_into) = match self.visit(path, real_path, depth) {
+                Ok(visited) => visited,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(real_path) = walk_into {
+                self.stack.push(WalkFrame {
+                    path: entry.path.clone(),
+                    real_path,
+                    depth: depth + 1,
+                    entries: self.client.read_dir_iter(entry.path.clone()),
+                });
+            }
+            if depth >= self.min_depth {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+/// An `AfcClient` with every path resolved under a root directory, made with `AfcClient::with_root`.
+/// Paths are relative to the root whether or not they start with `/`, and any path that
+/// would leave the root through `..` is rejected with `InvalidArg` before reaching the device.
+///
+/// Only the paths passed in are checked. Symlinks on the device are followed as usual,
+/// so a link inside the root can still point outside of it.
+#[derive(Debug, Clone)]
+pub struct ScopedAfc<'a> {
+    client: &'a AfcClient<'a>,
+    root: String,
+}
+
+impl<'a> ScopedAfc<'a> {
+    /// Gets the client the scope runs on, for the handle based `file_*` functions
+    pub fn client(&self) -> &'a AfcClient<'a> {
+        self.client
+    }
+
+    /// Gets the root directory, normalized to an absolute path without a trailing `/`
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Resolves a path in the scope to the full path on the device
+    /// # Arguments
+    /// * `path` - The path relative to the root
+    /// # Returns
+    /// The full path, or `InvalidArg` if the path escapes the root
+    ///
+    /// ***Verified:*** False
+    pub fn resolve(&self, path: impl Into<String>) -> Result<String, AfcError> {
+        let path = path.into();
+        match scoped_path(&self.root, &path) {
+            Some(resolved) => Ok(resolved),
+            None => {
+                warn!("Refusing to use {} outside of {}", path, self.root);
+                Err(AfcError::InvalidArg)
+            }
+        }
+    }
+
+    /// Reads a directory in the scope, see `AfcClient::read_directory`
+    ///
+    /// ***Verified:*** False
+    pub fn read_directory(&self, directory: impl Into<String>) -> Result<Vec<String>, AfcError> {
+        self.client.read_directory(self.resolve(directory)?)
+    }
+
+    /// Lists a directory in the scope with the details of each entry, see `AfcClient::list_dir_detailed`.
+    /// The `path` of each entry is the full path on the device.
+    ///
+    /// ***Verified:*** False
+    pub fn list_dir_detailed(&self, path: impl Into<String>) -> Result<Vec<AfcDirEntry>, AfcError> {
+        self.client.list_dir_detailed(self.resolve(path)?)
+    }
+
+    /// Walks a directory tree in the scope, see `AfcClient::walk`.
+    /// The `path` of each entry is the full path on the device, and followed symlinks can leave the scope.
+    ///
+    /// ***Verified:*** False
+    pub fn walk(&self, path: impl Into<String>) -> Result<AfcWalk<'a>, AfcError> {
+        Ok(self.client.walk(self.resolve(path)?))
+    }
+
+    /// Gets information about a file in the scope, see `AfcClient::get_file_info`
+    ///
+    /// ***Verified:*** False
+    pub fn get_file_info(
+        &self,
+        path: impl Into<String>,
+    ) -> Result<HashMap<String, String>, AfcError> {
+        self.client.get_file_info(self.resolve(path)?)
+    }
+
+    /// Gets the typed information about a file in the scope, see `AfcClient::metadata`
+    ///
+    /// ***Verified:*** False
+    pub fn metadata(&self, path: impl Into<String>) -> Result<AfcFileInfo, AfcError> {
+        self.client.metadata(self.resolve(path)?)
+    }
+
+    /// Gets the target of a symlink in the scope, see `AfcClient::read_link`.
+    /// The target is returned as stored and isn't checked against the root.
+    ///
+    /// ***Verified:*** False
+    pub fn read_link(&self, path: impl Into<String>) -> Result<Option<String>, AfcError> {
+        self.client.read_link(self.resolve(path)?)
+    }
+
+    /// Opens a file in the scope and returns a handle to it, see `AfcClient::file_open`
+    ///
+    /// ***Verified:*** False
+    pub fn file_open(&self, path: impl Into<String>, mode: AfcFileMode) -> Result<u64, AfcError> {
+        self.client.file_open(self.resolve(path)?, mode)
+    }
+
+    /// Opens a file in the scope for use with `std::io`, see `AfcClient::open`
+    ///
+    /// ***Verified:*** False
+    pub fn open(
+        &self,
+        path: impl Into<String>,
+        mode: AfcFileMode,
+    ) -> Result<AfcFile<'a>, AfcError> {
+        self.client.open(self.resolve(path)?, mode)
+    }
+
+    /// Opens a file in the scope with options like `std::fs::OpenOptions`, see `AfcClient::open_with`
+    ///
+    /// ***Verified:*** False
+    pub fn open_with(
+        &self,
+        path: impl Into<String>,
+        options: &AfcOpenOptions,
+    ) -> Result<AfcFile<'a>, AfcError> {
+        self.client.open_with(self.resolve(path)?, options)
+    }
+
+    /// Writes a file in the scope without leaving a partial file behind, see `AfcClient::write_atomic`
+    ///
+    /// ***Verified:*** False
+    pub fn write_atomic(&self, path: impl Into<String>, data: &[u8]) -> Result<(), AfcError> {
+        self.client.write_atomic(self.resolve(path)?, data)
+    }
+
+    /// Removes a path in the scope, see `AfcClient::remove_path`
+    ///
+    /// ***Verified:*** False
+    pub fn remove_path(&self, path: impl Into<String>) -> Result<(), AfcError> {
+        self.client.remove_path(self.resolve(path)?)
+    }
+
+    /// Removes a path in the scope and the files inside it, see `AfcClient::remove_path_and_contents`
+    ///
+    /// ***Verified:*** False
+    pub fn remove_path_and_contents(&self, path: impl Into<String>) -> Result<(), AfcError> {
+        self.client.remove_path_and_contents(self.resolve(path)?)
+    }
+
+    /// Renames a path in the scope, see `AfcClient::rename_path`
+    ///
+    /// ***Verified:*** False
+    pub fn rename_path(
+        &self,
+        old_path: impl Into<String>,
+        new_path: impl Into<String>,
+    ) -> Result<(), AfcError> {
+        self.client
+            .rename_path(self.resolve(old_path)?, self.resolve(new_path)?)
+    }
+
+    /// Renames a file in the scope, copying it if the device refuses, see `AfcClient::rename`
+    ///
+    /// ***Verified:*** False
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), AfcError> {
+        self.client.rename(&self.resolve(from)?, &self.resolve(to)?)
+    }
+
+    /// Copies a file within the scope, see `AfcClient::copy`
+    ///
+    /// ***Verified:*** False
+    pub fn copy(&self, from: &str, to: &str) -> Result<(), AfcError> {
+        self.client.copy(&self.resolve(from)?, &self.resolve(to)?)
+    }
+
+    /// Creates a directory in the scope, see `AfcClient::make_directory`
+    ///
+    /// ***Verified:*** False
+    pub fn make_directory(&self, path: impl Into<String>) -> Result<(), AfcError> {
+        self.client.make_directory(self.resolve(path)?)
+    }
+
+    /// Creates a directory in the scope along with any missing parents, see `AfcClient::create_dir_all`.
+    /// The root itself is created too if it doesn't exist, and the created paths are full paths on the device.
+    ///
+    /// ***Verified:*** False
+    pub fn create_dir_all(&self, path: impl Into<String>) -> Result<Vec<String>, AfcError> {
+        self.client.create_dir_all(self.resolve(path)?)
+    }
+
+    /// Truncates a file in the scope, see `AfcClient::truncate`
+    ///
+    /// ***Verified:*** False
+    pub fn truncate(&self, path: impl Into<String>, length: u64) -> Result<(), AfcError> {
+        self.client.truncate(self.resolve(path)?, length)
+    }
+
+    /// Sets the size of a file in the scope, see `AfcClient::set_file_size`
+    ///
+    /// ***Verified:*** False
+    pub fn set_file_size(
+        &self,
+        path: impl Into<String>,
+        size: u64,
+        create: bool,
+    ) -> Result<(), AfcError> {
+        self.client.set_file_size(self.resolve(path)?, size, create)
+    }
+
+    /// Sets the modification time of a file in the scope, see `AfcClient::set_file_time`
+    ///
+    /// ***Verified:*** False
+    pub fn set_file_time(&self, path: impl Into<String>, mtime: u64) -> Result<(), AfcError> {
+        self.client.set_file_time(self.resolve(path)?, mtime)
+    }
+
+    /// Uploads a local directory into the scope, see `AfcClient::upload_dir`
+    ///
+    /// ***Verified:*** False
+    pub fn upload_dir(
+        &self,
+        local: &Path,
+        remote: &str,
+        symlinks: SymlinkMode,
+        progress: impl FnMut(&Path, u64),
+    ) -> Result<(), AfcError> {
+        self.client
+            .upload_dir(local, &self.resolve(remote)?, symlinks, progress)
+    }
+
+    /// Downloads a directory in the scope to the host, see `AfcClient::download_dir`
+    ///
+    /// ***Verified:*** False
+    pub fn download_dir(
+        &self,
+        remote: &str,
+        local: &Path,
+        symlinks: SymlinkMode,
+        progress: impl FnMut(&Path, u64),
+    ) -> Result<(), AfcError> {
+        self.client
+            .download_dir(&self.resolve(remote)?, local, symlinks, progress)
+    }
+
+    /// Uploads a local file into the scope, see `AfcClient::upload_file`
+    ///
+    /// ***Verified:*** False
+    pub fn upload_file(
+        &self,
+        local: &Path,
+        remote: &str,
+        progress: impl FnMut(Transfer) -> ControlFlow<()>,
+    ) -> Result<u64, AfcError> {
+        self.client
+            .upload_file(local, &self.resolve(remote)?, progress)
+    }
+
+    /// Downloads a file in the scope to the host, see `AfcClient::download_file`
+    ///
+    /// ***Verified:*** False
+    pub fn download_file(
+        &self,
+        remote: &str,
+        local: &Path,
+        progress: impl FnMut(Transfer) -> ControlFlow<()>,
+    ) -> Result<u64, AfcError> {
+        self.client
+            .download_file(&self.resolve(remote)?, local, progress)
+    }
+
+    /// Uploads a local directory into the scope, see `AfcClient::upload_dir_with_transfer`
+    ///
+    /// ***Verified:*** False
+    pub fn upload_dir_with_transfer(
+        &self,
+        local: &Path,
+        remote: &str,
+        symlinks: SymlinkMode,
+        progress: impl FnMut(&Path, Transfer),
+    ) -> Result<(), AfcError> {
+        self.client
+            .upload_dir_with_transfer(local, &self.resolve(remote)?, symlinks, progress)
+    }
+
+    /// Downloads a directory in the scope to the host, see `AfcClient::download_dir_with_transfer`
+    ///
+    /// ***Verified:*** False
+    pub fn download_dir_with_transfer(
+        &self,
+        remote: &str,
+        local: &Path,
+        symlinks: SymlinkMode,
+        progress: impl FnMut(&Path, Transfer),
+    ) -> Result<(), AfcError> {
+        self.client
+            .download_dir_with_transfer(&self.resolve(remote)?, local, symlinks, progress)
+    }
 }
 
-impl TryFrom<HouseArrest<'_>> for AfcClient<'_> {
-    type Error = AfcError;
+/// The progress of an upload or download, passed to the `_with_transfer` callbacks,
+/// `upload_file` and `download_file`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    /// The bytes of file data copied so far
+    pub bytes_done: u64,
+    /// The bytes of file data in the whole transfer, if known
+    pub total: Option<u64>,
+    /// The time since the transfer started
+    pub elapsed: Duration,
+    started: Instant,
+}
 
-    fn try_from(house_arrest: HouseArrest<'_>) -> Result<Self, Self::Error> {
-        let mut to_fill = unsafe { std::mem::zeroed() };
-        let result = unsafe {
-            unsafe_bindings::afc_client_new_from_house_arrest_client(
-                house_arrest.pointer,
-                &mut to_fill,
-            )
+impl Transfer {
+    fn start(total: Option<u64>) -> Self {
+        Transfer {
+            bytes_done: 0,
+            total,
+            elapsed: Duration::ZERO,
+            started: Instant::now(),
         }
-        .into();
-        if result != AfcError::Success {
-            return Err(result);
+    }
+
+    fn advance(&mut self, bytes: u64) {
+        self.bytes_done += bytes;
+        self.elapsed = self.started.elapsed();
+    }
+
+    /// Gets the average rate of the transfer so far
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The bytes copied per second, or 0 before any time has passed
+    ///
+    /// ***Verified:*** False
+    pub fn throughput_bytes_per_sec(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
         }
-        Ok(Self {
-            pointer: to_fill,
-            phantom: std::marker::PhantomData,
+        self.bytes_done as f64 / seconds
+    }
+}
+
+/// The kind of an entry on the device, as reported in `st_ifmt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfcFileType {
+    File,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    Unknown,
+}
+
+impl From<&str> for AfcFileType {
+    fn from(ifmt: &str) -> Self {
+        match ifmt {
+            "S_IFREG" => AfcFileType::File,
+            "S_IFDIR" => AfcFileType::Directory,
+            "S_IFLNK" => AfcFileType::Symlink,
+            "S_IFCHR" => AfcFileType::CharDevice,
+            "S_IFBLK" => AfcFileType::BlockDevice,
+            "S_IFIFO" => AfcFileType::Fifo,
+            "S_IFSOCK" => AfcFileType::Socket,
+            _ => AfcFileType::Unknown,
+        }
+    }
+}
+
+/// Information about a file on the device, from `AfcClient::metadata` and directory listings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AfcFileInfo {
+    /// The size in bytes
+    pub size: u64,
+    /// The number of 512 byte blocks allocated to the file
+    pub blocks: u64,
+    /// The modification time
+    pub mtime: SystemTime,
+    /// The creation time, which older versions of iOS don't report
+    pub birthtime: Option<SystemTime>,
+    /// What kind of file this is
+    pub ifmt: AfcFileType,
+    /// The number of hard links to the file
+    pub nlink: u64,
+    /// The target stored in the link, if the file is a symlink
+    pub link_target: Option<String>,
+}
+
+impl AfcFileInfo {
+    pub(crate) fn from_info(info: &HashMap<String, String>) -> Option<Self> {
+        let number = |key: &str| info.get(key).and_then(|v| v.parse::<u64>().ok());
+        Some(AfcFileInfo {
+            size: number("st_size")?,
+            blocks: number("st_blocks").unwrap_or(0),
+            mtime: parse_afc_time(info.get("st_mtime")?)?,
+            birthtime: info.get("st_birthtime").and_then(|v| parse_afc_time(v)),
+            ifmt: info.get("st_ifmt")?.as_str().into(),
+            nlink: number("st_nlink").unwrap_or(0),
+            link_target: link_target(info),
         })
     }
+
+    /// Checks if the file is a regular file
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// True if it's a regular file
+    ///
+    /// ***Verified:*** False
+    pub fn is_file(&self) -> bool {
+        self.ifmt == AfcFileType::File
+    }
+
+    /// Checks if the file is a directory
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// True if it's a directory
+    ///
+    /// ***Verified:*** False
+    pub fn is_dir(&self) -> bool {
+        self.ifmt == AfcFileType::Directory
+    }
+
+    /// Checks if the file is a symlink
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// True if it's a symlink
+    ///
+    /// ***Verified:*** False
+    pub fn is_symlink(&self) -> bool {
+        self.ifmt == AfcFileType::Symlink
+    }
+}
+
+/// An entry found by `list_dir_detailed` or `AfcClient::walk`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AfcDirEntry {
+    /// The name of the entry
+    pub name: String,
+    /// The full path of the entry on the device, through any symlinks the walk followed
+    pub path: String,
+    /// How far below the directory being listed or walked the entry is, where that directory is 0
+    pub depth: usize,
+    /// The entry's metadata, or that of what the link points to if a walk followed it
+    pub info: AfcFileInfo,
+}
+
+impl AfcDirEntry {
+    /// Checks if the entry is a symlink, whether or not it was followed
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// True if the entry is a symlink
+    ///
+    /// ***Verified:*** False
+    pub fn is_symlink(&self) -> bool {
+        self.info.is_symlink() || self.info.link_target.is_some()
+    }
+}
+
+/// How symlinks are handled when copying a directory tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkMode {
+    /// Create a symlink with the same target at the destination
+    Recreate,
+    /// Copy the file or directory the symlink points to
+    Follow,
+}
+
+/// Logs an error from the host's filesystem and converts it for returning from AFC functions
+fn local_io_error(path: &Path, error: std::io::Error) -> AfcError {
+    warn!("Unable to access {}: {}", path.display(), error);
+    AfcError::IoError
+}
+
+/// Adds up the size of the files `upload_dir` would upload from a local directory
+fn local_dir_size(local: &Path, symlinks: SymlinkMode) -> Result<u64, AfcError> {
+    let mut total = 0;
+    for entry in fs::read_dir(local).map_err(|e| local_io_error(local, e))? {
+        let path = entry.map_err(|e| local_io_error(local, e))?.path();
+        let mut metadata = fs::symlink_metadata(&path).map_err(|e| local_io_error(&path, e))?;
+        if metadata.file_type().is_symlink() {
+            if symlinks == SymlinkMode::Recreate {
+                continue;
+            }
+            metadata = fs::metadata(&path).map_err(|e| local_io_error(&path, e))?;
+        }
+        total += if metadata.is_dir() {
+            local_dir_size(&path, symlinks)?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(total)
+}
+
+/// Reads the target out of the info `get_file_info` returned, if it describes a symlink
+pub(crate) fn link_target(info: &HashMap<String, String>) -> Option<String> {
+    if info.get("st_ifmt").map(|v| v.as_str()) != Some("S_IFLNK") {
+        return None;
+    }
+    info.get("LinkTarget").cloned()
+}
+
+/// AFC reports times as nanoseconds since the Unix epoch
+pub(crate) fn parse_afc_time(value: &str) -> Option<SystemTime> {
+    let nanos: u64 = value.parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_nanos(nanos))
+}
+
+/// Converts a time on the host to the nanoseconds `set_file_time` takes
+pub(crate) fn afc_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// Joins a directory and an entry name into a device path
+pub(crate) fn join_path(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Whether a failed rename is retried as a copy and remove.
+/// AFC has no cross-device error, so only a refused rename across directories qualifies
+fn rename_falls_back(error: AfcError, from: &str, to: &str) -> bool {
+    let parent = |path: &str| path.rsplit_once('/').map(|(dir, _)| dir.to_string());
+    error == AfcError::OpNotSupported && parent(from) != parent(to)
+}
+
+/// Joins path components into an absolute path, resolving `.` and `..`.
+/// A `..` that would climb above `/` is ignored if `clamp` is set, otherwise `None` is returned
+fn normalize_path<'p>(components: impl Iterator<Item = &'p str>, clamp: bool) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in components {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() && !clamp {
+                    return None;
+                }
+            }
+            _ => parts.push(component),
+        }
+    }
+    Some(format!("/{}", parts.join("/")))
+}
+
+/// Resolves `path` under `root`, or `None` if it would end up outside of it
+fn scoped_path(root: &str, path: &str) -> Option<String> {
+    let resolved = normalize_path(root.split('/').chain(path.split('/')), false)?;
+    if root == "/" || resolved == root || resolved.starts_with(&format!("{}/", root)) {
+        Some(resolved)
+    } else {
+        None
+    }
+}
+
+/// Matches a single path segment against a glob segment containing `*` and `?`
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume if the last `*` has to swallow another character
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -655,10 +2698,300 @@ impl From<LinkType> for u32 {
     }
 }
 
-impl Drop for AfcClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::afc_client_free(self.pointer);
+impl<'a> Service<'a> for AfcClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.afc";
+    type Error = String;
+
+    fn from_descriptor(device: &'a Device, descriptor: LockdowndService) -> Result<Self, String> {
+        Self::with_service(device, descriptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creating_a_file_twice_fails_the_second_time() {
+        let mut exists = false;
+
+        assert_eq!(
+            create_new_options().mode(|| Ok(exists)),
+            Ok(AfcFileMode::WriteOnly)
+        );
+        exists = true;
+        assert_eq!(
+            create_new_options().mode(|| Ok(exists)),
+            Err(AfcError::ObjectExists)
+        );
+    }
+
+    #[test]
+    fn creating_an_existing_file_never_opens_it() {
+        // `open_with` only opens the file with the mode this returns
+        let mut checked = false;
+        let mode = create_new_options().mode(|| {
+            checked = true;
+            Ok(true)
+        });
+        assert!(checked);
+        assert_eq!(mode, Err(AfcError::ObjectExists));
+    }
+
+    #[test]
+    fn renames_in_the_same_directory_never_fall_back() {
+        let (from, to) = ("/Downloads/a.txt", "/Downloads/b.txt");
+        assert!(!rename_falls_back(AfcError::OpNotSupported, from, to));
+        assert!(!rename_falls_back(AfcError::PermDenied, from, to));
+    }
+
+    #[test]
+    fn renames_across_directories_only_fall_back_when_unsupported() {
+        let (from, to) = ("/Downloads/a.txt", "/Documents/a.txt");
+        assert!(rename_falls_back(AfcError::OpNotSupported, from, to));
+        assert!(!rename_falls_back(AfcError::PermDenied, from, to));
+        assert!(!rename_falls_back(AfcError::UnknownError, from, to));
+        assert!(!rename_falls_back(AfcError::InternalError, from, to));
+    }
+
+    #[test]
+    fn scoped_paths_stay_under_the_root() {
+        let root = "/Documents";
+        assert_eq!(
+            scoped_path(root, "a/b.txt").as_deref(),
+            Some("/Documents/a/b.txt")
+        );
+        assert_eq!(
+            scoped_path(root, "a/../b.txt").as_deref(),
+            Some("/Documents/b.txt")
+        );
+        assert_eq!(scoped_path(root, ".").as_deref(), Some("/Documents"));
+        // Absolute paths are taken relative to the root rather than the device's root
+        assert_eq!(
+            scoped_path(root, "/etc/passwd").as_deref(),
+            Some("/Documents/etc/passwd")
+        );
+    }
+
+    #[test]
+    fn scoped_paths_reject_escapes() {
+        let root = "/Documents";
+        for path in [
+            "..",
+            "../etc",
+            "a/../../etc",
+            "/../Documents2",
+            "/a/../../..",
+        ] {
+            assert_eq!(scoped_path(root, path), None, "{:?}", path);
+        }
+        // A sibling sharing the root as a prefix is still outside it
+        assert_eq!(scoped_path("/Doc", "../Documents"), None);
+        // Nothing can climb above the device's root
+        assert_eq!(scoped_path("/", "../etc"), None);
+    }
+
+    #[test]
+    fn normalized_paths_clamp_only_when_asked() {
+        assert_eq!(
+            normalize_path("/a/../../b".split('/'), true).as_deref(),
+            Some("/b")
+        );
+        assert_eq!(normalize_path("/a/../../b".split('/'), false), None);
+        assert_eq!(
+            normalize_path("a/./b/".split('/'), false).as_deref(),
+            Some("/a/b")
+        );
+    }
+
+    #[test]
+    fn seeks_use_the_c_whence_values() {
+        assert_eq!(seek_args(SeekFrom::Start(7)), Ok((7, libc::SEEK_SET as u8)));
+        assert_eq!(
+            seek_args(SeekFrom::Current(-3)),
+            Ok((-3, libc::SEEK_CUR as u8))
+        );
+        assert_eq!(seek_args(SeekFrom::End(-4)), Ok((-4, libc::SEEK_END as u8)));
+        assert_eq!(
+            seek_args(SeekFrom::Start(u64::MAX)),
+            Err(AfcError::InvalidArg)
+        );
+    }
+
+    #[test]
+    fn seeks_land_where_std_puts_them() {
+        // The device applies whence like lseek, so a file of known bytes can stand in for it
+        let pattern = (0..32u8).collect::<Vec<u8>>();
+        let mut cursor = std::io::Cursor::new(pattern.clone());
+        let mut position = 0i64;
+        let seeks = [
+            SeekFrom::Start(10),
+            SeekFrom::Current(5),
+            SeekFrom::Current(-12),
+            SeekFrom::End(-4),
+            SeekFrom::End(0),
+            SeekFrom::Start(0),
+        ];
+        for pos in seeks {
+            let (offset, whence) = seek_args(pos).unwrap();
+            position = match whence {
+                0 => offset,
+                1 => position + offset,
+                2 => pattern.len() as i64 + offset,
+                _ => unreachable!(),
+            };
+            assert_eq!(cursor.seek(pos).unwrap(), position as u64, "{:?}", pos);
+
+            // Each byte of the pattern is its own offset
+            let mut byte = [0u8];
+            if cursor.read(&mut byte).unwrap() == 1 {
+                assert_eq!(byte[0] as i64, position);
+            }
+            cursor.seek(SeekFrom::Start(position as u64)).unwrap();
         }
     }
+
+    fn file_info(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn symlinks_report_their_target() {
+        let info = file_info(&[
+            ("st_size", "18"),
+            ("st_mtime", "1700000000000000000"),
+            ("st_ifmt", "S_IFLNK"),
+            ("st_nlink", "1"),
+            ("LinkTarget", "/var/mobile/Media"),
+        ]);
+        assert_eq!(link_target(&info).as_deref(), Some("/var/mobile/Media"));
+
+        let parsed = AfcFileInfo::from_info(&info).unwrap();
+        assert!(parsed.is_symlink());
+        assert_eq!(parsed.link_target.as_deref(), Some("/var/mobile/Media"));
+        assert_eq!(parsed.size, 18);
+    }
+
+    #[test]
+    fn only_symlinks_have_a_target() {
+        let info = file_info(&[
+            ("st_size", "0"),
+            ("st_mtime", "0"),
+            ("st_ifmt", "S_IFREG"),
+            ("LinkTarget", "/var/mobile/Media"),
+        ]);
+        assert_eq!(link_target(&info), None);
+        assert_eq!(AfcFileInfo::from_info(&info).unwrap().link_target, None);
+
+        let dangling = file_info(&[("st_ifmt", "S_IFLNK")]);
+        assert_eq!(link_target(&dangling), None);
+    }
+
+    #[test]
+    fn resizing_only_truncates_through_the_handle() {
+        // Read-write leaves an existing file as it is, so `file_truncate` sets the size, even to 0
+        assert_eq!(
+            resize_options(false).mode(|| Ok(true)),
+            Ok(AfcFileMode::ReadWrite)
+        );
+        assert_eq!(
+            resize_options(true).mode(|| Ok(true)),
+            Ok(AfcFileMode::ReadWrite)
+        );
+    }
+
+    #[test]
+    fn resizing_only_creates_files_when_asked() {
+        assert_eq!(
+            resize_options(false).mode(|| Ok(false)),
+            Err(AfcError::ObjectNotFound)
+        );
+        assert_eq!(
+            resize_options(true).mode(|| Ok(false)),
+            Ok(AfcFileMode::WriteOnly)
+        );
+    }
+
+    /// Reads `contents` a few bytes at a time, like `hash_file` reads a file from the device
+    #[cfg(feature = "openssl")]
+    fn hash_contents(contents: &[u8], digest: MessageDigest) -> String {
+        let mut chunks = contents.chunks(5);
+        let hash = hash_chunks(digest, || Ok(chunks.next().unwrap_or_default().to_vec())).unwrap();
+        hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "openssl")]
+    fn small_files_hash_to_their_known_digests() {
+        let contents = b"The quick brown fox jumps over the lazy dog";
+        assert_eq!(
+            hash_contents(contents, MessageDigest::sha256()),
+            "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592"
+        );
+        assert_eq!(
+            hash_contents(contents, MessageDigest::md5()),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        assert_eq!(
+            hash_contents(b"", MessageDigest::sha256()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hash_contents(b"", MessageDigest::md5()),
+            "d41d8cd98f00b204e9800998ecf8427e"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "openssl")]
+    fn read_errors_stop_the_hash() {
+        let mut reads = 0;
+        let hash = hash_chunks(MessageDigest::sha256(), || {
+            reads += 1;
+            match reads {
+                1 => Ok(b"part".to_vec()),
+                _ => Err(AfcError::IoError),
+            }
+        });
+        assert_eq!(hash, Err(AfcError::IoError));
+    }
+
+    #[test]
+    fn closed_files_are_not_closed_again_on_drop() {
+        let mut handle = Some(7);
+        let mut closed = Vec::new();
+
+        // `close`, then `Drop` on the same file
+        assert_eq!(
+            close_handle(&mut handle, |h| {
+                closed.push(h);
+                Ok(())
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            close_handle(&mut handle, |h| {
+                closed.push(h);
+                Ok(())
+            }),
+            Ok(())
+        );
+        assert_eq!(closed, [7]);
+    }
+
+    #[test]
+    fn failed_closes_are_not_retried_on_drop() {
+        let mut handle = Some(7);
+        assert_eq!(
+            close_handle(&mut handle, |_| Err(AfcError::IoError)),
+            Err(AfcError::IoError)
+        );
+        assert_eq!(
+            close_handle(&mut handle, |_| panic!("closed twice")),
+            Ok(())
+        );
+    }
 }
@@ -1,7 +1,10 @@
 // jkcoxson
 
 use crate::{
-    bindings as unsafe_bindings, error::CompanionProxyError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::CompanionProxyError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 use std::ffi::CString;
@@ -10,13 +13,19 @@ use plist_plus::Plist;
 
 /// A proxy for interoping with devices paired with the iOS device
 /// This includes the Apple Watch
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CompanionProxy<'a> {
-    pub(crate) pointer: unsafe_bindings::companion_proxy_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::companion_proxy_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl CompanionProxy<'_> {
+impl ClientPointer for unsafe_bindings::companion_proxy_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::companion_proxy_client_free(self)
+    }
+}
+
+impl<'a> CompanionProxy<'a> {
     /// Creates a new companion proxy from a lockdown connection
     /// # Arguments
     /// * `device` - The device of which to connect to
@@ -25,7 +34,10 @@ impl CompanionProxy<'_> {
     /// A companion proxy struct
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, CompanionProxyError> {
+    pub fn new(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, CompanionProxyError> {
         let mut pointer = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::companion_proxy_client_new(
@@ -40,7 +52,7 @@ impl CompanionProxy<'_> {
         }
 
         Ok(CompanionProxy {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -54,7 +66,7 @@ impl CompanionProxy<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, CompanionProxyError> {
         let label_c_string = CString::new(label.into()).unwrap();
@@ -73,7 +85,7 @@ impl CompanionProxy<'_> {
         }
 
         Ok(CompanionProxy {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -86,9 +98,10 @@ impl CompanionProxy<'_> {
     ///
     /// ***Verified:*** False
     pub fn send(&self, message: Plist) -> Result<(), CompanionProxyError> {
-        let result =
-            unsafe { unsafe_bindings::companion_proxy_send(self.pointer, message.get_pointer()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::companion_proxy_send(*self.pointer.lock(), message.get_pointer())
+        }
+        .into();
         if result != CompanionProxyError::Success {
             return Err(result);
         }
@@ -107,7 +120,8 @@ impl CompanionProxy<'_> {
     pub fn receive(&self) -> Result<Plist, CompanionProxyError> {
         let mut plist = unsafe { std::mem::zeroed() };
         let result =
-            unsafe { unsafe_bindings::companion_proxy_receive(self.pointer, &mut plist) }.into();
+            unsafe { unsafe_bindings::companion_proxy_receive(*self.pointer.lock(), &mut plist) }
+                .into();
         if result != CompanionProxyError::Success {
             return Err(result);
         }
@@ -126,7 +140,7 @@ impl CompanionProxy<'_> {
     pub fn get_device_registry(self) -> Result<Plist, CompanionProxyError> {
         let mut plist = unsafe { std::mem::zeroed() };
         let result = unsafe {
-            unsafe_bindings::companion_proxy_get_device_registry(self.pointer, &mut plist)
+            unsafe_bindings::companion_proxy_get_device_registry(*self.pointer.lock(), &mut plist)
         }
         .into();
         if result != CompanionProxyError::Success {
@@ -152,7 +166,7 @@ impl CompanionProxy<'_> {
         let mut plist = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::companion_proxy_get_value_from_registry(
-                self.pointer,
+                *self.pointer.lock(),
                 udid_c_string.as_ptr(),
                 key_c_string.as_ptr(),
                 &mut plist,
@@ -186,7 +200,7 @@ impl CompanionProxy<'_> {
 
         let result = unsafe {
             unsafe_bindings::companion_proxy_start_forwarding_service_port(
-                self.pointer,
+                *self.pointer.lock(),
                 port,
                 service_name_c_string.as_ptr(),
                 &mut result_port,
@@ -210,7 +224,10 @@ impl CompanionProxy<'_> {
     /// ***Verified:*** False
     pub fn stop_forwarding_service_port(&self, port: u16) -> Result<(), CompanionProxyError> {
         let result = unsafe {
-            unsafe_bindings::companion_proxy_stop_forwarding_service_port(self.pointer, port)
+            unsafe_bindings::companion_proxy_stop_forwarding_service_port(
+                *self.pointer.lock(),
+                port,
+            )
         }
         .into();
         if result != CompanionProxyError::Success {
@@ -219,12 +236,33 @@ impl CompanionProxy<'_> {
 
         Ok(())
     }
-}
 
-impl Drop for CompanionProxy<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::companion_proxy_client_free(self.pointer);
+    /// Closes the companion proxy connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), CompanionProxyError> {
+        let result = self.pointer.close().into();
+        if result != CompanionProxyError::Success {
+            return Err(result);
         }
+
+        Ok(())
+    }
+}
+
+impl<'a> Service<'a> for CompanionProxy<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.companion_proxy";
+    type Error = CompanionProxyError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, CompanionProxyError> {
+        Self::new(device, descriptor)
     }
 }
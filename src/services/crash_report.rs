@@ -0,0 +1,153 @@
+// jkcoxson
+
+use std::io::Read;
+
+use crate::{
+    connection::DeviceConnection,
+    error::AfcError,
+    idevice::Device,
+    services::{
+        afc::{AfcClient, AfcFileMode, AfcFileType},
+        lockdownd::LockdowndClient,
+    },
+};
+
+use log::{info, warn};
+
+/// Moves pending crash reports into the directory served by the copy service
+const MOVER_SERVICE: &str = "com.apple.crashreportmover";
+/// An AFC service rooted at the device's crash report directory
+const COPY_SERVICE: &str = "com.apple.crashreportcopymobile";
+/// How long to wait for the mover to finish. It only answers once every report has been moved
+const MOVER_TIMEOUT_MS: u32 = 30_000;
+
+/// Reads the crash reports stored on the device.
+///
+/// New reports are only visible after the crash report mover has run, which `connect` does first.
+/// Reports written while the client is open won't show up until `flush` is called again.
+#[derive(Debug)]
+pub struct CrashReportClient<'a> {
+    afc: AfcClient<'a>,
+}
+
+impl<'a> CrashReportClient<'a> {
+    /// Moves any pending crash reports into place, then connects to the crash report directory
+    /// # Arguments
+    /// * `device` - The device to read the crash reports of
+    /// # Returns
+    /// A client for the crash report directory
+    ///
+    /// ***Verified:*** False
+    pub fn connect(device: &'a Device) -> Result<Self, AfcError> {
+        Self::flush(device)?;
+        Ok(CrashReportClient {
+            afc: AfcClient::start_service(device, COPY_SERVICE)?,
+        })
+    }
+
+    /// Triggers the crash report mover and waits for it to finish.
+    /// Until the mover runs, new reports stay where the system wrote them and aren't listed.
+    /// # Arguments
+    /// * `device` - The device to flush the crash reports of
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn flush(device: &Device) -> Result<(), AfcError> {
+        let mut lockdownd =
+            LockdowndClient::new(device, "rusty_libimobiledevice").map_err(|e| {
+                warn!(
+                    "Unable to connect to lockdownd to move crash reports: {}",
+                    e
+                );
+                AfcError::ServiceNotConnected
+            })?;
+        let service = lockdownd.start_service(MOVER_SERVICE, false).map_err(|e| {
+            warn!("Unable to start {}: {}", MOVER_SERVICE, e);
+            AfcError::ServiceNotConnected
+        })?;
+        let connection = DeviceConnection::service_connect(device, &service).map_err(|e| {
+            warn!("Unable to connect to {}: {}", MOVER_SERVICE, e);
+            AfcError::MuxError
+        })?;
+
+        info!("Waiting for the crash report mover");
+        let reply = connection.receive(4, Some(MOVER_TIMEOUT_MS)).map_err(|e| {
+            warn!("The crash report mover didn't finish: {}", e);
+            AfcError::OpTimeout
+        })?;
+        if reply != b"ping" {
+            warn!("Unexpected reply from the crash report mover: {:?}", reply);
+            return Err(AfcError::UnknownPacketType);
+        }
+        Ok(())
+    }
+
+    /// Gets the AFC client rooted at the crash report directory, for removing or copying reports
+    pub fn afc(&self) -> &AfcClient<'a> {
+        &self.afc
+    }
+
+    /// Lists the crash reports on the device, including those in subdirectories such as `Retired`
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The paths of the reports relative to the crash report directory, sorted
+    ///
+    /// ***Verified:*** False
+    pub fn list_reports(&self) -> Result<Vec<String>, AfcError> {
+        let mut reports = Vec::new();
+        self.list_reports_in("/", &mut reports)?;
+        reports.sort();
+        Ok(reports)
+    }
+
+    fn list_reports_in(&self, dir: &str, reports: &mut Vec<String>) -> Result<(), AfcError> {
+        for entry in self.afc.list_dir_detailed(dir)? {
+            match entry.info.ifmt {
+                AfcFileType::Directory => self.list_reports_in(&entry.path, reports)?,
+                AfcFileType::File => {
+                    reports.push(entry.path.trim_start_matches('/').to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a crash report
+    /// # Arguments
+    /// * `name` - The path of the report as returned by `list_reports`
+    /// # Returns
+    /// The contents of the report
+    ///
+    /// ***Verified:*** False
+    pub fn read_report(&self, name: &str) -> Result<Vec<u8>, AfcError> {
+        let path = self.afc.with_root("/").resolve(name)?;
+        let mut file = self.afc.open(path, AfcFileMode::ReadOnly)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).map_err(|e| {
+            warn!("Unable to read crash report {}: {}", name, e);
+            AfcError::ReadError
+        })?;
+        file.close()?;
+        Ok(contents)
+    }
+
+    /// Closes the connection to the crash report directory, returning any error from the teardown
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(self) -> Result<(), AfcError> {
+        self.afc.close()
+    }
+}
+
+impl<'a> From<CrashReportClient<'a>> for AfcClient<'a> {
+    fn from(client: CrashReportClient<'a>) -> Self {
+        client.afc
+    }
+}
@@ -8,16 +8,24 @@ use std::{
 
 use log::info;
 
-use crate::{bindings as unsafe_bindings, error::DebugServerError, idevice::Device};
+use crate::{
+    bindings as unsafe_bindings,
+    error::DebugServerError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct DebugServer<'a> {
-    pub(crate) pointer: unsafe_bindings::debugserver_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::debugserver_client_t>,
     pub(crate) phantom: std::marker::PhantomData<&'a Device>,
 }
 
-unsafe impl Send for DebugServer<'_> {}
-unsafe impl Sync for DebugServer<'_> {}
+impl ClientPointer for unsafe_bindings::debugserver_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::debugserver_client_free(self)
+    }
+}
 
 /// A command that can be sent to the debug server service
 pub struct DebugServerCommand {
@@ -27,8 +35,9 @@ pub struct DebugServerCommand {
 unsafe impl Send for DebugServerCommand {}
 unsafe impl Sync for DebugServerCommand {}
 
-impl DebugServer<'_> {
+impl<'a> DebugServer<'a> {
     /// Starts a new debug server on the device
+    /// The developer disk image must be mounted first, see `Device::ensure_developer_image`
     /// # Arguments
     /// * `device` - The device to start the debug server on
     /// * `label` - The label to use for the debug server
@@ -36,7 +45,7 @@ impl DebugServer<'_> {
     /// A debug server struct
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, label: &str) -> Result<Self, DebugServerError> {
+    pub fn new(device: &'a Device, label: &str) -> Result<Self, DebugServerError> {
         let mut client: unsafe_bindings::debugserver_client_t = unsafe { std::mem::zeroed() };
         let client_ptr: *mut unsafe_bindings::debugserver_client_t = &mut client;
 
@@ -55,7 +64,7 @@ impl DebugServer<'_> {
         }
 
         Ok(DebugServer {
-            pointer: unsafe { *client_ptr },
+            pointer: ClientHandle::new(unsafe { *client_ptr }),
             phantom: std::marker::PhantomData,
         })
     }
@@ -72,7 +81,7 @@ impl DebugServer<'_> {
         let mut sent = 0;
         let result = unsafe {
             unsafe_bindings::debugserver_client_send(
-                self.pointer,
+                *self.pointer.lock(),
                 data_c_string.as_ptr(),
                 data_c_string.as_bytes().len().try_into().unwrap(),
                 &mut sent,
@@ -101,7 +110,7 @@ impl DebugServer<'_> {
         if timeout == 0 {
             let result = unsafe {
                 unsafe_bindings::debugserver_client_receive(
-                    self.pointer,
+                    *self.pointer.lock(),
                     data.as_mut_ptr() as *mut c_char,
                     size,
                     &mut received,
@@ -114,7 +123,7 @@ impl DebugServer<'_> {
         } else {
             let result = unsafe {
                 unsafe_bindings::debugserver_client_receive_with_timeout(
-                    self.pointer,
+                    *self.pointer.lock(),
                     data.as_mut_ptr() as *mut c_char,
                     size,
                     &mut received,
@@ -145,7 +154,7 @@ impl DebugServer<'_> {
         let mut size = 0;
         let result = unsafe {
             unsafe_bindings::debugserver_client_receive_response(
-                self.pointer,
+                *self.pointer.lock(),
                 data as *mut *mut c_char,
                 &mut size,
             )
@@ -170,7 +179,7 @@ impl DebugServer<'_> {
     /// ***Verified:*** False
     pub fn set_ack_mode(&self, enabled: bool) -> Result<(), DebugServerError> {
         let result = unsafe {
-            unsafe_bindings::debugserver_client_set_ack_mode(self.pointer, enabled as c_int)
+            unsafe_bindings::debugserver_client_set_ack_mode(*self.pointer.lock(), enabled as c_int)
         }
         .into();
         if result != DebugServerError::Success {
@@ -195,7 +204,7 @@ impl DebugServer<'_> {
         let mut response = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::debugserver_client_set_environment_hex_encoded(
-                self.pointer,
+                *self.pointer.lock(),
                 env_c_string.as_ptr(),
                 &mut response,
             )
@@ -228,7 +237,7 @@ impl DebugServer<'_> {
         info!("Sending command to debug server");
         let result = unsafe {
             unsafe_bindings::debugserver_client_send_command(
-                self.pointer,
+                *self.pointer.lock(),
                 command.command,
                 response_ptr_ptr,
                 response_size,
@@ -275,7 +284,7 @@ impl DebugServer<'_> {
         info!("Setting argv for debug server");
         let result = unsafe {
             unsafe_bindings::debugserver_client_set_argv(
-                self.pointer,
+                *self.pointer.lock(),
                 argv.len() as i32,
                 argv.as_mut_ptr(),
                 response_ptr_ptr,
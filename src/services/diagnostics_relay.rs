@@ -4,20 +4,30 @@ use std::ffi::CString;
 use std::os::raw::c_uint;
 
 use crate::{
-    bindings as unsafe_bindings, error::DiagnosticsRelayError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::DiagnosticsRelayError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 
+use log::warn;
 use plist_plus::Plist;
 
 /// Relays diagnostic logs from the iOS device to the host
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct DiagnosticsRelay<'a> {
-    pub(crate) pointer: unsafe_bindings::diagnostics_relay_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::diagnostics_relay_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl DiagnosticsRelay<'_> {
+impl ClientPointer for unsafe_bindings::diagnostics_relay_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::diagnostics_relay_client_free(self)
+    }
+}
+
+impl<'a> DiagnosticsRelay<'a> {
     /// Creates a new diagnostics relay service from a lockdown service
     /// # Arguments
     /// * `device` - The device to create the sevice with
@@ -26,7 +36,10 @@ impl DiagnosticsRelay<'_> {
     /// A struct containing the handle to the service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, service: LockdowndService) -> Result<Self, DiagnosticsRelayError> {
+    pub fn new(
+        device: &'a Device,
+        service: LockdowndService,
+    ) -> Result<Self, DiagnosticsRelayError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::diagnostics_relay_client_new(
@@ -42,7 +55,7 @@ impl DiagnosticsRelay<'_> {
         }
 
         Ok(DiagnosticsRelay {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -56,7 +69,7 @@ impl DiagnosticsRelay<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, DiagnosticsRelayError> {
         let mut pointer = std::ptr::null_mut();
@@ -75,7 +88,7 @@ impl DiagnosticsRelay<'_> {
         }
 
         Ok(DiagnosticsRelay {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -89,7 +102,8 @@ impl DiagnosticsRelay<'_> {
     ///
     /// ***Verified:*** False
     pub fn goodbye(self) -> Result<(), DiagnosticsRelayError> {
-        let result = unsafe { unsafe_bindings::diagnostics_relay_goodbye(self.pointer) }.into();
+        let result =
+            unsafe { unsafe_bindings::diagnostics_relay_goodbye(*self.pointer.lock()) }.into();
 
         if result != DiagnosticsRelayError::Success {
             return Err(result);
@@ -99,61 +113,55 @@ impl DiagnosticsRelay<'_> {
     }
 
     /// Puts the connected device to sleep, breaking the connection.
+    /// Returns once the device has acknowledged the request.
     /// # Arguments
     /// *none*
     /// # Returns
-    /// *none*
+    /// *none*, or `Refused` if the device declined
     ///
     /// ***Verified:*** False
     pub fn sleep(self) -> Result<(), DiagnosticsRelayError> {
-        let result = unsafe { unsafe_bindings::diagnostics_relay_sleep(self.pointer) }.into();
-
-        if result != DiagnosticsRelayError::Success {
-            return Err(result);
-        }
-
-        Ok(())
+        let result =
+            unsafe { unsafe_bindings::diagnostics_relay_sleep(*self.pointer.lock()) }.into();
+        check_action(result, "sleep")
     }
 
     /// Restarts the connected device, breaking the connection.
+    /// Returns once the device has acknowledged the request, not once it has gone down.
+    /// With `DIAGNOSTICS_RELAY_ACTION_FLAG_WAIT_FOR_DISCONNECT` the device waits for this client to be dropped,
+    /// which happens when this returns. `Device::restart_and_wait` also waits for it to come back.
     /// # Arguments
     /// * `flag` - A flag to determine actions for the restart
     /// # Returns
-    /// *none*
+    /// *none*, or `Refused` if the device declined
     ///
     /// ***Verified:*** False
     pub fn restart(self, flag: c_uint) -> Result<(), DiagnosticsRelayError> {
         let result =
-            unsafe { unsafe_bindings::diagnostics_relay_restart(self.pointer, flag) }.into();
-
-        if result != DiagnosticsRelayError::Success {
-            return Err(result);
-        }
-
-        Ok(())
+            unsafe { unsafe_bindings::diagnostics_relay_restart(*self.pointer.lock(), flag) }
+                .into();
+        check_action(result, "restart")
     }
 
     /// Shuts the device off, breaking the connection.
+    /// Returns once the device has acknowledged the request, not once it has gone down.
     /// # Arguments
     /// * `flag` - A flag to determine actions for the restart
     /// # Returns
-    /// *none*
+    /// *none*, or `Refused` if the device declined
     ///
     /// ***Verified:*** False
     pub fn shutdown(self, flag: c_uint) -> Result<(), DiagnosticsRelayError> {
         let result =
-            unsafe { unsafe_bindings::diagnostics_relay_shutdown(self.pointer, flag) }.into();
-
-        if result != DiagnosticsRelayError::Success {
-            return Err(result);
-        }
-
-        Ok(())
+            unsafe { unsafe_bindings::diagnostics_relay_shutdown(*self.pointer.lock(), flag) }
+                .into();
+        check_action(result, "shutdown")
     }
 
     /// Requests diagnostics from the device
+    /// `DiagnosticType::All` returns every category, such as GasGauge, HDMI, NAND and WiFi, in one dictionary
     /// # Arguments
-    /// * `type_` - The type of diagnostics to request
+    /// * `type_` - The type of diagnostics to request, either a `DiagnosticType` or its name
     /// # Returns
     /// A plist containing the diagnostics data
     ///
@@ -166,7 +174,7 @@ impl DiagnosticsRelay<'_> {
         let type_c_string = CString::new(type_.into()).unwrap();
         let result = unsafe {
             unsafe_bindings::diagnostics_relay_request_diagnostics(
-                self.pointer,
+                *self.pointer.lock(),
                 type_c_string.as_ptr(),
                 &mut plist,
             )
@@ -180,18 +188,18 @@ impl DiagnosticsRelay<'_> {
         Ok(plist.into())
     }
 
-    /// Usage unknown
+    /// Queries MobileGestalt for the given keys
     /// # Arguments
-    /// `keys` - Unknown
+    /// `keys` - An array of the key names to query
     /// # Returns
-    /// A plist with unknown usage
+    /// The device's response, with the values under `MobileGestalt`
     ///
     /// ***Verified:*** False
     pub fn query_mobilegestalt(&self, keys: Plist) -> Result<Plist, DiagnosticsRelayError> {
         let mut plist = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::diagnostics_relay_query_mobilegestalt(
-                self.pointer,
+                *self.pointer.lock(),
                 keys.get_pointer(),
                 &mut plist,
             )
@@ -205,6 +213,44 @@ impl DiagnosticsRelay<'_> {
         Ok(plist.into())
     }
 
+    /// Reads MobileGestalt keys, which cover many hardware and software properties lockdownd doesn't expose.
+    /// Newer versions of iOS only answer some keys from the restore or diagnostics environment
+    /// and leave the rest out, so missing keys are reported as restricted rather than as an error.
+    /// # Arguments
+    /// * `keys` - The keys to read
+    /// # Returns
+    /// The values that were returned, and the keys that weren't
+    ///
+    /// ***Verified:*** False
+    pub fn mobilegestalt(
+        &self,
+        keys: &[&str],
+    ) -> Result<MobileGestaltResponse, DiagnosticsRelayError> {
+        let mut key_array = Plist::new_array();
+        for key in keys {
+            key_array
+                .array_append_item(Plist::new_string(key))
+                .map_err(|_| DiagnosticsRelayError::PlistError)?;
+        }
+
+        let response = self.query_mobilegestalt(key_array)?;
+        let nested = response.dict_get_item("MobileGestalt").ok();
+        let gestalt = nested.as_ref().unwrap_or(&response);
+
+        let mut values = Plist::new_dict();
+        let mut restricted = Vec::new();
+        for key in keys {
+            match gestalt.dict_get_item(key) {
+                Ok(value) => values
+                    .dict_set_item(key, value.clone())
+                    .map_err(|_| DiagnosticsRelayError::PlistError)?,
+                Err(_) => restricted.push(key.to_string()),
+            }
+        }
+
+        Ok(MobileGestaltResponse { values, restricted })
+    }
+
     /// Requests data from the device's IO registry
     /// # Arguments
     /// * `entry_name` - The name to request
@@ -224,7 +270,7 @@ impl DiagnosticsRelay<'_> {
 
         let result = unsafe {
             unsafe_bindings::diagnostics_relay_query_ioregistry_entry(
-                self.pointer,
+                *self.pointer.lock(),
                 entry_name_c_string.as_ptr(),
                 entry_class_c_string.as_ptr(),
                 &mut plist,
@@ -239,6 +285,34 @@ impl DiagnosticsRelay<'_> {
         Ok(plist.into())
     }
 
+    /// Gets whether the device is charging, from the `IOPMPowerSource` IO registry entry
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The charging state derived from `IsCharging`, `FullyCharged` and `ExternalConnected`
+    ///
+    /// ***Verified:*** False
+    pub fn charging_state(&self) -> Result<ChargingState, DiagnosticsRelayError> {
+        let response = self.query_ioregistry_entry("", "IOPMPowerSource")?;
+        let registry = response
+            .dict_get_item("IORegistry")
+            .map_err(|_| DiagnosticsRelayError::PlistError)?;
+
+        let flag = |key: &str| -> Result<bool, DiagnosticsRelayError> {
+            registry
+                .dict_get_item(key)
+                .map_err(|_| DiagnosticsRelayError::PlistError)?
+                .get_bool_val()
+                .map_err(|_| DiagnosticsRelayError::PlistError)
+        };
+
+        Ok(ChargingState::from_flags(
+            flag("IsCharging")?,
+            flag("FullyCharged")?,
+            flag("ExternalConnected")?,
+        ))
+    }
+
     /// Usage unknown
     /// # Arguments
     /// * `plane` - Unknown
@@ -254,7 +328,7 @@ impl DiagnosticsRelay<'_> {
         let plane_c_string = CString::new(plane.into()).unwrap();
         let result = unsafe {
             unsafe_bindings::diagnostics_relay_query_ioregistry_plane(
-                self.pointer,
+                *self.pointer.lock(),
                 plane_c_string.as_ptr(),
                 &mut plist,
             )
@@ -267,6 +341,103 @@ impl DiagnosticsRelay<'_> {
 
         Ok(plist.into())
     }
+
+    /// Closes the diagnostics relay connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), DiagnosticsRelayError> {
+        let result = self.pointer.close().into();
+        if result != DiagnosticsRelayError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the result of a sleep, restart or shutdown request.
+/// libimobiledevice reads the device's reply before returning, and reports a `Failure` status as an unknown error.
+fn check_action(result: DiagnosticsRelayError, action: &str) -> Result<(), DiagnosticsRelayError> {
+    match result {
+        DiagnosticsRelayError::Success => Ok(()),
+        DiagnosticsRelayError::UnknownError => {
+            warn!("Device refused to {}", action);
+            Err(DiagnosticsRelayError::Refused)
+        }
+        e => Err(e),
+    }
+}
+
+/// The result of a MobileGestalt query
+pub struct MobileGestaltResponse {
+    /// A dictionary of the keys the device answered and their values
+    pub values: Plist,
+    /// The requested keys the device didn't answer, usually because they are restricted
+    pub restricted: Vec<String>,
+}
+
+/// Whether the battery is charging, as reported by the power source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargingState {
+    /// Running on battery
+    Discharging,
+    Charging,
+    /// Plugged in and fully charged
+    Full,
+    /// Plugged in but neither charging nor full, such as when charging is paused to save the battery
+    Unknown,
+}
+
+impl ChargingState {
+    /// Works out the charging state from the power source's flags
+    /// # Arguments
+    /// * `is_charging` - The `IsCharging` flag
+    /// * `fully_charged` - The `FullyCharged` flag
+    /// * `external_connected` - The `ExternalConnected` flag
+    /// # Returns
+    /// The charging state
+    ///
+    /// ***Verified:*** False
+    pub fn from_flags(is_charging: bool, fully_charged: bool, external_connected: bool) -> Self {
+        // A full battery on its own power is already discharging
+        if !external_connected {
+            ChargingState::Discharging
+        } else if is_charging {
+            ChargingState::Charging
+        } else if fully_charged {
+            ChargingState::Full
+        } else {
+            ChargingState::Unknown
+        }
+    }
+}
+
+/// The categories of diagnostics that can be requested
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticType {
+    All,
+    WiFi,
+    GasGauge,
+    NAND,
+    /// Any other type the device understands
+    Other(String),
+}
+
+impl From<DiagnosticType> for String {
+    fn from(type_: DiagnosticType) -> Self {
+        match type_ {
+            DiagnosticType::All => "All".to_string(),
+            DiagnosticType::WiFi => "WiFi".to_string(),
+            DiagnosticType::GasGauge => "GasGauge".to_string(),
+            DiagnosticType::NAND => "NAND".to_string(),
+            DiagnosticType::Other(type_) => type_,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -286,10 +457,45 @@ impl From<DiagnosticsRelayAction> for c_uint {
     }
 }
 
-impl Drop for DiagnosticsRelay<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::diagnostics_relay_client_free(self.pointer);
+impl<'a> Service<'a> for DiagnosticsRelay<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobile.diagnostics_relay";
+    type Error = DiagnosticsRelayError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, DiagnosticsRelayError> {
+        Self::new(device, descriptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charging_state_covers_every_flag_combination() {
+        use ChargingState::*;
+        // (is_charging, fully_charged, external_connected)
+        let cases = [
+            ((false, false, false), Discharging),
+            ((false, true, false), Discharging),
+            ((true, false, false), Discharging),
+            ((true, true, false), Discharging),
+            ((false, false, true), Unknown),
+            ((false, true, true), Full),
+            ((true, false, true), Charging),
+            ((true, true, true), Charging),
+        ];
+        for ((is_charging, fully_charged, external_connected), expected) in cases {
+            assert_eq!(
+                ChargingState::from_flags(is_charging, fully_charged, external_connected),
+                expected,
+                "is_charging: {}, fully_charged: {}, external_connected: {}",
+                is_charging,
+                fully_charged,
+                external_connected
+            );
         }
     }
 }
@@ -3,18 +3,28 @@
 use std::{ffi::CString, os::raw::c_char};
 
 use crate::{
-    bindings as unsafe_bindings, connection::DeviceConnection, error::FileRelayError,
-    idevice::Device, services::lockdownd::LockdowndService,
+    bindings as unsafe_bindings,
+    connection::DeviceConnection,
+    error::FileRelayError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
+    services::lockdownd::LockdowndService,
 };
 
 /// Relays files from the iOS device to the host
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct FileRelay<'a> {
-    pub pointer: unsafe_bindings::file_relay_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::file_relay_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl FileRelay<'_> {
+impl ClientPointer for unsafe_bindings::file_relay_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::file_relay_client_free(self)
+    }
+}
+
+impl<'a> FileRelay<'a> {
     /// Creates a new file relay service from a lockdown service
     /// # Arguments
     /// * `device` - The device to create the sevice with
@@ -23,7 +33,7 @@ impl FileRelay<'_> {
     /// A struct containing the handle to the service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, service: LockdowndService) -> Result<Self, FileRelayError> {
+    pub fn new(device: &'a Device, service: LockdowndService) -> Result<Self, FileRelayError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::file_relay_client_new(device.pointer, service.pointer, &mut pointer)
@@ -35,7 +45,7 @@ impl FileRelay<'_> {
         }
 
         Ok(FileRelay {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -49,7 +59,7 @@ impl FileRelay<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, FileRelayError> {
         let mut pointer = std::ptr::null_mut();
@@ -68,7 +78,7 @@ impl FileRelay<'_> {
         }
 
         Ok(FileRelay {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -98,7 +108,7 @@ impl FileRelay<'_> {
         if timeout == 0 {
             let result = unsafe {
                 unsafe_bindings::file_relay_request_sources(
-                    self.pointer,
+                    *self.pointer.lock(),
                     source_c_strings_ptrs.as_mut_ptr(),
                     &mut connection.pointer,
                 )
@@ -111,7 +121,7 @@ impl FileRelay<'_> {
         } else {
             let result = unsafe {
                 unsafe_bindings::file_relay_request_sources_timeout(
-                    self.pointer,
+                    *self.pointer.lock(),
                     source_c_strings_ptrs.as_mut_ptr(),
                     &mut connection.pointer,
                     timeout,
@@ -126,6 +136,23 @@ impl FileRelay<'_> {
 
         Ok(())
     }
+
+    /// Closes the file relay connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), FileRelayError> {
+        let result = self.pointer.close().into();
+        if result != FileRelayError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
 /// The different types of interface sources that can have data requested for
@@ -156,10 +183,14 @@ impl From<FileRelaySources> for CString {
     }
 }
 
-impl Drop for FileRelay<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::file_relay_client_free(self.pointer);
-        }
+impl<'a> Service<'a> for FileRelay<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobile.file_relay";
+    type Error = FileRelayError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, FileRelayError> {
+        Self::new(device, descriptor)
     }
 }
@@ -2,7 +2,12 @@
 
 use std::{ffi::CString, future::Future, pin::Pin};
 
-use crate::{bindings as unsafe_bindings, error::HeartbeatError, idevice::Device};
+use crate::{
+    bindings as unsafe_bindings,
+    error::HeartbeatError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
+};
 
 use log::info;
 use plist_plus::Plist;
@@ -18,18 +23,22 @@ use plist_plus::Plist;
 /// * The host will echo back the message at the interval defined in the message (give buffer time)
 ///
 /// **Note** The device will kill the heartbeat connection if packets are echoed too frequently
-#[derive(Debug, Clone)]
-pub struct HeartbeatClient {
-    pub(crate) pointer: unsafe_bindings::heartbeat_client_t,
+#[derive(Debug)]
+pub struct HeartbeatClient<'a> {
+    pub(crate) pointer: ClientHandle<unsafe_bindings::heartbeat_client_t>,
+    phantom: std::marker::PhantomData<&'a Device>,
 }
 
-unsafe impl Send for HeartbeatClient {}
-unsafe impl Sync for HeartbeatClient {}
+impl ClientPointer for unsafe_bindings::heartbeat_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::heartbeat_client_free(self)
+    }
+}
 
 unsafe impl Send for HeartbeatClientFuture {}
 unsafe impl Sync for HeartbeatClientFuture {}
 
-impl HeartbeatClient {
+impl<'a> HeartbeatClient<'a> {
     /// Starts a new service with heartbeat
     /// # Arguments
     /// * `device` - The device to create the sevice with
@@ -38,7 +47,7 @@ impl HeartbeatClient {
     /// A struct containing the handle to the service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, label: impl Into<String>) -> Result<Self, HeartbeatError> {
+    pub fn new(device: &'a Device, label: impl Into<String>) -> Result<Self, HeartbeatError> {
         let mut pointer = unsafe { std::mem::zeroed() };
         let label_c_string = CString::new(label.into()).unwrap();
         let result = unsafe {
@@ -53,8 +62,8 @@ impl HeartbeatClient {
             return Err(result);
         }
         Ok(Self {
-            pointer,
-            // phantom: std::marker::PhantomData,
+            pointer: ClientHandle::new(pointer),
+            phantom: std::marker::PhantomData,
         })
     }
 
@@ -67,7 +76,8 @@ impl HeartbeatClient {
     /// ***Verified:*** False
     pub fn send(&self, message: Plist) -> Result<(), HeartbeatError> {
         let result =
-            unsafe { unsafe_bindings::heartbeat_send(self.pointer, message.get_pointer()) }.into();
+            unsafe { unsafe_bindings::heartbeat_send(*self.pointer.lock(), message.get_pointer()) }
+                .into();
         if result != HeartbeatError::Success {
             return Err(result);
         }
@@ -87,11 +97,11 @@ impl HeartbeatClient {
 
         let result = unsafe {
             if timeout == 0 {
-                unsafe_bindings::heartbeat_receive(self.pointer, &mut plist_ptr)
+                unsafe_bindings::heartbeat_receive(*self.pointer.lock(), &mut plist_ptr)
             } else {
                 {
                     unsafe_bindings::heartbeat_receive_with_timeout(
-                        self.pointer,
+                        *self.pointer.lock(),
                         &mut plist_ptr,
                         timeout,
                     )
@@ -116,11 +126,28 @@ impl HeartbeatClient {
     /// ***Verified:*** False
     pub fn receive_async(&self, timeout: u32) -> HeartbeatClientFuture {
         HeartbeatClientFuture {
-            pointer: self.pointer,
+            pointer: *self.pointer.lock(),
             start_time: std::time::Instant::now(),
             timeout,
         }
     }
+
+    /// Closes the heartbeat connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), HeartbeatError> {
+        let result = self.pointer.close().into();
+        if result != HeartbeatError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
 pub struct HeartbeatClientFuture {
@@ -172,12 +199,3 @@ impl Future for HeartbeatClientFuture {
         std::task::Poll::Pending
     }
 }
-
-impl Drop for HeartbeatClient {
-    fn drop(&mut self) {
-        info!("Dropping heartbeat client");
-        unsafe {
-            unsafe_bindings::heartbeat_client_free(self.pointer);
-        }
-    }
-}
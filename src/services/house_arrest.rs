@@ -3,7 +3,10 @@
 use std::ffi::CString;
 
 use crate::{
-    bindings as unsafe_bindings, error::HouseArrestError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::HouseArrestError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 
@@ -11,13 +14,19 @@ use plist_plus::Plist;
 
 /// iTunes file transfer service.
 /// This differs from AFC in that this is for managing files in app specific storage accessable by iTunes.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct HouseArrest<'a> {
-    pub(crate) pointer: unsafe_bindings::house_arrest_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::house_arrest_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl HouseArrest<'_> {
+impl ClientPointer for unsafe_bindings::house_arrest_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::house_arrest_client_free(self)
+    }
+}
+
+impl<'a> HouseArrest<'a> {
     /// Creates a new house arrest service from a lockdown service
     /// # Arguments
     /// * `device` - The device to create the sevice with
@@ -26,7 +35,7 @@ impl HouseArrest<'_> {
     /// A struct containing the handle to the service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, service: &LockdowndService) -> Result<Self, HouseArrestError> {
+    pub fn new(device: &'a Device, service: &LockdowndService) -> Result<Self, HouseArrestError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::house_arrest_client_new(device.pointer, service.pointer, &mut pointer)
@@ -38,7 +47,7 @@ impl HouseArrest<'_> {
         }
 
         Ok(HouseArrest {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -52,7 +61,7 @@ impl HouseArrest<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, HouseArrestError> {
         let mut pointer = std::ptr::null_mut();
@@ -71,7 +80,7 @@ impl HouseArrest<'_> {
         }
 
         Ok(HouseArrest {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -85,7 +94,7 @@ impl HouseArrest<'_> {
     /// ***Verified:*** False
     pub fn send_request(&self, request: Plist) -> Result<Plist, HouseArrestError> {
         let result = unsafe {
-            unsafe_bindings::house_arrest_send_request(self.pointer, request.get_pointer())
+            unsafe_bindings::house_arrest_send_request(*self.pointer.lock(), request.get_pointer())
         }
         .into();
 
@@ -96,7 +105,8 @@ impl HouseArrest<'_> {
         // Get result
         let mut plist_t = std::ptr::null_mut();
         let result =
-            unsafe { unsafe_bindings::house_arrest_get_result(self.pointer, &mut plist_t) }.into();
+            unsafe { unsafe_bindings::house_arrest_get_result(*self.pointer.lock(), &mut plist_t) }
+                .into();
 
         if result != HouseArrestError::Success {
             return Err(result);
@@ -123,7 +133,7 @@ impl HouseArrest<'_> {
 
         let result = unsafe {
             unsafe_bindings::house_arrest_send_command(
-                self.pointer,
+                *self.pointer.lock(),
                 command_c_string.as_ptr(),
                 app_id_c_string.as_ptr(),
             )
@@ -137,7 +147,8 @@ impl HouseArrest<'_> {
         // Get result
         let mut plist_t = std::ptr::null_mut();
         let result =
-            unsafe { unsafe_bindings::house_arrest_get_result(self.pointer, &mut plist_t) }.into();
+            unsafe { unsafe_bindings::house_arrest_get_result(*self.pointer.lock(), &mut plist_t) }
+                .into();
 
         if result != HouseArrestError::Success {
             return Err(result);
@@ -145,12 +156,33 @@ impl HouseArrest<'_> {
 
         Ok(plist_t.into())
     }
-}
 
-impl Drop for HouseArrest<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::house_arrest_client_free(self.pointer);
+    /// Closes the house arrest connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), HouseArrestError> {
+        let result = self.pointer.close().into();
+        if result != HouseArrestError::Success {
+            return Err(result);
         }
+
+        Ok(())
+    }
+}
+
+impl<'a> Service<'a> for HouseArrest<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobile.house_arrest";
+    type Error = HouseArrestError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, HouseArrestError> {
+        Self::new(device, &descriptor)
     }
 }
@@ -1,17 +1,23 @@
 // jkcoxson
 
 use std::{
+    collections::HashMap,
     ffi::{CStr, CString},
     mem::ManuallyDrop,
     ops::Deref,
     sync::Mutex,
 };
 
-use crate::{bindings as unsafe_bindings, error::InstProxyError, idevice::Device};
+use crate::{
+    bindings as unsafe_bindings,
+    error::InstProxyError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
+};
 
 use log::info;
 use once_cell::sync::Lazy;
-use plist_plus::Plist;
+use plist_plus::{Plist, PlistType};
 
 pub struct CommandPlist(ManuallyDrop<Plist>);
 pub struct StatusPlist(ManuallyDrop<Plist>);
@@ -52,13 +58,19 @@ static INSTALLATION_CALLBACK: Lazy<Mutex<Option<CallbackFunction>>> =
     Lazy::new(|| Mutex::new(None));
 
 /// Manages installing, removing and modifying applications on the device
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct InstProxyClient<'a> {
-    pub(crate) pointer: unsafe_bindings::instproxy_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::instproxy_client_t>,
     pub label: String,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
+impl ClientPointer for unsafe_bindings::instproxy_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::instproxy_client_free(self)
+    }
+}
+
 unsafe extern "C" fn installation_status_callback(
     command: *mut ::std::os::raw::c_void,
     status: *mut ::std::os::raw::c_void,
@@ -79,10 +91,7 @@ unsafe extern "C" fn installation_status_callback(
     }
 }
 
-unsafe impl Send for InstProxyClient<'_> {}
-unsafe impl Sync for InstProxyClient<'_> {}
-
-impl InstProxyClient<'_> {
+impl<'a> InstProxyClient<'a> {
     /// Starts a new service with house arrest
     /// # Arguments
     /// * `device` - The device to create the sevice with
@@ -91,7 +100,7 @@ impl InstProxyClient<'_> {
     /// A struct containing the handle to the service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, label: impl Into<String>) -> Result<Self, InstProxyError> {
+    pub fn new(device: &'a Device, label: impl Into<String>) -> Result<Self, InstProxyError> {
         let label: String = label.into();
         let mut instproxy_client = unsafe { std::mem::zeroed() };
         let label_c_string = CString::new(label.clone()).unwrap();
@@ -110,7 +119,7 @@ impl InstProxyClient<'_> {
         }
 
         Ok(InstProxyClient {
-            pointer: instproxy_client,
+            pointer: ClientHandle::new(instproxy_client),
             label,
             phantom: std::marker::PhantomData,
         })
@@ -128,13 +137,17 @@ impl InstProxyClient<'_> {
 
         let result = if option == BrowseOption::None {
             unsafe {
-                unsafe_bindings::instproxy_browse(self.pointer, std::ptr::null_mut(), &mut plist)
+                unsafe_bindings::instproxy_browse(
+                    *self.pointer.lock(),
+                    std::ptr::null_mut(),
+                    &mut plist,
+                )
             }
         } else {
             let option_plist: Plist = option.into();
             unsafe {
                 unsafe_bindings::instproxy_browse(
-                    self.pointer,
+                    *self.pointer.lock(),
                     option_plist.get_pointer(),
                     &mut plist,
                 )
@@ -161,7 +174,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_browse(
-                self.pointer,
+                *self.pointer.lock(),
                 client_options.get_pointer(),
                 &mut plist,
             )
@@ -216,9 +229,11 @@ impl InstProxyClient<'_> {
     }
 
     /// Looks up information about apps on the device
+    /// By default every attribute of every app is returned, which is slow on devices with many apps.
+    /// Pass an `InstproxyOptions` with `return_attributes` set to only fetch what is needed.
     /// # Arguments
     /// * `app_ids` - The bundle ID's of apps to lookup information about
-    /// * `client_options` - A plist containing options for the lookup. Create with `create_return_attributes`
+    /// * `client_options` - A plist containing options for the lookup. Create with `InstproxyOptions` or `create_return_attributes`
     /// # Returns
     /// A plist with the lookup results
     ///
@@ -255,7 +270,7 @@ impl InstProxyClient<'_> {
         info!("Instproxy lookup");
         let result = unsafe {
             unsafe_bindings::instproxy_lookup(
-                self.pointer,
+                *self.pointer.lock(),
                 cstring_pointers_ptr,
                 opt_ptr,
                 &mut res_plist,
@@ -272,6 +287,66 @@ impl InstProxyClient<'_> {
         Ok(res_plist.into())
     }
 
+    /// Looks up the install paths of apps, only requesting the attributes needed to launch them.
+    /// This is much faster than a full `lookup`, since the device doesn't serialize every attribute.
+    /// # Arguments
+    /// * `bundle_ids` - The bundle ID's of the apps
+    /// # Returns
+    /// A map of bundle ID to the path of the app bundle. Apps that aren't installed are left out.
+    ///
+    /// ***Verified:*** False
+    pub fn lookup_paths(
+        &self,
+        bundle_ids: &[&str],
+    ) -> Result<HashMap<String, String>, InstProxyError> {
+        let options = InstproxyOptions::new().return_attributes(["Path", "CFBundleExecutable"]);
+        let results = self.lookup(
+            bundle_ids.iter().map(|id| id.to_string()).collect(),
+            Some(options.into()),
+        )?;
+
+        let mut paths = HashMap::new();
+        for app in results {
+            let bundle_id = match app.key {
+                Some(k) => k,
+                None => continue,
+            };
+            let path = app
+                .plist
+                .dict_get_item("Path")
+                .ok()
+                .and_then(|p| p.get_string_val().ok());
+            if let Some(path) = path {
+                paths.insert(bundle_id, path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Looks up apps along with their entitlements and required device capabilities.
+    /// Only the attributes `AppInfo` reads are requested, so this is cheaper than a full `lookup`.
+    /// # Arguments
+    /// * `bundle_ids` - The bundle ID's of the apps
+    /// # Returns
+    /// The apps that are installed, in no particular order
+    ///
+    /// ***Verified:*** False
+    pub fn lookup_app_info(&self, bundle_ids: &[&str]) -> Result<Vec<AppInfo>, InstProxyError> {
+        let options = InstproxyOptions::new().return_attributes(AppInfo::ATTRIBUTES);
+        let results = self.lookup(
+            bundle_ids.iter().map(|id| id.to_string()).collect(),
+            Some(options.into()),
+        )?;
+
+        Ok(results
+            .into_iter()
+            .filter_map(|app| {
+                app.key
+                    .map(|bundle_id| AppInfo::from_plist(bundle_id, &app.plist))
+            })
+            .collect())
+    }
+
     /// Installs a package on the device
     /// # Arguments
     /// * `pkg_path` - The path to the .ipa or other package bundle
@@ -294,7 +369,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_install(
-                self.pointer,
+                *self.pointer.lock(),
                 pkg_path_c_string.as_ptr(),
                 ptr,
                 None, // I feel like this will segfault. The bindings are probably wrong.
@@ -333,7 +408,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_install(
-                self.pointer,
+                *self.pointer.lock(),
                 pkg_path_c_string.as_ptr(),
                 ptr,
                 Some(installation_status_callback), // I feel like this will segfault. The bindings are probably wrong.
@@ -373,7 +448,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_upgrade(
-                self.pointer,
+                *self.pointer.lock(),
                 pkg_path_c_string.as_ptr(),
                 ptr,
                 None, // I feel like this will segfault. The bindings are probably wrong.
@@ -410,7 +485,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_uninstall(
-                self.pointer,
+                *self.pointer.lock(),
                 app_id_c_string.as_ptr(),
                 ptr,
                 None, // I feel like this will segfault. The bindings are probably wrong.
@@ -441,7 +516,7 @@ impl InstProxyClient<'_> {
             .map_or(std::ptr::null_mut(), |v| v.get_pointer());
 
         let result = unsafe {
-            unsafe_bindings::instproxy_lookup_archives(self.pointer, ptr, &mut res_plist)
+            unsafe_bindings::instproxy_lookup_archives(*self.pointer.lock(), ptr, &mut res_plist)
         }
         .into();
         if result != InstProxyError::Success {
@@ -473,7 +548,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_archive(
-                self.pointer,
+                *self.pointer.lock(),
                 app_id_c_string.as_ptr(),
                 ptr,
                 None, // I feel like this will segfault. The bindings are probably wrong.
@@ -509,7 +584,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_restore(
-                self.pointer,
+                *self.pointer.lock(),
                 app_id_c_string.as_ptr(),
                 ptr,
                 None, // I feel like this will segfault. The bindings are probably wrong.
@@ -545,7 +620,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_remove_archive(
-                self.pointer,
+                *self.pointer.lock(),
                 app_id_c_string.as_ptr(),
                 ptr,
                 None, // I feel like this will segfault. The bindings are probably wrong.
@@ -564,7 +639,7 @@ impl InstProxyClient<'_> {
     /// * `capabilities` - A list of capabilities to check
     /// * `client_options` - The options for checking. There are no known uses of this, pass None.
     /// # Returns
-    /// A plist with the results of the check
+    /// A plist with the results of the check, see `has_capabilities` for a parsed answer
     ///
     /// ***Verified:*** False
     pub fn check_capabilities_match(
@@ -587,7 +662,7 @@ impl InstProxyClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::instproxy_check_capabilities_match(
-                self.pointer,
+                *self.pointer.lock(),
                 capabilities_c_str_ptrs.as_mut_ptr(),
                 ptr,
                 &mut res_plist,
@@ -600,6 +675,45 @@ impl InstProxyClient<'_> {
         Ok(res_plist.into())
     }
 
+    /// Checks whether the device has all of the given capabilities, such as `arm64e`.
+    /// Useful for refusing an install early with a clear message instead of failing partway through.
+    /// # Arguments
+    /// * `capabilities` - The capabilities the device needs
+    /// * `client_options` - The options for checking. There are no known uses of this, pass None.
+    /// # Returns
+    /// Whether the device has every capability
+    ///
+    /// ***Verified:*** False
+    pub fn has_capabilities(
+        &self,
+        capabilities: &[&str],
+        client_options: Option<Plist>,
+    ) -> Result<bool, InstProxyError> {
+        let result = self.check_capabilities_match(
+            capabilities.iter().map(|c| c.to_string()).collect(),
+            client_options,
+        )?;
+
+        // Depending on the version, the device answers with a single bool,
+        // a bool for each capability, or a list of the capabilities that are missing
+        match result.plist_type {
+            PlistType::Boolean => result
+                .get_bool_val()
+                .map_err(|_| InstProxyError::PlistError),
+            PlistType::Dictionary => {
+                for capability in result.into_iter() {
+                    if !capability.plist.get_bool_val().unwrap_or(false) {
+                        info!("Device is missing capability {:?}", capability.key);
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            PlistType::Array => Ok(result.array_get_size().unwrap_or(0) == 0),
+            _ => Err(InstProxyError::PlistError),
+        }
+    }
+
     /// Gets the path for an app's bundle ID
     /// # Arguments
     /// * `bundle_identifier` - The bundle identifier of the app
@@ -617,7 +731,7 @@ impl InstProxyClient<'_> {
         info!("Instproxy get_path_for_bundle_identifier");
         let result = unsafe {
             unsafe_bindings::instproxy_client_get_path_for_bundle_identifier(
-                self.pointer,
+                *self.pointer.lock(),
                 bundle_id_c_string.as_ptr(),
                 &mut path_ptr,
             )
@@ -631,6 +745,23 @@ impl InstProxyClient<'_> {
         info!("Instproxy get_path_for_bundle_identifier done");
         Ok(unsafe { CStr::from_ptr(path_ptr).to_string_lossy().into_owned() })
     }
+
+    /// Closes the installation proxy connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), InstProxyError> {
+        let result = self.pointer.close().into();
+        if result != InstProxyError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
 /// The options that can be used when browsing installed apps
@@ -659,12 +790,147 @@ impl From<BrowseOption> for Plist {
     }
 }
 
-impl Drop for InstProxyClient<'_> {
-    fn drop(&mut self) {
-        info!("Dropping instproxy client");
-        unsafe {
-            unsafe_bindings::instproxy_client_free(self.pointer);
+/// A builder for the client options passed to `browse_with_options` and `lookup`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstproxyOptions {
+    application_type: Option<BrowseOption>,
+    return_attributes: Vec<String>,
+}
+
+impl InstproxyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only include apps of the given type
+    pub fn application_type(mut self, application_type: BrowseOption) -> Self {
+        self.application_type = Some(application_type);
+        self
+    }
+
+    /// Only return the given attributes for each app, such as `Path` or `CFBundleIdentifier`
+    pub fn return_attributes<S: Into<String>>(
+        mut self,
+        attributes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.return_attributes
+            .extend(attributes.into_iter().map(Into::into));
+        self
+    }
+}
+
+impl From<InstproxyOptions> for Plist {
+    fn from(options: InstproxyOptions) -> Self {
+        let mut dict = match options.application_type {
+            Some(option) if option != BrowseOption::None => option.into(),
+            _ => InstProxyClient::client_options_new(),
+        };
+        if !options.return_attributes.is_empty() {
+            let mut return_attributes = Plist::new_array();
+            for attribute in options.return_attributes {
+                return_attributes
+                    .array_append_item(Plist::new_string(&attribute))
+                    .unwrap();
+            }
+            dict.dict_set_item("ReturnAttributes", return_attributes)
+                .unwrap();
+        }
+        dict
+    }
+}
+
+/// An app returned by `lookup_app_info`, with its entitlements and capabilities parsed out of the lookup result.
+/// A result from `lookup` or `browse` works too, if it was asked for `Entitlements` and `UIRequiredDeviceCapabilities`.
+#[derive(Clone)]
+pub struct AppInfo {
+    pub bundle_id: String,
+    /// The path of the app bundle on the device
+    pub path: Option<String>,
+    /// The capabilities the device needs to run the app, such as `arm64` or `metal`
+    pub required_capabilities: Vec<String>,
+    entitlements: Option<Plist>,
+}
+
+impl AppInfo {
+    /// The attributes to request from instproxy to fill in every field
+    pub const ATTRIBUTES: [&'static str; 4] = [
+        "CFBundleIdentifier",
+        "Path",
+        "Entitlements",
+        "UIRequiredDeviceCapabilities",
+    ];
+
+    /// Reads an app out of a lookup or browse result
+    /// # Arguments
+    /// * `bundle_id` - The bundle ID of the app, the key of the result in a lookup
+    /// * `app` - The app's dictionary from the result
+    /// # Returns
+    /// The app, with the fields instproxy didn't return left empty
+    ///
+    /// ***Verified:*** False
+    pub fn from_plist(bundle_id: impl Into<String>, app: &Plist) -> Self {
+        let path = app
+            .dict_get_item("Path")
+            .ok()
+            .and_then(|p| p.get_string_val().ok());
+        let entitlements = app
+            .dict_get_item("Entitlements")
+            .ok()
+            .filter(|e| e.plist_type == PlistType::Dictionary);
+
+        // Older apps list the capabilities, newer ones map each to whether it's required
+        let required_capabilities = match app.dict_get_item("UIRequiredDeviceCapabilities") {
+            Ok(caps) if caps.plist_type == PlistType::Array => caps
+                .into_iter()
+                .filter_map(|cap| cap.plist.get_string_val().ok())
+                .collect(),
+            Ok(caps) if caps.plist_type == PlistType::Dictionary => caps
+                .into_iter()
+                .filter(|cap| cap.plist.get_bool_val().unwrap_or(false))
+                .filter_map(|cap| cap.key)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        AppInfo {
+            bundle_id: bundle_id.into(),
+            path,
+            required_capabilities,
+            entitlements,
+        }
+    }
+
+    /// Gets the app's entitlements
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A dictionary of entitlement to value, or `None` if instproxy didn't return them
+    ///
+    /// ***Verified:*** False
+    pub fn entitlements(&self) -> Option<&Plist> {
+        self.entitlements.as_ref()
+    }
+
+    /// Checks whether the app has an entitlement, such as `get-task-allow`, which means it can be debugged
+    /// # Arguments
+    /// * `key` - The entitlement to check for
+    /// # Returns
+    /// True if the app has the entitlement and it isn't set to false
+    ///
+    /// ***Verified:*** False
+    pub fn has_entitlement(&self, key: &str) -> bool {
+        let value = match self
+            .entitlements
+            .as_ref()
+            .and_then(|e| e.dict_get_item(key).ok())
+        {
+            Some(value) => value,
+            None => return false,
+        };
+        if value.plist_type == PlistType::Boolean {
+            return value.get_bool_val().unwrap_or(false);
         }
+        true
     }
 }
 
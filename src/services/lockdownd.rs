@@ -2,23 +2,52 @@
 
 use std::ffi::CString;
 use std::os::raw::c_char;
+use std::sync::Mutex;
 
 use crate::bindings as unsafe_bindings;
 use crate::error::LockdowndError;
-use crate::idevice::Device;
-
-use log::info;
-use plist_plus::Plist;
+use crate::idevice::{Device, IosVersion};
+use crate::service::{ClientHandle, ClientPointer, Direction, MessageHook};
+
+use log::{info, warn};
+#[cfg(feature = "openssl")]
+use openssl::{
+    asn1::Asn1Time,
+    bn::BigNum,
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{HasPublic, PKey, PKeyRef, Private},
+    rand::rand_bytes,
+    rsa::Rsa,
+    x509::{
+        extension::{BasicConstraints, KeyUsage, SubjectKeyIdentifier},
+        X509Builder, X509,
+    },
+};
+use plist_plus::{Plist, PlistType};
+
+/// How long the certificates of a generated pair record are valid for, matching libimobiledevice
+#[cfg(feature = "openssl")]
+const PAIR_CERT_VALID_DAYS: u32 = 365 * 10;
 
 /// A jumping point for other services.
 /// Lockdownd is in charge of starting other services and opening ports for them.
 /// Lockdown can be used for simple data transactions, but most requests will be done through other services.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct LockdowndClient<'a> {
-    pub(crate) pointer: unsafe_bindings::lockdownd_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::lockdownd_client_t>,
+    hook: MessageHook,
+    /// The error string from the last `request` the device refused
+    device_error: Mutex<Option<String>>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
+impl ClientPointer for unsafe_bindings::lockdownd_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::lockdownd_client_free(self)
+    }
+}
+
 /// A pair record for lockdown
 #[derive(Debug)]
 pub struct LockdowndPairRecord {
@@ -29,9 +58,66 @@ pub struct LockdowndPairRecord {
     pub system_buid: String,
 }
 
-unsafe impl Send for LockdowndClient<'_> {}
-unsafe impl Sync for LockdowndClient<'_> {}
+/// Whether a device has been activated, as reported by lockdownd's `ActivationState` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActivationState {
+    Unactivated,
+    Activated,
+    /// Activated at the factory, as with demo and development devices
+    FactoryActivated,
+    /// A state this crate doesn't know, such as `MismatchedIMEI`, with the string the device sent
+    Unknown(String),
+}
+
+/// The identifying values of a device, as read by `LockdowndClient::device_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub udid: String,
+    /// The name the user gave the device
+    pub name: Option<String>,
+    /// The product type, such as `iPhone14,2`
+    pub product_type: String,
+    pub ios_version: IosVersion,
+    /// The iOS build, such as `20E252`
+    pub build_version: Option<String>,
+    pub serial_number: Option<String>,
+    /// The hardware model, such as `D63AP`
+    pub hardware_model: Option<String>,
+    /// The kind of device, such as `iPhone` or `iPad`
+    pub device_class: Option<String>,
+    /// The MAC address of the WiFi interface
+    pub wifi_address: Option<String>,
+    /// The MAC address of the Bluetooth interface
+    pub bluetooth_address: Option<String>,
+    pub phone_number: Option<String>,
+    pub activation_state: Option<ActivationState>,
+    /// The battery level in percent
+    pub battery_level: Option<u64>,
+    pub battery_charging: Option<bool>,
+}
 
+impl From<&str> for ActivationState {
+    fn from(state: &str) -> Self {
+        match state {
+            "Unactivated" => ActivationState::Unactivated,
+            "Activated" => ActivationState::Activated,
+            "FactoryActivated" => ActivationState::FactoryActivated,
+            _ => ActivationState::Unknown(state.to_string()),
+        }
+    }
+}
+
+/// A service started by lockdownd, used to connect the service's client.
+/// The service wrappers pass this to libimobiledevice's `*_client_new` functions, which enable SSL
+/// when the descriptor asks for it. Raw connections should use `DeviceConnection::service_connect`.
+/// Descriptors built by hand must set the SSL flag to match the service, or the device will reset the connection.
+///
+/// The client constructors take the descriptor by value and free it once connected, except for
+/// `AfcClient::with_service`, which keeps it for `AfcClient::lockdownd_service`.
+/// The device listens on the service's port for a single connection, so a descriptor can't be used
+/// to connect twice. To open another client, call `start_service` again on the same `LockdowndClient`;
+/// the session is reused, so that doesn't repeat the handshake.
+#[derive(Debug)]
 pub struct LockdowndService<'a> {
     pub(crate) pointer: unsafe_bindings::lockdownd_service_descriptor_t,
     pub port: u32,
@@ -41,7 +127,7 @@ pub struct LockdowndService<'a> {
 unsafe impl Send for LockdowndService<'_> {}
 unsafe impl Sync for LockdowndService<'_> {}
 
-impl LockdowndClient<'_> {
+impl<'a> LockdowndClient<'a> {
     /// Creates a new lockdown service and starts initial handshake
     /// # Arguments
     /// * `device` - The device to start the service on
@@ -50,7 +136,7 @@ impl LockdowndClient<'_> {
     /// A struct containing the handle to the service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, label: impl Into<String>) -> Result<Self, LockdowndError> {
+    pub fn new(device: &'a Device, label: impl Into<String>) -> Result<Self, LockdowndError> {
         let mut client: unsafe_bindings::lockdownd_client_t = unsafe { std::mem::zeroed() };
         let client_ptr: *mut unsafe_bindings::lockdownd_client_t = &mut client;
 
@@ -71,7 +157,9 @@ impl LockdowndClient<'_> {
         }
 
         Ok(LockdowndClient {
-            pointer: unsafe { *client_ptr },
+            pointer: ClientHandle::new(unsafe { *client_ptr }),
+            hook: MessageHook::default(),
+            device_error: Mutex::new(None),
             phantom: std::marker::PhantomData,
         })
     }
@@ -108,7 +196,7 @@ impl LockdowndClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::lockdownd_get_value(
-                self.pointer,
+                *self.pointer.lock(),
                 domain_c_string_ptr,
                 key_c_str_ptr,
                 &mut value,
@@ -155,7 +243,7 @@ impl LockdowndClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::lockdownd_set_value(
-                self.pointer,
+                *self.pointer.lock(),
                 domain_c_string_ptr,
                 key_c_string_ptr,
                 value.get_pointer(),
@@ -202,7 +290,7 @@ impl LockdowndClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::lockdownd_remove_value(
-                self.pointer,
+                *self.pointer.lock(),
                 domain_c_string_ptr,
                 key_c_string_ptr,
             )
@@ -244,8 +332,8 @@ impl LockdowndClient<'_> {
         info!("Starting lockdown service");
         let result = if escrow_bag {
             unsafe {
-                unsafe_bindings::lockdownd_start_service(
-                    self.pointer,
+                unsafe_bindings::lockdownd_start_service_with_escrow_bag(
+                    *self.pointer.lock(),
                     label_c_string_ptr,
                     &mut service,
                 )
@@ -253,8 +341,8 @@ impl LockdowndClient<'_> {
             .into()
         } else {
             unsafe {
-                unsafe_bindings::lockdownd_start_service_with_escrow_bag(
-                    self.pointer,
+                unsafe_bindings::lockdownd_start_service(
+                    *self.pointer.lock(),
                     label_c_string_ptr,
                     &mut service,
                 )
@@ -275,6 +363,26 @@ impl LockdowndClient<'_> {
         })
     }
 
+    /// Commands lockdownd to start a service using the escrow bag from the pair record.
+    /// Services that access data protected by the passcode, such as `com.apple.mobilebackup2`,
+    /// must be started this way or they will fail on passcode-locked devices.
+    /// # Arguments
+    /// * `identifier` - The identifier of the service to start
+    /// # Returns
+    /// A raw lockdownd service.
+    /// `PasswordProtected` is returned if the device is locked and must be unlocked by the user.
+    ///
+    /// ***Verified:*** False
+    pub fn start_service_with_escrow_bag(
+        &mut self,
+        identifier: impl Into<String>,
+    ) -> Result<LockdowndService, LockdowndError> {
+        match self.start_service(identifier, true) {
+            Err(LockdowndError::EscrowLocked) => Err(LockdowndError::PasswordProtected),
+            res => res,
+        }
+    }
+
     /// Opens a session with lockdownd and switches to SSL if requested by the device
     /// # Arguments
     /// * `host_id` - The ID of the host
@@ -292,7 +400,7 @@ impl LockdowndClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::lockdownd_start_session(
-                self.pointer,
+                *self.pointer.lock(),
                 host_id_c_string.as_ptr(),
                 &mut session_id,
                 &mut ssl_enabled,
@@ -329,9 +437,10 @@ impl LockdowndClient<'_> {
             session_id_c_str.as_ptr()
         };
 
-        let result =
-            unsafe { unsafe_bindings::lockdownd_stop_session(self.pointer, session_id_c_str_ptr) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::lockdownd_stop_session(*self.pointer.lock(), session_id_c_str_ptr)
+        }
+        .into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -340,6 +449,67 @@ impl LockdowndClient<'_> {
         Ok(())
     }
 
+    /// Starts a session with the host ID from a pair record and makes sure the connection
+    /// switched to SSL if the device asked for it.
+    /// The session is stopped when the returned guard is dropped.
+    /// # Arguments
+    /// * `pair_record` - The pair record of this host
+    /// # Returns
+    /// A guard for the session
+    ///
+    /// ***Verified:*** False
+    pub fn start_authenticated_session(
+        &self,
+        pair_record: &LockdowndPairRecord,
+    ) -> Result<SessionGuard<'_>, LockdowndError> {
+        let (session_id, ssl_enabled) = self.start_session(pair_record.host_id.clone())?;
+
+        // libimobiledevice switches to SSL itself, but an old build may leave it to the caller
+        if ssl_enabled && !self.enable_session_ssl() {
+            warn!("Unable to enable SSL for the lockdownd session");
+            if let Err(e) = self.stop_session(session_id) {
+                warn!("Unable to stop the lockdownd session: {}", e);
+            }
+            return Err(LockdowndError::SslError);
+        }
+
+        Ok(SessionGuard {
+            client: self,
+            session_id: Some(session_id),
+            ssl_enabled,
+        })
+    }
+
+    /// Switches the connection to SSL unless libimobiledevice already has, holding the lock throughout
+    fn enable_session_ssl(&self) -> bool {
+        let pointer = self.pointer.lock();
+        unsafe {
+            if (**pointer).ssl_enabled != 0 {
+                return true;
+            }
+            let result = unsafe_bindings::property_list_service_enable_ssl((**pointer).parent);
+            if result
+                != unsafe_bindings::property_list_service_error_t_PROPERTY_LIST_SERVICE_E_SUCCESS
+            {
+                return false;
+            }
+            (**pointer).ssl_enabled = 1;
+        }
+        true
+    }
+
+    /// Registers a hook that sees every message passed to `send` and returned from `receive`.
+    /// Useful for inspecting the protocol. Replaces any hook registered before
+    /// # Arguments
+    /// * `hook` - Called with the direction and the message
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn on_message(&mut self, hook: impl FnMut(Direction, &Plist) + Send + 'static) {
+        self.hook.set(hook);
+    }
+
     /// Sends a message to lockdownd
     /// # Arguments
     /// * `message` - The message to send
@@ -348,8 +518,10 @@ impl LockdowndClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn send(&self, message: Plist) -> Result<(), LockdowndError> {
+        self.hook.call(Direction::Sent, &message);
         let result =
-            unsafe { unsafe_bindings::lockdownd_send(self.pointer, message.get_pointer()) }.into();
+            unsafe { unsafe_bindings::lockdownd_send(*self.pointer.lock(), message.get_pointer()) }
+                .into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -369,13 +541,58 @@ impl LockdowndClient<'_> {
     pub fn receive(&self) -> Result<Plist, LockdowndError> {
         let mut plist: unsafe_bindings::plist_t = unsafe { std::mem::zeroed() };
 
-        let result = unsafe { unsafe_bindings::lockdownd_receive(self.pointer, &mut plist) }.into();
+        let result =
+            unsafe { unsafe_bindings::lockdownd_receive(*self.pointer.lock(), &mut plist) }.into();
 
         if result != LockdowndError::Success {
             return Err(result);
         }
 
-        Ok(plist.into())
+        let plist: Plist = plist.into();
+        self.hook.call(Direction::Received, &plist);
+        Ok(plist)
+    }
+
+    /// Sends a request to lockdownd and reads its response, for requests this crate has no function for.
+    /// The message is sent as is, so it needs at least a `Request` key naming the request.
+    /// # Arguments
+    /// * `message` - The request dictionary
+    /// # Returns
+    /// The response dictionary, or `DeviceError` if it has an `Error` key, see `device_error`
+    ///
+    /// ***Verified:*** False
+    pub fn request(&self, message: Plist) -> Result<Plist, LockdowndError> {
+        if message.plist_type != PlistType::Dictionary {
+            warn!("Lockdownd requests must be dictionaries");
+            return Err(LockdowndError::InvalidArg);
+        }
+        self.send(message)?;
+
+        let response = self.receive()?;
+        if response.plist_type != PlistType::Dictionary {
+            return Err(LockdowndError::InvalidResponse);
+        }
+        if let Ok(error) = response.dict_get_item("Error") {
+            let error = error.get_string_val().unwrap_or_default();
+            warn!("Lockdownd request failed: {}", error);
+            *self.device_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error);
+            return Err(LockdowndError::DeviceError);
+        }
+        Ok(response)
+    }
+
+    /// The error string the device sent with the last `DeviceError` returned by `request`
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The error string, or None if no request has failed that way
+    ///
+    /// ***Verified:*** False
+    pub fn device_error(&self) -> Option<String> {
+        self.device_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
     }
 
     /// Attempts to pair with the device.
@@ -399,7 +616,7 @@ impl LockdowndClient<'_> {
         let result = if let Some(options) = options {
             unsafe {
                 unsafe_bindings::lockdownd_pair_with_options(
-                    self.pointer,
+                    *self.pointer.lock(),
                     pair_ptr,
                     options.get_pointer(),
                     &mut response,
@@ -407,7 +624,7 @@ impl LockdowndClient<'_> {
             }
             .into()
         } else {
-            unsafe { unsafe_bindings::lockdownd_pair(self.pointer, pair_ptr) }.into()
+            unsafe { unsafe_bindings::lockdownd_pair(*self.pointer.lock(), pair_ptr) }.into()
         };
 
         if result != LockdowndError::Success {
@@ -417,6 +634,62 @@ impl LockdowndClient<'_> {
         Ok(())
     }
 
+    /// Reads the pair record that the host stored for this device
+    /// The record can be passed to `validate_pair` or `start_session` flows that need the host's identity
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The host ID, system BUID and certificates of the pair record
+    ///
+    /// ***Verified:*** False
+    pub fn get_pair_record(&self) -> Result<LockdowndPairRecord, LockdowndError> {
+        let udid = unsafe { std::ffi::CStr::from_ptr((*(**self.pointer.lock()).device).udid) }
+            .to_string_lossy()
+            .into_owned();
+        info!("Reading pair record for {}", udid);
+        let record = match crate::services::userpref::read_pair_record(udid) {
+            Ok(record) => record,
+            Err(_) => return Err(LockdowndError::MissingPairRecord),
+        };
+
+        let get_string = |key: &str| -> Result<String, LockdowndError> {
+            record
+                .dict_get_item(key)
+                .map_err(|_| LockdowndError::InvalidPairRecord)?
+                .get_string_val()
+                .map_err(|_| LockdowndError::InvalidPairRecord)
+        };
+        let get_data = |key: &str| -> Result<String, LockdowndError> {
+            let item = record
+                .dict_get_item(key)
+                .map_err(|_| LockdowndError::InvalidPairRecord)?;
+            if item.plist_type != PlistType::Data {
+                return Err(LockdowndError::InvalidPairRecord);
+            }
+            let mut data = std::ptr::null_mut();
+            let mut length = 0;
+            unsafe {
+                unsafe_bindings::plist_get_data_val(item.get_pointer(), &mut data, &mut length)
+            };
+            if data.is_null() {
+                return Err(LockdowndError::InvalidPairRecord);
+            }
+            // The certificates are stored as PEM text
+            let bytes = unsafe { std::slice::from_raw_parts(data as *const u8, length as usize) };
+            let text = String::from_utf8_lossy(bytes).into_owned();
+            unsafe { libc::free(data as *mut libc::c_void) };
+            Ok(text)
+        };
+
+        Ok(LockdowndPairRecord {
+            device_certificate: get_data("DeviceCertificate")?,
+            host_certificate: get_data("HostCertificate")?,
+            root_certificate: get_data("RootCertificate")?,
+            host_id: get_string("HostID")?,
+            system_buid: get_string("SystemBUID")?,
+        })
+    }
+
     /// Validates that the device is paired with a specified host
     /// # Arguments
     /// * `pairing_record` - The host pairing record
@@ -426,9 +699,10 @@ impl LockdowndClient<'_> {
     /// ***Verified:*** False
     pub fn validate_pair(&self, pairing_record: LockdowndPairRecord) -> Result<(), LockdowndError> {
         let mut pairing_record = pairing_record.into();
-        let result =
-            unsafe { unsafe_bindings::lockdownd_validate_pair(self.pointer, &mut pairing_record) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::lockdownd_validate_pair(*self.pointer.lock(), &mut pairing_record)
+        }
+        .into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -447,7 +721,8 @@ impl LockdowndClient<'_> {
     pub fn unpair(&self, pairing_record: LockdowndPairRecord) -> Result<(), LockdowndError> {
         let mut pairing_record = pairing_record.into();
         let result =
-            unsafe { unsafe_bindings::lockdownd_unpair(self.pointer, &mut pairing_record) }.into();
+            unsafe { unsafe_bindings::lockdownd_unpair(*self.pointer.lock(), &mut pairing_record) }
+                .into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -456,38 +731,111 @@ impl LockdowndClient<'_> {
         Ok(())
     }
 
+    /// Gets whether the device has been activated
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The activation state, or `SessionRequired` if the device only answers within a trusted session
+    ///
+    /// ***Verified:*** False
+    pub fn activation_state(&self) -> Result<ActivationState, LockdowndError> {
+        let state = self
+            .get_value("ActivationState", "")
+            .map_err(session_required)?
+            .get_string_val()
+            .map_err(|_| LockdowndError::InvalidResponse)?;
+        Ok(ActivationState::from(state.as_str()))
+    }
+
+    /// Reads the common identifying values of the device in one request, plus the battery domain
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The device's info, or `MissingValue` if the device didn't report its UDID, product type or version.
+    /// The optional fields are `None` when the device doesn't report them, such as the phone number on an iPad
+    ///
+    /// ***Verified:*** False
+    pub fn device_info(&self) -> Result<DeviceInfo, LockdowndError> {
+        let values = self.get_value("", "")?;
+        let get_string = |key: &str| {
+            values
+                .dict_get_item(key)
+                .ok()
+                .and_then(|v| v.get_string_val().ok())
+        };
+        let require = |key: &str| {
+            get_string(key).ok_or_else(|| {
+                warn!("Lockdownd didn't report {}", key);
+                LockdowndError::MissingValue
+            })
+        };
+
+        let version = require("ProductVersion")?;
+        let ios_version = version.parse().map_err(|_| {
+            warn!("Unable to parse the iOS version {}", version);
+            LockdowndError::InvalidResponse
+        })?;
+
+        // The battery has its own domain, which some devices don't answer without a session
+        let battery = self.get_value("", "com.apple.mobile.battery").ok();
+        let battery_value = |key: &str| battery.as_ref().and_then(|b| b.dict_get_item(key).ok());
+
+        Ok(DeviceInfo {
+            udid: require("UniqueDeviceID")?,
+            name: get_string("DeviceName"),
+            product_type: require("ProductType")?,
+            ios_version,
+            build_version: get_string("BuildVersion"),
+            serial_number: get_string("SerialNumber"),
+            hardware_model: get_string("HardwareModel"),
+            device_class: get_string("DeviceClass"),
+            wifi_address: get_string("WiFiAddress"),
+            bluetooth_address: get_string("BluetoothAddress"),
+            phone_number: get_string("PhoneNumber"),
+            activation_state: get_string("ActivationState")
+                .map(|state| ActivationState::from(state.as_str())),
+            battery_level: battery_value("BatteryCurrentCapacity")
+                .and_then(|v| v.get_uint_val().ok()),
+            battery_charging: battery_value("BatteryIsCharging")
+                .and_then(|v| v.get_bool_val().ok()),
+        })
+    }
+
     /// Activates the device. You will need an activation record from Apple's servers. Only works with an open session.
     /// # Arguments
     /// * `activation_record` - The activation record from Apple's servers
     /// # Returns
-    /// *none*
+    /// *none*, or `SessionRequired` if there is no trusted session
     ///
     /// ***Verified:*** False
     pub fn activate(&self, activation_record: Plist) -> Result<(), LockdowndError> {
         let result = unsafe {
-            unsafe_bindings::lockdownd_activate(self.pointer, activation_record.get_pointer())
+            unsafe_bindings::lockdownd_activate(
+                *self.pointer.lock(),
+                activation_record.get_pointer(),
+            )
         }
         .into();
 
         if result != LockdowndError::Success {
-            return Err(result);
+            return Err(session_required(result));
         }
 
         Ok(())
     }
 
-    /// Deactivates a device, forcing it to show the "Activate with iTunes screen".
+    /// Deactivates a device, forcing it to show the "Activate with iTunes screen". Only works with an open session.
     /// # Arguments
     /// *none*
     /// # Returns
-    /// *none*
+    /// *none*, or `SessionRequired` if there is no trusted session
     ///
     /// ***Verified:*** False
     pub fn deactivate(&self) -> Result<(), LockdowndError> {
-        let result = unsafe { unsafe_bindings::lockdownd_deactivate(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::lockdownd_deactivate(*self.pointer.lock()) }.into();
 
         if result != LockdowndError::Success {
-            return Err(result);
+            return Err(session_required(result));
         }
 
         Ok(())
@@ -501,7 +849,8 @@ impl LockdowndClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn enter_recovery(&self) -> Result<(), LockdowndError> {
-        let result = unsafe { unsafe_bindings::lockdownd_enter_recovery(self.pointer) }.into();
+        let result =
+            unsafe { unsafe_bindings::lockdownd_enter_recovery(*self.pointer.lock()) }.into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -518,7 +867,7 @@ impl LockdowndClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn goodbye(self) -> Result<(), LockdowndError> {
-        let result = unsafe { unsafe_bindings::lockdownd_goodbye(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::lockdownd_goodbye(*self.pointer.lock()) }.into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -542,7 +891,9 @@ impl LockdowndClient<'_> {
             label_c_string.as_ptr()
         };
 
-        unsafe { unsafe_bindings::lockdownd_client_set_label(self.pointer, label_c_string_ptr) };
+        unsafe {
+            unsafe_bindings::lockdownd_client_set_label(*self.pointer.lock(), label_c_string_ptr)
+        };
     }
 
     /// Get the UDID of the device
@@ -555,9 +906,10 @@ impl LockdowndClient<'_> {
     pub fn get_device_udid(&self) -> Result<String, LockdowndError> {
         let mut udid_c_str = unsafe { std::mem::zeroed() };
 
-        let result =
-            unsafe { unsafe_bindings::lockdownd_get_device_udid(self.pointer, &mut udid_c_str) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::lockdownd_get_device_udid(*self.pointer.lock(), &mut udid_c_str)
+        }
+        .into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -580,9 +932,10 @@ impl LockdowndClient<'_> {
     pub fn get_device_name(&self) -> Result<String, LockdowndError> {
         let mut name_c_str = unsafe { std::mem::zeroed() };
 
-        let result =
-            unsafe { unsafe_bindings::lockdownd_get_device_name(self.pointer, &mut name_c_str) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::lockdownd_get_device_name(*self.pointer.lock(), &mut name_c_str)
+        }
+        .into();
 
         if result != LockdowndError::Success {
             return Err(result);
@@ -608,7 +961,7 @@ impl LockdowndClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::lockdownd_get_sync_data_classes(
-                self.pointer,
+                *self.pointer.lock(),
                 &mut classes_c_str,
                 &mut count,
             )
@@ -645,7 +998,8 @@ impl LockdowndClient<'_> {
     pub fn query_type(&self) -> Result<String, LockdowndError> {
         let mut type_c_str: *mut c_char = std::ptr::null_mut();
         let result =
-            unsafe { unsafe_bindings::lockdownd_query_type(self.pointer, &mut type_c_str) }.into();
+            unsafe { unsafe_bindings::lockdownd_query_type(*self.pointer.lock(), &mut type_c_str) }
+                .into();
         if result != LockdowndError::Success {
             return Err(result);
         }
@@ -657,6 +1011,137 @@ impl LockdowndClient<'_> {
 
         Ok(type_str)
     }
+
+    /// Says goodbye and closes the lockdownd connection, returning any error.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), LockdowndError> {
+        let result = self.pointer.close().into();
+        if result != LockdowndError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+
+    /// Takes the connection out of the client, so it can be kept without borrowing the device.
+    /// The connection still refers to the device, so it has to be dropped before the device is freed
+    pub(crate) fn into_handle(self) -> ClientHandle<unsafe_bindings::lockdownd_client_t> {
+        self.pointer
+    }
+
+    /// Wraps a connection taken out with `into_handle`, which has to have been made to `device`
+    pub(crate) fn from_handle(
+        _device: &'a Device,
+        pointer: ClientHandle<unsafe_bindings::lockdownd_client_t>,
+    ) -> Self {
+        LockdowndClient {
+            pointer,
+            hook: MessageHook::default(),
+            device_error: Mutex::new(None),
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+/// The private keys belonging to a generated pair record, as PEM.
+/// The host key is needed for SSL sessions with the device, so it must be stored with the record.
+#[cfg(feature = "openssl")]
+#[derive(Debug)]
+pub struct PairRecordKeys {
+    pub host_private_key: String,
+    pub root_private_key: String,
+}
+
+#[cfg(feature = "openssl")]
+impl LockdowndPairRecord {
+    /// Generates a pair record for a host that has never paired with the device.
+    /// This creates a root certificate, signs the host and device certificates with it,
+    /// and picks a random host ID and system BUID. The result can be passed to `pair`.
+    /// # Arguments
+    /// * `device_public_key` - The device's `DevicePublicKey` lockdownd value, a PEM RSA public key
+    /// # Returns
+    /// The pair record, and the private keys that go with it
+    ///
+    /// ***Verified:*** False
+    pub fn generate(
+        device_public_key: &[u8],
+    ) -> Result<(LockdowndPairRecord, PairRecordKeys), LockdowndError> {
+        let generate = || -> Result<(LockdowndPairRecord, PairRecordKeys), ErrorStack> {
+            let root_rsa = Rsa::generate(2048)?;
+            let host_rsa = Rsa::generate(2048)?;
+            let root_key = PKey::from_rsa(root_rsa.clone())?;
+            let host_key = PKey::from_rsa(host_rsa.clone())?;
+            let device_key = PKey::from_rsa(Rsa::public_key_from_pem_pkcs1(device_public_key)?)?;
+
+            let pem = |bytes: Vec<u8>| String::from_utf8_lossy(&bytes).into_owned();
+            let record = LockdowndPairRecord {
+                device_certificate: pem(
+                    sign_pair_certificate(&device_key, &root_key, false)?.to_pem()?
+                ),
+                host_certificate: pem(sign_pair_certificate(&host_key, &root_key, false)?.to_pem()?),
+                root_certificate: pem(sign_pair_certificate(&root_key, &root_key, true)?.to_pem()?),
+                host_id: random_uuid()?,
+                system_buid: random_uuid()?,
+            };
+            let keys = PairRecordKeys {
+                host_private_key: pem(host_rsa.private_key_to_pem()?),
+                root_private_key: pem(root_rsa.private_key_to_pem()?),
+            };
+            Ok((record, keys))
+        };
+
+        generate().map_err(|e| {
+            warn!("Unable to generate a pair record: {}", e);
+            LockdowndError::InvalidPairRecord
+        })
+    }
+}
+
+/// Builds a certificate for a pair record, signed by the root key
+#[cfg(feature = "openssl")]
+fn sign_pair_certificate<T: HasPublic>(
+    key: &PKeyRef<T>,
+    root_key: &PKeyRef<Private>,
+    is_root: bool,
+) -> Result<X509, ErrorStack> {
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&*BigNum::from_u32(0)?.to_asn1_integer()?)?;
+    builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+    builder.set_not_after(&*Asn1Time::days_from_now(PAIR_CERT_VALID_DAYS)?)?;
+    builder.set_pubkey(key)?;
+
+    if is_root {
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+    } else {
+        builder.append_extension(BasicConstraints::new().critical().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .key_encipherment()
+                .build()?,
+        )?;
+    }
+    let key_id = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(key_id)?;
+
+    builder.sign(root_key, MessageDigest::sha256())?;
+    Ok(builder.build())
+}
+
+/// Generates a random version 4 UUID in the uppercase form lockdownd uses for host IDs
+#[cfg(feature = "openssl")]
+fn random_uuid() -> Result<String, ErrorStack> {
+    let mut bytes = [0u8; 16];
+    rand_bytes(&mut bytes)?;
+    Ok(crate::service::format_uuid(bytes))
 }
 
 impl From<LockdowndPairRecord> for unsafe_bindings::lockdownd_pair_record {
@@ -685,10 +1170,76 @@ impl From<LockdowndPairRecord> for unsafe_bindings::lockdownd_pair_record {
     }
 }
 
-impl Drop for LockdowndClient<'_> {
+/// An open lockdownd session from `start_authenticated_session`, stopped when dropped
+#[derive(Debug)]
+pub struct SessionGuard<'a> {
+    client: &'a LockdowndClient<'a>,
+    session_id: Option<String>,
+    ssl_enabled: bool,
+}
+
+impl SessionGuard<'_> {
+    /// Gets the ID of the session
+    pub fn session_id(&self) -> &str {
+        self.session_id.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the session is encrypted with SSL
+    pub fn ssl_enabled(&self) -> bool {
+        self.ssl_enabled
+    }
+
+    /// Stops the session, returning the error that dropping would only log
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn stop(mut self) -> Result<(), LockdowndError> {
+        match self.session_id.take() {
+            Some(session_id) => self.client.stop_session(session_id),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SessionGuard<'_> {
     fn drop(&mut self) {
-        info!("Dropping LockdowndClient");
-        unsafe { unsafe_bindings::lockdownd_client_free(self.pointer) };
+        if let Some(session_id) = self.session_id.take() {
+            if let Err(e) = self.client.stop_session(session_id) {
+                warn!("Unable to stop the lockdownd session: {}", e);
+            }
+        }
+    }
+}
+
+/// Reports the errors lockdownd gives when an operation needs a trusted session as `SessionRequired`
+fn session_required(error: LockdowndError) -> LockdowndError {
+    match error {
+        LockdowndError::NoRunningSession
+        | LockdowndError::SessionInactive
+        | LockdowndError::GetProhibited => {
+            warn!("Lockdownd refused the operation without a trusted session");
+            LockdowndError::SessionRequired
+        }
+        e => e,
+    }
+}
+
+impl LockdowndService<'_> {
+    /// Whether the service must be spoken to over SSL
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// True if SSL must be enabled before talking to the service
+    ///
+    /// ***Verified:*** False
+    pub fn requires_ssl(&self) -> bool {
+        if self.pointer.is_null() {
+            return false;
+        }
+        unsafe { (*self.pointer).ssl_enabled != 0 }
     }
 }
 
@@ -700,3 +1251,51 @@ impl Drop for LockdowndService<'_> {
         }
     }
 }
+
+#[cfg(all(test, feature = "openssl"))]
+mod tests {
+    use super::*;
+
+    fn is_uuid(id: &str) -> bool {
+        let groups = id.split('-').map(str::len).collect::<Vec<_>>();
+        groups == [8, 4, 4, 4, 12]
+            && id
+                .chars()
+                .all(|c| c == '-' || c.is_ascii_digit() || c.is_ascii_uppercase())
+    }
+
+    #[test]
+    fn generated_pair_records_fill_every_field() {
+        let device_rsa = Rsa::generate(2048).unwrap();
+        let device_public_key = device_rsa.public_key_to_pem_pkcs1().unwrap();
+        let (record, keys) = LockdowndPairRecord::generate(&device_public_key).unwrap();
+
+        let root = X509::from_pem(record.root_certificate.as_bytes()).unwrap();
+        let host = X509::from_pem(record.host_certificate.as_bytes()).unwrap();
+        let device = X509::from_pem(record.device_certificate.as_bytes()).unwrap();
+        let root_key = root.public_key().unwrap();
+        for certificate in [&root, &host, &device] {
+            assert!(certificate.verify(&root_key).unwrap());
+        }
+
+        let device_key = PKey::from_rsa(device_rsa).unwrap();
+        assert!(device.public_key().unwrap().public_eq(&device_key));
+        let host_key = PKey::private_key_from_pem(keys.host_private_key.as_bytes()).unwrap();
+        assert!(host.public_key().unwrap().public_eq(&host_key));
+        let root_private_key =
+            PKey::private_key_from_pem(keys.root_private_key.as_bytes()).unwrap();
+        assert!(root_key.public_eq(&root_private_key));
+
+        assert!(is_uuid(&record.host_id), "{}", record.host_id);
+        assert!(is_uuid(&record.system_buid), "{}", record.system_buid);
+        assert_ne!(record.host_id, record.system_buid);
+    }
+
+    #[test]
+    fn pair_records_need_a_valid_device_key() {
+        assert_eq!(
+            LockdowndPairRecord::generate(b"not a key").err(),
+            Some(LockdowndError::InvalidPairRecord)
+        );
+    }
+}
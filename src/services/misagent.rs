@@ -1,7 +1,10 @@
 // jkcoxson
 
 use crate::{
-    bindings as unsafe_bindings, error::MisagentError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::MisagentError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 use std::{ffi::CString, os::raw::c_char};
@@ -9,13 +12,19 @@ use std::{ffi::CString, os::raw::c_char};
 use plist_plus::Plist;
 
 /// Manges and checks provisioning profiles
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct MisagentClient<'a> {
-    pub(crate) pointer: unsafe_bindings::misagent_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::misagent_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl MisagentClient<'_> {
+impl ClientPointer for unsafe_bindings::misagent_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::misagent_client_free(self)
+    }
+}
+
+impl<'a> MisagentClient<'a> {
     /// Creates a new misagent service connection to the device
     /// The use of this function is unknown
     /// # Arguments
@@ -24,7 +33,7 @@ impl MisagentClient<'_> {
     /// The lockdownd service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, MisagentError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, MisagentError> {
         let mut pointer = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::misagent_client_new(device.pointer, descriptor.pointer, &mut pointer)
@@ -35,7 +44,7 @@ impl MisagentClient<'_> {
         }
 
         Ok(MisagentClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -48,7 +57,10 @@ impl MisagentClient<'_> {
     /// An misagent service connection
     ///
     /// ***Verified:*** False
-    pub fn start_service(device: &Device, label: impl Into<String>) -> Result<Self, MisagentError> {
+    pub fn start_service(
+        device: &'a Device,
+        label: impl Into<String>,
+    ) -> Result<Self, MisagentError> {
         let mut pointer = unsafe { std::mem::zeroed() };
         let result = unsafe {
             unsafe_bindings::misagent_client_start_service(
@@ -63,7 +75,7 @@ impl MisagentClient<'_> {
         }
 
         Ok(MisagentClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -76,9 +88,10 @@ impl MisagentClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn install(&self, profile: Plist) -> Result<(), MisagentError> {
-        let result =
-            unsafe { unsafe_bindings::misagent_install(self.pointer, profile.get_pointer()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::misagent_install(*self.pointer.lock(), profile.get_pointer())
+        }
+        .into();
         if result != MisagentError::Success {
             return Err(result);
         }
@@ -96,9 +109,9 @@ impl MisagentClient<'_> {
     pub fn copy(&self, low_version: bool) -> Result<Plist, MisagentError> {
         let mut plist = unsafe { std::mem::zeroed() };
         let result = if low_version {
-            unsafe { unsafe_bindings::misagent_copy(self.pointer, &mut plist) }.into()
+            unsafe { unsafe_bindings::misagent_copy(*self.pointer.lock(), &mut plist) }.into()
         } else {
-            unsafe { unsafe_bindings::misagent_copy_all(self.pointer, &mut plist) }.into()
+            unsafe { unsafe_bindings::misagent_copy_all(*self.pointer.lock(), &mut plist) }.into()
         };
         if result != MisagentError::Success {
             return Err(result);
@@ -117,7 +130,8 @@ impl MisagentClient<'_> {
     pub fn remove(&self, id: impl Into<String>) -> Result<(), MisagentError> {
         let id_c_string = CString::new(id.into()).unwrap();
         let result =
-            unsafe { unsafe_bindings::misagent_remove(self.pointer, id_c_string.as_ptr()) }.into();
+            unsafe { unsafe_bindings::misagent_remove(*self.pointer.lock(), id_c_string.as_ptr()) }
+                .into();
         if result != MisagentError::Success {
             return Err(result);
         }
@@ -131,19 +145,40 @@ impl MisagentClient<'_> {
     /// # Returns
     /// The status code
     pub fn get_status_code(&self) -> Result<i32, MisagentError> {
-        let result = unsafe { unsafe_bindings::misagent_get_status_code(self.pointer) };
+        let result = unsafe { unsafe_bindings::misagent_get_status_code(*self.pointer.lock()) };
         if result == -1 {
             return Err(MisagentError::InvalidArg);
         }
 
         Ok(result)
     }
-}
 
-impl Drop for MisagentClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::misagent_client_free(self.pointer);
+    /// Closes the misagent connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), MisagentError> {
+        let result = self.pointer.close().into();
+        if result != MisagentError::Success {
+            return Err(result);
         }
+
+        Ok(())
+    }
+}
+
+impl<'a> Service<'a> for MisagentClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.misagent";
+    type Error = MisagentError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, MisagentError> {
+        Self::new(device, descriptor)
     }
 }
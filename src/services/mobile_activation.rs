@@ -3,19 +3,28 @@
 use std::ffi::CString;
 
 use crate::{
-    bindings as unsafe_bindings, error::MobileActivationError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::MobileActivationError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
     services::lockdownd::LockdowndService,
 };
 
 use plist_plus::Plist;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct MobileActivationClient<'a> {
-    pub(crate) pointer: unsafe_bindings::mobileactivation_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::mobileactivation_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl MobileActivationClient<'_> {
+impl ClientPointer for unsafe_bindings::mobileactivation_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::mobileactivation_client_free(self)
+    }
+}
+
+impl<'a> MobileActivationClient<'a> {
     /// Creates a new mobile activation service connection to the device
     /// The use of this function is unknown
     /// # Arguments
@@ -25,7 +34,7 @@ impl MobileActivationClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn new(
-        device: &Device,
+        device: &'a Device,
         descriptor: LockdowndService,
     ) -> Result<Self, MobileActivationError> {
         let mut client = unsafe { std::mem::zeroed() };
@@ -44,7 +53,7 @@ impl MobileActivationClient<'_> {
         }
 
         Ok(MobileActivationClient {
-            pointer: client,
+            pointer: ClientHandle::new(client),
             phantom: std::marker::PhantomData,
         })
     }
@@ -58,7 +67,7 @@ impl MobileActivationClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, MobileActivationError> {
         let label_c_string = CString::new(label.into()).unwrap();
@@ -78,7 +87,7 @@ impl MobileActivationClient<'_> {
         }
 
         Ok(MobileActivationClient {
-            pointer: client,
+            pointer: ClientHandle::new(client),
             phantom: std::marker::PhantomData,
         })
     }
@@ -94,7 +103,7 @@ impl MobileActivationClient<'_> {
         let mut plist = unsafe { std::mem::zeroed() };
 
         let result = unsafe {
-            unsafe_bindings::mobileactivation_get_activation_state(self.pointer, &mut plist)
+            unsafe_bindings::mobileactivation_get_activation_state(*self.pointer.lock(), &mut plist)
         }
         .into();
 
@@ -116,7 +125,7 @@ impl MobileActivationClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobileactivation_create_activation_session_info(
-                self.pointer,
+                *self.pointer.lock(),
                 &mut plist,
             )
         }
@@ -144,7 +153,7 @@ impl MobileActivationClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobileactivation_create_activation_info_with_session(
-                self.pointer,
+                *self.pointer.lock(),
                 plist,
                 &mut session_plist,
             )
@@ -174,12 +183,15 @@ impl MobileActivationClient<'_> {
         let result = unsafe {
             if let Some(session) = session {
                 unsafe_bindings::mobileactivation_activate_with_session(
-                    self.pointer,
+                    *self.pointer.lock(),
                     record.get_pointer(),
                     session.get_pointer(),
                 )
             } else {
-                unsafe_bindings::mobileactivation_activate(self.pointer, record.get_pointer())
+                unsafe_bindings::mobileactivation_activate(
+                    *self.pointer.lock(),
+                    record.get_pointer(),
+                )
             }
         }
         .into();
@@ -199,7 +211,8 @@ impl MobileActivationClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn deactivate(&self) -> Result<(), MobileActivationError> {
-        let result = unsafe { unsafe_bindings::mobileactivation_deactivate(self.pointer) }.into();
+        let result =
+            unsafe { unsafe_bindings::mobileactivation_deactivate(*self.pointer.lock()) }.into();
 
         if result != MobileActivationError::Success {
             return Err(result);
@@ -207,12 +220,21 @@ impl MobileActivationClient<'_> {
 
         Ok(())
     }
-}
 
-impl Drop for MobileActivationClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::mobileactivation_client_free(self.pointer);
+    /// Closes the mobile activation connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), MobileActivationError> {
+        let result = self.pointer.close().into();
+        if result != MobileActivationError::Success {
+            return Err(result);
         }
+
+        Ok(())
     }
 }
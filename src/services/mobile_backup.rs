@@ -2,34 +2,95 @@
 
 use std::{
     ffi::CString,
+    fs::{self, File},
+    io::{Read, Write},
     os::raw::{c_char, c_int, c_uint},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use crate::{
     bindings as unsafe_bindings,
     error::{MobileBackup2Error, MobileBackupError},
     idevice::Device,
-    services::lockdownd::LockdowndService,
+    service::{ClientHandle, ClientPointer, Service},
+    services::lockdownd::{LockdowndClient, LockdowndService},
 };
 
-use plist_plus::Plist;
+use log::{info, warn};
+use plist_plus::{Plist, PlistType};
+
+/// The mobilebackup2 protocol versions known to work with this crate.
+/// These are the same versions idevicebackup2 offers during the hello.
+pub const MOBILEBACKUP2_SUPPORTED_VERSIONS: [f64; 2] = [2.0, 2.1];
+
+// Codes that prefix each block of a file sent over the raw connection
+const CODE_SUCCESS: u8 = 0x00;
+const CODE_ERROR_REMOTE: u8 = 0x0b;
+const CODE_FILE_DATA: u8 = 0x0c;
+/// How much of a file is sent to the device per block
+const FILE_CHUNK_SIZE: usize = 32 * 1024;
+/// The status sent when some of the requested files couldn't be handled
+const STATUS_MULTI: c_int = -13;
+/// The status sent for requests that arrive after the backup was cancelled
+const STATUS_CANCELLED: c_int = -1;
+
+/// Cancels a running backup from another thread.
+/// Clones share the same flag, so one can be handed to the backup and another kept by the UI.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that hasn't been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the operation holding this token to stop.
+    /// The operation finishes the message it is handling and tells the device before returning.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` has been called on this token or any of its clones
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
 /// Manages backups on older devices
 /// This is only for old versions of iOS, you are probably looking for MobileBackup2
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct MobileBackupClient<'a> {
-    pub(crate) pointer: unsafe_bindings::mobilebackup_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::mobilebackup_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
+impl ClientPointer for unsafe_bindings::mobilebackup_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::mobilebackup_client_free(self)
+    }
+}
+
 /// Manages backups on new devices
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct MobileBackup2Client<'a> {
-    pub(crate) pointer: unsafe_bindings::mobilebackup2_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::mobilebackup2_client_t>,
+    /// The protocol version agreed with the device, once the versions have been exchanged
+    version: Mutex<Option<f64>>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl MobileBackupClient<'_> {
+impl ClientPointer for unsafe_bindings::mobilebackup2_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::mobilebackup2_client_free(self)
+    }
+}
+
+impl<'a> MobileBackupClient<'a> {
     /// Creates a new mobile backup service connection to the device
     /// The use of this function is unknown
     /// # Arguments
@@ -38,7 +99,7 @@ impl MobileBackupClient<'_> {
     /// The lockdownd service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, service: LockdowndService) -> Result<Self, MobileBackupError> {
+    pub fn new(device: &'a Device, service: LockdowndService) -> Result<Self, MobileBackupError> {
         let mut client = unsafe { std::mem::zeroed() };
 
         let result = unsafe {
@@ -51,7 +112,7 @@ impl MobileBackupClient<'_> {
         }
 
         Ok(MobileBackupClient {
-            pointer: client,
+            pointer: ClientHandle::new(client),
             phantom: std::marker::PhantomData,
         })
     }
@@ -65,7 +126,7 @@ impl MobileBackupClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, MobileBackupError> {
         let mut client = unsafe { std::mem::zeroed() };
@@ -84,7 +145,7 @@ impl MobileBackupClient<'_> {
         }
 
         Ok(MobileBackupClient {
-            pointer: client,
+            pointer: ClientHandle::new(client),
             phantom: std::marker::PhantomData,
         })
     }
@@ -101,7 +162,8 @@ impl MobileBackupClient<'_> {
         let mut plist = unsafe { std::mem::zeroed() };
 
         let result =
-            unsafe { unsafe_bindings::mobilebackup_receive(self.pointer, &mut plist) }.into();
+            unsafe { unsafe_bindings::mobilebackup_receive(*self.pointer.lock(), &mut plist) }
+                .into();
 
         if result != MobileBackupError::Success {
             return Err(result);
@@ -118,9 +180,10 @@ impl MobileBackupClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn send(&self, message: Plist) -> Result<(), MobileBackupError> {
-        let result =
-            unsafe { unsafe_bindings::mobilebackup_send(self.pointer, message.get_pointer()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::mobilebackup_send(*self.pointer.lock(), message.get_pointer())
+        }
+        .into();
 
         if result != MobileBackupError::Success {
             return Err(result);
@@ -149,7 +212,7 @@ impl MobileBackupClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobilebackup_request_backup(
-                self.pointer,
+                *self.pointer.lock(),
                 ptr,
                 base_path_c_string.as_ptr(),
                 backup_version_c_string.as_ptr(),
@@ -172,8 +235,10 @@ impl MobileBackupClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn send_backup_file_received(&self) -> Result<(), MobileBackupError> {
-        let result =
-            unsafe { unsafe_bindings::mobilebackup_send_backup_file_received(self.pointer) }.into();
+        let result = unsafe {
+            unsafe_bindings::mobilebackup_send_backup_file_received(*self.pointer.lock())
+        }
+        .into();
 
         if result != MobileBackupError::Success {
             return Err(result);
@@ -197,7 +262,7 @@ impl MobileBackupClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobilebackup_request_restore(
-                self.pointer,
+                *self.pointer.lock(),
                 manifest.get_pointer(),
                 flags.into(),
                 backup_version_c_string.as_ptr(),
@@ -224,7 +289,10 @@ impl MobileBackupClient<'_> {
         let mut plist = unsafe { std::mem::zeroed() };
 
         let result = unsafe {
-            unsafe_bindings::mobilebackup_receive_restore_file_received(self.pointer, &mut plist)
+            unsafe_bindings::mobilebackup_receive_restore_file_received(
+                *self.pointer.lock(),
+                &mut plist,
+            )
         }
         .into();
 
@@ -248,7 +316,7 @@ impl MobileBackupClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobilebackup_receive_restore_application_received(
-                self.pointer,
+                *self.pointer.lock(),
                 &mut plist,
             )
         }
@@ -271,7 +339,8 @@ impl MobileBackupClient<'_> {
     /// ***Verified:*** False
     pub fn send_restore_complete(&self) -> Result<(), MobileBackupError> {
         let result =
-            unsafe { unsafe_bindings::mobilebackup_send_restore_complete(self.pointer) }.into();
+            unsafe { unsafe_bindings::mobilebackup_send_restore_complete(*self.pointer.lock()) }
+                .into();
 
         if result != MobileBackupError::Success {
             return Err(result);
@@ -291,7 +360,7 @@ impl MobileBackupClient<'_> {
         let error_c_string = CString::new(error.into()).unwrap();
 
         let result = unsafe {
-            unsafe_bindings::mobilebackup_send_error(self.pointer, error_c_string.as_ptr())
+            unsafe_bindings::mobilebackup_send_error(*self.pointer.lock(), error_c_string.as_ptr())
         }
         .into();
 
@@ -301,9 +370,26 @@ impl MobileBackupClient<'_> {
 
         Ok(())
     }
+
+    /// Closes the mobilebackup connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), MobileBackupError> {
+        let result = self.pointer.close().into();
+        if result != MobileBackupError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
-impl MobileBackup2Client<'_> {
+impl<'a> MobileBackup2Client<'a> {
     /// Creates a new mobile backup service connection to the device
     /// The use of this function is unknown
     /// # Arguments
@@ -312,7 +398,7 @@ impl MobileBackup2Client<'_> {
     /// The lockdownd service
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, service: LockdowndService) -> Result<Self, MobileBackup2Error> {
+    pub fn new(device: &'a Device, service: LockdowndService) -> Result<Self, MobileBackup2Error> {
         let mut client = unsafe { std::mem::zeroed() };
 
         let result = unsafe {
@@ -325,43 +411,29 @@ impl MobileBackup2Client<'_> {
         }
 
         Ok(MobileBackup2Client {
-            pointer: client,
+            pointer: ClientHandle::new(client),
+            version: Mutex::new(None),
             phantom: std::marker::PhantomData,
         })
     }
 
-    /// Starts an afc service connection to the device
+    /// Starts a mobilebackup2 service connection to the device.
+    /// The service is started with the escrow bag, so backups work on passcode-protected devices.
     /// # Arguments
     /// * `device` - The device to create the service with
-    /// * `service_name` - The name of the service to start
+    /// * `label` - The label to give the lockdownd connection
     /// # Returns
-    /// An afc service connection
+    /// A mobilebackup2 service connection.
+    /// `DeviceLocked` is returned if the device must be unlocked first.
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, MobileBackup2Error> {
-        let mut client = unsafe { std::mem::zeroed() };
-        let label_c_string = CString::new(label.into()).unwrap();
-
-        let result = unsafe {
-            unsafe_bindings::mobilebackup2_client_start_service(
-                device.pointer,
-                &mut client,
-                label_c_string.as_ptr(),
-            )
-        }
-        .into();
-
-        if result != MobileBackup2Error::Success {
-            return Err(result);
-        }
-
-        Ok(MobileBackup2Client {
-            pointer: client,
-            phantom: std::marker::PhantomData,
-        })
+        let mut lockdownd = LockdowndClient::new(device, label)?;
+        let service = lockdownd.start_service_with_escrow_bag("com.apple.mobilebackup2")?;
+        Self::new(device, service)
     }
 
     /// Sends a message to the service
@@ -384,7 +456,7 @@ impl MobileBackup2Client<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobilebackup2_send_message(
-                self.pointer,
+                *self.pointer.lock(),
                 message_c_string_ptr,
                 options.get_pointer(),
             )
@@ -410,7 +482,11 @@ impl MobileBackup2Client<'_> {
         let mut options = unsafe { std::mem::zeroed() };
 
         let result = unsafe {
-            unsafe_bindings::mobilebackup2_receive_message(self.pointer, &mut options, &mut message)
+            unsafe_bindings::mobilebackup2_receive_message(
+                *self.pointer.lock(),
+                &mut options,
+                &mut message,
+            )
         }
         .into();
 
@@ -437,7 +513,7 @@ impl MobileBackup2Client<'_> {
         let mut sent = 0;
         let result = unsafe {
             unsafe_bindings::mobilebackup2_send_raw(
-                self.pointer,
+                *self.pointer.lock(),
                 data.as_ptr() as *const c_char,
                 data.len() as u32,
                 &mut sent,
@@ -465,7 +541,7 @@ impl MobileBackup2Client<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobilebackup2_receive_raw(
-                self.pointer,
+                *self.pointer.lock(),
                 data.as_mut_ptr() as *mut c_char,
                 len,
                 &mut received,
@@ -482,17 +558,24 @@ impl MobileBackup2Client<'_> {
     }
 
     /// Exchanges version with the service
+    /// The standard versions supported by host tools are in `MOBILEBACKUP2_SUPPORTED_VERSIONS`
     /// # Arguments
-    /// * `versions` - The versions to exchange
+    /// * `supported` - The protocol versions the host supports
     /// # Returns
-    /// * The version of the iOS device
+    /// The version agreed upon with the device.
+    /// `NoCommonVersion` is returned if the device shares none of the given versions.
     ///
     /// ***Verified:*** False
-    pub fn version_exchange(&self, versions: &mut [f64]) -> Result<f64, MobileBackup2Error> {
+    pub fn version_exchange(&self, supported: &[f64]) -> Result<f64, MobileBackup2Error> {
+        if supported.is_empty() {
+            return Err(MobileBackup2Error::InvalidArg);
+        }
+        // The C API takes a mutable pointer, so hand it a copy of the caller's slice
+        let mut versions = supported.to_vec();
         let mut version = 0.0;
         let result = unsafe {
             unsafe_bindings::mobilebackup2_version_exchange(
-                self.pointer,
+                *self.pointer.lock(),
                 versions.as_mut_ptr(),
                 versions.len() as c_char,
                 &mut version,
@@ -500,14 +583,45 @@ impl MobileBackup2Client<'_> {
         }
         .into();
 
-        if result != MobileBackup2Error::Success {
-            return Err(result);
+        match result {
+            MobileBackup2Error::Success => {}
+            MobileBackup2Error::NoCommonVersion => {
+                warn!(
+                    "Device does not support any of the mobilebackup2 versions {:?}",
+                    supported
+                );
+                return Err(result);
+            }
+            _ => return Err(result),
         }
 
+        if !supported.contains(&version) {
+            warn!(
+                "Device agreed on unsupported mobilebackup2 version {}",
+                version
+            );
+            return Err(MobileBackup2Error::NoCommonVersion);
+        }
+
+        *self.version.lock().unwrap_or_else(|e| e.into_inner()) = Some(version);
         Ok(version)
     }
 
-    /// Sends a request to the service
+    /// Exchanges version with the service using the standard supported versions.
+    /// `send_request` calls this if no version has been agreed yet, so backups and restores always negotiate.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The version agreed upon with the device
+    ///
+    /// ***Verified:*** False
+    pub fn negotiate_version(&self) -> Result<f64, MobileBackup2Error> {
+        self.version_exchange(&MOBILEBACKUP2_SUPPORTED_VERSIONS)
+    }
+
+    /// Sends a request to the service, such as a backup or a restore.
+    /// The device refuses requests until versions are exchanged, so `negotiate_version` is called first
+    /// if no version has been agreed on this connection yet.
     /// # Arguments
     /// * `request` - The type of request to send
     /// * `target` - The UDID of the target device
@@ -523,12 +637,21 @@ impl MobileBackup2Client<'_> {
         source: impl Into<String>,
         options: Plist,
     ) -> Result<(), MobileBackup2Error> {
+        let negotiated = self
+            .version
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some();
+        if !negotiated {
+            self.negotiate_version()?;
+        }
+
         let result = unsafe {
             let target_c_string = CString::new(target.into()).unwrap();
             let source_c_string = CString::new(source.into()).unwrap();
             let request: CString = request.into();
             unsafe_bindings::mobilebackup2_send_request(
-                self.pointer,
+                *self.pointer.lock(),
                 request.as_ptr(),
                 target_c_string.as_ptr(),
                 source_c_string.as_ptr(),
@@ -565,7 +688,7 @@ impl MobileBackup2Client<'_> {
 
         let result = unsafe {
             unsafe_bindings::mobilebackup2_send_status_response(
-                self.pointer,
+                *self.pointer.lock(),
                 code,
                 status_c_string_ptr,
                 status_plist,
@@ -579,6 +702,347 @@ impl MobileBackup2Client<'_> {
 
         Ok(())
     }
+
+    /// Backs up the device into a directory, answering the device's file requests until it's done.
+    /// The versions are exchanged by `send_request` unless that was already done on this connection.
+    /// The backup is written to `backup_dir/udid`, the same layout idevicebackup2 uses,
+    /// but no Info.plist is written for it.
+    ///
+    /// When the token is cancelled, the device is sent a cancel message and any requests already on their way
+    /// are answered with an error, so the device can clean up instead of leaving a half written backup.
+    /// # Arguments
+    /// * `udid` - The UDID of the device being backed up
+    /// * `backup_dir` - The directory to store backups in
+    /// * `progress` - Called with the overall progress from 0.0 to 100.0 whenever the device reports it
+    /// * `cancel` - Stops the backup when cancelled
+    /// # Returns
+    /// *none*, or `Cancelled` once the device has acknowledged a cancellation
+    ///
+    /// ***Verified:*** False
+    pub fn backup(
+        &self,
+        udid: impl Into<String>,
+        backup_dir: impl AsRef<Path>,
+        mut progress: impl FnMut(f64),
+        cancel: &CancellationToken,
+    ) -> Result<(), MobileBackup2Error> {
+        let udid = udid.into();
+        let backup_dir = backup_dir.as_ref();
+        fs::create_dir_all(backup_dir.join(&udid)).map_err(|e| {
+            warn!("Unable to create the backup directory: {}", e);
+            MobileBackup2Error::IoError
+        })?;
+
+        self.send_request(
+            MobileBackupRequest::Backup,
+            udid.clone(),
+            udid.clone(),
+            Plist::new_dict(),
+        )?;
+
+        let mut cancel_sent = false;
+        loop {
+            if cancel.is_cancelled() && !cancel_sent {
+                info!("Cancelling the backup of {}", udid);
+                let mut options = Plist::new_dict();
+                options
+                    .dict_set_item("TargetIdentifier", Plist::new_string(&udid))
+                    .map_err(|_| MobileBackup2Error::PlistError)?;
+                self.send_message(Some("Cancel".to_string()), options)?;
+                cancel_sent = true;
+            }
+
+            let (dl_message, message) = match self.receive_message() {
+                Ok(m) => m,
+                Err(MobileBackup2Error::ReceiveTimeout) => continue,
+                Err(e) if cancel_sent => {
+                    // The device is allowed to just hang up after a cancel
+                    info!("Device closed the connection after cancelling: {:?}", e);
+                    return Err(MobileBackup2Error::Cancelled);
+                }
+                Err(e) => return Err(e),
+            };
+            let items = message_items(&message);
+
+            match dl_message.as_str() {
+                "DLMessageUploadFiles" => {
+                    report_progress(&items, 2, &mut progress);
+                    // The file data follows the message, so it has to be read either way
+                    self.receive_files(backup_dir, cancel_sent)?;
+                }
+                "DLMessageDownloadFiles" => {
+                    report_progress(&items, 3, &mut progress);
+                    self.send_files(backup_dir, &items, cancel_sent)?;
+                }
+                "DLMessageProcessMessage" => {
+                    let status = items.get(1).ok_or(MobileBackup2Error::PlistError)?;
+                    let code = status
+                        .dict_get_item("ErrorCode")
+                        .ok()
+                        .and_then(|c| c.get_uint_val().ok())
+                        .unwrap_or(0);
+                    if code != 0 {
+                        if cancel_sent {
+                            return Err(MobileBackup2Error::Cancelled);
+                        }
+                        let description = status
+                            .dict_get_item("ErrorDescription")
+                            .ok()
+                            .and_then(|d| d.get_string_val().ok())
+                            .unwrap_or_default();
+                        warn!("Backup failed with code {}: {}", code, description);
+                        return Err(MobileBackup2Error::BackupFailed);
+                    }
+                    // The device may have finished before it saw the cancel
+                    progress(100.0);
+                    return Ok(());
+                }
+                "DLMessageDisconnect" => {
+                    if cancel_sent {
+                        return Err(MobileBackup2Error::Cancelled);
+                    }
+                    warn!("Device disconnected before the backup finished");
+                    return Err(MobileBackup2Error::BackupFailed);
+                }
+                _ if cancel_sent => {
+                    self.send_status_response(
+                        STATUS_CANCELLED,
+                        Some("Operation cancelled by the host".to_string()),
+                        Some(Plist::new_dict()),
+                    )?;
+                }
+                _ => {
+                    if matches!(
+                        dl_message.as_str(),
+                        "DLMessageMoveFiles"
+                            | "DLMessageMoveItems"
+                            | "DLMessageRemoveFiles"
+                            | "DLMessageRemoveItems"
+                    ) {
+                        report_progress(&items, 3, &mut progress);
+                    }
+                    let (response, status) = handle_file_message(backup_dir, &dl_message, &items);
+                    match status {
+                        Ok(()) => self.send_status_response(0, None, Some(response))?,
+                        Err((code, description)) => {
+                            self.send_status_response(code, Some(description), Some(response))?
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends the files the device asked for, in the framing used by the device link protocol
+    fn send_files(
+        &self,
+        backup_dir: &Path,
+        items: &[Plist],
+        cancelled: bool,
+    ) -> Result<(), MobileBackup2Error> {
+        let mut errors = Plist::new_dict();
+        let mut failed = false;
+
+        if !cancelled {
+            let paths = items.get(1).ok_or(MobileBackup2Error::PlistError)?;
+            for path in paths.clone().into_iter() {
+                let path = match path.plist.get_string_val() {
+                    Ok(p) => p,
+                    Err(_) => continue,
+                };
+                self.send_block(&(path.len() as u32).to_be_bytes(), path.as_bytes())?;
+
+                let sent = match backup_path(backup_dir, &path) {
+                    Ok(local) => self.send_file(&local)?,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = sent {
+                    let description = e.to_string();
+                    self.send_block(
+                        &(description.len() as u32 + 1).to_be_bytes(),
+                        &[&[CODE_ERROR_REMOTE], description.as_bytes()].concat(),
+                    )?;
+
+                    let mut error = Plist::new_dict();
+                    error
+                        .dict_set_item("DLFileErrorString", Plist::new_string(&description))
+                        .map_err(|_| MobileBackup2Error::PlistError)?;
+                    error
+                        .dict_set_item(
+                            "DLFileErrorCode",
+                            Plist::new_uint(device_error_code(&e) as u64),
+                        )
+                        .map_err(|_| MobileBackup2Error::PlistError)?;
+                    errors
+                        .dict_set_item(&path, error)
+                        .map_err(|_| MobileBackup2Error::PlistError)?;
+                    failed = true;
+                }
+            }
+        }
+
+        // A zero length ends the list of files
+        self.send_all(&0u32.to_be_bytes())?;
+
+        if cancelled {
+            self.send_status_response(
+                STATUS_CANCELLED,
+                Some("Operation cancelled by the host".to_string()),
+                Some(Plist::new_dict()),
+            )
+        } else if failed {
+            self.send_status_response(STATUS_MULTI, Some("Multi status".to_string()), Some(errors))
+        } else {
+            self.send_status_response(0, None, Some(Plist::new_dict()))
+        }
+    }
+
+    /// Sends the contents of one file.
+    /// The outer error is a connection failure, the inner one a local file that couldn't be read.
+    fn send_file(&self, path: &Path) -> Result<std::io::Result<()>, MobileBackup2Error> {
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => return Ok(Err(e)),
+        };
+        let mut chunk = vec![0u8; FILE_CHUNK_SIZE];
+        loop {
+            let read = match file.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => return Ok(Err(e)),
+            };
+            self.send_block(
+                &(read as u32 + 1).to_be_bytes(),
+                &[&[CODE_FILE_DATA], &chunk[..read]].concat(),
+            )?;
+        }
+        self.send_block(&1u32.to_be_bytes(), &[CODE_SUCCESS])?;
+        Ok(Ok(()))
+    }
+
+    /// Receives the files the device sends, writing them into the backup directory.
+    /// Once cancelled the data is still read, since it's already on its way, but thrown away.
+    fn receive_files(&self, backup_dir: &Path, cancelled: bool) -> Result<(), MobileBackup2Error> {
+        let mut failed = None;
+        loop {
+            let length = self.receive_length()?;
+            if length == 0 {
+                break;
+            }
+            // The directory name is only a hint, the file name holds the full path
+            self.receive_exact(length)?;
+            let length = self.receive_length()?;
+            let name = String::from_utf8_lossy(&self.receive_exact(length)?).into_owned();
+
+            let mut file = if cancelled {
+                None
+            } else {
+                let created = backup_path(backup_dir, &name).and_then(|path| {
+                    let _ = fs::remove_file(&path);
+                    File::create(&path)
+                });
+                match created {
+                    Ok(f) => Some(f),
+                    Err(e) => {
+                        warn!("Unable to create {}: {}", name, e);
+                        failed = Some(e.to_string());
+                        None
+                    }
+                }
+            };
+
+            loop {
+                let length = self.receive_length()?;
+                if length == 0 {
+                    break;
+                }
+                let code = self.receive_exact(1)?[0];
+                let data = self.receive_exact(length - 1)?;
+                match code {
+                    CODE_FILE_DATA => {
+                        if let Some(f) = file.as_mut() {
+                            if let Err(e) = f.write_all(&data) {
+                                warn!("Unable to write {}: {}", name, e);
+                                failed = Some(e.to_string());
+                                file = None;
+                            }
+                        }
+                    }
+                    CODE_ERROR_REMOTE => {
+                        warn!(
+                            "Device failed to send {}: {}",
+                            name,
+                            String::from_utf8_lossy(&data)
+                        );
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        if cancelled {
+            self.send_status_response(
+                STATUS_CANCELLED,
+                Some("Operation cancelled by the host".to_string()),
+                Some(Plist::new_dict()),
+            )
+        } else if let Some(description) = failed {
+            self.send_status_response(-1, Some(description), Some(Plist::new_dict()))
+        } else {
+            self.send_status_response(0, None, Some(Plist::new_dict()))
+        }
+    }
+
+    fn send_block(&self, header: &[u8], data: &[u8]) -> Result<(), MobileBackup2Error> {
+        self.send_all(header)?;
+        self.send_all(data)
+    }
+
+    fn send_all(&self, mut data: &[u8]) -> Result<(), MobileBackup2Error> {
+        while !data.is_empty() {
+            let sent = self.send_raw(data)? as usize;
+            if sent == 0 {
+                return Err(MobileBackup2Error::MuxError);
+            }
+            data = &data[sent..];
+        }
+        Ok(())
+    }
+
+    fn receive_exact(&self, len: u32) -> Result<Vec<u8>, MobileBackup2Error> {
+        let mut data = Vec::with_capacity(len as usize);
+        while data.len() < len as usize {
+            let chunk = self.receive_raw(len - data.len() as u32)?;
+            if chunk.is_empty() {
+                return Err(MobileBackup2Error::MuxError);
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data)
+    }
+
+    fn receive_length(&self) -> Result<u32, MobileBackup2Error> {
+        let bytes = self.receive_exact(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Closes the mobilebackup2 connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), MobileBackup2Error> {
+        let result = self.pointer.close().into();
+        if result != MobileBackup2Error::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -600,6 +1064,226 @@ pub enum MobileBackupRestoreFlags {
     CameraRoll,
 }
 
+/// Splits a device link message into its items, the first being the message name
+fn message_items(message: &Plist) -> Vec<Plist> {
+    if message.plist_type != PlistType::Array {
+        return Vec::new();
+    }
+    message
+        .clone()
+        .into_iter()
+        .map(|item| item.plist.clone())
+        .collect()
+}
+
+/// Passes the overall progress in a message to the callback, if the device included it
+fn report_progress(items: &[Plist], index: usize, progress: &mut impl FnMut(f64)) {
+    if let Some(item) = items.get(index) {
+        // Left untouched if the item isn't a real
+        let mut value = -1.0;
+        unsafe { unsafe_bindings::plist_get_real_val(item.get_pointer(), &mut value) };
+        if value >= 0.0 {
+            progress(value);
+        }
+    }
+}
+
+/// Handles the device link messages that only touch the backup directory.
+/// Returns the plist to answer with, and the error code and description if the request failed.
+fn handle_file_message(
+    backup_dir: &Path,
+    dl_message: &str,
+    items: &[Plist],
+) -> (Plist, Result<(), (c_int, String)>) {
+    let string_at = |index: usize| items.get(index).and_then(|p| p.get_string_val().ok());
+    let io_error = |e: std::io::Error| (device_error_code(&e), e.to_string());
+    let missing = || (-1, "Malformed request".to_string());
+
+    match dl_message {
+        "DLMessageGetFreeDiskSpace" => (Plist::new_uint(free_disk_space(backup_dir)), Ok(())),
+        "DLContentsOfDirectory" => {
+            let mut contents = Plist::new_dict();
+            let path = match string_at(1).map(|p| backup_path(backup_dir, &p)) {
+                Some(Ok(p)) => p,
+                Some(Err(e)) => return (contents, Err(io_error(e))),
+                None => return (contents, Err(missing())),
+            };
+            if let Ok(entries) = fs::read_dir(path) {
+                for entry in entries.flatten() {
+                    let metadata = match entry.metadata() {
+                        Ok(m) => m,
+                        Err(_) => continue,
+                    };
+                    let kind = if metadata.is_dir() {
+                        "DLFileTypeDirectory"
+                    } else if metadata.is_file() {
+                        "DLFileTypeRegular"
+                    } else {
+                        "DLFileTypeUnknown"
+                    };
+                    let mut info = Plist::new_dict();
+                    let _ = info.dict_set_item("DLFileType", Plist::new_string(kind));
+                    let _ = info.dict_set_item("DLFileSize", Plist::new_uint(metadata.len()));
+                    let _ = contents.dict_set_item(&entry.file_name().to_string_lossy(), info);
+                }
+            }
+            (contents, Ok(()))
+        }
+        "DLMessageCreateDirectory" => {
+            let result = match string_at(1) {
+                Some(p) => backup_path(backup_dir, &p)
+                    .and_then(fs::create_dir_all)
+                    .map_err(io_error),
+                None => Err(missing()),
+            };
+            (Plist::new_dict(), result)
+        }
+        "DLMessageMoveFiles" | "DLMessageMoveItems" => {
+            let moves = match items.get(1) {
+                Some(m) if m.plist_type == PlistType::Dictionary => m.clone(),
+                _ => return (Plist::new_dict(), Err(missing())),
+            };
+            let mut result = Ok(());
+            for item in moves.into_iter() {
+                let (source, destination) = match (item.key, item.plist.get_string_val()) {
+                    (Some(s), Ok(d)) => {
+                        match (backup_path(backup_dir, &s), backup_path(backup_dir, &d)) {
+                            (Ok(s), Ok(d)) => (s, d),
+                            (Err(e), _) | (_, Err(e)) => {
+                                result = Err(io_error(e));
+                                continue;
+                            }
+                        }
+                    }
+                    _ => continue,
+                };
+                // Moves replace whatever is already at the destination
+                if destination.is_dir() {
+                    let _ = fs::remove_dir_all(&destination);
+                } else {
+                    let _ = fs::remove_file(&destination);
+                }
+                if let Err(e) = fs::rename(&source, &destination) {
+                    result = Err(io_error(e));
+                }
+            }
+            (Plist::new_dict(), result)
+        }
+        "DLMessageRemoveFiles" | "DLMessageRemoveItems" => {
+            let paths = match items.get(1) {
+                Some(p) if p.plist_type == PlistType::Array => p.clone(),
+                _ => return (Plist::new_dict(), Err(missing())),
+            };
+            let mut result = Ok(());
+            for item in paths.into_iter() {
+                let path = match item.plist.get_string_val() {
+                    Ok(p) => match backup_path(backup_dir, &p) {
+                        Ok(p) => p,
+                        Err(e) => {
+                            result = Err(io_error(e));
+                            continue;
+                        }
+                    },
+                    Err(_) => continue,
+                };
+                let removed = if path.is_dir() {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_file(&path)
+                };
+                match removed {
+                    Ok(()) => {}
+                    // Removing something that isn't there is fine
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => result = Err(io_error(e)),
+                }
+            }
+            (Plist::new_dict(), result)
+        }
+        "DLMessageCopyItem" => {
+            let result = match (string_at(1), string_at(2)) {
+                (Some(source), Some(destination)) => backup_path(backup_dir, &source)
+                    .and_then(|source| copy_item(&source, &backup_path(backup_dir, &destination)?))
+                    .map_err(io_error),
+                _ => Err(missing()),
+            };
+            (Plist::new_dict(), result)
+        }
+        _ => {
+            warn!("Unhandled backup message {}", dl_message);
+            (
+                Plist::new_dict(),
+                Err((-1, format!("Unsupported message {}", dl_message))),
+            )
+        }
+    }
+}
+
+/// Resolves a path the device sent under the backup directory.
+/// The device only ever names paths inside it, so absolute paths and `..` are refused
+/// rather than letting whatever is on the other end of the connection touch the rest of the host.
+fn backup_path(backup_dir: &Path, relative: &str) -> std::io::Result<PathBuf> {
+    let relative = Path::new(relative);
+    let escapes = relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        warn!(
+            "Refusing backup path outside of the backup directory: {}",
+            relative.display()
+        );
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is outside of the backup directory", relative.display()),
+        ));
+    }
+    Ok(backup_dir.join(relative))
+}
+
+/// Copies a file, or a directory and everything in it
+fn copy_item(source: &Path, destination: &Path) -> std::io::Result<()> {
+    if source.is_dir() {
+        fs::create_dir_all(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_item(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(source, destination).map(|_| ())
+    }
+}
+
+/// Maps a local file error to the error codes the device expects, the same ones idevicebackup2 sends
+fn device_error_code(error: &std::io::Error) -> c_int {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => -6,
+        std::io::ErrorKind::AlreadyExists => -7,
+        _ => -1,
+    }
+}
+
+#[cfg(unix)]
+fn free_disk_space(path: &Path) -> u64 {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return 0,
+    };
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return 0;
+    }
+    stats.f_bavail as u64 * stats.f_frsize as u64
+}
+
+#[cfg(not(unix))]
+fn free_disk_space(_path: &Path) -> u64 {
+    // There's no portable way to ask without another dependency, so let the device go ahead
+    u64::MAX
+}
+
 impl From<MobileBackupRestoreFlags> for c_uint {
     fn from(flag: MobileBackupRestoreFlags) -> Self {
         match flag {
@@ -622,18 +1306,37 @@ impl From<MobileBackupRequest> for CString {
     }
 }
 
-impl Drop for MobileBackupClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::mobilebackup_client_free(self.pointer);
-        }
+impl<'a> Service<'a> for MobileBackup2Client<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobilebackup2";
+    type Error = MobileBackup2Error;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, MobileBackup2Error> {
+        Self::new(device, descriptor)
+    }
+
+    fn connect(device: &'a Device) -> Result<Self, MobileBackup2Error> {
+        // Backups need the escrow bag to work on passcode-protected devices
+        Self::start_service(device, "rusty_libimobiledevice")
     }
 }
 
-impl Drop for MobileBackup2Client<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::mobilebackup2_client_free(self.pointer);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_path_stays_in_backup_dir() {
+        let dir = Path::new("/backups");
+        assert_eq!(
+            backup_path(dir, "udid/Manifest.db").unwrap(),
+            dir.join("udid/Manifest.db")
+        );
+        assert_eq!(backup_path(dir, "./udid").unwrap(), dir.join("udid"));
+        assert!(backup_path(dir, "/Users/x").is_err());
+        assert!(backup_path(dir, "../x").is_err());
+        assert!(backup_path(dir, "udid/../../x").is_err());
     }
 }
@@ -8,23 +8,31 @@ use std::{
 };
 
 use log::{info, trace};
-use plist_plus::Plist;
+use plist_plus::{Plist, PlistType};
 use std::os::raw::c_void;
 
 use super::lockdownd::LockdowndService;
-use crate::{bindings as unsafe_bindings, error::MobileImageMounterError, idevice::Device};
+use crate::{
+    bindings as unsafe_bindings,
+    error::MobileImageMounterError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
+};
 
 /// A service for mounting developer disk images on the device
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct MobileImageMounter<'a> {
-    pub(crate) pointer: unsafe_bindings::mobile_image_mounter_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::mobile_image_mounter_client_t>,
     pub(crate) phantom: std::marker::PhantomData<&'a Device>,
 }
 
-unsafe impl Send for MobileImageMounter<'_> {}
-unsafe impl Sync for MobileImageMounter<'_> {}
+impl ClientPointer for unsafe_bindings::mobile_image_mounter_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::mobile_image_mounter_free(self)
+    }
+}
 
-impl MobileImageMounter<'_> {
+impl<'a> MobileImageMounter<'a> {
     /// Creates a new mobile image mounter service from a lockdown service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -34,7 +42,7 @@ impl MobileImageMounter<'_> {
     ///
     /// ***Verified:*** False
     pub fn new(
-        device: &Device,
+        device: &'a Device,
         descriptor: LockdowndService,
     ) -> Result<Self, MobileImageMounterError> {
         let mut client = unsafe { std::mem::zeroed() };
@@ -53,7 +61,7 @@ impl MobileImageMounter<'_> {
         }
 
         Ok(MobileImageMounter {
-            pointer: client,
+            pointer: ClientHandle::new(client),
             phantom: std::marker::PhantomData,
         })
     }
@@ -67,7 +75,7 @@ impl MobileImageMounter<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, MobileImageMounterError> {
         let mut client = unsafe { std::mem::zeroed() };
@@ -87,7 +95,7 @@ impl MobileImageMounter<'_> {
         }
 
         Ok(MobileImageMounter {
-            pointer: client,
+            pointer: ClientHandle::new(client),
             phantom: std::marker::PhantomData,
         })
     }
@@ -149,7 +157,7 @@ impl MobileImageMounter<'_> {
         info!("Uploading image");
         let result = unsafe {
             unsafe_bindings::mobile_image_mounter_upload_image(
-                self.pointer,
+                *self.pointer.lock(),
                 image_type_c_string_ptr,
                 dmg_size as c_ulong,
                 signature_buffer as *const c_char,
@@ -225,7 +233,7 @@ impl MobileImageMounter<'_> {
         info!("Mounting image");
         let result = unsafe {
             unsafe_bindings::mobile_image_mounter_mount_image(
-                self.pointer,
+                *self.pointer.lock(),
                 image_path.as_ptr() as *const c_char,
                 signature_buffer.as_ptr() as *const c_char,
                 signature_buffer.len() as u16,
@@ -264,7 +272,7 @@ impl MobileImageMounter<'_> {
         info!("Looking up image");
         let result = unsafe {
             unsafe_bindings::mobile_image_mounter_lookup_image(
-                self.pointer,
+                *self.pointer.lock(),
                 image_type_c_string_ptr,
                 &mut plist,
             )
@@ -276,18 +284,49 @@ impl MobileImageMounter<'_> {
         }
         Ok(plist.into())
     }
+
+    /// Checks if a developer disk image is mounted on the device
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// Whether a developer image is mounted
+    ///
+    /// ***Verified:*** False
+    pub fn is_developer_image_mounted(&self) -> Result<bool, MobileImageMounterError> {
+        let result = self.lookup_image("Developer")?;
+
+        // Newer versions of iOS list the signatures of mounted images
+        if let Ok(signatures) = result.dict_get_item("ImageSignature") {
+            if signatures.plist_type == PlistType::Array {
+                return Ok(signatures.array_get_size().unwrap_or(0) > 0);
+            }
+        }
+        // Older versions report whether an image is present
+        if let Ok(present) = result.dict_get_item("ImagePresent") {
+            return Ok(present.get_bool_val().unwrap_or(false));
+        }
+        Ok(false)
+    }
+
+    /// Closes the image mounter connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), MobileImageMounterError> {
+        let result = self.pointer.close().into();
+        if result != MobileImageMounterError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
 extern "C" fn image_mounter_callback(a: *mut c_void, b: c_ulong, c: *mut c_void) -> c_long {
     trace!("image_mounter_callback called");
     unsafe { libc::fread(a, 1, b as usize, c as *mut libc::FILE) as c_long }
 }
-
-impl Drop for MobileImageMounter<'_> {
-    fn drop(&mut self) {
-        info!("Dropping MobileImageMounter");
-        unsafe {
-            unsafe_bindings::mobile_image_mounter_free(self.pointer);
-        }
-    }
-}
@@ -1,32 +1,116 @@
 // jkcoxson
 
 use std::{
-    ffi::{c_uint, CString},
-    os::raw::c_char,
+    collections::HashMap,
+    ffi::{c_uint, CStr, CString},
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    bindings as unsafe_bindings, error::MobileSyncError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::MobileSyncError,
+    idevice::Device,
+    plist_util,
+    service::{timeout_millis, ClientHandle, ClientPointer, Direction, MessageHook, Service},
     services::lockdownd::LockdowndService,
 };
 
+use anchor_store::{AnchorStoreHandle, MobileSyncAnchorStore};
+
+use log::{info, warn};
 use plist_plus::{Plist, PlistType};
 
-#[derive(Debug, Clone)]
+/// Persists sync anchors between runs
+pub mod anchor_store;
+/// Typed records for the Bookmarks data class
+pub mod bookmarks;
+/// Typed records for the Calendars data class, and conversion to and from iCalendar
+pub mod calendars;
+/// Typed records for the Contacts data class
+pub mod contacts;
+/// Typed records for the Notes data class
+pub mod notes;
+/// A sync session that checks the order of the calls made in it
+pub mod session;
+
+/// The key records use for the kind of entity they hold, such as `com.apple.contacts.Contact`
+const RECORD_ENTITY_NAME: &str = "com.apple.syncservices.RecordEntityName";
+
+/// A connection to the mobilesync service.
+/// The client borrows the device it was made from, and its connection is closed when it's dropped.
+#[derive(Debug)]
 pub struct MobileSyncClient<'a> {
-    pub(crate) pointer: unsafe_bindings::mobilesync_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::mobilesync_client_t>,
+    cancel_on_error: bool,
+    dry_run: bool,
+    timeout: Option<u32>,
+    hook: MessageHook,
+    udid: String,
+    anchor_store: AnchorStoreHandle,
+    /// The data class of the running session and the anchors for the next sync, saved to the store once it finishes
+    session_anchors: Mutex<Option<(String, String)>>,
+    /// The reason the device gave the last time it cancelled the session
+    cancel_reason: Mutex<Option<String>>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
+/// Gets the property list client a mobilesync client sends and receives through.
+/// libimobiledevice has no public accessor for it, so this reads the private structs
+/// of the libimobiledevice the bindings were generated from, see `wrapper.h`.
+/// Linking against a libimobiledevice whose mobilesync or device link clients are laid out differently breaks this.
+/// # Safety
+/// `client` has to be an open mobilesync client
+unsafe fn property_list_client(
+    client: unsafe_bindings::mobilesync_client_t,
+) -> unsafe_bindings::property_list_service_client_t {
+    (*(*client).parent).parent
+}
+
+/// Whether libimobiledevice's client has a session running, which it tracks by the session's data class
+/// # Safety
+/// `client` has to be an open mobilesync client, see `property_list_client` for the layout this depends on
+unsafe fn session_running(client: unsafe_bindings::mobilesync_client_t) -> bool {
+    !(*client).data_class.is_null()
+}
+
+/// Records a started session in libimobiledevice's client, as `mobilesync_start` does once the device accepts.
+/// The later steps check the data class and direction, and `finish` and `cancel` free the data class.
+/// # Safety
+/// `client` has to be an open mobilesync client without a running session,
+/// see `property_list_client` for the layout this depends on
+unsafe fn begin_session(
+    client: unsafe_bindings::mobilesync_client_t,
+    data_class: &CStr,
+) -> Result<(), MobileSyncError> {
+    // libimobiledevice frees it with `free`
+    let data_class = libc::strdup(data_class.as_ptr());
+    if data_class.is_null() {
+        return Err(MobileSyncError::UnknownError);
+    }
+    (*client).direction =
+        unsafe_bindings::mobilesync_sync_direction_t_MOBILESYNC_SYNC_DIR_DEVICE_TO_COMPUTER;
+    (*client).data_class = data_class;
+    Ok(())
+}
+
+impl ClientPointer for unsafe_bindings::mobilesync_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::mobilesync_client_free(self)
+    }
+}
+
 #[derive(Debug)]
 pub struct MobileSyncAnchor {
-    c_struct: Box<unsafe_bindings::mobilesync_anchors>,
     device_anchor: CString,
     computer_anchor: CString,
 }
 
-impl MobileSyncClient<'_> {
+impl<'a> MobileSyncClient<'a> {
     /// Creates a new mobile sync service from a lockdown service
+    /// SSL is enabled on the connection if the descriptor requires it
     /// # Arguments
     /// * `device` - The device to connect to
     /// * `descriptor` - The lockdown service to connect on
@@ -34,7 +118,7 @@ impl MobileSyncClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: Device, descriptor: LockdowndService) -> Result<Self, MobileSyncError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, MobileSyncError> {
         let mut pointer: unsafe_bindings::mobilesync_client_t = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::mobilesync_client_new(device.pointer, descriptor.pointer, &mut pointer)
@@ -46,7 +130,15 @@ impl MobileSyncClient<'_> {
         }
 
         Ok(MobileSyncClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
+            cancel_on_error: true,
+            dry_run: false,
+            timeout: None,
+            hook: MessageHook::default(),
+            udid: device.get_udid(),
+            anchor_store: AnchorStoreHandle::default(),
+            session_anchors: Mutex::new(None),
+            cancel_reason: Mutex::new(None),
             phantom: std::marker::PhantomData,
         })
     }
@@ -60,7 +152,7 @@ impl MobileSyncClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, MobileSyncError> {
         let label_c_string = CString::new(label.into()).unwrap();
@@ -79,13 +171,101 @@ impl MobileSyncClient<'_> {
         }
 
         Ok(MobileSyncClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
+            cancel_on_error: true,
+            dry_run: false,
+            timeout: None,
+            hook: MessageHook::default(),
+            udid: device.get_udid(),
+            anchor_store: AnchorStoreHandle::default(),
+            session_anchors: Mutex::new(None),
+            cancel_reason: Mutex::new(None),
             phantom: std::marker::PhantomData,
         })
     }
 
+    /// Sets whether the session is cancelled when a step of the sync fails.
+    /// This is on by default, because a session that's left open stops the device from syncing
+    /// until it's reconnected. Turn it off to call `cancel` yourself.
+    /// The steps that cancel are `get_all_records_from_device`, `get_changes_from_device`,
+    /// `acknowledge_changes_from_device` and `send_changes`.
+    /// # Arguments
+    /// * `cancel_on_error` - Whether to cancel automatically
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    pub fn with_cancel_on_error(mut self, cancel_on_error: bool) -> Self {
+        self.cancel_on_error = cancel_on_error;
+        self
+    }
+
+    /// Sets whether the client only logs what it would change on the device instead of sending it.
+    /// This is off by default. Turn it on to run sync logic against a real device's data without risking it.
+    ///
+    /// In a dry run, these operations log the plist they would send at info level and return `Ok`
+    /// without sending anything:
+    /// * `send` and `send_unchecked`
+    /// * `send_changes`, and so the records sent by `push_records` and `reset_sync`
+    /// * `clear_all_records_on_device`
+    /// * `remap_identifiers`
+    ///
+    /// Everything else, including `start`, `finish`, `cancel` and the receive functions, still talks to the device.
+    /// The device never sees the pushed records, so `push_records` doesn't wait for its reply
+    /// and returns no remapped IDs.
+    /// # Arguments
+    /// * `dry_run` - Whether to suppress the operations that change the device
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets where the client keeps the anchors of each data class between runs, such as a `FileAnchorStore`.
+    ///
+    /// When `start` is given no anchors, it loads the ones saved for the device and data class,
+    /// with a new computer anchor, or starts a first sync if there are none.
+    /// Once `finish` succeeds, the anchors for the next sync are saved, see `next_anchors`.
+    /// # Arguments
+    /// * `store` - The store to load and save anchors with
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    pub fn with_anchor_store(mut self, store: impl MobileSyncAnchorStore + 'static) -> Self {
+        self.anchor_store = AnchorStoreHandle(Some(Arc::new(store)));
+        self
+    }
+
+    /// Sets how long `receive` waits for a message before returning `ReceiveTimeout`.
+    /// The sync steps that receive inside libimobiledevice, such as `start` and `finish`, still block.
+    /// # Arguments
+    /// * `timeout` - The time to wait, or `None` to block forever, which is the default
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout_millis(timeout);
+    }
+
+    /// Registers a hook that sees every message passed to `send` and returned from the receive functions.
+    /// Useful for inspecting the protocol. Replaces any hook registered before
+    /// # Arguments
+    /// * `hook` - Called with the direction and the message
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn on_message(&mut self, hook: impl FnMut(Direction, &Plist) + Send + 'static) {
+        self.hook.set(hook);
+    }
+
     /// Receives a message from the service.
-    /// Blocks until a full plist has been received
+    /// Blocks until a full plist has been received, or returns `ReceiveTimeout` if a timeout was set with `set_timeout`
     /// # Arguments
     /// *none*
     /// # Returns
@@ -93,27 +273,166 @@ impl MobileSyncClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn receive(&self) -> Result<Plist, MobileSyncError> {
+        if let Some(timeout) = self.timeout {
+            return self
+                .receive_within(timeout)?
+                .ok_or(MobileSyncError::ReceiveTimeout);
+        }
+
         let mut plist: unsafe_bindings::plist_t = std::ptr::null_mut();
         let result =
-            unsafe { unsafe_bindings::mobilesync_receive(self.pointer, &mut plist) }.into();
+            unsafe { unsafe_bindings::mobilesync_receive(*self.pointer.lock(), &mut plist) }.into();
 
         if result != MobileSyncError::Success {
             return Err(result);
         }
 
-        Ok(plist.into())
+        let plist: Plist = plist.into();
+        self.hook.call(Direction::Received, &plist);
+        expect_type(&plist, PlistType::Array)?;
+        Ok(plist)
     }
 
-    /// Sends a message to the service
+    /// Receives a message from the service if the device has started sending one, without waiting for it.
+    /// This allows polling from an event loop instead of dedicating a thread to `receive`.
+    /// Once the first bytes of a message have arrived, the rest is received like `receive` would.
+    /// Over an SSL connection, a message already read into the SSL buffer isn't seen until more data arrives.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A plist containing the message, or `None` if nothing was available
+    ///
+    /// ***Verified:*** False
+    #[cfg(unix)]
+    pub fn try_receive(&self) -> Result<Option<Plist>, MobileSyncError> {
+        let fd = {
+            let pointer = self.pointer.lock();
+            let mut fd = 0;
+            // SAFETY: the client is open while the lock is held, see `property_list_client`
+            let result = unsafe {
+                let service_client = (*property_list_client(*pointer)).parent;
+                unsafe_bindings::idevice_connection_get_fd((*service_client).connection, &mut fd)
+            };
+            if result != unsafe_bindings::idevice_error_t_IDEVICE_E_SUCCESS {
+                warn!(
+                    "Unable to get the mobilesync connection's socket: {}",
+                    result
+                );
+                return Err(MobileSyncError::MuxError);
+            }
+            fd
+        };
+
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, 0) };
+        if ready < 0 {
+            warn!(
+                "Unable to poll the mobilesync connection: {}",
+                std::io::Error::last_os_error()
+            );
+            return Err(MobileSyncError::UnknownError);
+        }
+        if ready == 0 {
+            return Ok(None);
+        }
+        // A hung up or failed socket is ready too, and the receive reports why
+        self.receive().map(Some)
+    }
+
+    /// Receives a message from the service, waiting at most `timeout` whatever was set with `set_timeout`.
+    /// Use this to recover from a device that stopped responding mid-sync, by cancelling the session.
+    /// # Arguments
+    /// * `timeout` - How long to wait for the message
+    /// # Returns
+    /// A plist containing the message, or `ReceiveTimeout` if none arrived in time
+    ///
+    /// ***Verified:*** False
+    pub fn receive_with_timeout(&self, timeout: Duration) -> Result<Plist, MobileSyncError> {
+        // Never `None`, since the timeout is set
+        let timeout = timeout_millis(Some(timeout)).unwrap_or(1);
+        self.receive_within(timeout)?
+            .ok_or(MobileSyncError::ReceiveTimeout)
+    }
+
+    fn receive_within(&self, timeout: u32) -> Result<Option<Plist>, MobileSyncError> {
+        // mobilesync has no receive with a timeout, so read from the property list service underneath
+        let mut plist: unsafe_bindings::plist_t = std::ptr::null_mut();
+        let result = {
+            let pointer = self.pointer.lock();
+            // SAFETY: the client is open while the lock is held, see `property_list_client`
+            unsafe {
+                unsafe_bindings::property_list_service_receive_plist_with_timeout(
+                    property_list_client(*pointer),
+                    &mut plist,
+                    timeout,
+                )
+            }
+        };
+
+        match result {
+            unsafe_bindings::property_list_service_error_t_PROPERTY_LIST_SERVICE_E_SUCCESS => {}
+            unsafe_bindings::property_list_service_error_t_PROPERTY_LIST_SERVICE_E_RECEIVE_TIMEOUT => {
+                return Ok(None)
+            }
+            // Only part of a message arrived, so the connection can't be read from reliably anymore
+            unsafe_bindings::property_list_service_error_t_PROPERTY_LIST_SERVICE_E_NOT_ENOUGH_DATA => {
+                return Err(MobileSyncError::UnknownError)
+            }
+            e => return Err(e.into()),
+        }
+
+        let plist: Plist = plist.into();
+        self.hook.call(Direction::Received, &plist);
+        expect_type(&plist, PlistType::Array)?;
+        Ok(Some(plist))
+    }
+
+    /// Sends a message to the service after checking it's a sync message.
+    /// The device answers malformed messages with confusing errors or by dropping the connection,
+    /// so they are refused here instead.
+    /// # Arguments
+    /// * `message` - The message to send, an array starting with an `SDMessage*` type string
+    /// # Returns
+    /// *none*, or `InvalidArg` if the message isn't an array starting with a known message type
+    ///
+    /// ***Verified:*** False
+    pub fn send(&self, message: Plist) -> Result<(), MobileSyncError> {
+        if let SyncMessage::Other(message_type, _) = SyncMessage::from_plist(&message)? {
+            warn!(
+                "Refusing to send unknown mobilesync message {}",
+                message_type
+            );
+            return Err(MobileSyncError::InvalidArg);
+        }
+        self.send_unchecked(message)
+    }
+
+    /// Sends a message to the service without checking it.
+    /// Use this for message types `send` doesn't know about.
     /// # Arguments
     /// * `message` - The message to send
     /// # Returns
     /// *none*
     ///
     /// ***Verified:*** False
-    pub fn send(&self, message: Plist) -> Result<(), MobileSyncError> {
-        let result =
-            unsafe { unsafe_bindings::mobilesync_send(self.pointer, message.get_pointer()) }.into();
+    pub fn send_unchecked(&self, message: Plist) -> Result<(), MobileSyncError> {
+        if self.skip_in_dry_run("send", &message) {
+            return Ok(());
+        }
+        self.send_message(&message)
+    }
+
+    /// Sends a message even in a dry run
+    fn send_message(&self, message: &Plist) -> Result<(), MobileSyncError> {
+        self.hook.call(Direction::Sent, message);
+        let result = unsafe {
+            unsafe_bindings::mobilesync_send(*self.pointer.lock(), message.get_pointer())
+        }
+        .into();
 
         if result != MobileSyncError::Success {
             return Err(result);
@@ -122,14 +441,17 @@ impl MobileSyncClient<'_> {
         Ok(())
     }
 
-    /// Starts the syncing of data
+    /// Starts the syncing of data.
+    /// The device answers with a new device anchor, which `next_anchors` returns until the session ends.
     /// # Arguments
     /// * `data_class` - The identifiers to sync
-    /// * `anchors` - The sync anchors to base off of
+    /// * `anchors` - The sync anchors to base off of, or none to use the anchor store, see `with_anchor_store`.
+    ///   Only the first anchors are sent
     /// * `computer_data_class_version` - The class version on the host
-    /// * `sync_type` - The type of sync to perform
+    /// * `sync_type` - The type of sync expected. The device picks the type from the anchors, and a different choice is logged
     /// # Returns
-    /// *none*
+    /// *none*, or why the device refused to start alongside the error code.
+    /// `SyncStartError::DeviceBusy` means another host is syncing, such as Finder's automatic sync, and is worth retrying.
     ///
     /// ***Verified:*** False
     pub fn start(
@@ -138,40 +460,306 @@ impl MobileSyncClient<'_> {
         mut anchors: Vec<MobileSyncAnchor>,
         computer_data_class_version: u64,
         sync_type: MobileSyncType,
-    ) -> Result<(), (String, MobileSyncError)> {
-        let data_class_c_string = CString::new(data_class.into()).unwrap();
+    ) -> Result<(), (SyncStartError, MobileSyncError)> {
+        let data_class = data_class.into();
+        let no_reason = |e| (SyncStartError::Other(String::new()), e);
+        if anchors.is_empty() {
+            if let Some(store) = &self.anchor_store.0 {
+                let loaded = store.load(&self.udid, &data_class).map_err(no_reason)?;
+                let computer_anchor = new_computer_anchor();
+                anchors.push(match loaded {
+                    Some(loaded) => MobileSyncAnchor::new(loaded.device_anchor(), computer_anchor),
+                    None => MobileSyncAnchor::first_sync(computer_anchor).map_err(no_reason)?,
+                });
+            }
+        }
+        let sent = anchors
+            .first()
+            .ok_or_else(|| no_reason(MobileSyncError::InvalidArg))?;
+        let data_class_c_string = CString::new(data_class.clone()).unwrap();
+        // SAFETY: the client is open, see `property_list_client` for the layout this reads
+        if unsafe { session_running(*self.pointer.lock()) } {
+            warn!("A mobilesync session is already running");
+            return Err(no_reason(MobileSyncError::InvalidArg));
+        }
 
-        let mut anchor_ptrs: Vec<*mut unsafe_bindings::mobilesync_anchors> =
-            anchors.iter_mut().map(|v| v.as_c_struct_ptr()).collect();
-        anchor_ptrs.push(std::ptr::null_mut());
+        // Sent here instead of by `mobilesync_start`, which drops the device anchor the device answers with
+        let message =
+            start_message(&data_class, sent, computer_data_class_version).map_err(no_reason)?;
+        self.send_message(&message).map_err(no_reason)?;
+        let reply = parse_start_reply(&self.receive().map_err(no_reason)?, sent)?;
+        if reply.sync_type != Some(sync_type) {
+            // The device decides, such as when the anchors don't match what it remembers
+            info!(
+                "Asked for a {} sync of {}, the device chose {:?}",
+                sync_type, data_class, reply.sync_type
+            );
+        }
 
-        let mut device_data_class_version = 0;
+        // SAFETY: as above, and the session was checked not to be running
+        unsafe { begin_session(*self.pointer.lock(), &data_class_c_string) }.map_err(no_reason)?;
+        *self
+            .session_anchors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) =
+            Some((data_class, reply.next_anchors.to_string()));
+        Ok(())
+    }
 
-        let mut error_description = std::ptr::null_mut();
+    /// The anchors to save for the next sync of the running session's data class.
+    /// These are the device anchor the device sent as the session started, and the computer anchor the host sent.
+    /// Anchors only match what the device remembers once the session finishes, so save them after `finish` succeeds.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The anchors, or None if no session is running
+    ///
+    /// ***Verified:*** False
+    pub fn next_anchors(&self) -> Option<MobileSyncAnchor> {
+        let session_anchors = self
+            .session_anchors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // Anchors are only stored by `start`, written by `to_string`, so they always parse
+        session_anchors
+            .as_ref()
+            .and_then(|(_, anchors)| MobileSyncAnchor::from_string(anchors).ok())
+    }
 
-        let result = unsafe {
-            unsafe_bindings::mobilesync_start(
-                self.pointer,
-                data_class_c_string.as_ptr(),
-                anchor_ptrs[0],
+    /// Performs a complete read-only sync of a data class in one call.
+    /// This starts the session, pulls every record from the device, acknowledges each batch and finishes the session.
+    /// Fast syncs only pull the changes since the given anchors, other sync types pull all records.
+    /// The lower level methods can still be used for custom flows.
+    /// # Arguments
+    /// * `data_class` - The identifier of the data class to sync
+    /// * `anchors` - The sync anchors to base off of
+    /// * `computer_data_class_version` - The class version on the host
+    /// * `sync_type` - The type of sync to perform
+    /// * `on_record` - Called once for every record received from the device
+    /// # Returns
+    /// The anchors to save and pass to `start` for the next sync, see `next_anchors`
+    ///
+    /// ***Verified:*** False
+    pub fn full_sync(
+        &self,
+        data_class: impl Into<String>,
+        anchors: Vec<MobileSyncAnchor>,
+        computer_data_class_version: u64,
+        sync_type: MobileSyncType,
+        mut on_record: impl FnMut(Plist),
+    ) -> Result<MobileSyncAnchor, MobileSyncError> {
+        self.start(data_class, anchors, computer_data_class_version, sync_type)
+            .map_err(|(_, e)| e)?;
+        // Taken now, since finishing clears them
+        let next = self.next_anchors().ok_or(MobileSyncError::UnknownError)?;
+
+        let mut batch = match sync_type {
+            MobileSyncType::Fast => self.get_changes_from_device()?,
+            _ => self.get_all_records_from_device()?,
+        };
+
+        loop {
+            if batch.records.plist_type == PlistType::Dictionary {
+                for record in batch.records.into_iter() {
+                    on_record(record.plist.clone());
+                }
+            }
+            self.acknowledge_changes_from_device()?;
+
+            if !batch.has_more {
+                self.finish()?;
+                return Ok(next);
+            }
+            batch = self.receive_changes()?;
+        }
+    }
+
+    /// Replaces everything the device has for a data class with the host's records.
+    /// Use this when a fast sync fails because the saved anchors no longer match the device,
+    /// for example after the device was restored or synced with another computer.
+    /// This starts a reset sync with a fresh computer anchor, clears the device's records,
+    /// sends every batch and finishes the session.
+    /// # Arguments
+    /// * `data_class` - The identifier of the data class to sync
+    /// * `computer_data_class_version` - The class version on the host
+    /// * `batches` - The host's records, as dictionaries of records keyed by record ID.
+    ///   They are packed into messages as `push_records` does with the default `BatchLimit`.
+    /// # Returns
+    /// The anchors to save for the next sync, and the record IDs the device changed
+    ///
+    /// ***Verified:*** False
+    pub fn reset_sync(
+        &self,
+        data_class: impl Into<String>,
+        computer_data_class_version: u64,
+        batches: impl IntoIterator<Item = Plist>,
+    ) -> Result<ResetSync, MobileSyncError> {
+        self.start(
+            data_class,
+            vec![MobileSyncAnchor::first_sync(new_computer_anchor())?],
+            computer_data_class_version,
+            MobileSyncType::Reset,
+        )
+        .map_err(|(_, e)| e)?;
+        // Taken now, since finishing clears them
+        let anchors = self.next_anchors().ok_or(MobileSyncError::UnknownError)?;
+        self.clear_all_records_on_device()
+            .map_err(|e| self.cancel_after(e, "clearing the device's records"))?;
+        let pushed = self.push_records(batches, None, BatchLimit::default())?;
+        self.finish()?;
+
+        Ok(ResetSync {
+            anchors,
+            remapped: pushed.remapped,
+        })
+    }
+
+    /// Reads every record of a data class from the device in one call.
+    /// This runs a slow sync, reads every batch into one set of records and joins them into typed records,
+    /// so records linked across batches end up together.
+    /// # Arguments
+    /// * `computer_data_class_version` - The class version on the host
+    /// # Returns
+    /// The records
+    ///
+    /// ***Verified:*** False
+    pub fn export<T: SyncRecord>(
+        &self,
+        computer_data_class_version: u64,
+    ) -> Result<Vec<T>, MobileSyncError> {
+        let mut session = self
+            .session(
+                T::DATA_CLASS,
+                vec![MobileSyncAnchor::first_sync(new_computer_anchor())?],
                 computer_data_class_version,
-                &mut sync_type.into(),
-                &mut device_data_class_version,
-                &mut error_description,
+                MobileSyncType::Slow,
             )
+            .map_err(|(_, e)| e)?
+            .get_all_records()?;
+
+        let mut records = Plist::new_dict();
+        let mut batches = 0;
+        while let Some(batch) = session.next_batch()? {
+            for record in batch.records.into_iter() {
+                if let Some(key) = record.key {
+                    set_record_item(&mut records, &key, record.plist.clone())?;
+                }
+            }
+            batches += 1;
+        }
+        session.finish()?;
+        info!("Exported {} in {} batches", T::DATA_CLASS, batches);
+
+        T::from_records(&records)
+    }
+
+    /// Replaces everything the device has for a data class with typed records, see `reset_sync`
+    /// # Arguments
+    /// * `items` - The records to put on the device. The IDs the device gives them are written back
+    /// * `computer_data_class_version` - The class version on the host
+    /// # Returns
+    /// The anchors to save for the next sync
+    ///
+    /// ***Verified:*** False
+    pub fn replace_all<T: SyncRecord>(
+        &self,
+        items: &mut [T],
+        computer_data_class_version: u64,
+    ) -> Result<MobileSyncAnchor, MobileSyncError> {
+        let batches = items
+            .iter()
+            .map(|item| item.to_records())
+            .collect::<Result<Vec<Plist>, MobileSyncError>>()?;
+        let reset = self.reset_sync(T::DATA_CLASS, computer_data_class_version, batches)?;
+
+        let remapped = remapped_ids(&reset.remapped);
+        for item in items.iter_mut() {
+            item.remap_ids(&remapped);
+        }
+        Ok(reset.anchors)
+    }
+
+    /// Sends the host's changes to the device and waits for it to accept them.
+    /// This tells the device the host is ready, sends the records with the last message flagged,
+    /// then reads the device's acknowledgement, so the push can't be left half done.
+    /// The session is still open afterwards, call `finish` once the sync is done.
+    ///
+    /// Records are packed into as few `send_changes` messages as `limit` allows, whatever batches they came in.
+    /// The device only answers the last message, with the remapped IDs, so there is one round trip
+    /// for the whole push, and the rest of the cost is one message per batch.
+    /// With the default limit, 10,000 small contacts sent one per dictionary go out as 10 messages instead of 10,000.
+    /// # Arguments
+    /// * `entities` - The changes, as dictionaries of records keyed by record ID.
+    ///   A record ID that appears twice is sent with its last value. An empty message is sent if there are no records.
+    /// * `actions` - Additional actions the device should perform, sent with every message
+    /// * `limit` - How many records and bytes to put in each message
+    /// # Returns
+    /// The record IDs the device changed
+    ///
+    /// ***Verified:*** False
+    pub fn push_records(
+        &self,
+        entities: impl IntoIterator<Item = Plist>,
+        actions: Option<Plist>,
+        limit: BatchLimit,
+    ) -> Result<PushResult, MobileSyncError> {
+        self.ready_to_send_changes_from_computer()
+            .map_err(|e| self.cancel_after(e, "preparing to send records"))?;
+
+        let mut pending = Plist::new_dict();
+        let mut pending_records = 0;
+        let mut pending_bytes = 0;
+        let mut messages = 0;
+        for batch in entities {
+            expect_type(&batch, PlistType::Dictionary)
+                .map_err(|e| self.cancel_after(e, "sending changes"))?;
+            for record in batch.into_iter() {
+                let key = match record.key {
+                    Some(key) => key,
+                    None => continue,
+                };
+                let size = plist_util::binary_size(&record.plist);
+                // A record bigger than the byte limit still goes out, in a message of its own
+                if pending_records > 0
+                    && (pending_records >= limit.max_records
+                        || pending_bytes + size > limit.max_bytes)
+                {
+                    let full = std::mem::replace(&mut pending, Plist::new_dict());
+                    self.send_changes(full, false, actions.clone())?;
+                    messages += 1;
+                    pending_records = 0;
+                    pending_bytes = 0;
+                }
+                pending
+                    .dict_set_item(&key, record.plist.clone())
+                    .map_err(|_| {
+                        self.cancel_after(MobileSyncError::PlistError, "sending changes")
+                    })?;
+                pending_records += 1;
+                pending_bytes += size;
+            }
+        }
+        self.send_changes(pending, true, actions)?;
+        info!("Pushed records in {} messages", messages + 1);
+        if self.dry_run {
+            return Ok(PushResult {
+                remapped: Plist::new_dict(),
+            });
         }
-        .into();
 
+        // The device acknowledges the last batch with the IDs it gave the new records
+        let mut remapped: unsafe_bindings::plist_t = std::ptr::null_mut();
+        let result = unsafe {
+            unsafe_bindings::mobilesync_remap_identifiers(*self.pointer.lock(), &mut remapped)
+        }
+        .into();
         if result != MobileSyncError::Success {
-            return Err((
-                unsafe { std::ffi::CStr::from_ptr(error_description) }
-                    .to_string_lossy()
-                    .into_owned(),
-                result,
-            ));
+            return Err(self.cancel_after(result, "receiving remapped identifiers"));
         }
 
-        Ok(())
+        Ok(PushResult {
+            remapped: remapped.into(),
+        })
     }
 
     /// Cancels a sync request
@@ -183,10 +771,15 @@ impl MobileSyncClient<'_> {
     /// ***Verified:*** False
     pub fn cancel(&self, reason: impl Into<String>) -> Result<(), MobileSyncError> {
         let reason_c_string = CString::new(reason.into()).unwrap();
+        self.session_anchors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
 
-        let result =
-            unsafe { unsafe_bindings::mobilesync_cancel(self.pointer, reason_c_string.as_ptr()) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::mobilesync_cancel(*self.pointer.lock(), reason_c_string.as_ptr())
+        }
+        .into();
 
         if result != MobileSyncError::Success {
             return Err(result);
@@ -199,16 +792,27 @@ impl MobileSyncClient<'_> {
     /// # Arguments
     /// *none*
     /// # Returns
-    /// *none*
+    /// *none*, or `IoError` if the session finished but its anchors couldn't be saved to the anchor store
     ///
     /// ***Verified:*** False
     pub fn finish(&self) -> Result<(), MobileSyncError> {
-        let result = unsafe { unsafe_bindings::mobilesync_finish(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::mobilesync_finish(*self.pointer.lock()) }.into();
 
+        let session_anchors = self
+            .session_anchors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
         if result != MobileSyncError::Success {
             return Err(result);
         }
 
+        if let (Some(store), Some((data_class, anchors))) = (&self.anchor_store.0, session_anchors)
+        {
+            // Anchors in the store were written by `to_string`, so they always parse
+            let anchors = MobileSyncAnchor::from_string(&anchors)?;
+            store.save(&self.udid, &data_class, &anchors)?;
+        }
         Ok(())
     }
 
@@ -216,36 +820,41 @@ impl MobileSyncClient<'_> {
     /// # Arguments
     /// *none*
     /// # Returns
-    /// The data, whether it's the end of the data and the anchors
+    /// The first batch of records
     ///
     /// ***Verified:*** False
-    pub fn get_all_records_from_device(&self) -> Result<(Plist, bool, Plist), MobileSyncError> {
-        let result =
-            unsafe { unsafe_bindings::mobilesync_get_all_records_from_device(self.pointer) }.into();
+    pub fn get_all_records_from_device(&self) -> Result<RecordBatch, MobileSyncError> {
+        let result = unsafe {
+            unsafe_bindings::mobilesync_get_all_records_from_device(*self.pointer.lock())
+        }
+        .into();
 
         if result != MobileSyncError::Success {
-            return Err(result);
+            return Err(self.cancel_after(result, "requesting all records"));
         }
 
         self.receive_changes()
+            .map_err(|e| self.cancel_after(e, "receiving all records"))
     }
 
     /// Gets all the changes from the device
     /// # Arguments
     /// *none*
     /// # Returns
-    /// The data, whether it's the end of the data and the anchors
+    /// The first batch of changes
     ///
     /// ***Verified:*** False
-    pub fn get_changes_from_device(&self) -> Result<(Plist, bool, Plist), MobileSyncError> {
+    pub fn get_changes_from_device(&self) -> Result<RecordBatch, MobileSyncError> {
         let result =
-            unsafe { unsafe_bindings::mobilesync_get_changes_from_device(self.pointer) }.into();
+            unsafe { unsafe_bindings::mobilesync_get_changes_from_device(*self.pointer.lock()) }
+                .into();
 
         if result != MobileSyncError::Success {
-            return Err(result);
+            return Err(self.cancel_after(result, "requesting changes"));
         }
 
         self.receive_changes()
+            .map_err(|e| self.cancel_after(e, "receiving changes"))
     }
 
     /// Clears the records on the device
@@ -256,8 +865,14 @@ impl MobileSyncClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn clear_all_records_on_device(&self) -> Result<(), MobileSyncError> {
-        let result =
-            unsafe { unsafe_bindings::mobilesync_clear_all_records_on_device(self.pointer) }.into();
+        if self.dry_run {
+            info!("Dry run, not clearing the records on the device");
+            return Ok(());
+        }
+        let result = unsafe {
+            unsafe_bindings::mobilesync_clear_all_records_on_device(*self.pointer.lock())
+        }
+        .into();
 
         if result != MobileSyncError::Success {
             return Err(result);
@@ -268,30 +883,83 @@ impl MobileSyncClient<'_> {
 
     /// Receive changes from the device
     /// # Arguments
-    /// *none* Returns
-    /// The data, whether it's the end of the data and the anchors
+    /// *none*
+    /// # Returns
+    /// The next batch of records, or `DeviceCancelled` if the device cancelled the session, see `cancel_reason`
     ///
     /// ***Verified:*** False
-    pub fn receive_changes(&self) -> Result<(Plist, bool, Plist), MobileSyncError> {
-        let mut plist: unsafe_bindings::plist_t = std::ptr::null_mut();
-        let mut has_more_changes = 0;
-        let mut anchor: unsafe_bindings::plist_t = std::ptr::null_mut();
+    pub fn receive_changes(&self) -> Result<RecordBatch, MobileSyncError> {
+        let message = self.receive()?;
+        self.parse_changes(&message)
+    }
 
-        let result = unsafe {
-            unsafe_bindings::mobilesync_receive_changes(
-                self.pointer,
-                &mut plist,
-                &mut has_more_changes,
-                &mut anchor,
-            )
-        }
-        .into();
+    /// Receive changes from the device, waiting at most `timeout` for them
+    /// # Arguments
+    /// * `timeout` - How long to wait for the batch
+    /// # Returns
+    /// The next batch of records, `ReceiveTimeout` if it didn't arrive in time,
+    /// or `DeviceCancelled` if the device cancelled the session, see `cancel_reason`
+    ///
+    /// ***Verified:*** False
+    pub fn receive_changes_with_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<RecordBatch, MobileSyncError> {
+        let message = self.receive_with_timeout(timeout)?;
+        self.parse_changes(&message)
+    }
 
-        if result != MobileSyncError::Success {
-            return Err(result);
-        }
+    /// The reason the device gave with the last `DeviceCancelled` returned while receiving changes
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The reason, or None if the device hasn't cancelled a session
+    ///
+    /// ***Verified:*** False
+    pub fn cancel_reason(&self) -> Option<String> {
+        self.cancel_reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn parse_changes(&self, message: &Plist) -> Result<RecordBatch, MobileSyncError> {
+        // Parsed here instead of by libimobiledevice, which only logs why the device cancelled
+        let payload = match SyncMessage::from_plist(message)? {
+            SyncMessage::ProcessChanges(payload) => payload,
+            SyncMessage::CancelSession(payload) => {
+                // The payload is the data class followed by the reason
+                let reason = payload
+                    .get(1)
+                    .and_then(|p| p.get_string_val().ok())
+                    .unwrap_or_default();
+                *self.cancel_reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason);
+                return Err(MobileSyncError::DeviceCancelled);
+            }
+            _ => return Err(MobileSyncError::PlistError),
+        };
+
+        // The data class, the records, whether more batches follow, then the actions
+        let mut payload = payload.into_iter().skip(1);
+        let records = payload.next().ok_or(MobileSyncError::PlistError)?;
+        expect_type(&records, PlistType::Dictionary)?;
+        let has_more = payload
+            .next()
+            .and_then(|p| p.get_bool_val().ok())
+            .unwrap_or(false);
+        let actions = match payload.next() {
+            Some(actions) if actions.plist_type == PlistType::Dictionary => actions,
+            _ => {
+                let none: unsafe_bindings::plist_t = std::ptr::null_mut();
+                none.into()
+            }
+        };
 
-        Ok((plist.into(), has_more_changes != 0, anchor.into()))
+        Ok(RecordBatch {
+            records,
+            has_more,
+            actions,
+        })
     }
 
     /// Acknoledge the changes from the device to continue sync
@@ -302,12 +970,13 @@ impl MobileSyncClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn acknowledge_changes_from_device(&self) -> Result<(), MobileSyncError> {
-        let result =
-            unsafe { unsafe_bindings::mobilesync_acknowledge_changes_from_device(self.pointer) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::mobilesync_acknowledge_changes_from_device(*self.pointer.lock())
+        }
+        .into();
 
         if result != MobileSyncError::Success {
-            return Err(result);
+            return Err(self.cancel_after(result, "acknowledging changes"));
         }
 
         Ok(())
@@ -322,7 +991,7 @@ impl MobileSyncClient<'_> {
     /// ***Verified:*** False
     pub fn ready_to_send_changes_from_computer(&self) -> Result<(), MobileSyncError> {
         let result = unsafe {
-            unsafe_bindings::mobilesync_ready_to_send_changes_from_computer(self.pointer)
+            unsafe_bindings::mobilesync_ready_to_send_changes_from_computer(*self.pointer.lock())
         }
         .into();
 
@@ -346,13 +1015,19 @@ impl MobileSyncClient<'_> {
         is_last: bool,
         actions: Option<Plist>,
     ) -> Result<(), MobileSyncError> {
+        if self.skip_in_dry_run("send_changes", &entities) {
+            if let Some(actions) = &actions {
+                self.skip_in_dry_run("send_changes actions", actions);
+            }
+            return Ok(());
+        }
         let actions = actions
             .as_ref()
             .map_or(std::ptr::null_mut(), |v| v.get_pointer());
 
         let result = unsafe {
             unsafe_bindings::mobilesync_send_changes(
-                self.pointer,
+                *self.pointer.lock(),
                 entities.get_pointer(),
                 is_last.into(),
                 actions,
@@ -361,7 +1036,7 @@ impl MobileSyncClient<'_> {
         .into();
 
         if result != MobileSyncError::Success {
-            return Err(result);
+            return Err(self.cancel_after(result, "sending changes"));
         }
 
         Ok(())
@@ -375,12 +1050,16 @@ impl MobileSyncClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn remap_identifiers(&self, mapping: Plist) -> Result<(), MobileSyncError> {
-        if mapping.plist_type != PlistType::Array {
-            return Err(MobileSyncError::InvalidArg);
+        expect_type(&mapping, PlistType::Array)?;
+        if self.skip_in_dry_run("remap_identifiers", &mapping) {
+            return Ok(());
         }
 
         let result = unsafe {
-            unsafe_bindings::mobilesync_remap_identifiers(self.pointer, &mut mapping.get_pointer())
+            unsafe_bindings::mobilesync_remap_identifiers(
+                *self.pointer.lock(),
+                &mut mapping.get_pointer(),
+            )
         }
         .into();
 
@@ -390,25 +1069,304 @@ impl MobileSyncClient<'_> {
 
         Ok(())
     }
+
+    /// Logs a plist that won't be sent because the client is in a dry run.
+    /// Returns whether the operation should be skipped.
+    fn skip_in_dry_run(&self, operation: &str, plist: &Plist) -> bool {
+        if self.dry_run {
+            info!(
+                "Dry run, not sending {}:\n{}",
+                operation,
+                plist_util::to_xml(plist)
+            );
+        }
+        self.dry_run
+    }
+
+    /// Cancels the session after a failed step, unless the caller opted out or the device already cancelled.
+    /// Returns the original error to pass on.
+    fn cancel_after(&self, error: MobileSyncError, operation: &str) -> MobileSyncError {
+        let device_cancelled = matches!(
+            error,
+            MobileSyncError::Cancelled | MobileSyncError::DeviceCancelled
+        );
+        if self.cancel_on_error && !device_cancelled {
+            if let Err(e) = self.cancel(format!("Host failed {}: {}", operation, error)) {
+                warn!(
+                    "Unable to cancel the sync session after {} failed: {}",
+                    operation, e
+                );
+            }
+        }
+        error
+    }
+
+    /// Disconnects from the device link and closes the mobilesync connection, returning any error.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), MobileSyncError> {
+        let result = self.pointer.close().into();
+        if result != MobileSyncError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+}
+
+/// The device's answer to a request to start a sync
+struct StartReply {
+    /// The anchors for the next sync: the anchor the device gave this sync, and the computer anchor that was sent
+    next_anchors: MobileSyncAnchor,
+    /// The type of sync the device chose, if it's one this crate knows
+    sync_type: Option<MobileSyncType>,
+}
+
+/// Builds the message that asks the device to start a sync, as `mobilesync_start` does
+fn start_message(
+    data_class: &str,
+    anchors: &MobileSyncAnchor,
+    computer_data_class_version: u64,
+) -> Result<Plist, MobileSyncError> {
+    // The device takes `---` for no device anchor
+    let device_anchor = match anchors.device_anchor() {
+        "" => "---",
+        anchor => anchor,
+    };
+    let mut message = Plist::new_array();
+    for item in [
+        Plist::new_string("SDMessageSyncDataClassWithDevice"),
+        Plist::new_string(data_class),
+        Plist::new_string(device_anchor),
+        Plist::new_string(anchors.computer_anchor()),
+        Plist::new_uint(computer_data_class_version),
+        Plist::new_string("___EmptyParameterString___"),
+    ] {
+        message
+            .array_append_item(item)
+            .map_err(|_| MobileSyncError::PlistError)?;
+    }
+    Ok(message)
+}
+
+/// Parses the device's answer to `start_message`
+fn parse_start_reply(
+    reply: &Plist,
+    sent: &MobileSyncAnchor,
+) -> Result<StartReply, (SyncStartError, MobileSyncError)> {
+    let no_reason = |e| (SyncStartError::Other(String::new()), e);
+    let string_at =
+        |payload: &[Plist], index: usize| payload.get(index).and_then(|p| p.get_string_val().ok());
+    // Each payload starts with the data class
+    match SyncMessage::from_plist(reply).map_err(no_reason)? {
+        // The data class, the device anchor sent, the device's new anchor, the sync type and the class version
+        SyncMessage::SyncDataClassWithComputer(payload) => Ok(StartReply {
+            next_anchors: MobileSyncAnchor::new(
+                string_at(&payload, 2).ok_or_else(|| no_reason(MobileSyncError::PlistError))?,
+                sent.computer_anchor(),
+            ),
+            sync_type: match string_at(&payload, 3).as_deref() {
+                Some("SDSyncTypeFast") => Some(MobileSyncType::Fast),
+                Some("SDSyncTypeSlow") => Some(MobileSyncType::Slow),
+                Some("SDSyncTypeReset") => Some(MobileSyncType::Reset),
+                _ => None,
+            },
+        }),
+        SyncMessage::RefuseToSyncDataClassWithComputer(payload) => {
+            let description = string_at(&payload, 1).unwrap_or_default();
+            let error = MobileSyncError::SyncRefused;
+            Err((SyncStartError::classify(description, error), error))
+        }
+        SyncMessage::CancelSession(payload) => {
+            let description = string_at(&payload, 1).unwrap_or_default();
+            let error = MobileSyncError::Cancelled;
+            Err((SyncStartError::classify(description, error), error))
+        }
+        _ => Err(no_reason(MobileSyncError::PlistError)),
+    }
+}
+
+/// Makes a computer anchor for a new sync from the current time
+fn new_computer_anchor() -> String {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+        .to_string()
+}
+
+/// Checks that an anchor passed to `first_sync` or `continuing` can be sent to the device
+fn validate_anchor(anchor: String, kind: &str) -> Result<String, MobileSyncError> {
+    if anchor.is_empty() || anchor.contains('\0') {
+        warn!("Invalid {} anchor: {:?}", kind, anchor);
+        return Err(MobileSyncError::InvalidArg);
+    }
+    Ok(anchor)
+}
+
+/// Checks the type of a plist sent to or received from mobilesync
+/// Returns `InvalidArg` if the types don't match
+fn expect_type(plist: &Plist, expected: PlistType) -> Result<&Plist, MobileSyncError> {
+    plist_util::expect_type(plist, expected, MobileSyncError::InvalidArg)
+}
+
+/// Gets a string field of a record, if it has one
+fn record_string(record: &Plist, key: &str) -> Option<String> {
+    record
+        .dict_get_item(key)
+        .ok()
+        .and_then(|value| value.get_string_val().ok())
+}
+
+/// Sets a string field of a record, leaving it out if there's no value
+fn set_record_string(
+    record: &mut Plist,
+    key: &str,
+    value: &Option<String>,
+) -> Result<(), MobileSyncError> {
+    if let Some(value) = value {
+        record
+            .dict_set_item(key, Plist::new_string(value))
+            .map_err(|_| MobileSyncError::PlistError)?;
+    }
+    Ok(())
+}
+
+/// Gets the record IDs a record links to, which are sent as an array of strings
+fn record_ids(record: &Plist, key: &str) -> Vec<String> {
+    match record.dict_get_item(key) {
+        Ok(ids) if ids.plist_type == PlistType::Array => ids
+            .into_iter()
+            .filter_map(|id| id.plist.get_string_val().ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Builds the array of record IDs a record links to
+fn id_array(ids: &[String]) -> Result<Plist, MobileSyncError> {
+    let mut array = Plist::new_array();
+    for id in ids {
+        array
+            .array_append_item(Plist::new_string(id))
+            .map_err(|_| MobileSyncError::PlistError)?;
+    }
+    Ok(array)
+}
+
+/// Starts a record of the given entity
+fn new_record(entity: &str) -> Result<Plist, MobileSyncError> {
+    let mut record = Plist::new_dict();
+    set_record_item(&mut record, RECORD_ENTITY_NAME, Plist::new_string(entity))?;
+    Ok(record)
+}
+
+fn set_record_item(record: &mut Plist, key: &str, value: Plist) -> Result<(), MobileSyncError> {
+    record
+        .dict_set_item(key, value)
+        .map_err(|_| MobileSyncError::PlistError)
+}
+
+fn record_uint(record: &Plist, key: &str) -> Option<u64> {
+    record
+        .dict_get_item(key)
+        .ok()
+        .and_then(|value| value.get_uint_val().ok())
+}
+
+fn record_date(record: &Plist, key: &str) -> Option<SystemTime> {
+    record
+        .dict_get_item(key)
+        .ok()
+        .and_then(|value| plist_util::get_date(&value))
+}
+
+/// Reads the remapped IDs the device sent after a push, keyed by the host's IDs
+fn remapped_ids(remapped: &Plist) -> HashMap<String, String> {
+    if remapped.plist_type != PlistType::Dictionary {
+        return HashMap::new();
+    }
+    remapped
+        .clone()
+        .into_iter()
+        .filter_map(|item| Some((item.key?, item.plist.get_string_val().ok()?)))
+        .collect()
+}
+
+/// Replaces an ID the device remapped
+fn remap_id(id: &mut String, remapped: &HashMap<String, String>) {
+    if let Some(new_id) = remapped.get(id.as_str()) {
+        *id = new_id.clone();
+    }
+}
+
+/// Typed records of a data class, such as `contacts::ContactRecord`.
+/// These can be read and written in one call with `MobileSyncClient::export` and `replace_all`.
+pub trait SyncRecord: Sized {
+    /// The data class the records belong to
+    const DATA_CLASS: &'static str;
+
+    /// Joins the records of a batch into typed records
+    fn from_records(records: &Plist) -> Result<Vec<Self>, MobileSyncError>;
+
+    /// Splits the record into a dictionary of the records the device expects, keyed by record ID
+    fn to_records(&self) -> Result<Plist, MobileSyncError>;
+
+    /// Replaces the IDs the device changed after a push, including those of linked records
+    fn remap_ids(&mut self, remapped: &HashMap<String, String>);
 }
 
 impl MobileSyncAnchor {
+    /// Creates anchors without checking them.
+    /// `first_sync` and `continuing` make the difference between a first and a later sync explicit.
     pub fn new(device_anchor: impl Into<String>, computer_anchor: impl Into<String>) -> Self {
-        let device_anchor_c_string = CString::new(device_anchor.into()).unwrap();
-        let computer_anchor_c_string = CString::new(computer_anchor.into()).unwrap();
-        let c_struct = unsafe_bindings::mobilesync_anchors {
-            device_anchor: device_anchor_c_string.as_ptr() as *mut c_char,
-            computer_anchor: computer_anchor_c_string.as_ptr() as *mut c_char,
-        };
         MobileSyncAnchor {
-            c_struct: Box::new(c_struct),
-            device_anchor: device_anchor_c_string,
-            computer_anchor: computer_anchor_c_string,
+            device_anchor: CString::new(device_anchor.into()).unwrap(),
+            computer_anchor: CString::new(computer_anchor.into()).unwrap(),
         }
     }
 
-    pub(crate) fn as_c_struct_ptr(&mut self) -> *mut unsafe_bindings::mobilesync_anchors {
-        self.c_struct.as_mut()
+    /// Creates the anchors for the first sync of a data class with this host.
+    /// The device anchor is left empty, which tells the device there is no earlier sync to continue,
+    /// so it will ask for a slow sync of every record.
+    /// # Arguments
+    /// * `computer_anchor` - An anchor the host chooses to recognize this sync by later, such as a timestamp
+    /// # Returns
+    /// The anchors, or `InvalidArg` if the computer anchor is empty or contains a null byte
+    ///
+    /// ***Verified:*** False
+    pub fn first_sync(computer_anchor: impl Into<String>) -> Result<Self, MobileSyncError> {
+        let computer_anchor = validate_anchor(computer_anchor.into(), "computer")?;
+        Ok(MobileSyncAnchor::new("", computer_anchor))
+    }
+
+    /// Creates the anchors for a sync that continues from an earlier one.
+    /// The device anchor must be the one the device sent at the end of the last sync, saved as is;
+    /// a stale or edited anchor doesn't match what the device remembers and makes it fall back to a slow sync.
+    /// # Arguments
+    /// * `device_anchor` - The anchor the device issued in the last sync
+    /// * `computer_anchor` - The host's anchor for this sync
+    /// # Returns
+    /// The anchors, or `InvalidArg` if either anchor is empty or contains a null byte
+    ///
+    /// ***Verified:*** False
+    pub fn continuing(
+        device_anchor: impl Into<String>,
+        computer_anchor: impl Into<String>,
+    ) -> Result<Self, MobileSyncError> {
+        let device_anchor = validate_anchor(device_anchor.into(), "device")?;
+        let computer_anchor = validate_anchor(computer_anchor.into(), "computer")?;
+        Ok(MobileSyncAnchor::new(device_anchor, computer_anchor))
+    }
+
+    /// Whether these anchors start a first sync, which is the case when the device anchor is empty
+    pub fn is_first_sync(&self) -> bool {
+        self.device_anchor.as_bytes().is_empty()
     }
 
     pub fn device_anchor(&self) -> &str {
@@ -418,6 +1376,255 @@ impl MobileSyncAnchor {
     pub fn computer_anchor(&self) -> &str {
         self.computer_anchor.as_c_str().to_str().unwrap()
     }
+
+    /// Parses anchors saved with `to_string`.
+    /// The format is the byte length of the device anchor in decimal, a `:`,
+    /// then the device anchor immediately followed by the computer anchor.
+    /// For example `5:abcdeHOST-1` is the device anchor `abcde` and the computer anchor `HOST-1`.
+    /// The result is plain text, so it can be stored as a JSON string.
+    /// # Arguments
+    /// * `anchors` - The saved anchors
+    /// # Returns
+    /// The anchors, or `InvalidArg` if the string isn't in the format above
+    ///
+    /// ***Verified:*** False
+    pub fn from_string(anchors: &str) -> Result<Self, MobileSyncError> {
+        let (length, rest) = anchors.split_once(':').ok_or(MobileSyncError::InvalidArg)?;
+        let length = length
+            .parse::<usize>()
+            .map_err(|_| MobileSyncError::InvalidArg)?;
+        if length > rest.len() || !rest.is_char_boundary(length) || rest.contains('\0') {
+            return Err(MobileSyncError::InvalidArg);
+        }
+        let (device_anchor, computer_anchor) = rest.split_at(length);
+        Ok(MobileSyncAnchor::new(device_anchor, computer_anchor))
+    }
+}
+
+impl Display for MobileSyncAnchor {
+    /// Writes the anchors in the format read by `from_string`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}{}",
+            self.device_anchor().len(),
+            self.device_anchor(),
+            self.computer_anchor()
+        )
+    }
+}
+
+impl FromStr for MobileSyncAnchor {
+    type Err = MobileSyncError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MobileSyncAnchor::from_string(s)
+    }
+}
+
+/// Why the device refused to start a sync, parsed from the description it sent.
+/// Every variant keeps the raw description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncStartError {
+    /// The data class versions of the host and device don't match, a slow sync may work
+    VersionMismatch(String),
+    /// The device doesn't sync the requested data class
+    UnsupportedDataClass(String),
+    /// The device cancelled the session
+    CancelledByDevice(String),
+    /// Another host is already syncing with the device, so retrying later may work.
+    /// This commonly happens when Finder or iTunes starts an automatic sync as the device is plugged in.
+    DeviceBusy(String),
+    Other(String),
+}
+
+/// Phrases the device uses when another sync session holds the data class
+const BUSY_PHRASES: [&str; 5] = [
+    "busy",
+    "already syncing",
+    "another sync",
+    "sync in progress",
+    "session in progress",
+];
+
+impl SyncStartError {
+    fn classify(description: String, error: MobileSyncError) -> Self {
+        let lower = description.to_lowercase();
+        // The device cancels with a reason when it's busy, which is the more useful of the two
+        if BUSY_PHRASES.iter().any(|phrase| lower.contains(phrase)) {
+            SyncStartError::DeviceBusy(description)
+        } else if error == MobileSyncError::Cancelled {
+            SyncStartError::CancelledByDevice(description)
+        } else if error == MobileSyncError::BadVersion || lower.contains("version") {
+            SyncStartError::VersionMismatch(description)
+        } else if lower.contains("data class") || lower.contains("unsupported") {
+            SyncStartError::UnsupportedDataClass(description)
+        } else {
+            SyncStartError::Other(description)
+        }
+    }
+
+    /// Gets the description the device sent
+    pub fn description(&self) -> &str {
+        match self {
+            SyncStartError::VersionMismatch(d)
+            | SyncStartError::UnsupportedDataClass(d)
+            | SyncStartError::CancelledByDevice(d)
+            | SyncStartError::DeviceBusy(d)
+            | SyncStartError::Other(d) => d,
+        }
+    }
+}
+
+impl std::fmt::Display for SyncStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            SyncStartError::VersionMismatch(_) => "VersionMismatch",
+            SyncStartError::UnsupportedDataClass(_) => "UnsupportedDataClass",
+            SyncStartError::CancelledByDevice(_) => "CancelledByDevice",
+            SyncStartError::DeviceBusy(_) => "DeviceBusy",
+            SyncStartError::Other(_) => "Other",
+        };
+        write!(f, "{}: {}", kind, self.description())
+    }
+}
+
+/// A batch of records received from the device during a sync.
+/// This replaces the `(Plist, bool, Plist)` tuple these functions used to return.
+/// Note that the old `bool` was true on the *last* batch, while `has_more` is true on every batch but the last.
+pub struct RecordBatch {
+    /// The records in the batch, a dictionary keyed by record ID
+    pub records: Plist,
+    /// Whether more batches follow, to be fetched with `receive_changes` after acknowledging this one
+    pub has_more: bool,
+    /// The extra actions the device sent with the batch, null if there were none
+    pub actions: Plist,
+}
+
+/// The result of `MobileSyncClient::reset_sync`
+pub struct ResetSync {
+    /// The anchors to pass to `start` for the next sync, with the device anchor the device gave the reset
+    pub anchors: MobileSyncAnchor,
+    /// The IDs the device gave the host's records, keyed by the host's IDs. Null if none were changed
+    pub remapped: Plist,
+}
+
+/// How many records `MobileSyncClient::push_records` packs into each `send_changes` message.
+/// A message is sent once either limit would be passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimit {
+    pub max_records: usize,
+    /// The total size of the records in binary plist form
+    pub max_bytes: usize,
+}
+
+impl Default for BatchLimit {
+    /// 1000 records or 4 MiB, whichever comes first
+    fn default() -> Self {
+        BatchLimit {
+            max_records: 1000,
+            max_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// The result of `MobileSyncClient::push_records`
+pub struct PushResult {
+    /// The IDs the device gave the host's records, keyed by the host's IDs. Null if none were changed
+    pub remapped: Plist,
+}
+
+impl PushResult {
+    /// Gets the IDs the device gave the host's records, keyed by the host's IDs
+    pub fn remapped_ids(&self) -> HashMap<String, String> {
+        remapped_ids(&self.remapped)
+    }
+}
+
+/// A message received from mobilesync, classified by the type string at the start of the array.
+/// Each variant holds the elements that came after the type string.
+pub enum SyncMessage {
+    SyncDataClassWithDevice(Vec<Plist>),
+    SyncDataClassWithComputer(Vec<Plist>),
+    RefuseToSyncDataClassWithComputer(Vec<Plist>),
+    GetAllRecordsFromDevice(Vec<Plist>),
+    GetChangesFromDevice(Vec<Plist>),
+    /// Records sent by the device or the host
+    ProcessChanges(Vec<Plist>),
+    AcknowledgeChangesFromDevice(Vec<Plist>),
+    DeviceReadyToReceiveChanges(Vec<Plist>),
+    RemapRecordIdentifiers(Vec<Plist>),
+    ClearAllRecordsOnDevice(Vec<Plist>),
+    DeviceWillClearAllRecords(Vec<Plist>),
+    FinishSessionOnDevice(Vec<Plist>),
+    DeviceFinishedSession(Vec<Plist>),
+    CancelSession(Vec<Plist>),
+    /// A message type this crate doesn't know, with its type string
+    Other(String, Vec<Plist>),
+}
+
+impl SyncMessage {
+    /// Classifies a message received from mobilesync
+    /// # Arguments
+    /// * `message` - The message, as returned by `receive`
+    /// # Returns
+    /// The message, or `InvalidArg` if it isn't an array starting with a string
+    ///
+    /// ***Verified:*** False
+    pub fn from_plist(message: &Plist) -> Result<Self, MobileSyncError> {
+        expect_type(message, PlistType::Array)?;
+        let mut items = message.clone().into_iter().map(|item| item.plist.clone());
+        let message_type = items
+            .next()
+            .and_then(|p| p.get_string_val().ok())
+            .ok_or(MobileSyncError::InvalidArg)?;
+        let payload = items.collect::<Vec<Plist>>();
+
+        Ok(match message_type.as_str() {
+            "SDMessageSyncDataClassWithDevice" => SyncMessage::SyncDataClassWithDevice(payload),
+            "SDMessageSyncDataClassWithComputer" => SyncMessage::SyncDataClassWithComputer(payload),
+            "SDMessageRefuseToSyncDataClassWithComputer" => {
+                SyncMessage::RefuseToSyncDataClassWithComputer(payload)
+            }
+            "SDMessageGetAllRecordsFromDevice" => SyncMessage::GetAllRecordsFromDevice(payload),
+            "SDMessageGetChangesFromDevice" => SyncMessage::GetChangesFromDevice(payload),
+            "SDMessageProcessChanges" => SyncMessage::ProcessChanges(payload),
+            "SDMessageAcknowledgeChangesFromDevice" => {
+                SyncMessage::AcknowledgeChangesFromDevice(payload)
+            }
+            "SDMessageDeviceReadyToReceiveChanges" => {
+                SyncMessage::DeviceReadyToReceiveChanges(payload)
+            }
+            "SDMessageRemapRecordIdentifiers" => SyncMessage::RemapRecordIdentifiers(payload),
+            "SDMessageClearAllRecordsOnDevice" => SyncMessage::ClearAllRecordsOnDevice(payload),
+            "SDMessageDeviceWillClearAllRecords" => SyncMessage::DeviceWillClearAllRecords(payload),
+            "SDMessageFinishSessionOnDevice" => SyncMessage::FinishSessionOnDevice(payload),
+            "SDMessageDeviceFinishedSession" => SyncMessage::DeviceFinishedSession(payload),
+            "SDMessageCancelSession" => SyncMessage::CancelSession(payload),
+            _ => SyncMessage::Other(message_type, payload),
+        })
+    }
+
+    /// Gets the elements of the message after the type string
+    pub fn payload(&self) -> &[Plist] {
+        match self {
+            SyncMessage::SyncDataClassWithDevice(p)
+            | SyncMessage::SyncDataClassWithComputer(p)
+            | SyncMessage::RefuseToSyncDataClassWithComputer(p)
+            | SyncMessage::GetAllRecordsFromDevice(p)
+            | SyncMessage::GetChangesFromDevice(p)
+            | SyncMessage::ProcessChanges(p)
+            | SyncMessage::AcknowledgeChangesFromDevice(p)
+            | SyncMessage::DeviceReadyToReceiveChanges(p)
+            | SyncMessage::RemapRecordIdentifiers(p)
+            | SyncMessage::ClearAllRecordsOnDevice(p)
+            | SyncMessage::DeviceWillClearAllRecords(p)
+            | SyncMessage::FinishSessionOnDevice(p)
+            | SyncMessage::DeviceFinishedSession(p)
+            | SyncMessage::CancelSession(p)
+            | SyncMessage::Other(_, p) => p,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -437,10 +1644,298 @@ impl From<MobileSyncType> for c_uint {
     }
 }
 
-impl Drop for MobileSyncClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::mobilesync_client_free(self.pointer);
+impl Display for MobileSyncType {
+    /// Writes the name read by `from_str`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MobileSyncType::Fast => "fast",
+            MobileSyncType::Slow => "slow",
+            MobileSyncType::Reset => "reset",
+        })
+    }
+}
+
+impl FromStr for MobileSyncType {
+    type Err = MobileSyncError;
+
+    /// Parses `fast`, `slow` or `reset`, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(MobileSyncType::Fast),
+            "slow" => Ok(MobileSyncType::Slow),
+            "reset" => Ok(MobileSyncType::Reset),
+            _ => Err(MobileSyncError::InvalidArg),
+        }
+    }
+}
+
+impl<'a> Service<'a> for MobileSyncClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobilesync";
+    type Error = MobileSyncError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, MobileSyncError> {
+        Self::new(device, descriptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_types_parse_their_names_in_any_case() {
+        let types = [
+            MobileSyncType::Fast,
+            MobileSyncType::Slow,
+            MobileSyncType::Reset,
+        ];
+        for type_ in types {
+            assert_eq!(type_.to_string().parse::<MobileSyncType>(), Ok(type_));
+            assert_eq!(type_.to_string().to_uppercase().parse(), Ok(type_));
+        }
+        assert_eq!("Fast".parse(), Ok(MobileSyncType::Fast));
+    }
+
+    #[test]
+    fn unknown_sync_types_are_rejected() {
+        for name in ["", "full", "fast ", "slowly"] {
+            assert_eq!(
+                name.parse::<MobileSyncType>(),
+                Err(MobileSyncError::InvalidArg),
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    fn message(items: &[&str]) -> Plist {
+        let mut array = Plist::new_array();
+        for item in items {
+            array.array_append_item(Plist::new_string(item)).unwrap();
+        }
+        array
+    }
+
+    #[test]
+    fn messages_are_classified_by_their_type_string() {
+        let received =
+            SyncMessage::from_plist(&message(&["SDMessageProcessChanges", "com.apple.Contacts"]))
+                .unwrap();
+        assert!(matches!(received, SyncMessage::ProcessChanges(_)));
+        let payload = received.payload();
+        assert_eq!(payload.len(), 1);
+        assert_eq!(
+            payload[0].get_string_val().ok().as_deref(),
+            Some("com.apple.Contacts")
+        );
+
+        let finished = SyncMessage::from_plist(&message(&["SDMessageDeviceFinishedSession"]));
+        assert!(matches!(finished, Ok(SyncMessage::DeviceFinishedSession(p)) if p.is_empty()));
+        let cancelled = SyncMessage::from_plist(&message(&["SDMessageCancelSession", "reason"]));
+        assert!(matches!(cancelled, Ok(SyncMessage::CancelSession(_))));
+    }
+
+    #[test]
+    fn unknown_message_types_keep_their_name() {
+        let received = SyncMessage::from_plist(&message(&["SDMessageSomethingNew", "a", "b"]));
+        assert!(matches!(
+            received,
+            Ok(SyncMessage::Other(name, payload)) if name == "SDMessageSomethingNew" && payload.len() == 2
+        ));
+    }
+
+    #[test]
+    fn messages_without_a_type_string_are_rejected() {
+        let mut untyped = Plist::new_array();
+        untyped.array_append_item(Plist::new_uint(1)).unwrap();
+        for received in [
+            Plist::new_string("SDMessageProcessChanges"),
+            Plist::new_array(),
+            untyped,
+        ] {
+            assert!(matches!(
+                SyncMessage::from_plist(&received),
+                Err(MobileSyncError::InvalidArg)
+            ));
+        }
+    }
+
+    #[test]
+    fn first_syncs_leave_the_device_anchor_empty() {
+        let anchors = MobileSyncAnchor::first_sync("2026-10-16T09:00:00Z").unwrap();
+        assert!(anchors.is_first_sync());
+        assert_eq!(anchors.device_anchor(), "");
+        assert_eq!(anchors.computer_anchor(), "2026-10-16T09:00:00Z");
+    }
+
+    #[test]
+    fn continuing_syncs_keep_both_anchors() {
+        let anchors = MobileSyncAnchor::continuing("device-7", "host-8").unwrap();
+        assert!(!anchors.is_first_sync());
+        assert_eq!(anchors.device_anchor(), "device-7");
+        assert_eq!(anchors.computer_anchor(), "host-8");
+    }
+
+    #[test]
+    fn empty_or_null_anchors_are_rejected() {
+        for anchors in [
+            MobileSyncAnchor::first_sync(""),
+            MobileSyncAnchor::first_sync("host\0"),
+            MobileSyncAnchor::continuing("", "host"),
+            MobileSyncAnchor::continuing("device", ""),
+            MobileSyncAnchor::continuing("dev\0ice", "host"),
+        ] {
+            assert!(matches!(anchors, Err(MobileSyncError::InvalidArg)));
         }
     }
+
+    #[test]
+    fn busy_descriptions_are_classified_as_device_busy() {
+        for description in [
+            "Device is busy",
+            "Another sync session is in progress",
+            "The data class is already syncing with another host",
+        ] {
+            for error in [MobileSyncError::SyncRefused, MobileSyncError::Cancelled] {
+                assert_eq!(
+                    SyncStartError::classify(description.to_string(), error),
+                    SyncStartError::DeviceBusy(description.to_string())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn other_descriptions_keep_their_classification() {
+        let classify =
+            |description: &str, error| SyncStartError::classify(description.to_string(), error);
+        assert_eq!(
+            classify("User cancelled", MobileSyncError::Cancelled),
+            SyncStartError::CancelledByDevice("User cancelled".to_string())
+        );
+        assert_eq!(
+            classify("", MobileSyncError::BadVersion),
+            SyncStartError::VersionMismatch(String::new())
+        );
+        assert_eq!(
+            classify("Unsupported data class", MobileSyncError::SyncRefused),
+            SyncStartError::UnsupportedDataClass("Unsupported data class".to_string())
+        );
+        assert_eq!(
+            classify("Try again", MobileSyncError::SyncRefused),
+            SyncStartError::Other("Try again".to_string())
+        );
+    }
+
+    fn start_reply(device_anchors: [&str; 2], sync_type: &str) -> Plist {
+        let mut reply = message(&[
+            "SDMessageSyncDataClassWithComputer",
+            "com.apple.Contacts",
+            device_anchors[0],
+            device_anchors[1],
+            sync_type,
+        ]);
+        reply.array_append_item(Plist::new_uint(106)).unwrap();
+        reply
+    }
+
+    #[test]
+    fn syncs_continue_from_the_device_anchor_sent_at_the_start() {
+        let sent = MobileSyncAnchor::continuing("device-1", "host-2").unwrap();
+        let reply = start_reply(["device-1", "device-2"], "SDSyncTypeFast");
+
+        // These are what `full_sync` returns
+        let reply = parse_start_reply(&reply, &sent).unwrap();
+        assert_eq!(reply.next_anchors.device_anchor(), "device-2");
+        assert_eq!(reply.next_anchors.computer_anchor(), "host-2");
+        assert_eq!(reply.next_anchors.to_string(), "8:device-2host-2");
+        assert_eq!(reply.sync_type, Some(MobileSyncType::Fast));
+    }
+
+    #[test]
+    fn start_replies_without_a_device_anchor_are_rejected() {
+        let sent = MobileSyncAnchor::first_sync("host-2").unwrap();
+        let mut reply = message(&["SDMessageSyncDataClassWithComputer", "com.apple.Contacts"]);
+        reply.array_append_item(Plist::new_uint(1)).unwrap();
+        assert!(matches!(
+            parse_start_reply(&reply, &sent),
+            Err((_, MobileSyncError::PlistError))
+        ));
+
+        // A batch of changes, whose last element is the actions dictionary, isn't a reply either
+        let changes = message(&["SDMessageProcessChanges", "com.apple.Contacts"]);
+        assert!(matches!(
+            parse_start_reply(&changes, &sent),
+            Err((_, MobileSyncError::PlistError))
+        ));
+    }
+
+    #[test]
+    fn refused_starts_keep_the_device_reason() {
+        let sent = MobileSyncAnchor::first_sync("host-2").unwrap();
+        let refused = message(&[
+            "SDMessageRefuseToSyncDataClassWithComputer",
+            "com.apple.Contacts",
+            "Device is busy",
+        ]);
+        assert_eq!(
+            parse_start_reply(&refused, &sent).err(),
+            Some((
+                SyncStartError::DeviceBusy("Device is busy".to_string()),
+                MobileSyncError::SyncRefused
+            ))
+        );
+
+        let cancelled = message(&["SDMessageCancelSession", "com.apple.Contacts", "Bye"]);
+        assert_eq!(
+            parse_start_reply(&cancelled, &sent).err(),
+            Some((
+                SyncStartError::CancelledByDevice("Bye".to_string()),
+                MobileSyncError::Cancelled
+            ))
+        );
+    }
+
+    #[test]
+    fn first_syncs_send_no_device_anchor() {
+        let first = MobileSyncAnchor::first_sync("host-2").unwrap();
+        let sent = start_message("com.apple.Contacts", &first, 106).unwrap();
+        let items = sent
+            .into_iter()
+            .map(|item| item.plist.clone())
+            .collect::<Vec<Plist>>();
+        assert_eq!(items.len(), 6);
+        assert_eq!(items[2].get_string_val().ok().as_deref(), Some("---"));
+        assert_eq!(items[3].get_string_val().ok().as_deref(), Some("host-2"));
+        assert_eq!(items[4].get_uint_val().ok(), Some(106));
+
+        let continuing = MobileSyncAnchor::continuing("device-2", "host-3").unwrap();
+        let sent = start_message("com.apple.Contacts", &continuing, 106).unwrap();
+        let device_anchor = sent.into_iter().nth(2).map(|item| item.plist.clone());
+        assert_eq!(
+            device_anchor
+                .and_then(|p| p.get_string_val().ok())
+                .as_deref(),
+            Some("device-2")
+        );
+    }
+
+    #[test]
+    fn resets_continue_from_the_anchor_the_device_gave_them() {
+        let sent = MobileSyncAnchor::first_sync("host-9").unwrap();
+        let reply = start_reply(["---", "reset-1"], "SDSyncTypeReset");
+
+        // These are `ResetSync::anchors`, so the next sync isn't another first sync
+        let reply = parse_start_reply(&reply, &sent).unwrap();
+        assert!(!reply.next_anchors.is_first_sync());
+        assert_eq!(reply.next_anchors.to_string(), "7:reset-1host-9");
+        assert_eq!(reply.sync_type, Some(MobileSyncType::Reset));
+
+        let saved = MobileSyncAnchor::from_string(&reply.next_anchors.to_string()).unwrap();
+        assert_eq!(saved.device_anchor(), "reset-1");
+        assert_eq!(saved.computer_anchor(), "host-9");
+    }
 }
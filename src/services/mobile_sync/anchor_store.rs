@@ -0,0 +1,137 @@
+// jkcoxson
+// Persists sync anchors between runs, so fast syncs keep working after the host restarts
+
+use std::{
+    fmt::Debug,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::error::MobileSyncError;
+
+use super::MobileSyncAnchor;
+
+use log::warn;
+
+/// Somewhere to keep the anchors of each device and data class between syncs.
+/// Give one to a client with `MobileSyncClient::with_anchor_store` to have `start` and `finish` use it.
+pub trait MobileSyncAnchorStore: Send + Sync {
+    /// Loads the anchors saved for a device and data class
+    /// # Arguments
+    /// * `udid` - The UDID of the device
+    /// * `data_class` - The data class, such as `com.apple.Contacts`
+    /// # Returns
+    /// The anchors, or `None` if none were saved
+    fn load(
+        &self,
+        udid: &str,
+        data_class: &str,
+    ) -> Result<Option<MobileSyncAnchor>, MobileSyncError>;
+
+    /// Saves the anchors for a device and data class, replacing any saved before
+    /// # Arguments
+    /// * `udid` - The UDID of the device
+    /// * `data_class` - The data class, such as `com.apple.Contacts`
+    /// * `anchors` - The anchors to save
+    /// # Returns
+    /// *none*
+    fn save(
+        &self,
+        udid: &str,
+        data_class: &str,
+        anchors: &MobileSyncAnchor,
+    ) -> Result<(), MobileSyncError>;
+}
+
+/// Saves anchors as files in a directory, one per device and data class at `<dir>/<udid>/<data class>.anchor`.
+/// The files hold the anchors in the format written by `MobileSyncAnchor::to_string`.
+#[derive(Debug, Clone)]
+pub struct FileAnchorStore {
+    dir: PathBuf,
+}
+
+impl FileAnchorStore {
+    /// Creates a store in a directory, which is created once anchors are saved
+    /// # Arguments
+    /// * `dir` - The directory to keep the anchors in
+    /// # Returns
+    /// The store
+    ///
+    /// ***Verified:*** False
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileAnchorStore { dir: dir.into() }
+    }
+
+    /// Gets the directory the anchors are kept in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, udid: &str, data_class: &str) -> PathBuf {
+        // Keep both parts to a single path component
+        let component = |s: &str| s.replace(['/', '\\'], "_");
+        self.dir
+            .join(component(udid))
+            .join(format!("{}.anchor", component(data_class)))
+    }
+}
+
+impl MobileSyncAnchorStore for FileAnchorStore {
+    fn load(
+        &self,
+        udid: &str,
+        data_class: &str,
+    ) -> Result<Option<MobileSyncAnchor>, MobileSyncError> {
+        let path = self.path(udid, data_class);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                warn!("Unable to read anchors from {}: {}", path.display(), e);
+                return Err(MobileSyncError::IoError);
+            }
+        };
+        MobileSyncAnchor::from_string(contents.trim_end_matches('\n'))
+            .map(Some)
+            .map_err(|e| {
+                warn!("Invalid anchors in {}", path.display());
+                e
+            })
+    }
+
+    fn save(
+        &self,
+        udid: &str,
+        data_class: &str,
+        anchors: &MobileSyncAnchor,
+    ) -> Result<(), MobileSyncError> {
+        let path = self.path(udid, data_class);
+        let io_error = |e: std::io::Error| {
+            warn!("Unable to save anchors to {}: {}", path.display(), e);
+            MobileSyncError::IoError
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        // Written next to the file and renamed over it, so a crash never leaves half an anchor
+        let temp = path.with_extension("anchor.tmp");
+        fs::write(&temp, format!("{}\n", anchors)).map_err(io_error)?;
+        fs::rename(&temp, &path).map_err(io_error)
+    }
+}
+
+/// The store a client was given, if any
+#[derive(Clone, Default)]
+pub(crate) struct AnchorStoreHandle(pub(crate) Option<Arc<dyn MobileSyncAnchorStore>>);
+
+impl Debug for AnchorStoreHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "Some(store)"
+        } else {
+            "None"
+        })
+    }
+}
@@ -0,0 +1,149 @@
+// jkcoxson
+// Typed records for the Bookmarks data class
+
+use std::collections::HashMap;
+
+use crate::error::MobileSyncError;
+
+use super::{
+    expect_type, id_array, new_record, record_ids, record_string, remap_id, set_record_item,
+    set_record_string, SyncRecord, RECORD_ENTITY_NAME,
+};
+
+use plist_plus::{Plist, PlistType};
+
+/// The data class to pass to `MobileSyncClient::start` for Safari bookmarks
+pub const DATA_CLASS: &str = "com.apple.Bookmarks";
+
+const BOOKMARK_ENTITY: &str = "com.apple.bookmarks.Bookmark";
+const FOLDER_ENTITY: &str = "com.apple.bookmarks.Folder";
+
+/// A bookmark or a folder of bookmarks.
+/// Read every bookmark with `MobileSyncClient::export::<Bookmark>`, and rebuild the tree from `parent`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bookmark {
+    /// The record ID of the bookmark
+    pub id: String,
+    pub title: Option<String>,
+    /// The address the bookmark opens, `None` for folders
+    pub url: Option<String>,
+    /// The record ID of the folder the bookmark is in, `None` at the top level
+    pub parent: Option<String>,
+}
+
+impl Bookmark {
+    /// Creates a bookmark
+    /// # Arguments
+    /// * `id` - The record ID to give the bookmark. New bookmarks can use any unique ID,
+    ///   the device sends the one it picked back in `PushResult::remapped`
+    /// * `title` - The title of the bookmark
+    /// * `url` - The address the bookmark opens
+    /// # Returns
+    /// The bookmark, at the top level
+    ///
+    /// ***Verified:*** False
+    pub fn new(id: impl Into<String>, title: impl Into<String>, url: impl Into<String>) -> Self {
+        Bookmark {
+            id: id.into(),
+            title: Some(title.into()),
+            url: Some(url.into()),
+            parent: None,
+        }
+    }
+
+    /// Creates a folder
+    /// # Arguments
+    /// * `id` - The record ID to give the folder
+    /// * `title` - The title of the folder
+    /// # Returns
+    /// The folder, at the top level
+    ///
+    /// ***Verified:*** False
+    pub fn folder(id: impl Into<String>, title: impl Into<String>) -> Self {
+        Bookmark {
+            id: id.into(),
+            title: Some(title.into()),
+            url: None,
+            parent: None,
+        }
+    }
+
+    /// Checks if this is a folder rather than a bookmark
+    pub fn is_folder(&self) -> bool {
+        self.url.is_none()
+    }
+
+    /// Gets the bookmarks and folders directly inside a folder
+    /// # Arguments
+    /// * `bookmarks` - Every bookmark, as returned by `export`
+    /// * `parent` - The ID of the folder, or `None` for the top level
+    /// # Returns
+    /// The bookmarks in the folder
+    ///
+    /// ***Verified:*** False
+    pub fn children<'b>(bookmarks: &'b [Bookmark], parent: Option<&str>) -> Vec<&'b Bookmark> {
+        bookmarks
+            .iter()
+            .filter(|bookmark| bookmark.parent.as_deref() == parent)
+            .collect()
+    }
+}
+
+impl SyncRecord for Bookmark {
+    const DATA_CLASS: &'static str = DATA_CLASS;
+
+    fn from_records(records: &Plist) -> Result<Vec<Self>, MobileSyncError> {
+        expect_type(records, PlistType::Dictionary)?;
+
+        let mut bookmarks = Vec::new();
+        for record in records.clone().into_iter() {
+            let id = match record.key {
+                Some(id) => id,
+                None => continue,
+            };
+            // Deleted records are sent as a string instead of a dictionary
+            if record.plist.plist_type != PlistType::Dictionary {
+                continue;
+            }
+            let url = match record_string(&record.plist, RECORD_ENTITY_NAME).as_deref() {
+                // A bookmark always has an address, even if the device left it out
+                Some(BOOKMARK_ENTITY) => {
+                    Some(record_string(&record.plist, "url").unwrap_or_default())
+                }
+                Some(FOLDER_ENTITY) => None,
+                _ => continue,
+            };
+            bookmarks.push(Bookmark {
+                id,
+                title: record_string(&record.plist, "name"),
+                url,
+                parent: record_ids(&record.plist, "parent").into_iter().next(),
+            });
+        }
+
+        Ok(bookmarks)
+    }
+
+    fn to_records(&self) -> Result<Plist, MobileSyncError> {
+        let mut record = match self.url {
+            Some(_) => new_record(BOOKMARK_ENTITY)?,
+            None => new_record(FOLDER_ENTITY)?,
+        };
+        set_record_string(&mut record, "name", &self.title)?;
+        set_record_string(&mut record, "url", &self.url)?;
+        if let Some(parent) = &self.parent {
+            set_record_item(&mut record, "parent", id_array(&[parent.clone()])?)?;
+        }
+
+        let mut records = Plist::new_dict();
+        set_record_item(&mut records, &self.id, record)?;
+        Ok(records)
+    }
+
+    fn remap_ids(&mut self, remapped: &HashMap<String, String>) {
+        remap_id(&mut self.id, remapped);
+        if let Some(parent) = &mut self.parent {
+            remap_id(parent, remapped);
+        }
+    }
+}
@@ -0,0 +1,669 @@
+// jkcoxson
+// Typed records for the Calendars data class, and conversion to and from iCalendar
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::MobileSyncError, plist_util};
+
+use super::{
+    expect_type, id_array, new_record, record_date, record_ids, record_string, record_uint,
+    remap_id, set_record_item, set_record_string, SyncRecord, RECORD_ENTITY_NAME,
+};
+
+use log::warn;
+use plist_plus::{Plist, PlistType};
+
+/// The data class to pass to `MobileSyncClient::start` for calendars
+pub const DATA_CLASS: &str = "com.apple.Calendars";
+
+const EVENT_ENTITY: &str = "com.apple.calendars.Event";
+const RECURRENCE_ENTITY: &str = "com.apple.calendars.Recurrence";
+const DISPLAY_ALARM_ENTITY: &str = "com.apple.calendars.DisplayAlarm";
+const AUDIO_ALARM_ENTITY: &str = "com.apple.calendars.AudioAlarm";
+
+/// A calendar event, put together from the records the device sends for it.
+///
+/// The device sends an event as one record for the event and one for each recurrence rule and alarm,
+/// linked by record ID. `from_records` joins them back up, and `to_records` splits an event into
+/// the records to pass to `MobileSyncClient::push_records`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CalendarEvent {
+    /// The record ID of the event, used as the `UID` in iCalendar
+    pub id: String,
+    /// The record ID of the calendar the event is in
+    pub calendar_id: Option<String>,
+    pub summary: Option<String>,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    pub url: Option<String>,
+    pub start: Option<SystemTime>,
+    pub end: Option<SystemTime>,
+    /// Whether the event takes whole days, in which case only the dates of `start` and `end` matter
+    pub all_day: bool,
+    pub recurrence: Option<RecurrenceRule>,
+    pub alarms: Vec<Alarm>,
+}
+
+/// How an event repeats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    /// The record ID, or `None` for one the device hasn't seen yet
+    pub id: Option<String>,
+    pub frequency: Frequency,
+    /// How many periods of `frequency` pass between occurrences
+    pub interval: u64,
+    /// How many times the event happens in total
+    pub count: Option<u64>,
+    /// When the event stops repeating
+    pub until: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A reminder for an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    /// The record ID, or `None` for one the device hasn't seen yet
+    pub id: Option<String>,
+    /// When the alarm goes off in seconds from the start of the event, negative for before it
+    pub offset: i64,
+    /// Whether the alarm plays a sound instead of only showing a notification
+    pub audio: bool,
+}
+
+impl Frequency {
+    /// Gets the name the device uses, which is the iCalendar name in lowercase
+    fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+            Frequency::Yearly => "yearly",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "daily" => Some(Frequency::Daily),
+            "weekly" => Some(Frequency::Weekly),
+            "monthly" => Some(Frequency::Monthly),
+            "yearly" => Some(Frequency::Yearly),
+            _ => None,
+        }
+    }
+}
+
+impl CalendarEvent {
+    /// Creates an empty event
+    /// # Arguments
+    /// * `id` - The record ID to give the event. New events can use any unique ID,
+    ///   the device sends the one it picked back in `PushResult::remapped`
+    /// # Returns
+    /// The event
+    ///
+    /// ***Verified:*** False
+    pub fn new(id: impl Into<String>) -> Self {
+        CalendarEvent {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Joins the records of a batch into events
+    /// # Arguments
+    /// * `records` - The records of a batch, such as `RecordBatch::records`
+    /// # Returns
+    /// The events in the order they were sent.
+    /// Deleted records are skipped, as are recurrences and alarms of events that aren't in the batch.
+    ///
+    /// ***Verified:*** False
+    pub fn from_records(records: &Plist) -> Result<Vec<CalendarEvent>, MobileSyncError> {
+        expect_type(records, PlistType::Dictionary)?;
+
+        let mut events = Vec::new();
+        let mut children = Vec::new();
+        for record in records.clone().into_iter() {
+            let id = match record.key {
+                Some(id) => id,
+                None => continue,
+            };
+            // Deleted records are sent as a string instead of a dictionary
+            if record.plist.plist_type != PlistType::Dictionary {
+                continue;
+            }
+            let record = record.plist.clone();
+            match record_string(&record, RECORD_ENTITY_NAME).as_deref() {
+                Some(EVENT_ENTITY) => events.push(CalendarEvent {
+                    calendar_id: record_ids(&record, "calendar").into_iter().next(),
+                    summary: record_string(&record, "summary"),
+                    location: record_string(&record, "location"),
+                    notes: record_string(&record, "description"),
+                    url: record_string(&record, "url"),
+                    start: record_date(&record, "start date"),
+                    end: record_date(&record, "end date"),
+                    all_day: record
+                        .dict_get_item("all day")
+                        .ok()
+                        .and_then(|v| v.get_bool_val().ok())
+                        .unwrap_or(false),
+                    ..CalendarEvent::new(id)
+                }),
+                Some(RECURRENCE_ENTITY) | Some(DISPLAY_ALARM_ENTITY) | Some(AUDIO_ALARM_ENTITY) => {
+                    children.push((id, record))
+                }
+                _ => {}
+            }
+        }
+
+        let index: HashMap<String, usize> = events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| (event.id.clone(), i))
+            .collect();
+        for (id, record) in children {
+            let event = match record_ids(&record, "owner")
+                .first()
+                .and_then(|owner| index.get(owner))
+            {
+                Some(i) => &mut events[*i],
+                None => {
+                    warn!("Skipping calendar record {} without its event", id);
+                    continue;
+                }
+            };
+            match record_string(&record, RECORD_ENTITY_NAME).as_deref() {
+                Some(RECURRENCE_ENTITY) => {
+                    let frequency = match record_string(&record, "frequency")
+                        .as_deref()
+                        .and_then(Frequency::parse)
+                    {
+                        Some(frequency) => frequency,
+                        None => {
+                            warn!("Skipping recurrence {} with an unknown frequency", id);
+                            continue;
+                        }
+                    };
+                    event.recurrence = Some(RecurrenceRule {
+                        id: Some(id),
+                        frequency,
+                        interval: record_uint(&record, "interval").unwrap_or(1),
+                        count: record_uint(&record, "count"),
+                        until: record_date(&record, "until"),
+                    });
+                }
+                Some(entity) => event.alarms.push(Alarm {
+                    id: Some(id),
+                    // Negative offsets are stored as the bits of a signed integer
+                    offset: record_uint(&record, "triggerduration").unwrap_or(0) as i64,
+                    audio: entity == AUDIO_ALARM_ENTITY,
+                }),
+                None => {}
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Splits the event into the records the device expects
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A dictionary of the records keyed by record ID, for `MobileSyncClient::push_records`.
+    /// Recurrences and alarms without an ID are given one based on the event's.
+    ///
+    /// ***Verified:*** False
+    pub fn to_records(&self) -> Result<Plist, MobileSyncError> {
+        let mut records = Plist::new_dict();
+        let mut event = new_record(EVENT_ENTITY)?;
+
+        if let Some(rule) = &self.recurrence {
+            let id = rule
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("{}/recurrence", self.id));
+            let mut record = self.child_record(RECURRENCE_ENTITY)?;
+            set_record_string(
+                &mut record,
+                "frequency",
+                &Some(rule.frequency.as_str().to_string()),
+            )?;
+            set_record_item(&mut record, "interval", Plist::new_uint(rule.interval))?;
+            if let Some(count) = rule.count {
+                set_record_item(&mut record, "count", Plist::new_uint(count))?;
+            }
+            if let Some(until) = rule.until {
+                set_record_item(&mut record, "until", plist_util::new_date(until))?;
+            }
+            set_record_item(&mut records, &id, record)?;
+            set_record_item(&mut event, "recurrences", id_array(&[id])?)?;
+        }
+
+        let mut alarm_ids = Vec::new();
+        for (i, alarm) in self.alarms.iter().enumerate() {
+            let id = alarm
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("{}/alarm/{}", self.id, i));
+            let entity = match alarm.audio {
+                true => AUDIO_ALARM_ENTITY,
+                false => DISPLAY_ALARM_ENTITY,
+            };
+            let mut record = self.child_record(entity)?;
+            set_record_item(
+                &mut record,
+                "triggerduration",
+                Plist::new_uint(alarm.offset as u64),
+            )?;
+            set_record_item(&mut records, &id, record)?;
+            alarm_ids.push(id);
+        }
+        if !alarm_ids.is_empty() {
+            set_record_item(&mut event, "alarms", id_array(&alarm_ids)?)?;
+        }
+
+        if let Some(calendar_id) = &self.calendar_id {
+            set_record_item(&mut event, "calendar", id_array(&[calendar_id.clone()])?)?;
+        }
+        set_record_string(&mut event, "summary", &self.summary)?;
+        set_record_string(&mut event, "location", &self.location)?;
+        set_record_string(&mut event, "description", &self.notes)?;
+        set_record_string(&mut event, "url", &self.url)?;
+        if let Some(start) = self.start {
+            set_record_item(&mut event, "start date", plist_util::new_date(start))?;
+        }
+        if let Some(end) = self.end {
+            set_record_item(&mut event, "end date", plist_util::new_date(end))?;
+        }
+        set_record_item(&mut event, "all day", Plist::new_bool(self.all_day))?;
+        set_record_item(&mut records, &self.id, event)?;
+
+        Ok(records)
+    }
+
+    /// Starts a recurrence or alarm record linked back to the event
+    fn child_record(&self, entity: &str) -> Result<Plist, MobileSyncError> {
+        let mut record = new_record(entity)?;
+        set_record_item(&mut record, "owner", id_array(&[self.id.clone()])?)?;
+        Ok(record)
+    }
+}
+
+impl SyncRecord for CalendarEvent {
+    const DATA_CLASS: &'static str = DATA_CLASS;
+
+    fn from_records(records: &Plist) -> Result<Vec<Self>, MobileSyncError> {
+        CalendarEvent::from_records(records)
+    }
+
+    fn to_records(&self) -> Result<Plist, MobileSyncError> {
+        CalendarEvent::to_records(self)
+    }
+
+    fn remap_ids(&mut self, remapped: &HashMap<String, String>) {
+        remap_id(&mut self.id, remapped);
+        let rule_id = self.recurrence.as_mut().map(|rule| &mut rule.id);
+        let alarm_ids = self.alarms.iter_mut().map(|alarm| &mut alarm.id);
+        for id in rule_id.into_iter().chain(alarm_ids).flatten() {
+            remap_id(id, remapped);
+        }
+    }
+}
+
+/// Writes events as an iCalendar file
+/// # Arguments
+/// * `events` - The events to write
+/// # Returns
+/// The contents of the `.ics` file. Times are written in UTC
+///
+/// ***Verified:*** False
+pub fn to_ics(events: &[CalendarEvent]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//rusty_libimobiledevice//mobilesync//EN".to_string(),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", escape_text(&event.id)));
+        // DTSTAMP is required, and the device doesn't keep when an event was last changed
+        lines.push(format!("DTSTAMP:{}", format_time(SystemTime::now(), false)));
+        for (name, time) in [("DTSTART", event.start), ("DTEND", event.end)] {
+            if let Some(time) = time {
+                match event.all_day {
+                    true => lines.push(format!("{};VALUE=DATE:{}", name, format_time(time, true))),
+                    false => lines.push(format!("{}:{}", name, format_time(time, false))),
+                }
+            }
+        }
+        for (name, value) in [
+            ("SUMMARY", &event.summary),
+            ("LOCATION", &event.location),
+            ("DESCRIPTION", &event.notes),
+            ("URL", &event.url),
+        ] {
+            if let Some(value) = value {
+                lines.push(format!("{}:{}", name, escape_text(value)));
+            }
+        }
+        if let Some(rule) = &event.recurrence {
+            let mut rrule = format!(
+                "RRULE:FREQ={};INTERVAL={}",
+                rule.frequency.as_str().to_ascii_uppercase(),
+                rule.interval
+            );
+            if let Some(count) = rule.count {
+                let _ = write!(rrule, ";COUNT={}", count);
+            }
+            if let Some(until) = rule.until {
+                let _ = write!(rrule, ";UNTIL={}", format_time(until, event.all_day));
+            }
+            lines.push(rrule);
+        }
+        for alarm in &event.alarms {
+            lines.push("BEGIN:VALARM".to_string());
+            lines.push(format!(
+                "ACTION:{}",
+                if alarm.audio { "AUDIO" } else { "DISPLAY" }
+            ));
+            // DISPLAY alarms need a description
+            if !alarm.audio {
+                lines.push(format!(
+                    "DESCRIPTION:{}",
+                    escape_text(event.summary.as_deref().unwrap_or("Reminder"))
+                ));
+            }
+            lines.push(format!("TRIGGER:{}", format_duration(alarm.offset)));
+            lines.push("END:VALARM".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines {
+        fold_line(&line, &mut ics);
+    }
+    ics
+}
+
+/// Reads the events of an iCalendar file
+/// # Arguments
+/// * `ics` - The contents of the `.ics` file
+/// # Returns
+/// The events, or `InvalidArg` if the components aren't properly nested.
+/// Times with a `TZID` are read as UTC, and properties this module doesn't model are ignored.
+/// Events without a `UID` are given one from their position in the file.
+///
+/// ***Verified:*** False
+pub fn from_ics(ics: &str) -> Result<Vec<CalendarEvent>, MobileSyncError> {
+    let mut events = Vec::new();
+    let mut event: Option<CalendarEvent> = None;
+    let mut alarm: Option<Alarm> = None;
+
+    for line in unfold_lines(ics) {
+        let (name, params, value) = match split_property(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+        let is_date = params
+            .iter()
+            .any(|param| param.eq_ignore_ascii_case("VALUE=DATE"));
+
+        match (name.as_str(), event.as_mut(), alarm.as_mut()) {
+            ("BEGIN", None, _) if value.eq_ignore_ascii_case("VEVENT") => {
+                event = Some(CalendarEvent::new(format!("ics-event-{}", events.len())));
+            }
+            ("BEGIN", Some(_), None) if value.eq_ignore_ascii_case("VALARM") => {
+                alarm = Some(Alarm {
+                    id: None,
+                    offset: 0,
+                    audio: false,
+                });
+            }
+            ("BEGIN", Some(_), _) => {
+                warn!("Unexpected {} inside an event", value);
+                return Err(MobileSyncError::InvalidArg);
+            }
+            ("END", Some(event), Some(_)) if value.eq_ignore_ascii_case("VALARM") => {
+                event.alarms.extend(alarm.take());
+            }
+            ("END", Some(_), None) if value.eq_ignore_ascii_case("VEVENT") => {
+                events.extend(event.take());
+            }
+            ("END", Some(_), _) => {
+                warn!("Unexpected END:{} inside an event", value);
+                return Err(MobileSyncError::InvalidArg);
+            }
+            ("ACTION", Some(_), Some(alarm)) => alarm.audio = value.eq_ignore_ascii_case("AUDIO"),
+            ("TRIGGER", Some(_), Some(alarm)) => match parse_duration(&value) {
+                Some(offset) => alarm.offset = offset,
+                None => warn!("Ignoring alarm trigger {}", value),
+            },
+            (_, Some(_), Some(_)) => {}
+            ("UID", Some(event), None) => event.id = unescape_text(&value),
+            ("SUMMARY", Some(event), None) => event.summary = Some(unescape_text(&value)),
+            ("LOCATION", Some(event), None) => event.location = Some(unescape_text(&value)),
+            ("DESCRIPTION", Some(event), None) => event.notes = Some(unescape_text(&value)),
+            ("URL", Some(event), None) => event.url = Some(value),
+            ("DTSTART", Some(event), None) => {
+                event.start = parse_time(&value);
+                event.all_day = is_date || value.len() == 8;
+            }
+            ("DTEND", Some(event), None) => event.end = parse_time(&value),
+            ("RRULE", Some(event), None) => event.recurrence = parse_rrule(&value),
+            _ => {}
+        }
+    }
+
+    if event.is_some() {
+        warn!("An event in the iCalendar file was never ended");
+        return Err(MobileSyncError::InvalidArg);
+    }
+    Ok(events)
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Writes a line, folded so no line is longer than the 75 bytes iCalendar allows
+fn fold_line(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Joins folded lines back together
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Splits a content line into its name in uppercase, its parameters and its value
+fn split_property(line: &str) -> Option<(String, Vec<String>, String)> {
+    let (head, value) = line.split_once(':')?;
+    let mut head = head.split(';');
+    let name = head.next()?.trim().to_ascii_uppercase();
+    Some((name, head.map(String::from).collect(), value.to_string()))
+}
+
+fn parse_rrule(value: &str) -> Option<RecurrenceRule> {
+    let mut rule = RecurrenceRule {
+        id: None,
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+    };
+    let mut has_frequency = false;
+    for part in value.split(';') {
+        let (key, value) = match part.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                rule.frequency = Frequency::parse(value)?;
+                has_frequency = true;
+            }
+            "INTERVAL" => rule.interval = value.parse().ok()?,
+            "COUNT" => rule.count = Some(value.parse().ok()?),
+            "UNTIL" => rule.until = parse_time(value),
+            _ => {}
+        }
+    }
+    match has_frequency {
+        true => Some(rule),
+        false => None,
+    }
+}
+
+/// Formats a time as an iCalendar UTC date-time, or a date for all day events
+fn format_time(time: SystemTime, date_only: bool) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    if date_only {
+        return format!("{:04}{:02}{:02}", year, month, day);
+    }
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an iCalendar date or date-time, reading local times as UTC
+fn parse_time(value: &str) -> Option<SystemTime> {
+    let value = value.trim_end_matches(|c| c == 'Z' || c == 'z');
+    let number = |range: std::ops::Range<usize>| value.get(range)?.parse::<u64>().ok();
+    let days = days_from_civil(
+        number(0..4)? as i64,
+        number(4..6)? as u32,
+        number(6..8)? as u32,
+    );
+    let secs_of_day = match value.len() {
+        8 => 0,
+        15 if value.as_bytes()[8] == b'T' => {
+            number(9..11)? * 3600 + number(11..13)? * 60 + number(13..15)?
+        }
+        _ => return None,
+    };
+    if days < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(days as u64 * 86_400 + secs_of_day))
+}
+
+/// Formats an alarm offset as an iCalendar duration
+fn format_duration(offset: i64) -> String {
+    let sign = if offset < 0 { "-" } else { "" };
+    format!("{}PT{}S", sign, offset.unsigned_abs())
+}
+
+/// Parses an iCalendar duration such as `-PT15M` or `-P1DT2H` into seconds
+fn parse_duration(value: &str) -> Option<i64> {
+    let (sign, value) = match value.as_bytes().first()? {
+        b'-' => (-1, &value[1..]),
+        b'+' => (1, &value[1..]),
+        _ => (1, value),
+    };
+    let value = value.strip_prefix(['P', 'p'])?;
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        let unit = match c.to_ascii_uppercase() {
+            'T' => continue,
+            'W' => 604_800,
+            'D' => 86_400,
+            'H' => 3600,
+            'M' => 60,
+            'S' => 1,
+            c if c.is_ascii_digit() => {
+                number.push(c);
+                continue;
+            }
+            _ => return None,
+        };
+        seconds += number.parse::<i64>().ok()? * unit;
+        number.clear();
+    }
+    Some(sign * seconds)
+}
+
+/// Converts days since the Unix epoch into a year, month and day
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a year, month and day into days since the Unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -0,0 +1,299 @@
+// jkcoxson
+// Typed records for the Contacts data class
+
+use std::collections::HashMap;
+
+use crate::error::MobileSyncError;
+
+use super::{
+    expect_type, id_array, record_ids, record_string, remap_id, set_record_string, SyncRecord,
+    RECORD_ENTITY_NAME,
+};
+
+use log::warn;
+use plist_plus::{Plist, PlistType};
+
+/// The data class to pass to `MobileSyncClient::start` for contacts
+pub const DATA_CLASS: &str = "com.apple.Contacts";
+
+const CONTACT_ENTITY: &str = "com.apple.contacts.Contact";
+const PHONE_ENTITY: &str = "com.apple.contacts.Phone Number";
+const EMAIL_ENTITY: &str = "com.apple.contacts.Email Address";
+const ADDRESS_ENTITY: &str = "com.apple.contacts.Street Address";
+
+/// A contact, put together from the records the device sends for it.
+///
+/// The device sends a contact as one record for the person and one record for each phone number,
+/// email address and street address, linked by record ID. `from_records` joins them back up,
+/// and `to_records` splits a contact into the records to pass to `MobileSyncClient::push_records`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContactRecord {
+    /// The record ID of the contact
+    pub id: String,
+    pub first_name: Option<String>,
+    pub middle_name: Option<String>,
+    pub last_name: Option<String>,
+    pub nickname: Option<String>,
+    pub company: Option<String>,
+    pub job_title: Option<String>,
+    pub notes: Option<String>,
+    pub phones: Vec<ContactField>,
+    pub emails: Vec<ContactField>,
+    pub addresses: Vec<PostalAddress>,
+}
+
+/// A phone number or email address of a contact
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContactField {
+    /// The record ID, or `None` for one the device hasn't seen yet
+    pub id: Option<String>,
+    /// The kind of number or address, such as `mobile`, `home` or `work`
+    pub label: Option<String>,
+    pub value: String,
+}
+
+/// A street address of a contact
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostalAddress {
+    /// The record ID, or `None` for one the device hasn't seen yet
+    pub id: Option<String>,
+    /// The kind of address, such as `home` or `work`
+    pub label: Option<String>,
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    pub country_code: Option<String>,
+}
+
+impl ContactRecord {
+    /// Creates an empty contact
+    /// # Arguments
+    /// * `id` - The record ID to give the contact. New contacts can use any unique ID,
+    ///   the device sends the one it picked back in `PushResult::remapped`
+    /// # Returns
+    /// The contact
+    ///
+    /// ***Verified:*** False
+    pub fn new(id: impl Into<String>) -> Self {
+        ContactRecord {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Joins the records of a batch into contacts
+    /// # Arguments
+    /// * `records` - The records of a batch, such as `RecordBatch::records`
+    /// # Returns
+    /// The contacts in the order they were sent.
+    /// Deleted records are skipped, as are phone numbers and addresses of contacts that aren't in the batch.
+    ///
+    /// ***Verified:*** False
+    pub fn from_records(records: &Plist) -> Result<Vec<ContactRecord>, MobileSyncError> {
+        expect_type(records, PlistType::Dictionary)?;
+
+        let mut contacts = Vec::new();
+        let mut children = Vec::new();
+        for record in records.clone().into_iter() {
+            let id = match record.key {
+                Some(id) => id,
+                None => continue,
+            };
+            // Deleted records are sent as a string instead of a dictionary
+            if record.plist.plist_type != PlistType::Dictionary {
+                continue;
+            }
+            match record_string(&record.plist, RECORD_ENTITY_NAME).as_deref() {
+                Some(CONTACT_ENTITY) => contacts.push(ContactRecord {
+                    id,
+                    first_name: record_string(&record.plist, "first name"),
+                    middle_name: record_string(&record.plist, "middle name"),
+                    last_name: record_string(&record.plist, "last name"),
+                    nickname: record_string(&record.plist, "nickname"),
+                    company: record_string(&record.plist, "company name"),
+                    job_title: record_string(&record.plist, "job title"),
+                    notes: record_string(&record.plist, "notes"),
+                    ..Default::default()
+                }),
+                Some(PHONE_ENTITY) | Some(EMAIL_ENTITY) | Some(ADDRESS_ENTITY) => {
+                    children.push((id, record.plist.clone()))
+                }
+                _ => {}
+            }
+        }
+
+        let index: HashMap<String, usize> = contacts
+            .iter()
+            .enumerate()
+            .map(|(i, contact)| (contact.id.clone(), i))
+            .collect();
+        for (id, record) in children {
+            let contact = match record_ids(&record, "contact")
+                .first()
+                .and_then(|parent| index.get(parent))
+            {
+                Some(i) => &mut contacts[*i],
+                None => {
+                    warn!("Skipping contact record {} without its contact", id);
+                    continue;
+                }
+            };
+            let label = record_string(&record, "type");
+            match record_string(&record, RECORD_ENTITY_NAME).as_deref() {
+                Some(ADDRESS_ENTITY) => contact.addresses.push(PostalAddress {
+                    id: Some(id),
+                    label,
+                    street: record_string(&record, "street"),
+                    city: record_string(&record, "city"),
+                    state: record_string(&record, "state"),
+                    postal_code: record_string(&record, "postal code"),
+                    country: record_string(&record, "country"),
+                    country_code: record_string(&record, "country code"),
+                }),
+                Some(entity) => {
+                    let field = ContactField {
+                        id: Some(id),
+                        label,
+                        value: record_string(&record, "value").unwrap_or_default(),
+                    };
+                    if entity == PHONE_ENTITY {
+                        contact.phones.push(field);
+                    } else {
+                        contact.emails.push(field);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        Ok(contacts)
+    }
+
+    /// Splits the contact into the records the device expects
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A dictionary of the records keyed by record ID, for `MobileSyncClient::push_records`.
+    /// Phone numbers and addresses without an ID are given one based on the contact's.
+    ///
+    /// ***Verified:*** False
+    pub fn to_records(&self) -> Result<Plist, MobileSyncError> {
+        let mut records = Plist::new_dict();
+        let mut phone_ids = Vec::new();
+        let mut email_ids = Vec::new();
+        let mut address_ids = Vec::new();
+
+        for (i, phone) in self.phones.iter().enumerate() {
+            let id = self.child_id(&phone.id, "phone", i);
+            records
+                .dict_set_item(&id, self.field_record(PHONE_ENTITY, phone)?)
+                .map_err(|_| MobileSyncError::PlistError)?;
+            phone_ids.push(id);
+        }
+        for (i, email) in self.emails.iter().enumerate() {
+            let id = self.child_id(&email.id, "email", i);
+            records
+                .dict_set_item(&id, self.field_record(EMAIL_ENTITY, email)?)
+                .map_err(|_| MobileSyncError::PlistError)?;
+            email_ids.push(id);
+        }
+        for (i, address) in self.addresses.iter().enumerate() {
+            let id = self.child_id(&address.id, "address", i);
+            let mut record = self.child_record(ADDRESS_ENTITY)?;
+            set_record_string(&mut record, "type", &address.label)?;
+            set_record_string(&mut record, "street", &address.street)?;
+            set_record_string(&mut record, "city", &address.city)?;
+            set_record_string(&mut record, "state", &address.state)?;
+            set_record_string(&mut record, "postal code", &address.postal_code)?;
+            set_record_string(&mut record, "country", &address.country)?;
+            set_record_string(&mut record, "country code", &address.country_code)?;
+            records
+                .dict_set_item(&id, record)
+                .map_err(|_| MobileSyncError::PlistError)?;
+            address_ids.push(id);
+        }
+
+        let mut contact = Plist::new_dict();
+        contact
+            .dict_set_item(RECORD_ENTITY_NAME, Plist::new_string(CONTACT_ENTITY))
+            .map_err(|_| MobileSyncError::PlistError)?;
+        set_record_string(&mut contact, "first name", &self.first_name)?;
+        set_record_string(&mut contact, "middle name", &self.middle_name)?;
+        set_record_string(&mut contact, "last name", &self.last_name)?;
+        set_record_string(&mut contact, "nickname", &self.nickname)?;
+        set_record_string(&mut contact, "company name", &self.company)?;
+        set_record_string(&mut contact, "job title", &self.job_title)?;
+        set_record_string(&mut contact, "notes", &self.notes)?;
+        for (key, ids) in [
+            ("phone numbers", &phone_ids),
+            ("email addresses", &email_ids),
+            ("street addresses", &address_ids),
+        ] {
+            if !ids.is_empty() {
+                contact
+                    .dict_set_item(key, id_array(ids)?)
+                    .map_err(|_| MobileSyncError::PlistError)?;
+            }
+        }
+        records
+            .dict_set_item(&self.id, contact)
+            .map_err(|_| MobileSyncError::PlistError)?;
+
+        Ok(records)
+    }
+
+    fn child_id(&self, id: &Option<String>, kind: &str, index: usize) -> String {
+        match id {
+            Some(id) => id.clone(),
+            None => format!("{}/{}/{}", self.id, kind, index),
+        }
+    }
+
+    /// Starts a phone number, email or address record linked back to the contact
+    fn child_record(&self, entity: &str) -> Result<Plist, MobileSyncError> {
+        let mut record = Plist::new_dict();
+        record
+            .dict_set_item(RECORD_ENTITY_NAME, Plist::new_string(entity))
+            .map_err(|_| MobileSyncError::PlistError)?;
+        record
+            .dict_set_item("contact", id_array(&[self.id.clone()])?)
+            .map_err(|_| MobileSyncError::PlistError)?;
+        Ok(record)
+    }
+
+    fn field_record(&self, entity: &str, field: &ContactField) -> Result<Plist, MobileSyncError> {
+        let mut record = self.child_record(entity)?;
+        set_record_string(&mut record, "type", &field.label)?;
+        record
+            .dict_set_item("value", Plist::new_string(&field.value))
+            .map_err(|_| MobileSyncError::PlistError)?;
+        Ok(record)
+    }
+}
+
+impl SyncRecord for ContactRecord {
+    const DATA_CLASS: &'static str = DATA_CLASS;
+
+    fn from_records(records: &Plist) -> Result<Vec<Self>, MobileSyncError> {
+        ContactRecord::from_records(records)
+    }
+
+    fn to_records(&self) -> Result<Plist, MobileSyncError> {
+        ContactRecord::to_records(self)
+    }
+
+    fn remap_ids(&mut self, remapped: &HashMap<String, String>) {
+        remap_id(&mut self.id, remapped);
+        let field_ids = self.phones.iter_mut().chain(self.emails.iter_mut());
+        let address_ids = self.addresses.iter_mut().map(|address| &mut address.id);
+        for id in field_ids
+            .map(|field| &mut field.id)
+            .chain(address_ids)
+            .flatten()
+        {
+            remap_id(id, remapped);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// jkcoxson
+// Typed records for the Notes data class
+
+use std::{collections::HashMap, time::SystemTime};
+
+use crate::{error::MobileSyncError, plist_util};
+
+use super::{
+    expect_type, new_record, record_date, record_string, remap_id, set_record_item,
+    set_record_string, SyncRecord, RECORD_ENTITY_NAME,
+};
+
+use plist_plus::{Plist, PlistType};
+
+/// The data class to pass to `MobileSyncClient::start` for notes
+pub const DATA_CLASS: &str = "com.apple.Notes";
+
+const NOTE_ENTITY: &str = "com.apple.notes.Note";
+
+/// A note. Read every note with `MobileSyncClient::export::<Note>`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Note {
+    /// The record ID of the note
+    pub id: String,
+    pub subject: Option<String>,
+    /// The body of the note, which is HTML on most versions of iOS
+    pub contents: Option<String>,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+impl Note {
+    /// Creates a note
+    /// # Arguments
+    /// * `id` - The record ID to give the note. New notes can use any unique ID,
+    ///   the device sends the one it picked back in `PushResult::remapped`
+    /// * `subject` - The subject of the note
+    /// * `contents` - The body of the note
+    /// # Returns
+    /// The note, created and modified now
+    ///
+    /// ***Verified:*** False
+    pub fn new(
+        id: impl Into<String>,
+        subject: impl Into<String>,
+        contents: impl Into<String>,
+    ) -> Self {
+        let now = SystemTime::now();
+        Note {
+            id: id.into(),
+            subject: Some(subject.into()),
+            contents: Some(contents.into()),
+            created: Some(now),
+            modified: Some(now),
+        }
+    }
+}
+
+impl SyncRecord for Note {
+    const DATA_CLASS: &'static str = DATA_CLASS;
+
+    fn from_records(records: &Plist) -> Result<Vec<Self>, MobileSyncError> {
+        expect_type(records, PlistType::Dictionary)?;
+
+        let mut notes = Vec::new();
+        for record in records.clone().into_iter() {
+            let id = match record.key {
+                Some(id) => id,
+                None => continue,
+            };
+            // Deleted records are sent as a string instead of a dictionary
+            if record.plist.plist_type != PlistType::Dictionary
+                || record_string(&record.plist, RECORD_ENTITY_NAME).as_deref() != Some(NOTE_ENTITY)
+            {
+                continue;
+            }
+            notes.push(Note {
+                id,
+                subject: record_string(&record.plist, "subject"),
+                contents: record_string(&record.plist, "contents"),
+                created: record_date(&record.plist, "date created"),
+                modified: record_date(&record.plist, "date modified"),
+            });
+        }
+
+        Ok(notes)
+    }
+
+    fn to_records(&self) -> Result<Plist, MobileSyncError> {
+        let mut record = new_record(NOTE_ENTITY)?;
+        set_record_string(&mut record, "subject", &self.subject)?;
+        set_record_string(&mut record, "contents", &self.contents)?;
+        if let Some(created) = self.created {
+            set_record_item(&mut record, "date created", plist_util::new_date(created))?;
+        }
+        if let Some(modified) = self.modified {
+            set_record_item(&mut record, "date modified", plist_util::new_date(modified))?;
+        }
+
+        let mut records = Plist::new_dict();
+        set_record_item(&mut records, &self.id, record)?;
+        Ok(records)
+    }
+
+    fn remap_ids(&mut self, remapped: &HashMap<String, String>) {
+        remap_id(&mut self.id, remapped);
+    }
+}
@@ -0,0 +1,305 @@
+// jkcoxson
+// A sync session that only allows the calls that are valid in its current phase
+
+use crate::error::MobileSyncError;
+
+use super::{
+    BatchLimit, MobileSyncAnchor, MobileSyncClient, MobileSyncType, PushResult, RecordBatch,
+    SyncStartError,
+};
+
+use log::warn;
+use plist_plus::Plist;
+
+/// A started sync session. What can be called depends on its phase:
+/// * `Started` - Pull records from the device, clear them, or move on to sending
+/// * `ReceivingFromDevice` - Read the batches the device sends, then finish or move on to sending
+/// * `SendingToDevice` - Push the host's records, which finishes the session
+///
+/// Moving to another phase consumes the session, so steps can't be called out of order.
+///
+/// A session that's dropped finishes itself if it's in a state the device accepts finishing from,
+/// and cancels itself otherwise, such as when batches are left unread. Errors from either are only logged,
+/// call `finish` or `cancel` to get them. After a step fails, the session is left as the client's
+/// `with_cancel_on_error` setting leaves it, and isn't finished or cancelled again.
+pub struct SyncSession<'c, 'a, P: SyncPhase> {
+    client: &'c MobileSyncClient<'a>,
+    phase: P,
+    closed: bool,
+}
+
+/// The phase a `SyncSession` is in
+pub trait SyncPhase: sealed::Phase {}
+
+mod sealed {
+    use super::MobileSyncClient;
+    use crate::error::MobileSyncError;
+
+    pub trait Phase {
+        /// Gets the session ready to finish, returning whether the device will accept it
+        fn settle(&mut self, client: &MobileSyncClient) -> Result<bool, MobileSyncError>;
+    }
+}
+
+/// The session has started and nothing has been exchanged yet
+pub struct Started;
+
+/// The device is sending its records
+pub struct ReceivingFromDevice {
+    /// The first batch, which is received as the records are requested
+    first: Option<RecordBatch>,
+    /// Whether the last batch handed out still needs acknowledging
+    unacknowledged: bool,
+    has_more: bool,
+}
+
+/// The host is about to send its records
+pub struct SendingToDevice;
+
+impl SyncPhase for Started {}
+impl SyncPhase for ReceivingFromDevice {}
+impl SyncPhase for SendingToDevice {}
+
+impl sealed::Phase for Started {
+    fn settle(&mut self, _: &MobileSyncClient) -> Result<bool, MobileSyncError> {
+        Ok(true)
+    }
+}
+
+impl sealed::Phase for ReceivingFromDevice {
+    fn settle(&mut self, client: &MobileSyncClient) -> Result<bool, MobileSyncError> {
+        if self.unacknowledged {
+            client.acknowledge_changes_from_device()?;
+            self.unacknowledged = false;
+        }
+        Ok(self.first.is_none() && !self.has_more)
+    }
+}
+
+impl sealed::Phase for SendingToDevice {
+    fn settle(&mut self, _: &MobileSyncClient) -> Result<bool, MobileSyncError> {
+        Ok(true)
+    }
+}
+
+impl<'a> MobileSyncClient<'a> {
+    /// Starts a sync session that checks the order of the calls made in it, see `SyncSession`
+    /// # Arguments
+    /// * `data_class` - The identifiers to sync
+    /// * `anchors` - The sync anchors to base off of
+    /// * `computer_data_class_version` - The class version on the host
+    /// * `sync_type` - The type of sync to perform
+    /// # Returns
+    /// The session, or why the device refused to start it as `start` returns
+    ///
+    /// ***Verified:*** False
+    pub fn session(
+        &self,
+        data_class: impl Into<String>,
+        anchors: Vec<MobileSyncAnchor>,
+        computer_data_class_version: u64,
+        sync_type: MobileSyncType,
+    ) -> Result<SyncSession<'_, 'a, Started>, (SyncStartError, MobileSyncError)> {
+        self.start(data_class, anchors, computer_data_class_version, sync_type)?;
+        Ok(SyncSession {
+            client: self,
+            phase: Started,
+            closed: false,
+        })
+    }
+}
+
+impl<'c, 'a, P: SyncPhase> SyncSession<'c, 'a, P> {
+    /// Finishes the session
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*, or `InvalidArg` if the device still has batches to send, in which case the session is cancelled
+    ///
+    /// ***Verified:*** False
+    pub fn finish(mut self) -> Result<(), MobileSyncError> {
+        self.closed = true;
+        if !self.phase.settle(self.client)? {
+            warn!("Cancelling a sync session finished before every batch was read");
+            self.client
+                .cancel("Host finished before receiving every batch")?;
+            return Err(MobileSyncError::InvalidArg);
+        }
+        self.client.finish()
+    }
+
+    /// Cancels the session
+    /// # Arguments
+    /// * `reason` - The reason for cancelling the sync
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn cancel(mut self, reason: impl Into<String>) -> Result<(), MobileSyncError> {
+        self.closed = true;
+        self.client.cancel(reason)
+    }
+
+    /// Moves the session to the next phase without finishing or cancelling it
+    fn into_phase<Q: SyncPhase>(mut self, phase: Q) -> SyncSession<'c, 'a, Q> {
+        self.closed = true;
+        SyncSession {
+            client: self.client,
+            phase,
+            closed: false,
+        }
+    }
+
+    /// Marks the session as closed if a step failed, since the client has dealt with it
+    fn check<T>(&mut self, result: Result<T, MobileSyncError>) -> Result<T, MobileSyncError> {
+        if result.is_err() {
+            self.closed = true;
+        }
+        result
+    }
+}
+
+impl<'c, 'a> SyncSession<'c, 'a, Started> {
+    /// Asks the device for all of its records
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The session, ready to read the batches
+    ///
+    /// ***Verified:*** False
+    pub fn get_all_records(
+        mut self,
+    ) -> Result<SyncSession<'c, 'a, ReceivingFromDevice>, MobileSyncError> {
+        let result = self.client.get_all_records_from_device();
+        let batch = self.check(result)?;
+        Ok(self.into_phase(ReceivingFromDevice {
+            first: Some(batch),
+            unacknowledged: false,
+            has_more: false,
+        }))
+    }
+
+    /// Asks the device for the records that changed since the anchors the session was started with
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The session, ready to read the batches
+    ///
+    /// ***Verified:*** False
+    pub fn get_changes(
+        mut self,
+    ) -> Result<SyncSession<'c, 'a, ReceivingFromDevice>, MobileSyncError> {
+        let result = self.client.get_changes_from_device();
+        let batch = self.check(result)?;
+        Ok(self.into_phase(ReceivingFromDevice {
+            first: Some(batch),
+            unacknowledged: false,
+            has_more: false,
+        }))
+    }
+
+    /// Clears the records on the device, before sending the host's with `send_to_device`
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn clear_all_records(&mut self) -> Result<(), MobileSyncError> {
+        let result = self.client.clear_all_records_on_device();
+        self.check(result)
+    }
+
+    /// Moves on to sending the host's records without reading the device's
+    pub fn send_to_device(self) -> SyncSession<'c, 'a, SendingToDevice> {
+        self.into_phase(SendingToDevice)
+    }
+}
+
+impl<'c, 'a> SyncSession<'c, 'a, ReceivingFromDevice> {
+    /// Reads the next batch of records, acknowledging the one before it
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The batch, or `None` once the device has sent all of them
+    ///
+    /// ***Verified:*** False
+    pub fn next_batch(&mut self) -> Result<Option<RecordBatch>, MobileSyncError> {
+        if self.phase.unacknowledged {
+            let result = self.client.acknowledge_changes_from_device();
+            self.check(result)?;
+            self.phase.unacknowledged = false;
+        }
+        let batch = match self.phase.first.take() {
+            Some(batch) => batch,
+            None if self.phase.has_more => {
+                let result = self.client.receive_changes();
+                self.check(result)?
+            }
+            None => return Ok(None),
+        };
+        self.phase.unacknowledged = true;
+        self.phase.has_more = batch.has_more;
+        Ok(Some(batch))
+    }
+
+    /// Moves on to sending the host's records, once every batch has been read
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The session, or `InvalidArg` if the device still has batches to send, in which case the session is cancelled
+    ///
+    /// ***Verified:*** False
+    pub fn send_to_device(
+        mut self,
+    ) -> Result<SyncSession<'c, 'a, SendingToDevice>, MobileSyncError> {
+        let result = self.phase.settle(self.client);
+        if !self.check(result)? {
+            warn!("Cancelling a sync session that started sending before every batch was read");
+            return Err(MobileSyncError::InvalidArg);
+        }
+        Ok(self.into_phase(SendingToDevice))
+    }
+}
+
+impl<'c, 'a> SyncSession<'c, 'a, SendingToDevice> {
+    /// Sends the host's records, waits for the device to accept them and finishes the session.
+    /// See `MobileSyncClient::push_records` for how the records are batched.
+    /// # Arguments
+    /// * `entities` - The changes, as dictionaries of records keyed by record ID
+    /// * `actions` - Additional actions the device should perform, sent with every message
+    /// * `limit` - How many records and bytes to put in each message
+    /// # Returns
+    /// The record IDs the device changed
+    ///
+    /// ***Verified:*** False
+    pub fn push_records(
+        mut self,
+        entities: impl IntoIterator<Item = Plist>,
+        actions: Option<Plist>,
+        limit: BatchLimit,
+    ) -> Result<PushResult, MobileSyncError> {
+        let result = self.client.push_records(entities, actions, limit);
+        let pushed = self.check(result)?;
+        self.finish()?;
+        Ok(pushed)
+    }
+}
+
+impl<P: SyncPhase> Drop for SyncSession<'_, '_, P> {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let result = match self.phase.settle(self.client) {
+            Ok(true) => self.client.finish(),
+            Ok(false) => self
+                .client
+                .cancel("Sync session dropped before it was finished"),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Unable to close the dropped sync session: {}", e);
+        }
+    }
+}
@@ -1,20 +1,32 @@
 // jkcoxson
 
-use std::ffi::CString;
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_void},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
 
 use crate::bindings as unsafe_bindings;
 use crate::error::NpError;
 use crate::idevice::Device;
+use crate::service::{ClientHandle, ClientPointer, Service};
 use crate::services::lockdownd::LockdowndService;
 
 /// A service to proxy notifications to the device
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct NotificationProxyClient<'a> {
-    pub(crate) pointer: unsafe_bindings::np_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::np_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl NotificationProxyClient<'_> {
+impl ClientPointer for unsafe_bindings::np_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::np_client_free(self)
+    }
+}
+
+impl<'a> NotificationProxyClient<'a> {
     /// Creates a new notification proxy from a lockdown service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -23,7 +35,7 @@ impl NotificationProxyClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, NpError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, NpError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::np_client_new(device.pointer, descriptor.pointer, &mut pointer)
@@ -35,7 +47,7 @@ impl NotificationProxyClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -48,7 +60,7 @@ impl NotificationProxyClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn start_service(device: &Device, label: impl Into<String>) -> Result<Self, NpError> {
+    pub fn start_service(device: &'a Device, label: impl Into<String>) -> Result<Self, NpError> {
         let label_c_string = CString::new(label.into()).unwrap();
 
         let mut pointer = std::ptr::null_mut();
@@ -66,7 +78,7 @@ impl NotificationProxyClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -81,7 +93,10 @@ impl NotificationProxyClient<'_> {
     pub fn post_notification(&self, notification: &str) -> Result<(), NpError> {
         let notification_c_string = CString::new(notification).unwrap();
         let result = unsafe {
-            unsafe_bindings::np_post_notification(self.pointer, notification_c_string.as_ptr())
+            unsafe_bindings::np_post_notification(
+                *self.pointer.lock(),
+                notification_c_string.as_ptr(),
+            )
         }
         .into();
 
@@ -102,7 +117,10 @@ impl NotificationProxyClient<'_> {
     pub fn observe_notification(&self, notification: &str) -> Result<(), NpError> {
         let notification_c_string = CString::new(notification).unwrap();
         let result = unsafe {
-            unsafe_bindings::np_observe_notification(self.pointer, notification_c_string.as_ptr())
+            unsafe_bindings::np_observe_notification(
+                *self.pointer.lock(),
+                notification_c_string.as_ptr(),
+            )
         }
         .into();
 
@@ -131,10 +149,107 @@ impl NotificationProxyClient<'_> {
         not_ptrs.push(std::ptr::null());
 
         let result = unsafe {
-            unsafe_bindings::np_observe_notifications(self.pointer, not_ptrs.as_mut_ptr())
+            unsafe_bindings::np_observe_notifications(*self.pointer.lock(), not_ptrs.as_mut_ptr())
+        }
+        .into();
+
+        if result != NpError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+
+    /// Posts a notification and waits for the device to answer with another one.
+    /// The proxy has no acknowledgement of its own, so this observes `ack_name` before posting
+    /// and waits for the device to post it back.
+    /// This replaces any callback the client was notifying, and removes it before returning.
+    /// # Arguments
+    /// * `name` - The notification to post
+    /// * `ack_name` - The notification the device posts once it has handled `name`
+    /// * `timeout` - How long to wait for `ack_name`
+    /// # Returns
+    /// *none*, or `Timeout` if the device didn't answer in time
+    ///
+    /// ***Verified:*** False
+    pub fn post_and_wait(
+        &self,
+        name: &str,
+        ack_name: &str,
+        timeout: Duration,
+    ) -> Result<(), NpError> {
+        let listener = self.listen(&[ack_name])?;
+        self.post_notification(name)?;
+        let deadline = Instant::now() + timeout;
+        // Notifications observed earlier on this client arrive too
+        while listener.wait(deadline)? != ack_name {}
+        Ok(())
+    }
+
+    /// Blocks until the device posts a notification, such as `com.apple.mobile.application_installed`
+    /// to wait for an install to finish.
+    /// This replaces any callback the client was notifying, and removes it before returning.
+    /// The proxy can't stop observing a name, so later notifications of it are ignored rather than unobserved.
+    /// # Arguments
+    /// * `name` - The notification to wait for
+    /// * `timeout` - How long to wait, or `None` to wait forever
+    /// # Returns
+    /// *none*, or `Timeout` if the notification didn't arrive in time
+    ///
+    /// ***Verified:*** False
+    pub fn wait_for(&self, name: &str, timeout: Option<Duration>) -> Result<(), NpError> {
+        let listener = self.listen(&[name])?;
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            // Notifications observed earlier on this client arrive too
+            let received = match deadline {
+                Some(deadline) => listener.wait(deadline)?,
+                None => listener.next()?,
+            };
+            if received == name {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Starts collecting the given notifications, replacing any callback the client was notifying
+    pub(crate) fn listen(&self, names: &[&str]) -> Result<NotificationListener<'_>, NpError> {
+        let (sender, receiver) = mpsc::channel();
+        let listener = NotificationListener {
+            pointer: *self.pointer.lock(),
+            receiver,
+            // Boxed so the callback's pointer stays put until the listener removes the callback
+            sender: Box::new(sender),
+            phantom: std::marker::PhantomData,
+        };
+        let result = unsafe {
+            unsafe_bindings::np_set_notify_callback(
+                *self.pointer.lock(),
+                Some(forward_notification),
+                &*listener.sender as *const Sender<String> as *mut c_void,
+            )
         }
         .into();
+        if result != NpError::Success {
+            return Err(result);
+        }
+
+        for name in names {
+            self.observe_notification(name)?;
+        }
+        Ok(listener)
+    }
 
+    /// Closes the notification proxy connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), NpError> {
+        let result = self.pointer.close().into();
         if result != NpError::Success {
             return Err(result);
         }
@@ -143,10 +258,56 @@ impl NotificationProxyClient<'_> {
     }
 }
 
-impl Drop for NotificationProxyClient<'_> {
+impl<'a> Service<'a> for NotificationProxyClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobile.notification_proxy";
+    type Error = NpError;
+
+    fn from_descriptor(device: &'a Device, descriptor: LockdowndService) -> Result<Self, NpError> {
+        Self::new(device, descriptor)
+    }
+}
+
+/// Notifications collected from the proxy's callback, see `NotificationProxyClient::listen`.
+/// The callback is removed when this is dropped.
+pub(crate) struct NotificationListener<'a> {
+    pointer: unsafe_bindings::np_client_t,
+    receiver: Receiver<String>,
+    sender: Box<Sender<String>>,
+    phantom: std::marker::PhantomData<&'a NotificationProxyClient<'a>>,
+}
+
+impl NotificationListener<'_> {
+    /// Waits for the next observed notification, or returns `Timeout` once the deadline passes
+    pub(crate) fn wait(&self, deadline: Instant) -> Result<String, NpError> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match self.receiver.recv_timeout(remaining) {
+            Ok(received) => Ok(received),
+            Err(RecvTimeoutError::Timeout) => Err(NpError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(NpError::UnknownError),
+        }
+    }
+
+    /// Waits for the next observed notification with no deadline
+    pub(crate) fn next(&self) -> Result<String, NpError> {
+        self.receiver.recv().map_err(|_| NpError::UnknownError)
+    }
+}
+
+impl Drop for NotificationListener<'_> {
     fn drop(&mut self) {
+        // Stops the notification thread, so nothing uses `sender` after it's dropped
         unsafe {
-            unsafe_bindings::np_client_free(self.pointer);
-        }
+            unsafe_bindings::np_set_notify_callback(self.pointer, None, std::ptr::null_mut())
+        };
+    }
+}
+
+/// Passes notifications from the proxy's thread to the `Sender` given as `user_data`
+unsafe extern "C" fn forward_notification(notification: *const c_char, user_data: *mut c_void) {
+    if notification.is_null() || user_data.is_null() {
+        return;
     }
+    let sender = &*(user_data as *const Sender<String>);
+    let notification = CStr::from_ptr(notification).to_string_lossy().into_owned();
+    let _ = sender.send(notification);
 }
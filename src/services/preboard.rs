@@ -3,7 +3,10 @@
 use std::ffi::CString;
 
 use crate::{
-    bindings as unsafe_bindings, error::PreboardError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::PreboardError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 
@@ -11,13 +14,19 @@ use plist_plus::Plist;
 
 /// A service that manages data at the first unlock screen after boot.
 /// Prepare to be boarded!
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct PreboardClient<'a> {
-    pub(crate) pointer: unsafe_bindings::preboard_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::preboard_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl PreboardClient<'_> {
+impl ClientPointer for unsafe_bindings::preboard_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::preboard_client_free(self)
+    }
+}
+
+impl<'a> PreboardClient<'a> {
     /// Creates a preboard client from a lockdown service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -26,7 +35,7 @@ impl PreboardClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, PreboardError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, PreboardError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::preboard_client_new(device.pointer, descriptor.pointer, &mut pointer)
@@ -38,7 +47,7 @@ impl PreboardClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -51,7 +60,10 @@ impl PreboardClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn start_service(device: &Device, label: impl Into<String>) -> Result<Self, PreboardError> {
+    pub fn start_service(
+        device: &'a Device,
+        label: impl Into<String>,
+    ) -> Result<Self, PreboardError> {
         let mut pointer = std::ptr::null_mut();
         let label_c_string = CString::new(label.into()).unwrap();
         let result = unsafe {
@@ -68,7 +80,7 @@ impl PreboardClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -82,7 +94,8 @@ impl PreboardClient<'_> {
     /// ***Verified:*** False
     pub fn send(&self, data: Plist) -> Result<(), PreboardError> {
         let result =
-            unsafe { unsafe_bindings::preboard_send(self.pointer, data.get_pointer()) }.into();
+            unsafe { unsafe_bindings::preboard_send(*self.pointer.lock(), data.get_pointer()) }
+                .into();
 
         if result != PreboardError::Success {
             return Err(result);
@@ -103,9 +116,13 @@ impl PreboardClient<'_> {
         let mut plist = std::ptr::null_mut();
         let result = unsafe {
             if timeout == 0 {
-                unsafe_bindings::preboard_receive(self.pointer, &mut plist)
+                unsafe_bindings::preboard_receive(*self.pointer.lock(), &mut plist)
             } else {
-                unsafe_bindings::preboard_receive_with_timeout(self.pointer, &mut plist, timeout)
+                unsafe_bindings::preboard_receive_with_timeout(
+                    *self.pointer.lock(),
+                    &mut plist,
+                    timeout,
+                )
             }
         }
         .into();
@@ -125,7 +142,7 @@ impl PreboardClient<'_> {
     pub fn create_stashbag(&self, manifest: Option<Plist>) -> Result<(), PreboardError> {
         let result = unsafe {
             unsafe_bindings::preboard_create_stashbag(
-                self.pointer,
+                *self.pointer.lock(),
                 manifest
                     .as_ref()
                     .map_or(std::ptr::null_mut(), |p| p.get_pointer()),
@@ -152,7 +169,7 @@ impl PreboardClient<'_> {
     pub fn commit_stashbag(&self, manifest: Option<Plist>) -> Result<(), PreboardError> {
         let result = unsafe {
             unsafe_bindings::preboard_commit_stashbag(
-                self.pointer,
+                *self.pointer.lock(),
                 manifest
                     .as_ref()
                     .map_or(std::ptr::null_mut(), |p| p.get_pointer()),
@@ -168,12 +185,33 @@ impl PreboardClient<'_> {
 
         Ok(())
     }
-}
 
-impl Drop for PreboardClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::preboard_client_free(self.pointer);
+    /// Closes the preboard connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), PreboardError> {
+        let result = self.pointer.close().into();
+        if result != PreboardError::Success {
+            return Err(result);
         }
+
+        Ok(())
+    }
+}
+
+impl<'a> Service<'a> for PreboardClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.preboardservice_v2";
+    type Error = PreboardError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, PreboardError> {
+        Self::new(device, descriptor)
     }
 }
@@ -1,19 +1,28 @@
 // jkcoxson
 
 use crate::{
-    bindings as unsafe_bindings, error::PropertyListServiceError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::PropertyListServiceError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
     services::lockdownd::LockdowndService,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct PropertyListServiceClient<'a> {
-    pub(crate) pointer: unsafe_bindings::property_list_service_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::property_list_service_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
+impl ClientPointer for unsafe_bindings::property_list_service_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::property_list_service_client_free(self)
+    }
+}
+
 use plist_plus::Plist;
 
-impl PropertyListServiceClient<'_> {
+impl<'a> PropertyListServiceClient<'a> {
     /// Creates a preboard client from a property list service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -23,7 +32,7 @@ impl PropertyListServiceClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn new(
-        device: &Device,
+        device: &'a Device,
         descriptor: LockdowndService,
     ) -> Result<Self, PropertyListServiceError> {
         let mut pointer = std::ptr::null_mut();
@@ -41,7 +50,7 @@ impl PropertyListServiceClient<'_> {
         }
 
         Ok(PropertyListServiceClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -55,7 +64,10 @@ impl PropertyListServiceClient<'_> {
     /// ***Verified:*** False
     pub fn send_xml_plist(&self, data: Plist) -> Result<(), PropertyListServiceError> {
         let result = unsafe {
-            unsafe_bindings::property_list_service_send_xml_plist(self.pointer, data.get_pointer())
+            unsafe_bindings::property_list_service_send_xml_plist(
+                *self.pointer.lock(),
+                data.get_pointer(),
+            )
         }
         .into();
 
@@ -76,7 +88,7 @@ impl PropertyListServiceClient<'_> {
     pub fn send_binary_plist(&self, data: Plist) -> Result<(), PropertyListServiceError> {
         let result = unsafe {
             unsafe_bindings::property_list_service_send_binary_plist(
-                self.pointer,
+                *self.pointer.lock(),
                 data.get_pointer(),
             )
         }
@@ -100,10 +112,13 @@ impl PropertyListServiceClient<'_> {
         let mut plist_t = std::ptr::null_mut();
         let result = unsafe {
             if timeout == 0 {
-                unsafe_bindings::property_list_service_receive_plist(self.pointer, &mut plist_t)
+                unsafe_bindings::property_list_service_receive_plist(
+                    *self.pointer.lock(),
+                    &mut plist_t,
+                )
             } else {
                 unsafe_bindings::property_list_service_receive_plist_with_timeout(
-                    self.pointer,
+                    *self.pointer.lock(),
                     &mut plist_t,
                     timeout,
                 )
@@ -127,7 +142,8 @@ impl PropertyListServiceClient<'_> {
     /// ***Verified:*** False
     pub fn enable_ssl(&self) -> Result<(), PropertyListServiceError> {
         let result =
-            unsafe { unsafe_bindings::property_list_service_enable_ssl(self.pointer) }.into();
+            unsafe { unsafe_bindings::property_list_service_enable_ssl(*self.pointer.lock()) }
+                .into();
 
         if result != PropertyListServiceError::Success {
             return Err(result);
@@ -145,7 +161,8 @@ impl PropertyListServiceClient<'_> {
     /// ***Verified:*** False
     pub fn disable_ssl(&self) -> Result<(), PropertyListServiceError> {
         let result =
-            unsafe { unsafe_bindings::property_list_service_disable_ssl(self.pointer) }.into();
+            unsafe { unsafe_bindings::property_list_service_disable_ssl(*self.pointer.lock()) }
+                .into();
 
         if result != PropertyListServiceError::Success {
             return Err(result);
@@ -153,12 +170,21 @@ impl PropertyListServiceClient<'_> {
 
         Ok(())
     }
-}
 
-impl Drop for PropertyListServiceClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::property_list_service_client_free(self.pointer);
+    /// Closes the property list service connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), PropertyListServiceError> {
+        let result = self.pointer.close().into();
+        if result != PropertyListServiceError::Success {
+            return Err(result);
         }
+
+        Ok(())
     }
 }
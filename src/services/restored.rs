@@ -2,18 +2,29 @@
 
 use std::ffi::CString;
 
-use crate::{bindings as unsafe_bindings, error::RestoredError, idevice::Device};
+use crate::{
+    bindings as unsafe_bindings,
+    error::RestoredError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer},
+};
 
 use plist_plus::Plist;
 
 /// Restores an iDevice to a specific backup or iOS version
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct RestoredClient<'a> {
-    pub(crate) pointer: unsafe_bindings::restored_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::restored_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl RestoredClient<'_> {
+impl ClientPointer for unsafe_bindings::restored_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::restored_client_free(self)
+    }
+}
+
+impl<'a> RestoredClient<'a> {
     /// Starts a new connection and adds a restored client to it
     /// # Arguments
     /// * `device` - The device to connect to
@@ -22,7 +33,7 @@ impl RestoredClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, label: impl Into<String>) -> Result<Self, RestoredError> {
+    pub fn new(device: &'a Device, label: impl Into<String>) -> Result<Self, RestoredError> {
         let mut pointer = unsafe { std::mem::zeroed() };
         let label_c_string = CString::new(label.into()).unwrap();
         let result = unsafe {
@@ -38,7 +49,7 @@ impl RestoredClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -53,9 +64,10 @@ impl RestoredClient<'_> {
     pub fn query_type(&self) -> Result<(String, u64), RestoredError> {
         let mut type_ = std::ptr::null_mut();
         let mut version = 0;
-        let result =
-            unsafe { unsafe_bindings::restored_query_type(self.pointer, &mut type_, &mut version) }
-                .into();
+        let result = unsafe {
+            unsafe_bindings::restored_query_type(*self.pointer.lock(), &mut type_, &mut version)
+        }
+        .into();
         if result != RestoredError::Success {
             return Err(result);
         }
@@ -79,7 +91,11 @@ impl RestoredClient<'_> {
         let mut value = std::ptr::null_mut();
         let key_c_string = CString::new(key.into()).unwrap();
         let result = unsafe {
-            unsafe_bindings::restored_query_value(self.pointer, key_c_string.as_ptr(), &mut value)
+            unsafe_bindings::restored_query_value(
+                *self.pointer.lock(),
+                key_c_string.as_ptr(),
+                &mut value,
+            )
         }
         .into();
         if result != RestoredError::Success {
@@ -100,7 +116,11 @@ impl RestoredClient<'_> {
         let mut value = std::ptr::null_mut();
         let key_c_string = CString::new(key.into()).unwrap();
         let result = unsafe {
-            unsafe_bindings::restored_get_value(self.pointer, key_c_string.as_ptr(), &mut value)
+            unsafe_bindings::restored_get_value(
+                *self.pointer.lock(),
+                key_c_string.as_ptr(),
+                &mut value,
+            )
         }
         .into();
         if result != RestoredError::Success {
@@ -119,7 +139,8 @@ impl RestoredClient<'_> {
     /// ***Verified:*** False
     pub fn send(&self, data: Plist) -> Result<(), RestoredError> {
         let result =
-            unsafe { unsafe_bindings::restored_send(self.pointer, data.get_pointer()) }.into();
+            unsafe { unsafe_bindings::restored_send(*self.pointer.lock(), data.get_pointer()) }
+                .into();
         if result != RestoredError::Success {
             return Err(result);
         }
@@ -136,7 +157,8 @@ impl RestoredClient<'_> {
     /// ***Verified:*** False
     pub fn receive(&self) -> Result<Plist, RestoredError> {
         let mut value = std::ptr::null_mut();
-        let result = unsafe { unsafe_bindings::restored_receive(self.pointer, &mut value) }.into();
+        let result =
+            unsafe { unsafe_bindings::restored_receive(*self.pointer.lock(), &mut value) }.into();
         if result != RestoredError::Success {
             return Err(result);
         }
@@ -152,7 +174,7 @@ impl RestoredClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn goodbye(self) -> Result<(), RestoredError> {
-        let result = unsafe { unsafe_bindings::restored_goodbye(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::restored_goodbye(*self.pointer.lock()) }.into();
         if result != RestoredError::Success {
             return Err(result);
         }
@@ -174,7 +196,8 @@ impl RestoredClient<'_> {
             .map_or(std::ptr::null_mut(), |v| v.get_pointer());
 
         let result =
-            unsafe { unsafe_bindings::restored_start_restore(self.pointer, ptr, version) }.into();
+            unsafe { unsafe_bindings::restored_start_restore(*self.pointer.lock(), ptr, version) }
+                .into();
         if result != RestoredError::Success {
             return Err(result);
         }
@@ -182,6 +205,48 @@ impl RestoredClient<'_> {
         Ok(())
     }
 
+    /// Starts a restore of the device and reports its progress
+    /// Progress messages are parsed and passed to `progress` until the device sends anything else,
+    /// such as a data request or the final status, which is returned to the caller.
+    /// After answering a data request, call `wait_for_progress` to keep following the restore.
+    /// # Arguments
+    /// * `options` - The options for the restore
+    /// * `version` - The restore protocol version
+    /// * `progress` - Called for every progress update from the device
+    /// # Returns
+    /// The first message that isn't a progress update
+    ///
+    /// ***Verified:*** False
+    pub fn start_restore_with_progress(
+        &self,
+        options: Option<Plist>,
+        version: u64,
+        progress: impl FnMut(RestoreProgress),
+    ) -> Result<Plist, RestoredError> {
+        self.start_restore(options, version)?;
+        self.wait_for_progress(progress)
+    }
+
+    /// Receives messages from the device, passing progress updates to `progress`
+    /// # Arguments
+    /// * `progress` - Called for every progress update from the device
+    /// # Returns
+    /// The first message that isn't a progress update
+    ///
+    /// ***Verified:*** False
+    pub fn wait_for_progress(
+        &self,
+        mut progress: impl FnMut(RestoreProgress),
+    ) -> Result<Plist, RestoredError> {
+        loop {
+            let message = self.receive()?;
+            match RestoreProgress::from_message(&message) {
+                Some(p) => progress(p),
+                None => return Ok(message),
+            }
+        }
+    }
+
     /// Reboots the device
     /// # Arguments
     /// *none*
@@ -190,7 +255,7 @@ impl RestoredClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn reboot(self) -> Result<(), RestoredError> {
-        let result = unsafe { unsafe_bindings::restored_reboot(self.pointer) }.into();
+        let result = unsafe { unsafe_bindings::restored_reboot(*self.pointer.lock()) }.into();
         if result != RestoredError::Success {
             return Err(result);
         }
@@ -208,15 +273,67 @@ impl RestoredClient<'_> {
     pub fn set_label(&self, label: impl Into<String>) {
         let label_c_string = CString::new(label.into()).unwrap();
         unsafe {
-            unsafe_bindings::restored_client_set_label(self.pointer, label_c_string.as_ptr())
+            unsafe_bindings::restored_client_set_label(
+                *self.pointer.lock(),
+                label_c_string.as_ptr(),
+            )
         };
     }
+
+    /// Closes the restored connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), RestoredError> {
+        let result = self.pointer.close().into();
+        if result != RestoredError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
-impl Drop for RestoredClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::restored_client_free(self.pointer);
+/// A progress update sent by the device during a restore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreProgress {
+    /// The operation code reported by restored
+    pub operation: String,
+    /// How far along the operation is, if the device reported it
+    pub percent: Option<u8>,
+}
+
+impl RestoreProgress {
+    /// Parses a `ProgressMsg` from restored, returning `None` for any other message
+    fn from_message(message: &Plist) -> Option<Self> {
+        let msg_type = message
+            .dict_get_item("MsgType")
+            .ok()?
+            .get_string_val()
+            .ok()?;
+        if msg_type != "ProgressMsg" {
+            return None;
         }
+
+        let operation = match message.dict_get_item("Operation") {
+            Ok(op) => op
+                .get_uint_val()
+                .map(|op| op.to_string())
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        // The device sends -1 when the operation has no meaningful percentage
+        let percent = message
+            .dict_get_item("Progress")
+            .ok()
+            .and_then(|p| p.get_uint_val().ok())
+            .filter(|p| *p <= 100)
+            .map(|p| p as u8);
+
+        Some(RestoreProgress { operation, percent })
     }
 }
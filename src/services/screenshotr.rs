@@ -1,21 +1,30 @@
 // jkcoxson
 
-use std::ffi::CString;
+use std::{ffi::CString, fs, path::Path};
 
-use log::info;
+use log::{info, warn};
 
 use crate::{
-    bindings as unsafe_bindings, error::ScreenshotrError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::ScreenshotrError,
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ScreenshotrClient<'a> {
-    pub(crate) pointer: unsafe_bindings::screenshotr_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::screenshotr_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl ScreenshotrClient<'_> {
+impl ClientPointer for unsafe_bindings::screenshotr_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::screenshotr_client_free(self)
+    }
+}
+
+impl<'a> ScreenshotrClient<'a> {
     /// Creates a preboard client from a screenshotr service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -24,7 +33,7 @@ impl ScreenshotrClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, ScreenshotrError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, ScreenshotrError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::screenshotr_client_new(
@@ -40,12 +49,13 @@ impl ScreenshotrClient<'_> {
         }
 
         Ok(ScreenshotrClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
 
     /// Starts a new connection and adds a screenshotr client to it
+    /// The developer disk image must be mounted first, see `Device::ensure_developer_image`
     /// # Arguments
     /// * `device` - The device to connect to
     /// * `label` - The label for the connection
@@ -54,7 +64,7 @@ impl ScreenshotrClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, ScreenshotrError> {
         let mut pointer = std::ptr::null_mut();
@@ -73,7 +83,7 @@ impl ScreenshotrClient<'_> {
         }
 
         Ok(ScreenshotrClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -82,14 +92,15 @@ impl ScreenshotrClient<'_> {
     /// # Arguments
     /// *none*
     /// # Returns
-    /// A vector of bytes containing a .png
+    /// The image as sent by the device, a TIFF on older versions of iOS and a PNG on newer ones.
+    /// `ScreenshotFormat::detect` tells them apart.
     ///
     /// ***Verified:*** False
     pub fn take_screenshot(&self) -> Result<Vec<u8>, ScreenshotrError> {
         let mut data = unsafe { std::mem::zeroed() };
         let mut size = 0;
         let result = unsafe {
-            unsafe_bindings::screenshotr_take_screenshot(self.pointer, &mut data, &mut size)
+            unsafe_bindings::screenshotr_take_screenshot(*self.pointer.lock(), &mut data, &mut size)
         }
         .into();
 
@@ -101,12 +112,109 @@ impl ScreenshotrClient<'_> {
 
         Ok(unsafe { std::slice::from_raw_parts(data as *mut u8, size as usize).to_vec() })
     }
+
+    /// Takes a screenshot and writes it to a file.
+    /// If the path ends in `.png` and the device sent a TIFF, the image is converted to PNG,
+    /// which needs the `image` feature. Otherwise the bytes are written as the device sent them.
+    /// # Arguments
+    /// * `path` - Where to write the screenshot
+    /// # Returns
+    /// *none*, or `ConversionUnavailable` if a conversion was needed without the `image` feature
+    ///
+    /// ***Verified:*** False
+    pub fn save_screenshot(&self, path: &Path) -> Result<(), ScreenshotrError> {
+        let data = self.take_screenshot()?;
+        let wants_png = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+
+        if wants_png && ScreenshotFormat::detect(&data) == ScreenshotFormat::Tiff {
+            return tiff_to_png(&data, path);
+        }
+
+        fs::write(path, data).map_err(|e| {
+            warn!("Unable to write screenshot to {}: {}", path.display(), e);
+            ScreenshotrError::IoError
+        })
+    }
+
+    /// Closes the screenshotr connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), ScreenshotrError> {
+        let result = self.pointer.close().into();
+        if result != ScreenshotrError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+}
+
+/// The image formats screenshotr sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    Png,
+    Tiff,
+    Unknown,
 }
 
-impl Drop for ScreenshotrClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::screenshotr_client_free(self.pointer);
+impl ScreenshotFormat {
+    /// Works out the format of a screenshot from its first bytes
+    /// # Arguments
+    /// * `data` - The screenshot, as returned by `take_screenshot`
+    /// # Returns
+    /// The format, or `Unknown` if the bytes aren't a PNG or TIFF
+    ///
+    /// ***Verified:*** False
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            ScreenshotFormat::Png
+        } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+            ScreenshotFormat::Tiff
+        } else {
+            ScreenshotFormat::Unknown
         }
     }
 }
+
+#[cfg(feature = "image")]
+fn tiff_to_png(data: &[u8], path: &Path) -> Result<(), ScreenshotrError> {
+    let image =
+        image::load_from_memory_with_format(data, image::ImageFormat::Tiff).map_err(|e| {
+            warn!("Unable to decode the screenshot: {}", e);
+            ScreenshotrError::ImageError
+        })?;
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| {
+            warn!("Unable to write screenshot to {}: {}", path.display(), e);
+            ScreenshotrError::IoError
+        })
+}
+
+#[cfg(not(feature = "image"))]
+fn tiff_to_png(_data: &[u8], path: &Path) -> Result<(), ScreenshotrError> {
+    warn!(
+        "The device sent a TIFF, converting it for {} needs the image feature",
+        path.display()
+    );
+    Err(ScreenshotrError::ConversionUnavailable)
+}
+
+impl<'a> Service<'a> for ScreenshotrClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.mobile.screenshotr";
+    type Error = ScreenshotrError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, ScreenshotrError> {
+        Self::new(device, descriptor)
+    }
+}
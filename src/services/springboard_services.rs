@@ -1,25 +1,42 @@
 // jkcoxson
 
 use std::{
+    collections::HashMap,
     ffi::CString,
     os::raw::{c_char, c_uint},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    bindings as unsafe_bindings, error::SbservicesError, idevice::Device,
-    services::lockdownd::LockdowndService,
+    bindings as unsafe_bindings,
+    error::{NpError, SbservicesError},
+    idevice::Device,
+    service::{ClientHandle, ClientPointer, Service},
+    services::{lockdownd::LockdowndService, notification_proxy::NotificationProxyClient},
 };
 
+use log::warn;
 use plist_plus::Plist;
 
+/// Posted by springboard just before the interface rotates
+const ORIENTATION_CHANGE_NOTIFICATION: &str = "com.apple.springboard.willChangeOrientation";
+/// How often the orientation is read again after the change notification until it differs
+const ORIENTATION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// A service to manage Springboard on iOS
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct SpringboardServicesClient<'a> {
-    pub(crate) pointer: unsafe_bindings::sbservices_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::sbservices_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl SpringboardServicesClient<'_> {
+impl ClientPointer for unsafe_bindings::sbservices_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::sbservices_client_free(self)
+    }
+}
+
+impl<'a> SpringboardServicesClient<'a> {
     /// Creates a preboard client from a springboard service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -28,7 +45,7 @@ impl SpringboardServicesClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, SbservicesError> {
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, SbservicesError> {
         let mut pointer = std::ptr::null_mut();
         let result = unsafe {
             unsafe_bindings::sbservices_client_new(device.pointer, descriptor.pointer, &mut pointer)
@@ -40,7 +57,7 @@ impl SpringboardServicesClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -54,7 +71,7 @@ impl SpringboardServicesClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, SbservicesError> {
         let mut pointer = std::ptr::null_mut();
@@ -73,7 +90,7 @@ impl SpringboardServicesClient<'_> {
         }
 
         Ok(Self {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -94,7 +111,7 @@ impl SpringboardServicesClient<'_> {
 
         let result = unsafe {
             unsafe_bindings::sbservices_get_icon_state(
-                self.pointer,
+                *self.pointer.lock(),
                 &mut plist,
                 format_version_c_string_ptr,
             )
@@ -117,7 +134,7 @@ impl SpringboardServicesClient<'_> {
     /// ***Verified:*** False
     pub fn set_icon_state(&self, state: Plist) -> Result<(), SbservicesError> {
         let result = unsafe {
-            unsafe_bindings::sbservices_set_icon_state(self.pointer, state.get_pointer())
+            unsafe_bindings::sbservices_set_icon_state(*self.pointer.lock(), state.get_pointer())
         }
         .into();
 
@@ -139,14 +156,14 @@ impl SpringboardServicesClient<'_> {
         &self,
         bundle_id: impl Into<String>,
     ) -> Result<Vec<u8>, SbservicesError> {
-        let data: *mut u8 = std::ptr::null_mut();
+        let mut data: *mut c_char = std::ptr::null_mut();
         let mut size = 0;
         let bundle_id_c_string = CString::new(bundle_id.into()).unwrap();
         let result = unsafe {
             unsafe_bindings::sbservices_get_icon_pngdata(
-                self.pointer,
+                *self.pointer.lock(),
                 bundle_id_c_string.as_ptr(),
-                &mut (data as *mut c_char),
+                &mut data,
                 &mut size,
             )
         }
@@ -159,11 +176,36 @@ impl SpringboardServicesClient<'_> {
         if data.is_null() {
             Ok(Vec::new())
         } else {
-            Ok(unsafe { std::slice::from_raw_parts(data, size as usize).to_vec() })
+            let png =
+                unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }.to_vec();
+            unsafe { libc::free(data as *mut libc::c_void) };
+            Ok(png)
+        }
+    }
+
+    /// Gets the icons of several apps.
+    /// Springboard has no call that returns icons in bulk, so this makes one request per app;
+    /// expect it to take a while for a full home screen.
+    /// # Arguments
+    /// * `bundle_ids` - The bundle IDs of the apps to take the icons from
+    /// # Returns
+    /// A map of bundle IDs to the bytes of their .png icons
+    ///
+    /// ***Verified:*** False
+    pub fn all_icons(
+        &self,
+        bundle_ids: &[&str],
+    ) -> Result<HashMap<String, Vec<u8>>, SbservicesError> {
+        let mut icons = HashMap::with_capacity(bundle_ids.len());
+        for bundle_id in bundle_ids {
+            icons.insert(bundle_id.to_string(), self.get_icon_png_data(*bundle_id)?);
         }
+        Ok(icons)
     }
 
-    /// Gets the orientation of the device
+    /// Gets the orientation of the device.
+    /// Springboard services can only read the orientation, there is no command to rotate the device.
+    /// Use `wait_for_orientation_change` to find out when the user or a test harness rotates it.
     /// # Arguments
     /// *none*
     /// # Returns
@@ -173,7 +215,10 @@ impl SpringboardServicesClient<'_> {
     pub fn get_interface_orientation(&self) -> Result<Orientation, SbservicesError> {
         let mut orientation: c_uint = unsafe { std::mem::zeroed() };
         let result = unsafe {
-            unsafe_bindings::sbservices_get_interface_orientation(self.pointer, &mut orientation)
+            unsafe_bindings::sbservices_get_interface_orientation(
+                *self.pointer.lock(),
+                &mut orientation,
+            )
         }
         .into();
 
@@ -184,6 +229,52 @@ impl SpringboardServicesClient<'_> {
         Ok(orientation.into())
     }
 
+    /// Waits for the interface to rotate.
+    /// Springboard only announces that it is about to rotate, so after the notification
+    /// the orientation is read again until it has actually changed.
+    /// # Arguments
+    /// * `notifications` - A notification proxy on the same device, used to observe the rotation
+    /// * `timeout` - How long to wait
+    /// # Returns
+    /// The new orientation, or `Timeout` if the interface didn't rotate in time
+    ///
+    /// ***Verified:*** False
+    pub fn wait_for_orientation_change(
+        &self,
+        notifications: &NotificationProxyClient,
+        timeout: Duration,
+    ) -> Result<Orientation, SbservicesError> {
+        let deadline = Instant::now() + timeout;
+        // Listen before reading so a rotation in between isn't missed
+        let listener = notifications
+            .listen(&[ORIENTATION_CHANGE_NOTIFICATION])
+            .map_err(|e| {
+                warn!("Unable to watch for orientation changes: {}", e);
+                SbservicesError::ConnFailed
+            })?;
+        let before = self.get_interface_orientation()?;
+
+        match listener.wait(deadline) {
+            Ok(_) => {}
+            Err(NpError::Timeout) => return Err(SbservicesError::Timeout),
+            Err(e) => {
+                warn!("Lost the orientation change notification: {}", e);
+                return Err(SbservicesError::ConnFailed);
+            }
+        }
+
+        loop {
+            let orientation = self.get_interface_orientation()?;
+            if orientation != before {
+                return Ok(orientation);
+            }
+            if Instant::now() >= deadline {
+                return Err(SbservicesError::Timeout);
+            }
+            std::thread::sleep(ORIENTATION_POLL_INTERVAL);
+        }
+    }
+
     /// Gets the wallpaper of the homescreen
     /// # Arguments
     /// *none*
@@ -196,7 +287,7 @@ impl SpringboardServicesClient<'_> {
         let mut size = 0;
         let result = unsafe {
             unsafe_bindings::sbservices_get_home_screen_wallpaper_pngdata(
-                self.pointer,
+                *self.pointer.lock(),
                 &mut (data as *mut c_char),
                 &mut size,
             )
@@ -214,6 +305,23 @@ impl SpringboardServicesClient<'_> {
 
         Ok(vec)
     }
+
+    /// Closes the springboard services connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), SbservicesError> {
+        let result = self.pointer.close().into();
+        if result != SbservicesError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
 }
 
 /// A device orientation
@@ -251,10 +359,14 @@ impl From<c_uint> for Orientation {
     }
 }
 
-impl Drop for SpringboardServicesClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::sbservices_client_free(self.pointer);
-        }
+impl<'a> Service<'a> for SpringboardServicesClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.springboardservices";
+    type Error = SbservicesError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, SbservicesError> {
+        Self::new(device, descriptor)
     }
 }
@@ -0,0 +1,421 @@
+// jkcoxson
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{BufWriter, Write},
+    os::raw::c_char,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bindings as unsafe_bindings,
+    error::SyslogRelayError,
+    idevice::Device,
+    service::{timeout_millis, ClientHandle, ClientPointer, Service},
+    services::lockdownd::LockdowndService,
+};
+
+use log::{info, warn};
+
+/// How long the capture thread waits for data before checking if it should stop
+const CAPTURE_POLL_MS: u32 = 500;
+/// How often buffered lines are flushed to the writer while the device keeps logging
+const CAPTURE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// How many bytes the capture thread asks for per read unless changed with `with_buffer_size`
+const DEFAULT_BUFFER_SIZE: u32 = 16 * 1024;
+
+/// Relays the syslog of the device to the host
+#[derive(Debug)]
+pub struct SyslogRelayClient<'a> {
+    pub(crate) pointer: ClientHandle<unsafe_bindings::syslog_relay_client_t>,
+    timeout: Option<u32>,
+    buffer_size: u32,
+    phantom: std::marker::PhantomData<&'a Device>,
+}
+
+impl ClientPointer for unsafe_bindings::syslog_relay_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::syslog_relay_client_free(self)
+    }
+}
+
+impl<'a> SyslogRelayClient<'a> {
+    /// Creates a new syslog relay client from a lockdown service
+    /// # Arguments
+    /// * `device` - The device to connect to
+    /// * `descriptor` - The lockdown service to connect on
+    /// # Returns
+    /// A struct containing the handle to the connection
+    ///
+    /// ***Verified:*** False
+    pub fn new(device: &'a Device, descriptor: LockdowndService) -> Result<Self, SyslogRelayError> {
+        let mut pointer = std::ptr::null_mut();
+        let result = unsafe {
+            unsafe_bindings::syslog_relay_client_new(
+                device.pointer,
+                descriptor.pointer,
+                &mut pointer,
+            )
+        }
+        .into();
+
+        if result != SyslogRelayError::Success {
+            return Err(result);
+        }
+
+        Ok(SyslogRelayClient {
+            pointer: ClientHandle::new(pointer),
+            timeout: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Starts a new connection and adds a syslog relay client to it
+    /// # Arguments
+    /// * `device` - The device to connect to
+    /// * `label` - The label for the connection
+    /// # Returns
+    /// A struct containing the handle to the connection
+    ///
+    /// ***Verified:*** False
+    pub fn start_service(
+        device: &'a Device,
+        label: impl Into<String>,
+    ) -> Result<Self, SyslogRelayError> {
+        let label_c_string = CString::new(label.into()).unwrap();
+        let mut pointer = std::ptr::null_mut();
+        let result = unsafe {
+            unsafe_bindings::syslog_relay_client_start_service(
+                device.pointer,
+                &mut pointer,
+                label_c_string.as_ptr(),
+            )
+        }
+        .into();
+
+        if result != SyslogRelayError::Success {
+            return Err(result);
+        }
+
+        Ok(SyslogRelayClient {
+            pointer: ClientHandle::new(pointer),
+            timeout: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Sets how many bytes the capture functions read from the device at a time, 16 KiB by default.
+    /// Bigger reads mean fewer calls into the connection when the device logs a lot,
+    /// smaller ones hand lines to the writer sooner when it logs a little.
+    /// # Arguments
+    /// * `bytes` - The size of each read
+    /// # Returns
+    /// The client
+    ///
+    /// ***Verified:*** False
+    pub fn with_buffer_size(mut self, bytes: u32) -> Self {
+        self.buffer_size = bytes.max(1);
+        self
+    }
+
+    /// Sets how long `receive` waits for data before returning `Timeout`
+    /// # Arguments
+    /// * `timeout` - The time to wait, or `None` to block forever, which is the default
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout_millis(timeout);
+    }
+
+    /// Receives raw syslog data from the device.
+    /// Blocks until data is received, or returns `Timeout` if a timeout was set with `set_timeout`
+    /// # Arguments
+    /// * `size` - The maximum number of bytes to receive
+    /// # Returns
+    /// The bytes received
+    ///
+    /// ***Verified:*** False
+    pub fn receive(&self, size: u32) -> Result<Vec<u8>, SyslogRelayError> {
+        receive_raw(*self.pointer.lock(), size, self.timeout)
+    }
+
+    /// Receives raw syslog data from the device
+    /// # Arguments
+    /// * `size` - The maximum number of bytes to receive
+    /// * `timeout` - How long to wait for data in milliseconds
+    /// # Returns
+    /// The bytes received
+    ///
+    /// ***Verified:*** False
+    pub fn receive_with_timeout(
+        &self,
+        size: u32,
+        timeout: u32,
+    ) -> Result<Vec<u8>, SyslogRelayError> {
+        receive_raw(*self.pointer.lock(), size, Some(timeout))
+    }
+
+    /// Streams the syslog into a writer on a background thread.
+    /// The writer is flushed whenever the device goes quiet and at least once a second.
+    /// The client moves to the capture thread, and `CaptureHandle::stop` gives it back.
+    /// # Arguments
+    /// * `writer` - Where to write the syslog lines
+    /// # Returns
+    /// A handle that stops the capture when dropped
+    ///
+    /// ***Verified:*** False
+    pub fn capture_to_writer(
+        self,
+        writer: impl Write + Send + 'static,
+    ) -> Result<CaptureHandle<'a>, SyslogRelayError> {
+        self.capture_to_writer_filtered(writer, |_| true)
+    }
+
+    /// Streams the syslog into a writer on a background thread, only keeping matching lines.
+    /// The writer is flushed whenever the device goes quiet and at least once a second.
+    /// The client moves to the capture thread, and `CaptureHandle::stop` gives it back.
+    /// # Arguments
+    /// * `writer` - Where to write the syslog lines
+    /// * `filter` - Returns whether a line should be written
+    /// # Returns
+    /// A handle that stops the capture when dropped
+    ///
+    /// ***Verified:*** False
+    pub fn capture_to_writer_filtered(
+        self,
+        writer: impl Write + Send + 'static,
+        filter: impl FnMut(&str) -> bool + Send + 'static,
+    ) -> Result<CaptureHandle<'a>, SyslogRelayError> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        // The thread owns the connection from here on, so nothing else can use or free it
+        let SyslogRelayClient {
+            pointer,
+            timeout,
+            buffer_size,
+            ..
+        } = self;
+
+        info!("Starting syslog capture");
+        let thread = std::thread::spawn(move || {
+            let result = capture(&pointer, buffer_size, &thread_stop, writer, filter);
+            (pointer, result)
+        });
+
+        Ok(CaptureHandle {
+            stop,
+            thread: Some(thread),
+            timeout,
+            buffer_size,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Streams the syslog into a file on a background thread.
+    /// The file is created, or truncated if it exists.
+    /// The client moves to the capture thread, and `CaptureHandle::stop` gives it back.
+    /// # Arguments
+    /// * `path` - The path of the file to write to
+    /// # Returns
+    /// A handle that stops the capture when dropped.
+    /// If the file can't be created the client is closed and `InvalidArg` returned.
+    ///
+    /// ***Verified:*** False
+    pub fn capture_to_file(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<CaptureHandle<'a>, SyslogRelayError> {
+        let file = match File::create(path.as_ref()) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Unable to create {}: {}", path.as_ref().display(), e);
+                return Err(SyslogRelayError::InvalidArg);
+            }
+        };
+        self.capture_to_writer(BufWriter::new(file))
+    }
+
+    /// Closes the syslog relay connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), SyslogRelayError> {
+        let result = self.pointer.close().into();
+        if result != SyslogRelayError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the syslog into the writer until told to stop or the connection fails
+fn capture(
+    relay: &RelayHandle,
+    buffer_size: u32,
+    stop: &AtomicBool,
+    mut writer: impl Write,
+    mut filter: impl FnMut(&str) -> bool,
+) -> Result<(), SyslogRelayError> {
+    let mut line = Vec::new();
+    let mut last_flush = Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        let data = match receive_raw(*relay.lock(), buffer_size, Some(CAPTURE_POLL_MS)) {
+            Ok(data) => data,
+            Err(SyslogRelayError::Timeout) => Vec::new(),
+            Err(e) => {
+                let _ = writer.flush();
+                return Err(e);
+            }
+        };
+
+        for byte in data {
+            match byte {
+                // The relay separates messages with null bytes
+                0 => continue,
+                b'\n' => {
+                    let text = String::from_utf8_lossy(&line);
+                    if filter(&text) {
+                        writeln!(writer, "{}", text).map_err(|e| {
+                            warn!("Failed to write syslog line: {}", e);
+                            SyslogRelayError::UnknownError
+                        })?;
+                    }
+                    line.clear();
+                }
+                _ => line.push(byte),
+            }
+        }
+
+        if last_flush.elapsed() >= CAPTURE_FLUSH_INTERVAL {
+            writer.flush().map_err(|e| {
+                warn!("Failed to flush syslog writer: {}", e);
+                SyslogRelayError::UnknownError
+            })?;
+            last_flush = Instant::now();
+        }
+    }
+
+    let _ = writer.flush();
+    Ok(())
+}
+
+/// A running syslog capture.
+/// The capture thread owns the client's connection until `stop` hands the client back.
+/// Dropping the handle stops the capture and closes the connection. Forgetting it leaves
+/// the thread reading until the connection fails, but never frees the connection under it.
+#[derive(Debug)]
+pub struct CaptureHandle<'a> {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<(RelayHandle, Result<(), SyslogRelayError>)>>,
+    timeout: Option<u32>,
+    buffer_size: u32,
+    phantom: std::marker::PhantomData<&'a Device>,
+}
+
+impl<'a> CaptureHandle<'a> {
+    /// Stops the capture and waits for the writer to be flushed
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The client, or the error that ended the capture early, in which case the connection is closed
+    ///
+    /// ***Verified:*** False
+    pub fn stop(mut self) -> Result<SyslogRelayClient<'a>, SyslogRelayError> {
+        Ok(SyslogRelayClient {
+            pointer: self.join()?,
+            timeout: self.timeout,
+            buffer_size: self.buffer_size,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    fn join(&mut self) -> Result<RelayHandle, SyslogRelayError> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => match thread.join() {
+                Ok((relay, Ok(()))) => Ok(relay),
+                Ok((_, Err(e))) => Err(e),
+                Err(_) => Err(SyslogRelayError::UnknownError),
+            },
+            None => Err(SyslogRelayError::UnknownError),
+        }
+    }
+}
+
+impl Drop for CaptureHandle<'_> {
+    fn drop(&mut self) {
+        if self.thread.is_none() {
+            return;
+        }
+        if let Err(e) = self.join() {
+            warn!("Syslog capture ended with an error: {:?}", e);
+        }
+    }
+}
+
+/// The connection of a client that moved to the capture thread
+type RelayHandle = ClientHandle<unsafe_bindings::syslog_relay_client_t>;
+
+fn receive_raw(
+    pointer: unsafe_bindings::syslog_relay_client_t,
+    size: u32,
+    timeout: Option<u32>,
+) -> Result<Vec<u8>, SyslogRelayError> {
+    let mut data = vec![0u8; size as usize];
+    let mut received = 0;
+
+    let result = unsafe {
+        match timeout {
+            Some(timeout) => unsafe_bindings::syslog_relay_receive_with_timeout(
+                pointer,
+                data.as_mut_ptr() as *mut c_char,
+                size,
+                &mut received,
+                timeout,
+            ),
+            None => unsafe_bindings::syslog_relay_receive(
+                pointer,
+                data.as_mut_ptr() as *mut c_char,
+                size,
+                &mut received,
+            ),
+        }
+    }
+    .into();
+
+    if result != SyslogRelayError::Success {
+        return Err(result);
+    }
+
+    data.truncate(received as usize);
+    Ok(data)
+}
+
+impl<'a> Service<'a> for SyslogRelayClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.syslog_relay";
+    type Error = SyslogRelayError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, SyslogRelayError> {
+        Self::new(device, descriptor)
+    }
+}
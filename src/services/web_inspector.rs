@@ -1,22 +1,35 @@
 // jkcoxson
 
-use std::ffi::CString;
+use std::{
+    ffi::CString,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    bindings as unsafe_bindings, error::WebInspectorError, idevice::Device,
+    bindings as unsafe_bindings,
+    error::WebInspectorError,
+    idevice::Device,
+    service::{new_uuid, timeout_millis, ClientHandle, ClientPointer, Service},
     services::lockdownd::LockdowndService,
 };
 
+use log::trace;
 use plist_plus::Plist;
 
 /// First used on MacOS, this service is used to inspect the JavaScript and HTML of a site running on the device
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct WebInspectorClient<'a> {
-    pub(crate) pointer: unsafe_bindings::webinspector_client_t,
+    pub(crate) pointer: ClientHandle<unsafe_bindings::webinspector_client_t>,
     phantom: std::marker::PhantomData<&'a Device>,
 }
 
-impl WebInspectorClient<'_> {
+impl ClientPointer for unsafe_bindings::webinspector_client_t {
+    unsafe fn free(self) -> i32 {
+        unsafe_bindings::webinspector_client_free(self)
+    }
+}
+
+impl<'a> WebInspectorClient<'a> {
     /// Creates a preboard client from a web inspector service
     /// # Arguments
     /// * `device` - The device to connect to
@@ -25,7 +38,10 @@ impl WebInspectorClient<'_> {
     /// A struct containing the handle to the connection
     ///
     /// ***Verified:*** False
-    pub fn new(device: &Device, descriptor: LockdowndService) -> Result<Self, WebInspectorError> {
+    pub fn new(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, WebInspectorError> {
         let mut pointer = std::ptr::null_mut();
 
         let result = unsafe {
@@ -42,7 +58,7 @@ impl WebInspectorClient<'_> {
         }
 
         Ok(WebInspectorClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -56,7 +72,7 @@ impl WebInspectorClient<'_> {
     ///
     /// ***Verified:*** False
     pub fn start_service(
-        device: &Device,
+        device: &'a Device,
         label: impl Into<String>,
     ) -> Result<Self, WebInspectorError> {
         let mut pointer = std::ptr::null_mut();
@@ -76,7 +92,7 @@ impl WebInspectorClient<'_> {
         }
 
         Ok(WebInspectorClient {
-            pointer,
+            pointer: ClientHandle::new(pointer),
             phantom: std::marker::PhantomData,
         })
     }
@@ -90,7 +106,8 @@ impl WebInspectorClient<'_> {
     /// ***Verified:*** False
     pub fn send(&self, data: Plist) -> Result<(), WebInspectorError> {
         let result =
-            unsafe { unsafe_bindings::webinspector_send(self.pointer, data.get_pointer()) }.into();
+            unsafe { unsafe_bindings::webinspector_send(*self.pointer.lock(), data.get_pointer()) }
+                .into();
 
         if result != WebInspectorError::Success {
             return Err(result);
@@ -111,10 +128,10 @@ impl WebInspectorClient<'_> {
 
         let result = unsafe {
             if timeout == 0 {
-                unsafe_bindings::webinspector_receive(self.pointer, &mut plist)
+                unsafe_bindings::webinspector_receive(*self.pointer.lock(), &mut plist)
             } else {
                 unsafe_bindings::webinspector_receive_with_timeout(
-                    self.pointer,
+                    *self.pointer.lock(),
                     &mut plist,
                     timeout,
                 )
@@ -128,12 +145,276 @@ impl WebInspectorClient<'_> {
 
         Ok(plist.into())
     }
+
+    /// Announces this host to the web inspector and returns a session for talking to it
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// A session identified by a freshly generated connection identifier
+    ///
+    /// ***Verified:*** False
+    pub fn open_session(&self) -> Result<InspectorSession<'_>, WebInspectorError> {
+        let session = InspectorSession {
+            client: self,
+            connection_id: new_uuid(),
+        };
+        session.send_rpc("_rpc_reportIdentifier:", session.argument())?;
+        Ok(session)
+    }
+
+    /// Closes the web inspector connection, returning any error from the teardown.
+    /// Dropping the client does the same, but has nowhere to report the error.
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// *none*
+    ///
+    /// ***Verified:*** False
+    pub fn close(mut self) -> Result<(), WebInspectorError> {
+        let result = self.pointer.close().into();
+        if result != WebInspectorError::Success {
+            return Err(result);
+        }
+
+        Ok(())
+    }
+}
+
+/// How long a session waits for the device to answer a request, including any unrelated messages it sends first
+const SESSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the web inspector, announced with `_rpc_reportIdentifier:`
+#[derive(Debug)]
+pub struct InspectorSession<'a> {
+    client: &'a WebInspectorClient<'a>,
+    connection_id: String,
+}
+
+/// An application on the device that can be inspected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectorApplication {
+    pub identifier: String,
+    pub name: Option<String>,
+    pub bundle_id: Option<String>,
+    pub is_active: bool,
+    pub is_proxy: bool,
+}
+
+/// A page, such as a tab or web view, inside an inspectable application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectorPage {
+    pub identifier: u64,
+    pub page_type: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+}
+
+/// A socket forwarded to a page, identified by its sender key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectorSocket {
+    pub application: String,
+    pub page: u64,
+    pub sender_id: String,
 }
 
-impl Drop for WebInspectorClient<'_> {
-    fn drop(&mut self) {
-        unsafe {
-            unsafe_bindings::webinspector_client_free(self.pointer);
+impl InspectorSession<'_> {
+    /// Gets the identifier this session announced itself with
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The connection identifier
+    ///
+    /// ***Verified:*** False
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+
+    /// Lists the applications that can currently be inspected
+    /// # Arguments
+    /// *none*
+    /// # Returns
+    /// The applications reported in `WIRApplicationDictionaryKey`
+    ///
+    /// ***Verified:*** False
+    pub fn list_applications(&self) -> Result<Vec<InspectorApplication>, WebInspectorError> {
+        self.send_rpc("_rpc_getConnectedApplications:", self.argument())?;
+        let deadline = Instant::now() + SESSION_TIMEOUT;
+        let response = self.wait_for("_rpc_reportConnectedApplicationList:", deadline)?;
+        let argument = response
+            .dict_get_item("__argument")
+            .map_err(|_| WebInspectorError::PlistError)?;
+        let applications = argument
+            .dict_get_item("WIRApplicationDictionaryKey")
+            .map_err(|_| WebInspectorError::PlistError)?;
+
+        let mut found = Vec::new();
+        for item in applications.into_iter() {
+            let identifier = match item.key {
+                Some(key) => key,
+                None => continue,
+            };
+            found.push(InspectorApplication {
+                identifier,
+                name: string_item(&item.plist, "WIRApplicationNameKey"),
+                bundle_id: string_item(&item.plist, "WIRApplicationBundleIdentifierKey"),
+                is_active: bool_item(&item.plist, "WIRIsApplicationActiveKey"),
+                is_proxy: bool_item(&item.plist, "WIRIsApplicationProxyKey"),
+            });
+        }
+        Ok(found)
+    }
+
+    /// Lists the pages of an application
+    /// # Arguments
+    /// * `application` - The identifier of the application, from `list_applications`
+    /// # Returns
+    /// The pages reported in `WIRListingKey`
+    ///
+    /// ***Verified:*** False
+    pub fn list_pages(&self, application: &str) -> Result<Vec<InspectorPage>, WebInspectorError> {
+        let mut argument = self.argument();
+        argument
+            .dict_set_item(
+                "WIRApplicationIdentifierKey",
+                Plist::new_string(application),
+            )
+            .map_err(|_| WebInspectorError::PlistError)?;
+        self.send_rpc("_rpc_forwardGetListing:", argument)?;
+
+        // Other applications may send their listings first, so keep waiting for ours
+        let deadline = Instant::now() + SESSION_TIMEOUT;
+        loop {
+            let response = self.wait_for("_rpc_applicationSentListing:", deadline)?;
+            let argument = response
+                .dict_get_item("__argument")
+                .map_err(|_| WebInspectorError::PlistError)?;
+            if string_item(&argument, "WIRApplicationIdentifierKey").as_deref() != Some(application)
+            {
+                continue;
+            }
+            let listing = argument
+                .dict_get_item("WIRListingKey")
+                .map_err(|_| WebInspectorError::PlistError)?;
+
+            let mut pages = Vec::new();
+            for item in listing.into_iter() {
+                let identifier = match item.plist.dict_get_item("WIRPageIdentifierKey") {
+                    Ok(id) => id
+                        .get_uint_val()
+                        .map_err(|_| WebInspectorError::PlistError)?,
+                    Err(_) => continue,
+                };
+                pages.push(InspectorPage {
+                    identifier,
+                    page_type: string_item(&item.plist, "WIRTypeKey"),
+                    title: string_item(&item.plist, "WIRTitleKey"),
+                    url: string_item(&item.plist, "WIRURLKey"),
+                });
+            }
+            return Ok(pages);
         }
     }
+
+    /// Sets up a socket to a page so inspector messages can be forwarded to it
+    /// # Arguments
+    /// * `application` - The identifier of the application
+    /// * `page` - The identifier of the page, from `list_pages`
+    /// # Returns
+    /// The socket, with the sender key that identifies it
+    ///
+    /// ***Verified:*** False
+    pub fn forward_socket(
+        &self,
+        application: &str,
+        page: u64,
+    ) -> Result<InspectorSocket, WebInspectorError> {
+        let sender_id = new_uuid();
+
+        let mut argument = self.argument();
+        let items = [
+            (
+                "WIRApplicationIdentifierKey",
+                Plist::new_string(application),
+            ),
+            ("WIRPageIdentifierKey", Plist::new_uint(page)),
+            ("WIRSenderKey", Plist::new_string(&sender_id)),
+            ("WIRAutomaticallyPause", Plist::new_bool(false)),
+        ];
+        for (key, value) in items {
+            argument
+                .dict_set_item(key, value)
+                .map_err(|_| WebInspectorError::PlistError)?;
+        }
+        self.send_rpc("_rpc_forwardSocketSetup:", argument)?;
+
+        Ok(InspectorSocket {
+            application: application.to_string(),
+            page,
+            sender_id,
+        })
+    }
+
+    /// Builds an argument dictionary holding this session's connection identifier
+    fn argument(&self) -> Plist {
+        let mut argument = Plist::new_dict();
+        // Setting a string on a fresh dictionary can't fail
+        let _ = argument.dict_set_item(
+            "WIRConnectionIdentifierKey",
+            Plist::new_string(&self.connection_id),
+        );
+        argument
+    }
+
+    fn send_rpc(&self, selector: &str, argument: Plist) -> Result<(), WebInspectorError> {
+        let mut message = Plist::new_dict();
+        message
+            .dict_set_item("__selector", Plist::new_string(selector))
+            .map_err(|_| WebInspectorError::PlistError)?;
+        message
+            .dict_set_item("__argument", argument)
+            .map_err(|_| WebInspectorError::PlistError)?;
+        self.client.send(message)
+    }
+
+    /// Receives messages until one with the given selector arrives, skipping unrelated reports.
+    /// Returns `ReceiveTimeout` once the deadline passes, however many messages were skipped
+    fn wait_for(&self, selector: &str, deadline: Instant) -> Result<Plist, WebInspectorError> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(WebInspectorError::ReceiveTimeout);
+            }
+            // Never `None`, since the timeout is set
+            let timeout = timeout_millis(Some(remaining)).unwrap_or(1);
+            let message = self.client.receive(timeout)?;
+            match string_item(&message, "__selector") {
+                Some(s) if s == selector => return Ok(message),
+                Some(s) => trace!("Skipping web inspector message {}", s),
+                None => trace!("Skipping web inspector message without a selector"),
+            }
+        }
+    }
+}
+
+fn string_item(dict: &Plist, key: &str) -> Option<String> {
+    dict.dict_get_item(key).ok()?.get_string_val().ok()
+}
+
+fn bool_item(dict: &Plist, key: &str) -> bool {
+    dict.dict_get_item(key)
+        .ok()
+        .and_then(|p| p.get_bool_val().ok())
+        .unwrap_or(false)
+}
+
+impl<'a> Service<'a> for WebInspectorClient<'a> {
+    const SERVICE_NAME: &'static str = "com.apple.webinspector";
+    type Error = WebInspectorError;
+
+    fn from_descriptor(
+        device: &'a Device,
+        descriptor: LockdowndService,
+    ) -> Result<Self, WebInspectorError> {
+        Self::new(device, descriptor)
+    }
 }
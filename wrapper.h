@@ -25,6 +25,8 @@
 #include "submodules/libimobiledevice/include/libimobiledevice/service.h"
 #include "submodules/libimobiledevice/include/libimobiledevice/syslog_relay.h"
 #include "submodules/libimobiledevice/include/libimobiledevice/webinspector.h"
+#include "submodules/libimobiledevice/src/device_link_service.h"
 #include "submodules/libimobiledevice/src/idevice.h"
 #include "submodules/libimobiledevice/src/lockdown.h"
+#include "submodules/libimobiledevice/src/mobilesync.h"
 #include "submodules/libplist/include/plist/plist.h"
\ No newline at end of file